@@ -0,0 +1,3156 @@
+use anyhow::{anyhow, Context, Result};
+use rhai::Scope;
+use serde_yaml;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::changelog::NotedChange;
+use crate::computed::{record_scope, ComputedFields};
+use crate::dbc::{
+    build_string_map, check_written_dbc, compact_string_block, dbc_file_name, find_dbc_file, intern_string, pending_string_at, write_dbc, DbcHeader,
+};
+use crate::diagnostics::{capture, check_strict, clear_location, set_field, set_location, Captured, Location};
+use crate::expr::{eval_value, has_expression, TextValue};
+use crate::helpers::{
+    expand_item_display, expand_learn_spell, expand_spell_ranks, expand_talent, find_icon_blp, icon_name, spell_family,
+};
+use crate::ids::{resolve_symbols, symbol_name, IdMap};
+use crate::l10n::{is_per_locale, per_locale_texts, Translations};
+use crate::migrate::warn_deprecations;
+use crate::parallel::map_parallel;
+use crate::patch::{Condition, EntryNote, PatchEntry, PatchFile, ValueType};
+use crate::rewrite::rewrite_strings;
+use crate::schema::{load_schema, FieldDef, FieldKind, Schema};
+use crate::script::run_script;
+use crate::source::{DbcSource, DbcTable, FileSource, Overlay};
+use crate::spelltext::{check_spell_text, is_spell_text_field};
+use crate::stats::{print_summary, AppliedTable, TableStats};
+use crate::templates::{collect_templates, collect_vars, expand_instances, substitute_vars};
+use crate::units::Transformers;
+use crate::zones::{zone_keys, AreaTable};
+
+/// Resolves a key column name or index to a numeric index
+fn resolve_key_column_index(
+    key_column: &Option<String>,
+    schema_map: &Option<Schema>,
+    file_name: &str,
+    pf_origin: &str,
+) -> usize {
+    let index = match key_column {
+        Some(ref col_name) => {
+            // Try to parse as a number first
+            if let Ok(idx) = col_name.parse::<usize>() {
+                idx
+            } else {
+                // Look up by name in schema map
+                if let Some(ref schema) = schema_map {
+                    if let Some(idx) = schema.column(col_name) {
+                        idx
+                    } else {
+                        warning!(
+                            "unknown key column '{}' in {} (patch file: {}) – defaulting to 0",
+                            col_name, file_name, pf_origin
+                        );
+                        0
+                    }
+                } else {
+                    warning!(
+                        "no schema for {} (patch file: {}), cannot resolve key column '{}', defaulting to 0",
+                        file_name, pf_origin, col_name
+                    );
+                    0
+                }
+            }
+        }
+        None => 0,
+    };
+    trace!(
+        "key column {} of {} is column {}",
+        key_column.as_deref().unwrap_or("(default)"),
+        file_name,
+        index
+    );
+    index
+}
+
+/// Whether the schema declares column `index` as a float.
+fn is_float_column(schema_map: &Option<Schema>, index: usize) -> bool {
+    schema_map
+        .as_ref()
+        .and_then(|schema| schema.field_at(index))
+        .is_some_and(|field| field.kind == FieldKind::Float)
+}
+
+/// What a key column cell is indexed under.  Float columns are compared
+/// by value, so `key: 35` finds a radius of 35.0 rather than its bit
+/// pattern; -0.0 is folded into 0.0 for the same reason.
+fn index_key(cell: u32, float: bool) -> u32 {
+    if float && f32::from_bits(cell) == 0.0 {
+        0
+    } else {
+        cell
+    }
+}
+
+/// What the cells holding `key` are indexed under.
+fn lookup_key(key: u32, float: bool) -> u32 {
+    if float {
+        (key as f32).to_bits()
+    } else {
+        key
+    }
+}
+
+/// Resolves a field name or index to a numeric index
+pub(crate) fn resolve_field_index(
+    field_name: &str,
+    schema_map: &Option<Schema>,
+) -> Option<usize> {
+    // Try parse as number
+    if let Ok(idx) = field_name.parse::<usize>() {
+        Some(idx)
+    } else {
+        schema_map
+            .as_ref()
+            .and_then(|schema| schema.column(field_name))
+    }
+}
+
+/// A cell value for `--trace`: floats as a number with their bit pattern,
+/// other values as decimal and hex.
+fn traced_cell(cell: u32, field: Option<&FieldDef>, value: &ValueType) -> String {
+    if field.is_some_and(|f| f.kind == FieldKind::Float) || matches!(value, ValueType::Float(_)) {
+        format!("{:?} (bits {:#010x})", f32::from_bits(cell), cell)
+    } else {
+        format!("{} ({:#x})", cell, cell)
+    }
+}
+
+/// Applies values to a record, handling string allocation.  Fails when a
+/// value does not match the type the schema declares for its field.
+///
+/// A localized string may be written by its bare name (`Name` for the
+/// `Name_lang_*` columns): the value goes to the slot of each of `locales`
+/// and the locale's bit is set in the mask column.  A mapping of locale
+/// names to texts, `Name: { enUS: ..., deDE: ... }`, writes each text to
+/// its own locale's slot instead.  A slot the values also name explicitly,
+/// e.g. a translation, keeps that value.
+///
+/// Values are written in column order (then by name), not in the map's
+/// order, so new strings are queued in the same order on every run and
+/// the string block comes out byte for byte the same.
+///
+/// A value written as `"=<expression>"` is evaluated first, with the
+/// fields of the record as it was before this entry in scope (see
+/// [`TextValue`]), and must then fit its field like any other value.
+/// Fields whose schema names their values take those names (see
+/// [`crate::schema::Constants`]), and a value such as `{or: 0x80}`
+/// changes bits of what the field holds (see [`crate::patch::BitOp`]);
+/// `{add: 5}` or `{mul: 1.1}` changes the number it holds, by the type
+/// the schema gives it (see [`crate::patch::NumberOp`]).
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn apply_values_to_record(
+    values: &HashMap<String, ValueType>,
+    record: &mut [u32],
+    schema_map: &Option<Schema>,
+    locales: &[usize],
+    string_map: &mut HashMap<String, u32>,
+    new_strings: &mut Vec<String>,
+    string_block: &[u8],
+    file_name: &str,
+    pf_origin: &str,
+    record_key: u32,
+) -> Result<()> {
+    let mut names: Vec<&String> = values.keys().collect();
+    names.sort();
+    let mut resolved = Vec::with_capacity(names.len());
+    let mut localized = Vec::new();
+    for field_name in names {
+        set_field(Some(field_name));
+        if let Some(i) = resolve_field_index(field_name, schema_map) {
+            resolved.push((i, field_name, &values[field_name]));
+        } else if let Some((first, mask)) = schema_map.as_ref().and_then(|s| s.loc_columns(field_name)) {
+            localized.push((first, mask, field_name));
+        } else {
+            warning!(
+                "unknown field '{}' in {} (patch file: {}) – skipping",
+                field_name, file_name, pf_origin
+            );
+        }
+    }
+    let mut masks: BTreeMap<usize, u32> = BTreeMap::new();
+    for (first, mask, field_name) in localized {
+        let value = &values[field_name];
+        let texts = match value {
+            ValueType::Unit(texts) if is_per_locale(value) => per_locale_texts(texts).map_err(|err| {
+                anyhow!(
+                    "Invalid value for '{}' of record {} in {}: {} (patch file: {})",
+                    field_name,
+                    record_key,
+                    file_name,
+                    err,
+                    pf_origin
+                )
+            })?,
+            _ => locales.iter().map(|&slot| (slot, value)).collect(),
+        };
+        for (slot, text) in texts {
+            if resolved.iter().any(|&(i, _, _)| i == first + slot) {
+                continue;
+            }
+            resolved.push((first + slot, field_name, text));
+            *masks.entry(mask).or_default() |= 1 << slot;
+        }
+    }
+    resolved.sort_by_key(|&(i, name, _)| (i, name));
+
+    let mut scope = match schema_map {
+        Some(schema) if has_expression(values.values()) => {
+            record_scope(record, schema, string_block, new_strings)
+        }
+        _ => Scope::new(),
+    };
+    for (field_idx, field_name, value) in resolved {
+        set_field(Some(field_name));
+        if field_idx >= record.len() {
+            warning!(
+                "field {} out of range for record with key {} in {} (patch file: {})",
+                field_idx, record_key, file_name, pf_origin
+            );
+            continue;
+        }
+
+        let field = schema_map.as_ref().and_then(|schema| schema.field_at(field_idx));
+        let evaluated;
+        let value = match value {
+            ValueType::String(text) => match TextValue::of(text) {
+                TextValue::Expression(expression) => {
+                    let float = field.is_some_and(|f| f.kind == FieldKind::Float);
+                    evaluated = eval_value(expression, &mut scope, float).map_err(|err| {
+                        anyhow!(
+                            "Invalid expression for '{}' of record {} in {}: {} (patch file: {})",
+                            field_name,
+                            record_key,
+                            file_name,
+                            err,
+                            pf_origin
+                        )
+                    })?;
+                    &evaluated
+                }
+                TextValue::Literal(literal) if literal.len() < text.len() => {
+                    evaluated = ValueType::String(literal.to_string());
+                    &evaluated
+                }
+                // A symbol left unresolved, such as a typo, is not text
+                // to intern unless the field holds strings
+                TextValue::Literal(_) => match symbol_name(text) {
+                    Some(symbol) if field.is_none_or(|f| f.kind != FieldKind::String) => anyhow::bail!(
+                        "Unknown symbol ${} for '{}' of record {} in {}: only the keys of new records are given IDs (patch file: {})",
+                        symbol,
+                        field_name,
+                        record_key,
+                        file_name,
+                        pf_origin
+                    ),
+                    _ => value,
+                },
+            },
+            _ => value,
+        };
+        let named;
+        let value = match field.and_then(|f| f.constants.as_ref()).and_then(|c| c.resolve(value)) {
+            Some(Ok(number)) => {
+                named = number;
+                &named
+            }
+            Some(Err(err)) => anyhow::bail!(
+                "Invalid value for '{}' of record {} in {}: {} (patch file: {})",
+                field_name,
+                record_key,
+                file_name,
+                err,
+                pf_origin
+            ),
+            None => value,
+        };
+        let combined;
+        let value = match value.bit_operations() {
+            Some(operations) => {
+                let mut cell = record[field_idx];
+                for (op, operand) in operations {
+                    // Flag names count as their bits
+                    let bits = match field.and_then(|f| f.constants.as_ref()).and_then(|c| c.resolve(operand)) {
+                        Some(resolved) => resolved,
+                        None => Ok(operand.clone()),
+                    };
+                    let bits = bits.and_then(|bits| match bits {
+                        ValueType::Int(_) | ValueType::UInt(_) => {
+                            bits.as_u32().ok_or_else(|| format!("{:?} does not fit in 32 bits", operand))
+                        }
+                        _ => Err(format!("{:?} is not a whole number", operand)),
+                    });
+                    let bits = bits.map_err(|err| {
+                        anyhow!(
+                            "Invalid bits for '{}' of record {} in {}: {} (patch file: {})",
+                            field_name,
+                            record_key,
+                            file_name,
+                            err,
+                            pf_origin
+                        )
+                    })?;
+                    cell = op.apply(cell, bits);
+                }
+                combined = match field.map(|f| f.kind) {
+                    Some(FieldKind::Int32) => ValueType::Int(cell as i32 as i64),
+                    _ => ValueType::UInt(cell as u64),
+                };
+                &combined
+            }
+            None => value,
+        };
+        let computed;
+        let value = match value.number_operations() {
+            Some(operations) => {
+                let cell = record[field_idx];
+                let kind = field.map_or(FieldKind::Int, |f| f.kind);
+                let mut number = match kind {
+                    FieldKind::Float => f32::from_bits(cell) as f64,
+                    FieldKind::Int32 => cell as i32 as f64,
+                    FieldKind::Int => cell as f64,
+                    FieldKind::String | FieldKind::Bool => anyhow::bail!(
+                        "Invalid value for {} field '{}' of record {} in {}: `add` and `mul` need a number field (patch file: {})",
+                        kind.name(),
+                        field_name,
+                        record_key,
+                        file_name,
+                        pf_origin
+                    ),
+                };
+                for (op, operand) in operations {
+                    let operand = match *operand {
+                        ValueType::Int(v) => v as f64,
+                        ValueType::UInt(v) => v as f64,
+                        ValueType::Float(v) => v,
+                        _ => anyhow::bail!(
+                            "Invalid operand for '{}' of record {} in {}: {:?} is not a number (patch file: {})",
+                            field_name,
+                            record_key,
+                            file_name,
+                            operand,
+                            pf_origin
+                        ),
+                    };
+                    number = op.apply(number, operand);
+                }
+                // Integer fields take the nearest whole number, which must
+                // fit the field rather than wrap around
+                let range = match kind {
+                    FieldKind::Float => None,
+                    FieldKind::Int32 => Some((i32::MIN as f64, i32::MAX as f64)),
+                    _ => Some((0.0, u32::MAX as f64)),
+                };
+                computed = match range {
+                    None => ValueType::Float(number),
+                    Some((low, high)) if (low..=high).contains(&number.round()) => ValueType::Int(number.round() as i64),
+                    Some(_) => anyhow::bail!(
+                        "Invalid value for {} field '{}' of record {} in {}: `add` and `mul` give {}, which does not fit it (patch file: {})",
+                        kind.name(),
+                        field_name,
+                        record_key,
+                        file_name,
+                        number,
+                        pf_origin
+                    ),
+                };
+                &computed
+            }
+            None => value,
+        };
+        if let (Some(field), ValueType::String(text)) = (field, value) {
+            if is_spell_text_field(file_name, &field.name) {
+                for problem in check_spell_text(text) {
+                    warning!(
+                        "malformed tooltip token in {} of record {} in {}: {} (patch file: {})",
+                        field.name, record_key, file_name, problem, pf_origin
+                    );
+                }
+            }
+        }
+
+        // Fields with a declared type only take values of that type
+        let cell = if let Some(field) = field.filter(|f| f.typed) {
+            let cell = value.typed_cell(field.kind).map_err(|err| {
+                anyhow::anyhow!(
+                    "Invalid value for {} field '{}' of record {} in {}: {} (patch file: {})",
+                    field.kind.name(),
+                    field.name,
+                    record_key,
+                    file_name,
+                    err,
+                    pf_origin
+                )
+            })?;
+            match (cell, value) {
+                (Some(cell), _) => cell,
+                (None, ValueType::String(s)) => intern_string(s, string_map, new_strings, string_block.len()),
+                (None, _) => continue,
+            }
+        } else {
+            match value {
+                // Units are converted in patch values only, not in templates
+                ValueType::Unit(_) => anyhow::bail!(
+                    "Unconverted unit for field '{}' of record {} in {} (patch file: {})",
+                    field_name,
+                    record_key,
+                    file_name,
+                    pf_origin
+                ),
+                ValueType::List(_) => anyhow::bail!(
+                    "Invalid value for '{}' of record {} in {}: a list needs a field whose schema declares `flags` (patch file: {})",
+                    field_name,
+                    record_key,
+                    file_name,
+                    pf_origin
+                ),
+                // Reuse the offset if the string already exists
+                ValueType::String(s) => intern_string(s, string_map, new_strings, string_block.len()),
+                // IDs and foreign keys are integers whether or not the
+                // schema says so; a float there is a typo, not bits
+                ValueType::Float(f) if field_idx == 0 || field.is_some_and(|f| f.reference.is_some()) => anyhow::bail!(
+                    "Invalid value for '{}' of record {} in {}: {:?} is a float, but the column holds IDs (patch file: {})",
+                    field_name,
+                    record_key,
+                    file_name,
+                    f,
+                    pf_origin
+                ),
+                _ => match value.as_u32() {
+                    Some(int_val) => int_val,
+                    None => continue,
+                },
+            }
+        };
+        trace!(
+            "{} of record {}: column {} {} -> {}",
+            field_name,
+            record_key,
+            field_idx,
+            traced_cell(record[field_idx], field, value),
+            traced_cell(cell, field, value)
+        );
+        record[field_idx] = cell;
+    }
+    set_field(None);
+    for (mask, bits) in masks {
+        if let Some(cell) = record.get_mut(mask) {
+            *cell |= bits;
+        }
+    }
+    Ok(())
+}
+
+/// The patch files to use: the explicitly given ones, or else every
+/// `.yaml`/`.yml`/`.json` file in `patch_dir`.
+pub fn patch_paths_or_dir(patches: &[PathBuf], patch_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !patches.is_empty() {
+        return Ok(patches.to_vec());
+    }
+    let mut files = Vec::new();
+    if patch_dir.exists() {
+        for entry in fs::read_dir(patch_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if is_patch_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Drop patch files marked `experimental: true` unless `include` is set,
+/// and list the ones left out so a release build shows what it skipped.
+pub fn exclude_experimental(patch_paths: Vec<PathBuf>, include: bool) -> Result<Vec<PathBuf>> {
+    let mut kept = Vec::new();
+    let mut excluded = Vec::new();
+    for path in patch_paths {
+        // Read the flags without parsing the entries, whose symbols may
+        // not have IDs yet
+        let content = fs::read_to_string(&path).with_context(|| format!("Failed to read patch file {:?}", path))?;
+        let (documents, settings) = patch_documents(&content, &path)?;
+        let experimental = settings.experimental
+            || documents
+                .iter()
+                .filter_map(serde_yaml::Value::as_sequence)
+                .flatten()
+                .any(|item| item.get("experimental").and_then(serde_yaml::Value::as_bool) == Some(true));
+        if experimental && !include {
+            excluded.push(path);
+        } else {
+            kept.push(path);
+        }
+    }
+    if !excluded.is_empty() {
+        excluded.sort();
+        say!(
+            "Excluded {} experimental patch file(s) (pass --include-experimental to apply them):",
+            excluded.len()
+        );
+        for path in &excluded {
+            say!("  {}", path.display());
+        }
+    }
+    Ok(kept)
+}
+
+/// Drop patch files that declare a `profile` or `min_build`/`max_build`
+/// that `target` does not match, and list them with what they are for.
+/// A file with a profile is only applied when the target names one of
+/// its profiles, and a file with a build range only when the target's
+/// build lies in it.
+pub fn exclude_other_clients(patch_paths: Vec<PathBuf>, target: &ClientTarget) -> Result<Vec<PathBuf>> {
+    let mut kept = Vec::new();
+    let mut excluded = Vec::new();
+    for path in patch_paths {
+        let content = fs::read_to_string(&path).with_context(|| format!("Failed to read patch file {:?}", path))?;
+        match patch_documents(&content, &path)?.1.not_for(target) {
+            Some(reason) => excluded.push((path, reason)),
+            None => kept.push(path),
+        }
+    }
+    if !excluded.is_empty() {
+        excluded.sort();
+        say!(
+            "Excluded {} patch file(s) for other clients (see --profile and --client-build):",
+            excluded.len()
+        );
+        for (path, reason) in &excluded {
+            say!("  {} ({})", path.display(), reason);
+        }
+    }
+    Ok(kept)
+}
+
+/// List every `.dbc` file in a directory, sorted by file name.  A missing
+/// directory yields an empty list.
+pub fn list_dbc_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if dir.exists() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext.to_string_lossy().eq_ignore_ascii_case("dbc"))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The `description` and `issue` of each of `entries`, as written; empty
+/// when none has either.  An issue may be written as a number.
+fn entry_notes(entries: Option<&serde_yaml::Sequence>) -> Vec<EntryNote> {
+    let text = |entry: &serde_yaml::Value, key: &str| match entry.get(key)? {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    };
+    let notes: Vec<EntryNote> = entries
+        .into_iter()
+        .flatten()
+        .map(|entry| EntryNote {
+            description: text(entry, "description"),
+            issue: text(entry, "issue"),
+        })
+        .collect();
+    if notes.iter().all(EntryNote::is_empty) {
+        Vec::new()
+    } else {
+        notes
+    }
+}
+
+/// A table of the mapping form written as a mapping rather than a list
+/// of changes, to give table‑level directives:
+/// `Spell.dbc: { truncate: true, changes: [...] }`.
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableSection {
+    #[serde(default)]
+    truncate: bool,
+    #[serde(default)]
+    changes: Vec<PatchEntry>,
+}
+
+/// Parse a YAML document into one or more `PatchFile` values.  A patch
+/// document can take several forms:
+///
+/// 1. A single patch object with fields `dbc` and `changes` (deprecated).
+/// 2. A sequence of patch objects as described above (deprecated).
+/// 3. A mapping of DBC file names to arrays of changes.  In this case
+///    the key becomes the `dbc` field of a new `PatchFile` and the value
+///    must be a sequence of change objects, or a `TableSection`.
+fn parse_patch_value(value: serde_yaml::Value, path: &Path, lines: &mut EntryLines) -> Result<Vec<PatchFile>> {
+    use serde::Deserialize;
+    use serde_yaml::Value;
+    let mut patch_files = Vec::new();
+    match value {
+        // An empty document (null) or an empty mapping yields no patches.  This
+        // allows YAML files with only comments or whitespace to be ignored.
+        Value::Null => {
+            return Ok(patch_files);
+        }
+        Value::Mapping(ref map) if map.is_empty() => {
+            return Ok(patch_files);
+        }
+        Value::Sequence(seq) => {
+            for item in seq {
+                // Try to parse each element as a PatchFile
+                let mut pf: PatchFile = serde_yaml::from_value(item.clone()).with_context(|| {
+                    format!("Failed to parse patch entry in {:?}", path)
+                })?;
+                pf.legacy_form = true;
+                pf.notes = entry_notes(item.get("changes").and_then(Value::as_sequence));
+                patch_files.push(pf);
+            }
+        }
+        Value::Mapping(map) => {
+            // Heuristic: if the mapping contains keys "dbc" and "changes", treat
+            // it as a single patch file
+            let has_dbc = map.contains_key(Value::String("dbc".to_string()));
+            let has_changes = map.contains_key(Value::String("changes".to_string()));
+            if has_dbc && has_changes {
+                let notes = entry_notes(map.get("changes").and_then(Value::as_sequence));
+                let mut pf: PatchFile = serde_yaml::from_value(Value::Mapping(map)).with_context(|| {
+                    format!("Failed to parse patch file {:?}", path)
+                })?;
+                pf.legacy_form = true;
+                pf.notes = notes;
+                patch_files.push(pf);
+            } else {
+                // Otherwise treat the mapping as a collection of DBC name to changes
+                for (k, v) in map {
+                    // Key must be a string representing the DBC name
+                    let dbc_name = match k {
+                        Value::String(s) => s,
+                        _ => {
+                            return Err(
+                                anyhow::anyhow!("Invalid DBC key in patch file {:?}: {:?}", path, k)
+                            );
+                        }
+                    };
+                    let entry_lines = lines.take(&dbc_name);
+                    let entries = match &v {
+                        Value::Mapping(section) => section.get("changes").and_then(Value::as_sequence),
+                        changes => changes.as_sequence(),
+                    };
+                    let notes = entry_notes(entries);
+                    // Value must be a sequence of changes, or a mapping of
+                    // them with table‑level directives
+                    let section = match &v {
+                        Value::Mapping(_) => TableSection::deserialize(&v),
+                        changes => Vec::<PatchEntry>::deserialize(changes).map(|changes| TableSection {
+                            truncate: false,
+                            changes,
+                        }),
+                    }
+                    .with_context(|| {
+                        // Name the entry that fails, by its line when known
+                        let failing = entries.and_then(|entries| {
+                            entries
+                                .iter()
+                                .position(|entry| PatchEntry::deserialize(entry).is_err())
+                        });
+                        let at = match failing {
+                            Some(i) => match entry_lines.get(i) {
+                                Some(line) => format!(" entry {} ({}:{})", i + 1, path.display(), line),
+                                None => format!(" entry {} in {:?}", i + 1, path),
+                            },
+                            None => format!(" in {:?}", path),
+                        };
+                        format!("Failed to parse changes for {}{}", dbc_name, at)
+                    })?;
+                    let pf = PatchFile {
+                        dbc: dbc_name,
+                        entry_lines: if entry_lines.len() == section.changes.len() { entry_lines } else { Vec::new() },
+                        changes: section.changes,
+                        experimental: false,
+                        truncate: section.truncate,
+                        origin: None,
+                        legacy_form: false,
+                        notes,
+                    };
+                    patch_files.push(pf);
+                }
+            }
+        }
+        _ => {
+            return Err(anyhow::anyhow!("Unexpected YAML structure in patch file {:?}", path));
+        }
+    }
+    Ok(patch_files)
+}
+
+/// Read the patch file at `path` and parse it with `parse_patch_str`,
+/// with no IDs for symbolic keys.
+pub fn parse_patch_file(path: &Path) -> Result<Vec<PatchFile>> {
+    use std::fs;
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read patch file {:?}", path))?;
+    parse_patch_str(&content, path, &IdMap::new())
+}
+
+/// Whether `path` names a patch file, by its `.yaml`, `.yml` or `.json`
+/// extension.
+fn is_patch_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy().to_lowercase();
+        ext == "yaml" || ext == "yml" || ext == "json"
+    })
+}
+
+/// Whether `path` is a JSON patch file rather than a YAML one.
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// The sections of one top‑level value of a JSON patch file.  An object
+/// is split before each `Table.dbc` key, as a YAML file is split before
+/// each `Table.dbc:` line, so a table may be listed more than once.
+struct JsonSections(Vec<serde_yaml::Value>);
+
+impl<'de> serde::Deserialize<'de> for JsonSections {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(JsonSectionsVisitor)
+    }
+}
+
+struct JsonSectionsVisitor;
+
+impl<'de> serde::de::Visitor<'de> for JsonSectionsVisitor {
+    type Value = JsonSections;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an object of tables and their changes, or a list of `dbc`/`changes` objects")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<JsonSections, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(JsonSections(vec![serde_yaml::Value::Sequence(items)]))
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<JsonSections, A::Error> {
+        let mut sections = Vec::new();
+        let mut current = serde_yaml::Mapping::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if key.ends_with(".dbc") && !current.is_empty() {
+                sections.push(serde_yaml::Value::Mapping(std::mem::take(&mut current)));
+            }
+            let value: serde_yaml::Value = map.next_value()?;
+            if current.insert(serde_yaml::Value::String(key.clone()), value).is_some() {
+                return Err(serde::de::Error::custom(format!("duplicate key {:?}", key)));
+            }
+        }
+        sections.push(serde_yaml::Value::Mapping(current));
+        Ok(JsonSections(sections))
+    }
+}
+
+/// Whether `line` of a YAML file starts (`---`) or ends (`...`) a
+/// document of a multi‑document stream.
+pub(crate) fn is_document_marker(line: &str) -> bool {
+    line.strip_prefix("---")
+        .or_else(|| line.strip_prefix("..."))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Split the text of a YAML patch file into sections by its top‑level
+/// `Table.dbc:` lines, and parse each.  A section may hold several
+/// documents of a `---` separated stream, or the end of one and the
+/// start of the next; each document gives a value of its own.
+fn yaml_sections(content: &str, path: &Path) -> Result<Vec<serde_yaml::Value>> {
+    // Split into sections by top‑level DBC keys.  Each section starts
+    // with a blank line for every line before it, so errors give the
+    // line in the file
+    let mut sections: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for (n, line) in content.lines().enumerate() {
+        // If the line has no leading indentation and ends with `.dbc:`, start a new section
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        if indent == 0 && trimmed.ends_with(".dbc:") && !current.trim().is_empty() {
+            sections.push(current);
+            current = "\n".repeat(n);
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        sections.push(current);
+    }
+    // If no sections were detected, treat the whole file as a single section
+    if sections.is_empty() {
+        sections.push(content.to_string());
+    }
+    let mut values = Vec::with_capacity(sections.len());
+    for section in &sections {
+        for document in serde_yaml::Deserializer::from_str(section) {
+            let value = serde::Deserialize::deserialize(document)
+                .with_context(|| format!("Failed to parse YAML section in {:?}", path))?;
+            values.push(value);
+        }
+    }
+    Ok(values)
+}
+
+/// The sections of a JSON patch file: each top‑level value, one after
+/// another, split as `JsonSections` describes.
+fn json_sections(content: &str, path: &Path) -> Result<Vec<serde_yaml::Value>> {
+    let mut sections = Vec::new();
+    for value in serde_json::Deserializer::from_str(content).into_iter::<JsonSections>() {
+        sections.extend(value.with_context(|| format!("Failed to parse JSON in {:?}", path))?.0);
+    }
+    Ok(sections)
+}
+
+/// Settings a patch file gives for the whole file as top‑level keys.
+#[derive(Debug, Default)]
+pub(crate) struct FileSettings {
+    /// `experimental: true`: unfinished work left out of release builds.
+    pub experimental: bool,
+    /// `priority: N`: files are applied in ascending priority, then by
+    /// file name, so a higher priority wins where files overlap.
+    pub priority: i64,
+    /// `requires: [other.yaml]`: file names of patch files that must be
+    /// applied before this one.
+    pub requires: Vec<String>,
+    /// `profile: 1.18` or a list of them: the client profiles the file is
+    /// for.  Empty for files that suit every client.
+    pub profiles: Vec<String>,
+    /// `min_build` and `max_build`: the client builds the file is for,
+    /// inclusive.
+    pub builds: (Option<u32>, Option<u32>),
+}
+
+impl FileSettings {
+    /// Why the file is not for `target`, or `None` when it is.
+    fn not_for(&self, target: &ClientTarget) -> Option<String> {
+        if !self.profiles.is_empty() {
+            match &target.profile {
+                Some(profile) if self.profiles.iter().any(|p| p.eq_ignore_ascii_case(profile)) => {}
+                _ => return Some(format!("profile {}", self.profiles.join(", "))),
+            }
+        }
+        let (min, max) = self.builds;
+        if min.is_some() || max.is_some() {
+            let range = format!(
+                "builds {}-{}",
+                min.map_or(String::new(), |b| b.to_string()),
+                max.map_or(String::new(), |b| b.to_string())
+            );
+            match target.build {
+                Some(build) if min.is_none_or(|m| build >= m) && max.is_none_or(|m| build <= m) => {}
+                _ => return Some(range),
+            }
+        }
+        None
+    }
+}
+
+/// The client a run targets, for patch files that declare a `profile` or
+/// a build range.
+#[derive(Debug, Default)]
+pub struct ClientTarget {
+    pub profile: Option<String>,
+    pub build: Option<u32>,
+}
+
+/// A profile name as written in a file's `profile`; plain numbers such
+/// as `1.18` are read as their text.
+fn profile_name(value: serde_yaml::Value, path: &Path) -> Result<String> {
+    match value {
+        serde_yaml::Value::String(s) => Ok(s),
+        serde_yaml::Value::Number(n) => Ok(n.to_string()),
+        _ => anyhow::bail!("`profile` must be a name or a list of names in {:?}", path),
+    }
+}
+
+/// The documents of a patch file's text, one per section, each followed
+/// by the templates it instantiates, with the file's `vars` filled in,
+/// and the file's settings.  JSON files
+/// give the same documents as the YAML file with the same content.
+pub(crate) fn patch_documents(content: &str, path: &Path) -> Result<(Vec<serde_yaml::Value>, FileSettings)> {
+    let (documents, settings) = written_documents(content, path)?;
+    Ok((documents.into_iter().map(|(document, _)| document).collect(), settings))
+}
+
+/// The documents of `patch_documents`, each with whether it is a section
+/// written in the file rather than a template instance.
+fn written_documents(content: &str, path: &Path) -> Result<(Vec<(serde_yaml::Value, bool)>, FileSettings)> {
+    let sections = if is_json(path) {
+        json_sections(content, path)?
+    } else {
+        yaml_sections(content, path)?
+    };
+    let mut parsed = Vec::with_capacity(sections.len());
+    let mut settings = FileSettings::default();
+    // Templates and vars may be used anywhere in the file, so collect them
+    // first
+    let mut templates = HashMap::new();
+    let mut vars = serde_yaml::Mapping::new();
+    for mut value in sections {
+        // Top‑level settings apply to the whole file
+        if let Some(map) = value.as_mapping_mut() {
+            if let Some(flag) = map.remove("experimental") {
+                settings.experimental |= flag
+                    .as_bool()
+                    .with_context(|| format!("`experimental` must be true or false in {:?}", path))?;
+            }
+            if let Some(priority) = map.remove("priority") {
+                settings.priority = priority
+                    .as_i64()
+                    .with_context(|| format!("`priority` must be an integer in {:?}", path))?;
+            }
+            if let Some(requires) = map.remove("requires") {
+                let requires: Vec<String> = serde_yaml::from_value(requires)
+                    .with_context(|| format!("`requires` must be a list of patch file names in {:?}", path))?;
+                settings.requires.extend(requires);
+            }
+            match map.remove("profile") {
+                Some(serde_yaml::Value::Sequence(profiles)) => {
+                    for profile in profiles {
+                        settings.profiles.push(profile_name(profile, path)?);
+                    }
+                }
+                Some(profile) => settings.profiles.push(profile_name(profile, path)?),
+                None => {}
+            }
+            for (key, bound) in [("min_build", &mut settings.builds.0), ("max_build", &mut settings.builds.1)] {
+                if let Some(build) = map.remove(key) {
+                    let build = build.as_u64().and_then(|b| u32::try_from(b).ok());
+                    *bound = Some(build.with_context(|| format!("`{}` must be a build number in {:?}", key, path))?);
+                }
+            }
+        }
+        collect_templates(&mut value, &mut templates, path)?;
+        collect_vars(&mut value, &mut vars, path)?;
+        parsed.push(value);
+    }
+    let mut documents = Vec::new();
+    for mut value in parsed {
+        // Instances follow the tables of the section they are listed in
+        let instances = expand_instances(&mut value, &templates, &vars, path)?;
+        substitute_vars(&mut value, &vars, path)?;
+        documents.push((value, true));
+        documents.extend(instances.into_iter().map(|instance| (instance, false)));
+    }
+    Ok((documents, settings))
+}
+
+/// Parse the text of a patch file as `parse_patch_file` does, with the
+/// symbols it uses replaced by their IDs in `ids`; `path` is used in
+/// errors and recorded as the origin.
+pub fn parse_patch_str(content: &str, path: &Path, ids: &IdMap) -> Result<Vec<PatchFile>> {
+    Ok(parse_patch_text(content, path, ids)?.0)
+}
+
+/// The top‑level `Table.dbc:` keys of a YAML patch file, in order, each
+/// with the 1‑based lines its entries start on: the list items at the
+/// indentation of the first one.  A table whose entries are not written
+/// one per line, as in flow style, has none.
+fn table_entry_lines(content: &str) -> Vec<(&str, Vec<usize>)> {
+    let mut tables: Vec<(&str, Vec<usize>)> = Vec::new();
+    let mut in_table = false;
+    let mut item_indent = None;
+    for (n, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if is_document_marker(line) {
+            in_table = false;
+            continue;
+        }
+        // Any other top‑level key ends the entries of the table before it
+        if indent == 0 && !trimmed.starts_with('-') {
+            let (key, rest) = trimmed.split_once(':').unwrap_or((trimmed, ""));
+            let key = key.trim().trim_matches(|c| c == '"' || c == '\'');
+            in_table = key.ends_with(".dbc");
+            if in_table {
+                tables.push((key, Vec::new()));
+                let rest = rest.trim();
+                in_table = rest.is_empty() || rest.starts_with('#');
+            }
+            item_indent = None;
+            continue;
+        }
+        if in_table && (trimmed == "-" || trimmed.starts_with("- ")) && *item_indent.get_or_insert(indent) == indent {
+            if let Some((_, lines)) = tables.last_mut() {
+                lines.push(n + 1);
+            }
+        }
+    }
+    tables
+}
+
+/// The entry lines of a file's table keys (see `table_entry_lines`),
+/// handed out in order as its sections are parsed.
+#[derive(Default)]
+struct EntryLines<'a> {
+    tables: Vec<(&'a str, Vec<usize>)>,
+    next: usize,
+}
+
+impl EntryLines<'_> {
+    /// The entry lines of the next `table:` key, or none when the file
+    /// has no more.
+    fn take(&mut self, table: &str) -> Vec<usize> {
+        match self.tables[self.next..].iter().position(|(key, _)| *key == table) {
+            Some(i) => {
+                self.next += i + 1;
+                std::mem::take(&mut self.tables[self.next - 1].1)
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Parse the text of a patch file, and give its settings as well.
+fn parse_patch_text(content: &str, path: &Path, ids: &IdMap) -> Result<(Vec<PatchFile>, FileSettings)> {
+    let (documents, settings) = written_documents(content, path)?;
+    // Sections follow the file's table keys in order; template instances
+    // and JSON files have no entry lines
+    let mut lines = EntryLines {
+        tables: if is_json(path) { Vec::new() } else { table_entry_lines(content) },
+        next: 0,
+    };
+    let mut pfs_all = Vec::new();
+    for (mut value, written) in documents {
+        resolve_symbols(&mut value, ids, path)?;
+        let mut pfs = match written {
+            true => parse_patch_value(value, path, &mut lines)?,
+            false => parse_patch_value(value, path, &mut EntryLines::default())?,
+        };
+        // Set the origin on each patch file to the current path
+        for pf in &mut pfs {
+            pf.origin = Some(path.to_path_buf());
+        }
+        pfs_all.append(&mut pfs);
+    }
+    for pf in &mut pfs_all {
+        pf.experimental |= settings.experimental;
+    }
+    Ok((pfs_all, settings))
+}
+
+/// The file name of a patch path, which orders files and names them in
+/// `requires`.
+fn patch_file_name(path: &Path) -> &str {
+    path.file_name().and_then(|s| s.to_str()).unwrap_or("")
+}
+
+/// The order to apply patch files in: a file comes after the files it
+/// `requires`, and otherwise by ascending priority, then file name.
+/// Fails when a required file is not among `files` or files require each
+/// other in a cycle.
+pub(crate) fn apply_order(files: &[(&Path, FileSettings)]) -> Result<Vec<usize>> {
+    let mut deps = Vec::with_capacity(files.len());
+    for (path, settings) in files {
+        let mut of_file = Vec::new();
+        for name in &settings.requires {
+            let required = Path::new(name).file_name().and_then(|s| s.to_str()).unwrap_or(name);
+            match files.iter().position(|(other, _)| patch_file_name(other) == required) {
+                Some(i) => of_file.push(i),
+                None => anyhow::bail!(
+                    "{} requires {}, which is not among the patch files being applied",
+                    path.display(),
+                    name
+                ),
+            }
+        }
+        deps.push(of_file);
+    }
+
+    let mut order = Vec::with_capacity(files.len());
+    let mut placed = vec![false; files.len()];
+    while order.len() < files.len() {
+        let next = (0..files.len())
+            .filter(|&i| !placed[i] && deps[i].iter().all(|&d| placed[d]))
+            .min_by_key(|&i| (files[i].1.priority, patch_file_name(files[i].0), i));
+        let Some(next) = next else {
+            let mut cycle: Vec<&str> = (0..files.len())
+                .filter(|&i| !placed[i])
+                .map(|i| patch_file_name(files[i].0))
+                .collect();
+            cycle.sort_unstable();
+            anyhow::bail!(
+                "Patch files require each other in a cycle; check `requires` in {}",
+                cycle.join(", ")
+            );
+        };
+        placed[next] = true;
+        order.push(next);
+    }
+    Ok(order)
+}
+
+/// Parse every patch file in `patch_paths`, in the order `apply_order`
+/// gives (by file name unless files set `priority` or `requires`),
+/// grouped by the lower‑cased name of the table each patches.  Symbolic
+/// keys have no IDs; see `load_patches_with_ids`.
+pub fn load_patches(patch_paths: &[PathBuf]) -> Result<HashMap<String, Vec<PatchFile>>> {
+    load_patches_with_ids(patch_paths, &IdMap::new())
+}
+
+/// Like `load_patches`, with the symbols patches use replaced by their
+/// IDs in `ids` (see [`crate::ids::assign_ids`]).
+pub fn load_patches_with_ids(patch_paths: &[PathBuf], ids: &IdMap) -> Result<HashMap<String, Vec<PatchFile>>> {
+    let mut parsed = Vec::with_capacity(patch_paths.len());
+    let mut settings = Vec::with_capacity(patch_paths.len());
+    for path in patch_paths {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read patch file {:?}", path))?;
+        let (pfs, file_settings) = parse_patch_text(&content, path, ids)?;
+        parsed.push(Some(pfs));
+        settings.push((path.as_path(), file_settings));
+    }
+    let order = apply_order(&settings)?;
+    debug!(
+        "patch files in the order they are applied: {}",
+        order.iter().map(|&i| patch_file_name(settings[i].0)).collect::<Vec<_>>().join(", ")
+    );
+    let mut patches_map: HashMap<String, Vec<PatchFile>> = HashMap::new();
+    for i in order {
+        for pf in parsed[i].take().unwrap_or_default() {
+            let key = pf.dbc.to_lowercase();
+            patches_map.entry(key).or_default().push(pf);
+        }
+    }
+    Ok(patches_map)
+}
+
+/// Mutable state of one table while patches are applied to it.
+struct TableState<'a> {
+    file_name: String,
+    field_count: u32,
+    records: Vec<Vec<u32>>,
+    string_block: Vec<u8>,
+    /// Offsets of existing and newly allocated strings
+    string_map: HashMap<String, u32>,
+    /// New strings to append to the block, in allocation order
+    new_strings: Vec<String>,
+    schema: Option<Schema>,
+    /// Locale slots that values of `loc` fields written by their bare name
+    /// go to
+    locales: Vec<usize>,
+    /// Directory of extra archive files, used to check referenced assets
+    includes_dir: PathBuf,
+    /// Directory of source DBCs, for entries that consult other tables
+    dbc_dir: PathBuf,
+    /// Where those tables are read from, so they are seen as the patch
+    /// sets before this one left them
+    source: &'a dyn DbcSource,
+    stats: TableStats,
+    /// Patch entry being applied, as `file entry N`, for provenance
+    current_entry: String,
+    /// Entries that changed each record (by column 0), when requested
+    provenance: Option<BTreeMap<u32, Vec<ProvenanceStep>>>,
+    /// Keys of the records the current entry changed, for the changelog
+    touched: BTreeSet<u32>,
+    /// Row of the first record with each key, by key column and whether
+    /// it is compared as a float.  Built the first time a column is
+    /// searched and kept in step as records change, so a large patch set
+    /// does not scan the table once per entry.
+    index: HashMap<(usize, bool), HashMap<u32, usize>>,
+}
+
+/// A patch entry that changed a record, with the record as the entry left
+/// it (as it was before, for a delete).  String offsets stay valid in the
+/// finished table, whose string block only grows.
+pub struct ProvenanceStep {
+    /// `file entry N`
+    pub entry: String,
+    /// `update`, `insert`, `delete`…
+    pub action: &'static str,
+    /// Whether the step removed the record from the table, as `delete`,
+    /// a hard `delete_where` or `truncate` do
+    pub removed: bool,
+    pub record: Vec<u32>,
+}
+
+/// Run the script of a `script` entry, given inline or as a file relative
+/// to the patch file, and return the entries it generated.
+fn script_entries(
+    state: &TableState,
+    code: &Option<String>,
+    file: &Option<PathBuf>,
+    pf_origin: &str,
+) -> Result<Vec<PatchEntry>> {
+    let source = match (code, file) {
+        (Some(code), None) => code.clone(),
+        (None, Some(file)) => {
+            let path = Path::new(pf_origin)
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(file);
+            fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read script {:?}", path))?
+        }
+        _ => anyhow::bail!(
+            "script entry in {} (patch file: {}) needs exactly one of `code` or `file`",
+            state.file_name,
+            pf_origin
+        ),
+    };
+    run_script(
+        &source,
+        &state.file_name,
+        &state.records,
+        &state.string_block,
+        &state.new_strings,
+        &state.schema,
+    )
+    .with_context(|| {
+        format!("Script failed for {} (patch file: {})", state.file_name, pf_origin)
+    })
+}
+
+/// Whether a table‑specific helper entry targets the table being patched;
+/// warns and returns false otherwise.
+fn helper_applies(state: &mut TableState, entry_type: &str, table: &str, pf_origin: &str) -> bool {
+    if state.file_name.eq_ignore_ascii_case(table) {
+        return true;
+    }
+    state.stats.skipped += 1;
+    warning!(
+        "{} entries only apply to {}, not {} (patch file: {})",
+        entry_type, table, state.file_name, pf_origin
+    );
+    false
+}
+
+impl<'a> TableState<'a> {
+    /// Read a DBC from `source` and prepare it for patching.
+    fn load(
+        source: &'a dyn DbcSource,
+        dbc_path: &Path,
+        dbc_dir: &Path,
+        schema_dir: &Path,
+        includes_dir: &Path,
+    ) -> Result<(DbcHeader, TableState<'a>)> {
+        let file_name = dbc_file_name(dbc_path)?;
+        let DbcTable {
+            header,
+            records,
+            string_block,
+        } = source.read_table(dbc_path)?;
+        let state = TableState {
+            field_count: header.field_count,
+            stats: TableStats {
+                read: records.len(),
+                ..Default::default()
+            },
+            records,
+            // Build string offset map for existing strings
+            string_map: build_string_map(&string_block),
+            string_block,
+            // Keep track of new strings appended (in order)
+            new_strings: Vec::new(),
+            // Load a schema mapping for this DBC (if available)
+            schema: load_schema(schema_dir, &file_name),
+            locales: vec![0],
+            includes_dir: includes_dir.to_path_buf(),
+            dbc_dir: dbc_dir.to_path_buf(),
+            source,
+            file_name,
+            current_entry: String::new(),
+            provenance: None,
+            touched: BTreeSet::new(),
+            index: HashMap::new(),
+        };
+        Ok((header, state))
+    }
+
+    /// The row of the first record whose column `col` holds `key`,
+    /// compared by value when `float`.
+    fn find_row(&mut self, col: usize, key: u32, float: bool) -> Option<usize> {
+        let records = &self.records;
+        let rows = self.index.entry((col, float)).or_insert_with(|| {
+            let mut rows = HashMap::new();
+            for (row, record) in records.iter().enumerate() {
+                if let Some(&cell) = record.get(col) {
+                    rows.entry(index_key(cell, float)).or_insert(row);
+                }
+            }
+            rows
+        });
+        rows.get(&lookup_key(key, float)).copied()
+    }
+
+    /// Append `record`, adding it to the indexes.
+    fn push_record(&mut self, record: Vec<u32>) {
+        let row = self.records.len();
+        for (&(col, float), rows) in self.index.iter_mut() {
+            if let Some(&cell) = record.get(col) {
+                rows.entry(index_key(cell, float)).or_insert(row);
+            }
+        }
+        self.records.push(record);
+    }
+
+    /// Remove the record at `row`.  Later rows move up, so the indexes are
+    /// rebuilt when next used.
+    fn remove_record(&mut self, row: usize) -> Vec<u32> {
+        self.index.clear();
+        self.records.remove(row)
+    }
+
+    /// Remove every record, for a patch file that repopulates the table.
+    fn truncate(&mut self) {
+        let removed = std::mem::take(&mut self.records);
+        self.index.clear();
+        for record in &removed {
+            self.record_removal(record, "truncate");
+        }
+        self.stats.deleted += removed.len();
+        say!("Truncated {}, removing {} record(s)", self.file_name, removed.len());
+    }
+
+    /// Note that the record at `row`, which was `before`, has been changed
+    /// in place: indexes of columns whose value changed are rebuilt when
+    /// next used.
+    fn record_changed(&mut self, row: usize, before: &[u32]) {
+        let after = &self.records[row];
+        self.index.retain(|&(col, _), _| after.get(col) == before.get(col));
+    }
+
+    /// Note that the current entry changed `record` with `action`.
+    fn record_provenance(&mut self, record: &[u32], action: &'static str) {
+        self.record_step(record, action, false);
+    }
+
+    /// Note that the current entry removed `record`, as it was, with
+    /// `action`.
+    fn record_removal(&mut self, record: &[u32], action: &'static str) {
+        self.record_step(record, action, true);
+    }
+
+    fn record_step(&mut self, record: &[u32], action: &'static str, removed: bool) {
+        if let Some(&id) = record.first() {
+            self.touched.insert(id);
+        }
+        if let (Some(provenance), Some(&id)) = (self.provenance.as_mut(), record.first()) {
+            provenance.entry(id).or_default().push(ProvenanceStep {
+                entry: self.current_entry.clone(),
+                action,
+                removed,
+                record: record.to_vec(),
+            });
+        }
+    }
+}
+
+/// Write the provenance map of `file_name` next to its output DBC as
+/// `<Table>.dbc.provenance.yaml`.
+fn write_provenance(file_name: &str, provenance: &BTreeMap<u32, Vec<ProvenanceStep>>, out_path: &Path) -> Result<()> {
+    let mut path = out_path.as_os_str().to_owned();
+    path.push(".provenance.yaml");
+    let path = PathBuf::from(path);
+    let mut yaml = format!(
+        "# Patch entries that produced each modified or inserted record of {}, by ID\n",
+        file_name
+    );
+    for (id, entries) in provenance {
+        yaml.push_str(&format!("{}:\n", id));
+        for step in entries {
+            yaml.push_str(&format!("  - {:?}\n", format!("{} ({})", step.entry, step.action)));
+        }
+    }
+    fs::write(&path, yaml).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Set the schema's tombstone fields on the record whose `key_column`
+/// matches `key`, soft‑deleting it.  `action` is the entry kind named in
+/// warnings; provenance records every soft delete as `disable`.
+fn disable_record(
+    state: &mut TableState,
+    key: u32,
+    key_column: &Option<String>,
+    action: &'static str,
+    pf_origin: &str,
+) -> Result<()> {
+    let Some(tombstone) = state.schema.as_ref().and_then(|s| s.tombstone()).cloned() else {
+        warning!(
+            "the schema for {} declares no tombstone (patch file: {}), cannot disable {}",
+            state.file_name, pf_origin, key
+        );
+        state.stats.skipped += 1;
+        return Ok(());
+    };
+    let key_col_index = resolve_key_column_index(key_column, &state.schema, &state.file_name, pf_origin);
+    let float_key = is_float_column(&state.schema, key_col_index);
+    let Some(row) = state.find_row(key_col_index, key, float_key) else {
+        state.stats.skipped += 1;
+        state.stats.missing += 1;
+        warning!(
+            "no record found with key {} in {} (patch file: {}) to {}",
+            key, state.file_name, pf_origin, action
+        );
+        return Ok(());
+    };
+    trace!("key {} found at row {} of {}", key, row, state.file_name);
+    disable_row(state, row, &tombstone, pf_origin)
+}
+
+/// Set the `tombstone` fields on the record at `row`.  It counts as
+/// deleted, as the client no longer sees it.
+fn disable_row(state: &mut TableState, row: usize, tombstone: &HashMap<String, ValueType>, pf_origin: &str) -> Result<()> {
+    let before = state.records[row].clone();
+    let key = before.first().copied().unwrap_or(0);
+    apply_values_to_record(
+        tombstone,
+        &mut state.records[row],
+        &state.schema,
+        &state.locales,
+        &mut state.string_map,
+        &mut state.new_strings,
+        &state.string_block,
+        &state.file_name,
+        pf_origin,
+        key,
+    )?;
+    state.record_changed(row, &before);
+    state.stats.deleted += 1;
+    let record = state.records[row].clone();
+    state.record_provenance(&record, "disable");
+    Ok(())
+}
+
+/// The rows of every record meeting all `conditions` of a `where`, or
+/// `None` when the entry is skipped (with a warning): it has no
+/// conditions, names an unknown field or matches nothing.  `entry` is the
+/// entry kind named in warnings.
+fn matching_rows(
+    state: &mut TableState,
+    conditions: &BTreeMap<String, Condition>,
+    entry: &str,
+    pf_origin: &str,
+) -> Result<Option<Vec<usize>>> {
+    if conditions.is_empty() {
+        warning!(
+            "{} without conditions in {} (patch file: {}) – skipping entry",
+            entry, state.file_name, pf_origin
+        );
+        state.stats.skipped += 1;
+        return Ok(None);
+    }
+    let mut columns = Vec::with_capacity(conditions.len());
+    for (field_name, condition) in conditions {
+        let Some(col) = condition_column(field_name, &state.schema) else {
+            warning!(
+                "unknown field '{}' in the conditions of {} in {} (patch file: {}) – skipping entry",
+                field_name, entry, state.file_name, pf_origin
+            );
+            state.stats.skipped += 1;
+            return Ok(None);
+        };
+        columns.push((field_name, col, condition));
+    }
+
+    let mut rows = Vec::new();
+    for (row, record) in state.records.iter().enumerate() {
+        let mut holds = true;
+        for &(field_name, col, condition) in &columns {
+            holds = condition_holds(state, record, col, condition).map_err(|err| {
+                anyhow!(
+                    "Invalid condition on '{}' of {} in {}: {} (patch file: {})",
+                    field_name,
+                    entry,
+                    state.file_name,
+                    err,
+                    pf_origin
+                )
+            })?;
+            if !holds {
+                break;
+            }
+        }
+        if holds {
+            rows.push(row);
+        }
+    }
+    if rows.is_empty() {
+        state.stats.skipped += 1;
+        warning!(
+            "no records of {} match the conditions of {} (patch file: {})",
+            state.file_name, entry, pf_origin
+        );
+        return Ok(None);
+    }
+    Ok(Some(rows))
+}
+
+/// Column an `update_where` condition names: a field, a column index, or
+/// a localized string by its bare name (its first, enUS, slot).
+fn condition_column(field_name: &str, schema_map: &Option<Schema>) -> Option<usize> {
+    resolve_field_index(field_name, schema_map)
+        .or_else(|| schema_map.as_ref()?.loc_columns(field_name).map(|(first, _)| first))
+}
+
+/// Whether `record` meets the condition on column `col`.  `Err` describes
+/// a condition that cannot apply to the field.
+fn condition_holds(state: &TableState, record: &[u32], col: usize, condition: &Condition) -> Result<bool, String> {
+    let Some(&cell) = record.get(col) else {
+        return Ok(false);
+    };
+    let field = state.schema.as_ref().and_then(|s| s.field_at(col));
+    let kind = field.map(|f| f.kind);
+    let text = || pending_string_at(&state.string_block, &state.new_strings, cell).unwrap_or_default();
+    // A named value of an enum must match; named flags must all be set
+    if let (Some(constants), Condition::Equals(value)) = (field.and_then(|f| f.constants.as_ref()), condition) {
+        if let Some(resolved) = constants.resolve(value) {
+            let want = resolved?.as_u32().unwrap_or_default();
+            return Ok(if constants.flags { cell & want == want } else { cell == want });
+        }
+    }
+    match condition {
+        Condition::Contains { contains } if kind == Some(FieldKind::String) => Ok(text().contains(contains.as_str())),
+        Condition::Contains { .. } => Err("`contains` needs a field the schema declares as a string".to_string()),
+        Condition::Range { range: [low, high] } => {
+            let number = if kind == Some(FieldKind::Float) {
+                f32::from_bits(cell) as f64
+            } else if kind == Some(FieldKind::Int32) {
+                cell as i32 as f64
+            } else {
+                cell as f64
+            };
+            Ok((*low..=*high).contains(&number))
+        }
+        Condition::Equals(ValueType::String(want)) if kind == Some(FieldKind::String) => Ok(text() == *want),
+        Condition::Equals(value) if kind == Some(FieldKind::Float) => match value {
+            ValueType::Float(v) => Ok(f32::from_bits(cell) == *v as f32),
+            ValueType::Int(v) => Ok(f32::from_bits(cell) == *v as f32),
+            ValueType::UInt(v) => Ok(f32::from_bits(cell) == *v as f32),
+            _ => Err(format!("{:?} is not a number", value)),
+        },
+        Condition::Equals(value) => {
+            let want = match (field.filter(|f| f.typed), value) {
+                (Some(f), _) => value.typed_cell(f.kind)?,
+                // Untyped columns compare negative numbers by their two's
+                // complement, as they would be stored
+                (None, _) => value.as_u32(),
+            };
+            want.map(|want| cell == want)
+                .ok_or_else(|| format!("{:?} cannot be compared with a number", value))
+        }
+    }
+}
+
+/// Apply a single patch entry to a table.  `pf_origin` names the patch file
+/// the entry came from, with its line when known (see
+/// [`PatchFile::entry_origin`]), for warnings; files the entry names are
+/// found next to it.
+fn apply_entry(state: &mut TableState, change: &PatchEntry, pf_origin: &str) -> Result<()> {
+    match change {
+        PatchEntry::Update {
+            key,
+            key_column,
+            values,
+        } => {
+            let key_col_index = resolve_key_column_index(key_column, &state.schema, &state.file_name, pf_origin);
+
+            // Find the record with matching key
+            let float_key = is_float_column(&state.schema, key_col_index);
+            if let Some(row) = state.find_row(key_col_index, *key, float_key) {
+                trace!("key {} found at row {} of {}", key, row, state.file_name);
+                let before = state.records[row].clone();
+                apply_values_to_record(
+                    values,
+                    &mut state.records[row],
+                    &state.schema,
+                    &state.locales,
+                    &mut state.string_map,
+                    &mut state.new_strings,
+                    &state.string_block,
+                    &state.file_name,
+                    pf_origin,
+                    *key,
+                )?;
+                state.record_changed(row, &before);
+                state.stats.updated += 1;
+                let record = state.records[row].clone();
+                state.record_provenance(&record, "update");
+            } else {
+                state.stats.skipped += 1;
+                state.stats.missing += 1;
+                warning!(
+                    "no record found with key {} in {} (patch file: {})",
+                    key,
+                    state.file_name,
+                    pf_origin
+                );
+            }
+        }
+        PatchEntry::UpdateWhere { conditions, values } => {
+            // Match every record first, so the updates cannot change
+            // which records match
+            let Some(rows) = matching_rows(state, conditions, "update_where", pf_origin)? else {
+                return Ok(());
+            };
+
+            let mut changed = 0usize;
+            for &row in &rows {
+                let before = state.records[row].clone();
+                let key = before.first().copied().unwrap_or(0);
+                trace!("key {} matched at row {} of {}", key, row, state.file_name);
+                apply_values_to_record(
+                    values,
+                    &mut state.records[row],
+                    &state.schema,
+                    &state.locales,
+                    &mut state.string_map,
+                    &mut state.new_strings,
+                    &state.string_block,
+                    &state.file_name,
+                    pf_origin,
+                    key,
+                )?;
+                if state.records[row] != before {
+                    state.record_changed(row, &before);
+                    changed += 1;
+                    let record = state.records[row].clone();
+                    state.record_provenance(&record, "update_where");
+                }
+            }
+            if changed == 0 {
+                state.stats.skipped += 1;
+            }
+            state.stats.updated += changed;
+            say!(
+                "update_where matched {} record(s) of {}, {} changed",
+                rows.len(),
+                state.file_name,
+                changed
+            );
+        }
+        PatchEntry::Insert {
+            key,
+            key_column,
+            template,
+            values,
+        } => {
+            let key_col_index = resolve_key_column_index(key_column, &state.schema, &state.file_name, pf_origin);
+
+            // Create new record filled with zeros, then the schema's
+            // field defaults
+            let mut new_record = vec![0u32; state.field_count as usize];
+            if let Some(defaults) = state.schema.as_ref().map(Schema::defaults).filter(|d| !d.is_empty()) {
+                apply_values_to_record(
+                    defaults,
+                    &mut new_record,
+                    &state.schema,
+                    &state.locales,
+                    &mut state.string_map,
+                    &mut state.new_strings,
+                    &state.string_block,
+                    &state.file_name,
+                    pf_origin,
+                    key.unwrap_or(0),
+                )?;
+            }
+
+            // Start from the named template; the entry's own values win
+            if let Some(name) = template {
+                let Some(defaults) = state.schema.as_ref().and_then(|schema| schema.template(name)) else {
+                    warning!(
+                        "no template named {} in the schema for {} (patch file: {}) – skipping insert",
+                        name,
+                        state.file_name,
+                        pf_origin
+                    );
+                    state.stats.skipped += 1;
+                    return Ok(());
+                };
+                apply_values_to_record(
+                    defaults,
+                    &mut new_record,
+                    &state.schema,
+                    &state.locales,
+                    &mut state.string_map,
+                    &mut state.new_strings,
+                    &state.string_block,
+                    &state.file_name,
+                    pf_origin,
+                    key.unwrap_or(0),
+                )?;
+            }
+
+            // If a key is provided and the field is not explicitly set in values, write it to the key column
+            if let Some(k) = key {
+                let provided_key = values.keys().any(|field_name| {
+                    // Determine if this field matches the key column
+                    if let Ok(idx) = field_name.parse::<usize>() {
+                        idx == key_col_index
+                    } else {
+                        state
+                            .schema
+                            .as_ref()
+                            .and_then(|schema| schema.column(field_name))
+                            .is_some_and(|idx| idx == key_col_index)
+                    }
+                });
+                if key_col_index < new_record.len() && !provided_key {
+                    // `key` is a reference when matching on &PatchEntry; dereference it
+                    new_record[key_col_index] = *k;
+                }
+            }
+
+            // Fill in specified fields from the values map
+            let effective_key = key.unwrap_or(0); // Use a default key for apply_values_to_record
+            apply_values_to_record(
+                values,
+                &mut new_record,
+                &state.schema,
+                &state.locales,
+                &mut state.string_map,
+                &mut state.new_strings,
+                &state.string_block,
+                &state.file_name,
+                pf_origin,
+                effective_key,
+            )?;
+
+            // Check for duplicate keys: if the key value in the new record already exists in the
+            // records list at the same key column, warn and skip this insert.
+            if key_col_index < new_record.len() {
+                let new_key_val = new_record[key_col_index];
+                if state.find_row(key_col_index, new_key_val, false).is_some() {
+                    trace!("key {} already taken in {}", new_key_val, state.file_name);
+                    warning!(
+                        "record with key {} already exists in {} (patch file: {}) – skipping insert",
+                        new_key_val,
+                        state.file_name,
+                        pf_origin
+                    );
+                    // Do not push the duplicate record
+                    state.stats.skipped += 1;
+                    state.stats.duplicates += 1;
+                } else {
+                    trace!(
+                        "key {} inserted at row {} of {}",
+                        new_key_val,
+                        state.records.len(),
+                        state.file_name
+                    );
+                    state.record_provenance(&new_record, "insert");
+                    state.push_record(new_record);
+                    state.stats.inserted += 1;
+                }
+            } else {
+                // If the key column is out of bounds, just append the record (no duplicate check)
+                state.record_provenance(&new_record, "insert");
+                state.push_record(new_record);
+                state.stats.inserted += 1;
+            }
+        }
+        PatchEntry::Copy {
+            key,
+            key_column,
+            new_key,
+            values,
+        } => {
+            let key_col_index = resolve_key_column_index(key_column, &state.schema, &state.file_name, pf_origin);
+            // Find the record to copy
+            let float_key = is_float_column(&state.schema, key_col_index);
+            if let Some(row) = state.find_row(key_col_index, *key, float_key) {
+                trace!("key {} found at row {} of {}", key, row, state.file_name);
+                // Clone the existing record
+                let mut new_record = state.records[row].clone();
+                if let Some(new_key) = new_key {
+                    if state.find_row(key_col_index, *new_key, float_key).is_some() {
+                        anyhow::bail!(
+                                "Cannot copy key {} to new_key {} in {} (patch file: {}): a record with that key already exists",
+                                key,
+                                new_key,
+                                state.file_name,
+                                pf_origin
+                            );
+                    }
+                    if let Some(cell) = new_record.get_mut(key_col_index) {
+                        *cell = lookup_key(*new_key, float_key);
+                    }
+                }
+                // Apply updates to the new record
+                apply_values_to_record(
+                    values,
+                    &mut new_record,
+                    &state.schema,
+                    &state.locales,
+                    &mut state.string_map,
+                    &mut state.new_strings,
+                    &state.string_block,
+                    &state.file_name,
+                    pf_origin,
+                    *key,
+                )?;
+                // After applying updates, ensure we are not duplicating the key.  Use the
+                // resolved key column to retrieve the new key value and check against
+                // existing state.records.  If a duplicate is found, skip adding the new record and
+                // warn.  Otherwise, push it to the list.
+                if key_col_index < new_record.len() {
+                    let new_key_val = new_record[key_col_index];
+                    if state.find_row(key_col_index, new_key_val, false).is_some() {
+                        warning!(
+                            "record with key {} already exists in {} (patch file: {}) – skipping copy",
+                            new_key_val,
+                            state.file_name,
+                            pf_origin
+                        );
+                        state.stats.skipped += 1;
+                        state.stats.duplicates += 1;
+                    } else {
+                        state.record_provenance(&new_record, "copy");
+                        state.push_record(new_record);
+                        state.stats.copied += 1;
+                    }
+                } else {
+                    // If the key column is out of bounds, append without duplicate check
+                    state.record_provenance(&new_record, "copy");
+                    state.push_record(new_record);
+                    state.stats.copied += 1;
+                }
+            } else {
+                state.stats.skipped += 1;
+                state.stats.missing += 1;
+                warning!(
+                    "no record found with key {} in {} (patch file: {}) to copy",
+                    key,
+                    state.file_name,
+                    pf_origin
+                );
+            }
+        }
+        PatchEntry::Delete { key, key_column, hard } => {
+            if !hard && state.schema.as_ref().is_some_and(|s| s.tombstone().is_some()) {
+                return disable_record(state, *key, key_column, "delete", pf_origin);
+            }
+            let key_col_index = resolve_key_column_index(key_column, &state.schema, &state.file_name, pf_origin);
+            let float_key = is_float_column(&state.schema, key_col_index);
+            match state.find_row(key_col_index, *key, float_key) {
+                Some(row) => {
+                    trace!("key {} found at row {} of {}", key, row, state.file_name);
+                    let record = state.remove_record(row);
+                    state.record_removal(&record, "delete");
+                    state.stats.deleted += 1;
+                }
+                None => {
+                    state.stats.skipped += 1;
+                    state.stats.missing += 1;
+                    warning!(
+                        "no record found with key {} in {} (patch file: {}) to delete",
+                        key,
+                        state.file_name,
+                        pf_origin
+                    );
+                }
+            }
+        }
+        PatchEntry::DeleteWhere { conditions, hard } => {
+            let Some(rows) = matching_rows(state, conditions, "delete_where", pf_origin)? else {
+                return Ok(());
+            };
+            let tombstone = state.schema.as_ref().and_then(|s| s.tombstone()).cloned();
+            match tombstone.filter(|_| !hard) {
+                Some(tombstone) => {
+                    for &row in &rows {
+                        disable_row(state, row, &tombstone, pf_origin)?;
+                    }
+                    say!("delete_where disabled {} record(s) of {}", rows.len(), state.file_name);
+                }
+                None => {
+                    let doomed: HashSet<usize> = rows.iter().copied().collect();
+                    let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut state.records)
+                        .into_iter()
+                        .enumerate()
+                        .partition(|(row, _)| doomed.contains(row));
+                    state.records = kept.into_iter().map(|(_, record)| record).collect();
+                    state.index.clear();
+                    for (_, record) in &removed {
+                        state.record_removal(record, "delete_where");
+                    }
+                    state.stats.deleted += removed.len();
+                    say!(
+                        "delete_where removed {} record(s) of {}",
+                        removed.len(),
+                        state.file_name
+                    );
+                }
+            }
+        }
+        PatchEntry::Disable { key, key_column } => {
+            disable_record(state, *key, key_column, "disable", pf_origin)?;
+        }
+        PatchEntry::ReplaceString { from, to, ignore_case } => {
+            let Some(schema) = state.schema.as_ref() else {
+                warning!(
+                    "no schema for {} (patch file: {}), cannot locate string fields for replace_string",
+                    state.file_name,
+                    pf_origin
+                );
+                state.stats.skipped += 1;
+                return Ok(());
+            };
+            let changed = rewrite_strings(
+                &mut state.records,
+                schema,
+                from,
+                to,
+                *ignore_case,
+                &mut state.string_map,
+                &mut state.new_strings,
+                &state.string_block,
+            );
+            if changed.is_empty() {
+                state.stats.skipped += 1;
+                warning!(
+                    "no string fields equal to {:?} in {} (patch file: {})",
+                    from,
+                    state.file_name,
+                    pf_origin
+                );
+            } else {
+                // Any column may hold a string offset, keys included
+                state.index.clear();
+                let rows: HashSet<u32> = changed.iter().map(|c| c.key).collect();
+                state.stats.updated += rows.len();
+                let mut rows: Vec<u32> = rows.into_iter().collect();
+                rows.sort_unstable();
+                for id in rows {
+                    if let Some(row) = state.find_row(0, id, false) {
+                        let record = state.records[row].clone();
+                        state.record_provenance(&record, "replace_string");
+                    }
+                }
+                say!(
+                    "Replaced {:?} with {:?} in {} field(s) of {}",
+                    from,
+                    to,
+                    changed.len(),
+                    state.file_name
+                );
+            }
+        }
+        PatchEntry::Script { code, file } => {
+            let entries = script_entries(state, code, file, pf_origin)?;
+            say!(
+                "Script generated {} entr{} for {}",
+                entries.len(),
+                if entries.len() == 1 { "y" } else { "ies" },
+                state.file_name
+            );
+            for entry in &entries {
+                apply_entry(state, entry, pf_origin)?;
+            }
+        }
+        PatchEntry::Zone { zone, members, changes } => {
+            let members = Path::new(pf_origin)
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(members);
+            let areas = AreaTable::load(state.source, &state.dbc_dir)?;
+            let keys = zone_keys(&areas, &members, zone)
+                .with_context(|| format!("Invalid zone entry (patch file: {})", pf_origin))?;
+            let mut applied = 0usize;
+            let mut skipped = 0usize;
+            for change in changes {
+                let generated;
+                let candidates: &[PatchEntry] = match change {
+                    PatchEntry::Script { code, file } => {
+                        generated = script_entries(state, code, file, pf_origin)?;
+                        &generated
+                    }
+                    PatchEntry::Update { .. } | PatchEntry::Copy { .. } => std::slice::from_ref(change),
+                    _ => {
+                        warning!(
+                                "zone filters only apply to update, copy and script entries in {} (patch file: {}) – skipping entry",
+                                state.file_name, pf_origin
+                            );
+                        state.stats.skipped += 1;
+                        continue;
+                    }
+                };
+                for entry in candidates {
+                    let key = match entry {
+                        PatchEntry::Update { key, .. } | PatchEntry::Copy { key, .. } => Some(*key),
+                        _ => None,
+                    };
+                    if key.is_none_or(|k| keys.contains(&k)) {
+                        apply_entry(state, entry, pf_origin)?;
+                        applied += 1;
+                    } else {
+                        skipped += 1;
+                    }
+                }
+            }
+            say!(
+                "Zone {}: applied {} entr{} to {}, skipped {} outside the zone",
+                zone,
+                applied,
+                if applied == 1 { "y" } else { "ies" },
+                state.file_name,
+                skipped
+            );
+        }
+        PatchEntry::ItemDisplay { id, icon, base, values } => {
+            if !helper_applies(state, "item_display", "ItemDisplayInfo.dbc", pf_origin) {
+                return Ok(());
+            }
+            // A stock icon is already named by the original string block;
+            // anything else has to ship with the patch.
+            let name = icon_name(icon);
+            let stock = state
+                .string_map
+                .iter()
+                .any(|(s, &offset)| (offset as usize) < state.string_block.len() && s.eq_ignore_ascii_case(&name));
+            if !stock && find_icon_blp(&state.includes_dir, &name)?.is_none() {
+                warning!(
+                        "icon {} for item display {} is not a stock icon and {:?} has no Interface/Icons/{}.blp (patch file: {})",
+                        name, id, state.includes_dir, name, pf_origin
+                    );
+            }
+            let entry = expand_item_display(*id, icon, *base, values);
+            apply_entry(state, &entry, pf_origin)?;
+        }
+        PatchEntry::LearnSpell {
+            id,
+            spell,
+            skill_line,
+            race_mask,
+            class_mask,
+            min_skill_rank,
+            acquire_method,
+            values,
+        } => {
+            if !helper_applies(state, "learn_spell", "SkillLineAbility.dbc", pf_origin) {
+                return Ok(());
+            }
+            let entry = expand_learn_spell(
+                *id,
+                *spell,
+                *skill_line,
+                *race_mask,
+                *class_mask,
+                *min_skill_rank,
+                *acquire_method,
+                values,
+                &state.records,
+            );
+            apply_entry(state, &entry, pf_origin)?;
+        }
+        PatchEntry::Talent {
+            id,
+            tab,
+            tier,
+            column,
+            ranks,
+            requires,
+            values,
+        } => {
+            if !helper_applies(state, "talent", "Talent.dbc", pf_origin) {
+                return Ok(());
+            }
+            let entry = expand_talent(
+                *id,
+                *tab,
+                *tier,
+                *column,
+                ranks,
+                requires.as_ref().map(|r| (r.talent, r.points)),
+                values,
+                &state.records,
+            )
+            .with_context(|| format!("Invalid talent entry (patch file: {})", pf_origin))?;
+            apply_entry(state, &entry, pf_origin)?;
+        }
+        PatchEntry::SpellRanks {
+            spell,
+            same_name,
+            values,
+        } => {
+            if !helper_applies(state, "spell_ranks", "Spell.dbc", pf_origin) {
+                return Ok(());
+            }
+            let abilities_path = find_dbc_file(&state.dbc_dir, "SkillLineAbility.dbc");
+            let abilities = match state.source.read_table(&abilities_path) {
+                Ok(table) => table.records,
+                Err(err) => {
+                    warning!(
+                        "cannot read {:?} to resolve the ranks of spell {}: {:#} (patch file: {})",
+                        abilities_path,
+                        spell,
+                        err,
+                        pf_origin
+                    );
+                    Vec::new()
+                }
+            };
+            let family = spell_family(*spell, *same_name, &state.records, &abilities, |offset| {
+                pending_string_at(&state.string_block, &state.new_strings, offset)
+            });
+            let entries = expand_spell_ranks(&family, values, &state.records);
+            if entries.is_empty() {
+                state.stats.missing += 1;
+                warning!(
+                    "no record found with key {} in {} (patch file: {})",
+                    spell,
+                    state.file_name,
+                    pf_origin
+                );
+                return Ok(());
+            }
+            let ids: Vec<String> = family.iter().map(|id| id.to_string()).collect();
+            say!("Spell {}: updating ranks {}", spell, ids.join(", "));
+            for entry in &entries {
+                apply_entry(state, entry, pf_origin)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What patching has done to a table so far, carried from one patch set
+/// to the next.
+pub struct TableProgress {
+    /// Records and string block size before the first patch set
+    pub rows_before: usize,
+    pub block_before: usize,
+    pub stats: TableStats,
+    /// Entries that changed each record, when provenance was requested
+    pub provenance: Option<BTreeMap<u32, Vec<ProvenanceStep>>>,
+    /// Entries with a description or an issue, and what they changed
+    pub changelog: Vec<NotedChange>,
+}
+
+/// Apply `patches` to one table, read from `source`, continuing from
+/// what earlier patch sets did to it.  Returns the table's progress and
+/// its patched contents.
+#[allow(clippy::too_many_arguments)]
+fn patch_table(
+    source: &dyn DbcSource,
+    dbc_path: &Path,
+    file_name: &str,
+    patches: &[PatchFile],
+    table: Option<TableProgress>,
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    provenance: bool,
+    translations: Option<&Translations>,
+) -> Result<(TableProgress, DbcTable)> {
+    say!("Processing {}", file_name);
+
+    // Read the DBC, or the result of the previous patch sets
+    let (header, mut state) = TableState::load(source, dbc_path, dbc_dir, schema_dir, includes_dir)?;
+    let mut table = table.unwrap_or_else(|| TableProgress {
+        rows_before: state.records.len(),
+        block_before: state.string_block.len(),
+        stats: state.stats,
+        provenance: provenance.then(BTreeMap::new),
+        changelog: Vec::new(),
+    });
+    state.stats = table.stats;
+    state.provenance = table.provenance.take();
+    if let Some(translations) = translations {
+        state.locales = translations.slots().to_vec();
+    }
+    let computed = match &state.schema {
+        Some(schema) => ComputedFields::compile(schema, file_name)?,
+        None => None,
+    };
+    // Records as read, so computed fields are only set on the ones the
+    // patches create or change
+    let loaded: HashMap<u32, Vec<u32>> = match computed {
+        Some(_) => state.records.iter().filter_map(|r| Some((*r.first()?, r.clone()))).collect(),
+        None => HashMap::new(),
+    };
+
+    // Entries are numbered on through every section of a file
+    // that names this table, so each label is unique
+    let mut numbered: HashMap<String, usize> = HashMap::new();
+    for pf in patches {
+        // Determine the origin of this patch file for warnings
+        let pf_origin = pf
+            .origin
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "<unknown>".to_string());
+        let before = state.stats;
+        let first = numbered.entry(pf_origin.clone()).or_default();
+        let offset = *first;
+        *first += pf.changes.len();
+        let location = |key| Location {
+            table: Some(file_name.to_string()),
+            patch_file: Some(pf_origin.clone()),
+            line: None,
+            key,
+            field: None,
+        };
+        set_location(location(None));
+        if pf.truncate {
+            state.current_entry = format!("{} truncate", pf_origin);
+            state.truncate();
+        }
+        for (i, change) in pf.changes.iter().enumerate() {
+            set_location(Location {
+                line: pf.entry_lines.get(i).copied(),
+                ..location(entry_key(change))
+            });
+            state.current_entry = format!("{} entry {}", pf_origin, offset + i + 1);
+            trace!("{}: {} in {}", state.current_entry, describe_entry(change), file_name);
+            state.touched.clear();
+            apply_entry(&mut state, change, &pf.entry_origin(i))?;
+            let keys = std::mem::take(&mut state.touched);
+            if let Some(note) = pf.entry_note(i) {
+                table.changelog.push(NotedChange {
+                    note: note.clone(),
+                    entry: pf.entry_origin(i),
+                    keys,
+                });
+            }
+        }
+        // Make a patch file whose every entry missed stand out
+        let after = state.stats;
+        if (!pf.changes.is_empty() || pf.truncate)
+            && after.updated + after.inserted + after.copied + after.deleted
+                == before.updated + before.inserted + before.copied + before.deleted
+        {
+            set_location(location(None));
+            warning!(
+                "no entry of {} changed anything in {}",
+                pf_origin, file_name
+            );
+        }
+    }
+    clear_location();
+    if let Some(computed) = &computed {
+        set_computed_fields(&mut state, computed, &loaded)?;
+    }
+
+    // Build final string block by appending new strings
+    for s in &state.new_strings {
+        // Strings are stored as bytes followed by a null terminator
+        state.string_block.extend_from_slice(s.as_bytes());
+        state.string_block.push(0);
+    }
+    state.stats.strings_added += state.new_strings.len();
+
+    table.stats = state.stats;
+    table.provenance = state.provenance;
+    Ok((
+        table,
+        DbcTable {
+            header,
+            records: state.records,
+            string_block: state.string_block,
+        },
+    ))
+}
+
+/// Set the schema's computed fields on every record that is not as it was
+/// read (`loaded`, by key), so derived values follow the patches' edits.
+fn set_computed_fields(state: &mut TableState, computed: &ComputedFields, loaded: &HashMap<u32, Vec<u32>>) -> Result<()> {
+    let Some(schema) = state.schema.take() else {
+        return Ok(());
+    };
+    state.current_entry = format!("schema {}.yaml", state.file_name);
+    let mut updated = 0;
+    for row in 0..state.records.len() {
+        let record = &state.records[row];
+        if record.first().and_then(|key| loaded.get(key)) == Some(record) {
+            continue;
+        }
+        let before = record.clone();
+        let changed = computed
+            .apply(&mut state.records[row], &schema, &state.string_block, &state.new_strings)
+            .with_context(|| format!("Failed to compute fields of {}", state.file_name))?;
+        if changed {
+            state.record_changed(row, &before);
+            let record = state.records[row].clone();
+            state.record_provenance(&record, "computed");
+            updated += 1;
+        }
+    }
+    state.schema = Some(schema);
+    debug!("computed fields changed {} record(s) of {}", updated, state.file_name);
+    Ok(())
+}
+
+/// Apply patches to the given DBC files in memory.  Returns the patched
+/// tables, on top of the unpatched ones, and what was done to each.
+///
+/// `patch_sets` are applied in order, each on top of the in‑memory result
+/// of the ones before it (see [`Overlay`]), so a base pack and its
+/// tweaks compose without intermediate files.
+#[allow(clippy::too_many_arguments)]
+pub fn patch_tables(
+    dbc_files: &[PathBuf],
+    patch_sets: &[Vec<PathBuf>],
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    provenance: bool,
+    translations: Option<&Translations>,
+    units: &Transformers,
+    ids: &IdMap,
+) -> Result<(Overlay<FileSource>, HashMap<String, TableProgress>)> {
+    let mut overlay = Overlay::new(FileSource::new(schema_dir));
+    let mut progress: HashMap<String, TableProgress> = HashMap::new();
+    for (layer, patch_files) in patch_sets.iter().enumerate() {
+        if patch_sets.len() > 1 {
+            say!("Applying patch set {} of {}", layer + 1, patch_sets.len());
+        }
+        // Load patch files and group them by DBC name
+        let mut patches_map = load_patches_with_ids(patch_files, ids)?;
+        warn_deprecations(&patches_map, schema_dir);
+        for pfs in patches_map.values_mut() {
+            units.resolve_patches(pfs)?;
+        }
+        if let Some(translations) = translations {
+            for pfs in patches_map.values_mut() {
+                let schema = pfs.first().and_then(|pf| load_schema(schema_dir, &pf.dbc));
+                translations.translate_patches(pfs, schema.as_ref());
+            }
+        }
+
+        // Tables do not depend on each other, so each is patched on its own
+        // worker; what they print is held back and shown in table order
+        let mut jobs = Vec::new();
+        for dbc_path in dbc_files {
+            let file_name = dbc_file_name(dbc_path)?;
+            let key = file_name.to_lowercase();
+            // Apply all patches matching this DBC name (case insensitive)
+            let Some(patches_for_file) = patches_map.get(&key) else {
+                continue;
+            };
+            jobs.push((dbc_path, file_name, patches_for_file, progress.remove(&key)));
+        }
+        let patched = map_parallel(jobs, |(dbc_path, file_name, patches_for_file, table)| {
+            capture(|| {
+                let patched = patch_table(
+                    &overlay,
+                    dbc_path,
+                    &file_name,
+                    patches_for_file,
+                    table,
+                    dbc_dir,
+                    schema_dir,
+                    includes_dir,
+                    provenance,
+                    translations,
+                );
+                patched.map(|(table, contents)| (file_name, table, contents))
+            })
+        });
+        for (result, output) in patched {
+            output.replay();
+            let (file_name, table, contents) = result?;
+            progress.insert(file_name.to_lowercase(), table);
+            overlay.insert(&file_name, contents);
+        }
+    }
+    Ok((overlay, progress))
+}
+
+/// Apply patches to the given DBC files and write modified versions into
+/// the output directory.  Returns what was written for each table.  Called by
+/// both the `apply` and `build` subcommands.  With `compact_strings` each
+/// string block is rebuilt from the strings its table still references;
+/// with `self_check` each written file is read back and checked (see
+/// [`check_written_dbc`]).  The tables are written into a staging
+/// directory first and only moved into the output directory once all of
+/// them were, so a failed run leaves it as it was.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_command(
+    dbc_files: &[PathBuf],
+    patch_sets: &[Vec<PathBuf>],
+    out_dir: &Path,
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    provenance: bool,
+    translations: Option<&Translations>,
+    units: &Transformers,
+    ids: &IdMap,
+    compact_strings: bool,
+    self_check: bool,
+) -> Result<Vec<AppliedTable>> {
+    // Ensure output directory exists
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create output directory {:?}", out_dir))?;
+
+    let (overlay, mut progress) = patch_tables(
+        dbc_files,
+        patch_sets,
+        dbc_dir,
+        schema_dir,
+        includes_dir,
+        provenance,
+        translations,
+        units,
+        ids,
+    )?;
+    check_strict()?;
+
+    // Tables are written on workers too, and reported in table order
+    let mut jobs = Vec::new();
+    for dbc_path in dbc_files {
+        let file_name = dbc_file_name(dbc_path)?;
+        let table = progress.remove(&file_name.to_lowercase());
+        jobs.push((dbc_path, file_name, table));
+    }
+    // Nothing reaches the output directory until every table is written
+    let staging = out_dir.join(STAGING_DIR);
+    if staging.exists() {
+        fs::remove_dir_all(&staging).with_context(|| format!("Failed to clear {:?}", staging))?;
+    }
+    fs::create_dir_all(&staging).with_context(|| format!("Failed to create {:?}", staging))?;
+    let results = map_parallel(jobs, |(dbc_path, file_name, table)| {
+        capture(|| {
+            let compact_schema_dir = compact_strings.then_some(schema_dir);
+            let check_schema_dir = self_check.then_some(schema_dir);
+            write_table(&overlay, dbc_path, file_name, table, &staging, compact_schema_dir, check_schema_dir)
+        })
+    });
+    let mut staged = Vec::new();
+    let mut outputs = Vec::new();
+    for (result, output) in results {
+        match result {
+            Ok(applied) => {
+                staged.push(applied);
+                outputs.push(output);
+            }
+            Err(e) => {
+                outputs.into_iter().for_each(Captured::replay);
+                output.replay();
+                let _ = fs::remove_dir_all(&staging);
+                return Err(e.context(format!("Nothing was written to {:?}", out_dir)));
+            }
+        }
+    }
+    for (applied, output) in staged.iter().zip(outputs) {
+        say!("Wrote {}", out_dir.join(&applied.file_name).display());
+        output.replay();
+    }
+    // Warnings from writing fail a strict run too, before any table is
+    // moved into place
+    if let Err(e) = check_strict() {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    let written = move_staged(&staging, out_dir, staged)?;
+
+    print_summary(&written);
+    Ok(written)
+}
+
+/// Directory under the output directory that `apply_command` writes the
+/// tables into before moving them into place.
+const STAGING_DIR: &str = ".staging";
+
+/// Move the files written into `staging`, the tables of `staged` and their
+/// provenance, into `out_dir`, and remove `staging`.  Each is renamed
+/// within the output directory, so no file is ever left half written.
+fn move_staged(staging: &Path, out_dir: &Path, staged: Vec<AppliedTable>) -> Result<Vec<AppliedTable>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(staging).with_context(|| format!("Failed to read {:?}", staging))? {
+        names.push(entry?.file_name());
+    }
+    for name in names {
+        let (from, to) = (staging.join(&name), out_dir.join(&name));
+        fs::rename(&from, &to).with_context(|| format!("Failed to move {:?} to {:?}", from, to))?;
+    }
+    fs::remove_dir(staging).with_context(|| format!("Failed to remove {:?}", staging))?;
+    Ok(staged
+        .into_iter()
+        .map(|applied| AppliedTable {
+            out_path: out_dir.join(&applied.file_name),
+            ..applied
+        })
+        .collect())
+}
+
+/// Rebuild the string block of `table` from the strings its records
+/// still reference (see [`compact_string_block`]).  String columns are
+/// taken from the schema, which must name every column: an offset in a
+/// column it leaves out would be lost.  Otherwise the block is kept as
+/// it is, with a warning.
+fn compact_strings(file_name: &str, table: &mut DbcTable, schema_dir: &Path) {
+    let Some(schema) = load_schema(schema_dir, file_name) else {
+        warning!("not compacting strings of {}: it has no schema", file_name);
+        return;
+    };
+    let covered: HashSet<usize> = schema.fields.iter().map(|f| f.index).collect();
+    if let Some(col) = (0..table.header.table_fields()).find(|col| !covered.contains(col)) {
+        warning!(
+            "not compacting strings of {}: its schema does not name column {}",
+            file_name, col
+        );
+        return;
+    }
+    let columns: Vec<usize> = schema
+        .fields
+        .iter()
+        .filter(|f| f.kind == FieldKind::String)
+        .map(|f| f.index)
+        .collect();
+    let before = table.string_block.len();
+    match compact_string_block(&mut table.records, &table.string_block, &columns) {
+        Some(block) => {
+            if block.len() < before {
+                say!(
+                    "Compacted strings of {}: {} -> {} bytes",
+                    file_name,
+                    before,
+                    block.len()
+                );
+            }
+            table.string_block = block;
+        }
+        None => warning!(
+            "not compacting strings of {}: a string offset lies outside its string block",
+            file_name
+        ),
+    }
+}
+
+/// Write one patched table, and its provenance when it was recorded, into
+/// `out_dir`.  With `compact_schema_dir` its string block is compacted
+/// first, using the schemas there.  With `check_schema_dir` the written
+/// file is read back and checked, its string columns taken from the
+/// schemas there.
+fn write_table(
+    source: &dyn DbcSource,
+    dbc_path: &Path,
+    file_name: String,
+    table: Option<TableProgress>,
+    out_dir: &Path,
+    compact_schema_dir: Option<&Path>,
+    check_schema_dir: Option<&Path>,
+) -> Result<AppliedTable> {
+    let mut result = source.read_table(dbc_path)?;
+    if let Some(schema_dir) = compact_schema_dir {
+        compact_strings(&file_name, &mut result, schema_dir);
+    }
+    let table = table.unwrap_or_else(|| TableProgress {
+        rows_before: result.records.len(),
+        block_before: result.string_block.len(),
+        stats: TableStats {
+            read: result.records.len(),
+            ..Default::default()
+        },
+        provenance: None,
+        changelog: Vec::new(),
+    });
+
+    // Build output path
+    let out_path = out_dir.join(&file_name);
+    write_dbc(&out_path, &result.header, &result.records, &result.string_block)
+        .with_context(|| format!("Failed to write output DBC for {}", file_name))?;
+    if let Some(schema_dir) = check_schema_dir {
+        let string_columns: Vec<usize> = load_schema(schema_dir, &file_name)
+            .map(|schema| {
+                schema
+                    .fields
+                    .iter()
+                    .filter(|f| f.kind == FieldKind::String)
+                    .map(|f| f.index)
+                    .collect()
+            })
+            .unwrap_or_default();
+        check_written_dbc(&out_path, &result.header, &result.records, &result.string_block, &string_columns)?;
+        debug!("{} passed the self-check", file_name);
+    }
+    if let Some(provenance) = &table.provenance {
+        write_provenance(&file_name, provenance, &out_path)?;
+    }
+    let mut stats = table.stats;
+    stats.bytes_written = fs::metadata(&out_path).map(|m| m.len()).unwrap_or(0);
+    Ok(AppliedTable {
+        file_name,
+        out_path,
+        rows_added: result.records.len().saturating_sub(table.rows_before),
+        string_growth: result.string_block.len().saturating_sub(table.block_before) as u64,
+        stats,
+        changelog: table.changelog,
+    })
+}
+
+/// The key of the record an entry targets or creates, when it names one.
+fn entry_key(change: &PatchEntry) -> Option<u32> {
+    match change {
+        PatchEntry::Update { key, .. }
+        | PatchEntry::Copy { key, .. }
+        | PatchEntry::Delete { key, .. }
+        | PatchEntry::Disable { key, .. }
+        | PatchEntry::Insert { key: Some(key), .. } => Some(*key),
+        PatchEntry::ItemDisplay { id, .. } => Some(*id),
+        _ => None,
+    }
+}
+
+/// Short description of an entry for reports, e.g. `update 7373`.
+pub fn describe_entry(change: &PatchEntry) -> String {
+    match change {
+        PatchEntry::Update { key, .. } => format!("update {}", key),
+        PatchEntry::UpdateWhere { conditions, .. } => {
+            format!("update_where {}", conditions.keys().cloned().collect::<Vec<_>>().join(", "))
+        }
+        PatchEntry::Insert { key: Some(key), .. } => format!("insert {}", key),
+        PatchEntry::Insert { key: None, .. } => "insert".to_string(),
+        PatchEntry::Copy { key, .. } => format!("copy {}", key),
+        PatchEntry::Delete { key, .. } => format!("delete {}", key),
+        PatchEntry::DeleteWhere { conditions, .. } => {
+            format!("delete_where {}", conditions.keys().cloned().collect::<Vec<_>>().join(", "))
+        }
+        PatchEntry::Disable { key, .. } => format!("disable {}", key),
+        PatchEntry::ReplaceString { from, .. } => format!("replace_string {:?}", from),
+        PatchEntry::Script { .. } => "script".to_string(),
+        PatchEntry::Zone { zone, .. } => format!("zone {}", zone),
+        PatchEntry::ItemDisplay { id, .. } => format!("item_display {}", id),
+        PatchEntry::LearnSpell { spell, .. } => format!("learn_spell {}", spell),
+        PatchEntry::Talent { ranks, .. } => format!("talent {:?}", ranks),
+        PatchEntry::SpellRanks { spell, .. } => format!("spell_ranks {}", spell),
+    }
+}
+
+/// Whether `record` already holds every one of `values`, each compared as
+/// an `update_where` condition on the field compares it: floats by value,
+/// typed fields through [`ValueType::typed_cell`] and strings by text.
+/// Values that change the current one, such as `{add: 5}`, never count.
+fn values_in_effect(state: &TableState, record: &[u32], values: &HashMap<String, ValueType>) -> bool {
+    values.iter().all(|(name, value)| {
+        resolve_field_index(name, &state.schema).is_some_and(|col| {
+            condition_holds(state, record, col, &Condition::Equals(value.clone())) == Ok(true)
+        })
+    })
+}
+
+/// Replay patches against the tables in `live_dir` without writing
+/// anything, and report each entry that would be a no‑op (its target key
+/// is missing, or the row already holds the patched values) or a conflict
+/// (the key it creates is already taken).  Entries run through the same
+/// code as `apply`, so scripts and helper entries are checked too.
+pub fn validate_live_command(
+    live_dir: &Path,
+    patch_files: &[PathBuf],
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    units: &Transformers,
+    ids: &IdMap,
+) -> Result<()> {
+    let mut patches_map = load_patches_with_ids(patch_files, ids)?;
+    for pfs in patches_map.values_mut() {
+        units.resolve_patches(pfs)?;
+    }
+    let mut tables: Vec<&String> = patches_map.keys().collect();
+    tables.sort();
+
+    let (mut clean, mut no_ops, mut conflicts) = (0usize, 0usize, 0usize);
+    for key in tables {
+        // Map keys are lower case; show the name as the patch spells it
+        let table = patches_map[key].first().map_or(key.as_str(), |pf| pf.dbc.as_str());
+        let live_path = find_dbc_file(live_dir, table);
+        if !live_path.exists() {
+            println!(
+                "{}: not present in {:?}; patches would apply to the stock table",
+                table, live_dir
+            );
+            continue;
+        }
+        say!("Checking {}", live_path.display());
+        let source = FileSource::new(schema_dir);
+        let (_header, mut state) = TableState::load(&source, &live_path, dbc_dir, schema_dir, includes_dir)?;
+        for pf in &patches_map[key] {
+            if pf.truncate {
+                state.truncate();
+            }
+            for (i, change) in pf.changes.iter().enumerate() {
+                let pf_origin = pf.entry_origin(i);
+                // For updates, remember the target row to spot entries that
+                // are already in effect.  Keys are found as `apply` finds
+                // them, float key columns by value
+                let find_row = |state: &mut TableState| match change {
+                    PatchEntry::Update { key, key_column, .. } => {
+                        let col = resolve_key_column_index(key_column, &state.schema, &state.file_name, &pf_origin);
+                        let float_key = is_float_column(&state.schema, col);
+                        state.find_row(col, *key, float_key).map(|row| state.records[row].clone())
+                    }
+                    _ => None,
+                };
+                let row_before = find_row(&mut state);
+                let in_effect = match (change, &row_before) {
+                    (PatchEntry::Update { values, .. }, Some(row)) => values_in_effect(&state, row, values),
+                    _ => false,
+                };
+                // A copy onto a taken `new_key` is an error when applying;
+                // here it is one more conflict
+                let taken = match change {
+                    PatchEntry::Copy {
+                        key_column,
+                        new_key: Some(new_key),
+                        ..
+                    } => {
+                        let col = resolve_key_column_index(key_column, &state.schema, &state.file_name, &pf_origin);
+                        let float_key = is_float_column(&state.schema, col);
+                        state.find_row(col, *new_key, float_key).is_some()
+                    }
+                    _ => false,
+                };
+                let before = state.stats;
+                if taken {
+                    state.stats.duplicates += 1;
+                } else {
+                    apply_entry(&mut state, change, &pf_origin)?;
+                }
+                let after = state.stats;
+
+                let verdict = if after.duplicates > before.duplicates {
+                    Some(("conflict", "the key it creates already exists in the live table"))
+                } else if after.missing > before.missing {
+                    Some(("no-op", "its target key is missing from the live table"))
+                } else if in_effect || (row_before.is_some() && row_before == find_row(&mut state)) {
+                    Some(("no-op", "the live row already has these values"))
+                } else if after.skipped > before.skipped {
+                    Some(("no-op", "it changed nothing"))
+                } else {
+                    None
+                };
+                match verdict {
+                    Some((kind, reason)) => {
+                        println!(
+                            "  {}: entry {} ({}) is a {}: {}",
+                            pf_origin,
+                            i + 1,
+                            describe_entry(change),
+                            kind,
+                            reason
+                        );
+                        if kind == "conflict" {
+                            conflicts += 1;
+                        } else {
+                            no_ops += 1;
+                        }
+                    }
+                    None => clean += 1,
+                }
+            }
+        }
+    }
+    println!(
+        "{} entr{} would apply cleanly, {} would be no-ops, {} conflict with the live tables",
+        clean,
+        if clean == 1 { "y" } else { "ies" },
+        no_ops,
+        conflicts
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+
+    #[test]
+    fn new_strings_are_queued_in_column_order() {
+        // "10" sorts before "9" by name; column order must win
+        let block = vec![0u8];
+        let mut values = HashMap::new();
+        values.insert("10".to_string(), ValueType::String("Spells\\Zone.mdx".to_string()));
+        values.insert("9".to_string(), ValueType::String("Zone".to_string()));
+
+        let mut record = vec![0u32; 11];
+        let mut string_map = build_string_map(&block);
+        let mut new_strings = Vec::new();
+        apply_values_to_record(
+            &values,
+            &mut record,
+            &None,
+            &[0],
+            &mut string_map,
+            &mut new_strings,
+            &block,
+            "SpellVisualEffectName.dbc",
+            "test",
+            1,
+        )
+        .unwrap();
+        assert_eq!(new_strings, ["Zone", "Spells\\Zone.mdx"]);
+        assert_eq!((record[9], record[10]), (1, 6));
+    }
+
+    #[test]
+    fn unknown_symbols_are_not_interned() {
+        let block = vec![0u8];
+        let mut values = HashMap::new();
+        values.insert("3".to_string(), ValueType::String("$horsemen_mark_vsi".to_string()));
+        let mut string_map = build_string_map(&block);
+        let mut new_strings = Vec::new();
+        let err = apply_values_to_record(
+            &values,
+            &mut [0u32; 4],
+            &None,
+            &[0],
+            &mut string_map,
+            &mut new_strings,
+            &block,
+            "Spell.dbc",
+            "test",
+            1,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Unknown symbol $horsemen_mark_vsi"), "{}", err);
+        assert!(new_strings.is_empty());
+    }
+
+    #[test]
+    fn templates_expand_per_instance() {
+        let yaml = "templates:\n  kit:\n    SpellVisualKit.dbc:\n      - type: update\n        key: ${id}\n        values: { Name: \"Kit ${id}\" }\n\
+                    instantiate:\n  - template: kit\n    params: { id: 7 }\n  - template: kit\n    params: { id: 8 }\n";
+        let pfs = parse_patch_str(yaml, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        assert_eq!(pfs.len(), 2);
+        let PatchEntry::Update { key, values, .. } = &pfs[1].changes[0] else {
+            panic!("expected an update");
+        };
+        assert_eq!(*key, 8);
+        assert_eq!(values["Name"], ValueType::String("Kit 8".to_string()));
+    }
+
+    #[test]
+    fn vars_fill_values_and_expressions() {
+        let yaml = "vars: { base: 12 }\nSpellVisualEffectName.dbc:\n  - type: update\n    key: 1\n    \
+                    values: { Scale: \"=${base} * 0.5\", SpecialAttachPoint: \"${base}\", Name: \"==${base} ${$m1}\" }\n";
+        let pfs = parse_patch_str(yaml, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        let values = pfs[0].changes[0].values().unwrap();
+        assert_eq!(values["Scale"], ValueType::String("=12 * 0.5".to_string()));
+        assert_eq!(values["SpecialAttachPoint"], ValueType::Int(12));
+
+        // The built-in schema: ID, Name, FileName, SpecialAttachPoint, Scale
+        let schema = load_schema(Path::new(""), "SpellVisualEffectName.dbc");
+        let block = vec![0u8];
+        let mut record = vec![1, 0, 0, 0, 2f32.to_bits()];
+        let mut string_map = build_string_map(&block);
+        let mut new_strings = Vec::new();
+        apply_values_to_record(
+            values,
+            &mut record,
+            &schema,
+            &[0],
+            &mut string_map,
+            &mut new_strings,
+            &block,
+            "SpellVisualEffectName.dbc",
+            "test",
+            1,
+        )
+        .unwrap();
+        assert_eq!(f32::from_bits(record[4]), 6.0);
+        assert_eq!(new_strings, ["=12 ${$m1}"]);
+    }
+
+    #[test]
+    fn named_values_and_flags_resolve_by_schema() {
+        let yaml = "Spell.dbc:\n  - type: update\n    key: 133\n    values: { School: frost, Attributes: [PASSIVE, hidden, 0x10000] }\n";
+        let pfs = parse_patch_str(yaml, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        let schema = load_schema(Path::new(""), "Spell.dbc");
+        let school = resolve_field_index("School", &schema).unwrap();
+        let attributes = resolve_field_index("Attributes", &schema).unwrap();
+        let block = vec![0u8];
+        let mut record = vec![0u32; 173];
+        let mut string_map = build_string_map(&block);
+        let mut apply = |values: &HashMap<String, ValueType>, record: &mut Vec<u32>| {
+            apply_values_to_record(values, record, &schema, &[0], &mut string_map, &mut Vec::new(), &block, "Spell.dbc", "test", 133)
+        };
+        apply(pfs[0].changes[0].values().unwrap(), &mut record).unwrap();
+        assert_eq!((record[school], record[attributes]), (4, 0x100C0));
+        let unknown = HashMap::from([("School".to_string(), ValueType::String("Chaos".to_string()))]);
+        assert!(apply(&unknown, &mut record).is_err());
+    }
+
+    #[test]
+    fn bit_operations_change_current_bits() {
+        let yaml = "Spell.dbc:\n  - type: update\n    key: 133\n    \
+                    values: { Attributes: {or: [PASSIVE], andnot: 0x10001}, AttributesEx: {xor: 0x5}, Category: {OR: 2} }\n";
+        let mut pfs = parse_patch_str(yaml, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        Transformers::builtin().resolve_patches(&mut pfs).unwrap();
+        let schema = load_schema(Path::new(""), "Spell.dbc");
+        let columns = ["Category", "Attributes", "AttributesEx"].map(|name| resolve_field_index(name, &schema).unwrap());
+        let block = vec![0u8];
+        let mut record = vec![0u32; 173];
+        for (col, value) in columns.into_iter().zip([1, 0x10003, 0x6]) {
+            record[col] = value;
+        }
+        let mut string_map = build_string_map(&block);
+        let values = pfs[0].changes[0].values().unwrap();
+        apply_values_to_record(values, &mut record, &schema, &[0], &mut string_map, &mut Vec::new(), &block, "Spell.dbc", "test", 133)
+            .unwrap();
+        assert_eq!(columns.map(|col| record[col]), [3, 0x42, 0x3]);
+    }
+
+    #[test]
+    fn number_operations_scale_current_values() {
+        let yaml = "Spell.dbc:\n  - type: update\n    key: 133\n    \
+                    values: { Speed: {mul: 1.5}, EffectBasePoints_1: {add: -20}, StackAmount: {mul: 1.25, add: 1} }\n";
+        let mut pfs = parse_patch_str(yaml, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        Transformers::builtin().resolve_patches(&mut pfs).unwrap();
+        let schema = load_schema(Path::new(""), "Spell.dbc");
+        let columns = ["Speed", "EffectBasePoints_1", "StackAmount"].map(|name| resolve_field_index(name, &schema).unwrap());
+        let block = vec![0u8];
+        let mut record = vec![0u32; 173];
+        for (col, value) in columns.into_iter().zip([10.0f32.to_bits(), 5, 10]) {
+            record[col] = value;
+        }
+        let mut string_map = build_string_map(&block);
+        let values = pfs[0].changes[0].values().unwrap();
+        apply_values_to_record(values, &mut record, &schema, &[0], &mut string_map, &mut Vec::new(), &block, "Spell.dbc", "test", 133)
+            .unwrap();
+        assert_eq!(columns.map(|col| record[col]), [15.0f32.to_bits(), -15i32 as u32, 14]);
+
+        // Below zero for an unsigned field is an error, not a wrapped value
+        let below = parse_patch_str("Spell.dbc:\n  - type: update\n    key: 133\n    values: { StackAmount: {add: -20} }\n", Path::new("test.yaml"), &IdMap::new())
+            .unwrap();
+        let values = below[0].changes[0].values().unwrap();
+        let result = apply_values_to_record(values, &mut record, &schema, &[0], &mut string_map, &mut Vec::new(), &block, "Spell.dbc", "test", 133);
+        assert!(result.unwrap_err().to_string().contains("does not fit"));
+        assert_eq!(record[columns[2]], 14);
+    }
+
+    #[test]
+    fn entries_know_their_lines() {
+        let text = "vars:\n  r: 1\nSpell.dbc:\n  # note\n  - type: update\n    key: 133\n    values:\n      \
+                    SpellIconID: 1\n  - type: delete\n    key: 134\nSpellIcon.dbc:\n- type: delete\n  key: 1\n";
+        let pfs = parse_patch_str(text, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        assert_eq!(pfs.iter().map(|pf| pf.entry_lines.clone()).collect::<Vec<_>>(), [vec![5, 9], vec![12]]);
+        let flow = "Spell.dbc: [{ type: delete, key: 133 }]\n";
+        assert!(parse_patch_str(flow, Path::new("test.yaml"), &IdMap::new()).unwrap()[0].entry_lines.is_empty());
+    }
+
+    #[test]
+    fn parse_errors_give_file_lines() {
+        let syntax = "Spell.dbc:\n  - type: delete\n    key: 1\nSpell.dbc:\n  - type: delete\n    key: [1\n";
+        let err = parse_patch_str(syntax, Path::new("test.yaml"), &IdMap::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains("at line 6"), "{:#}", err);
+        let entry = "Spell.dbc:\n  - type: delete\n    key: 1\n  - type: delete\n    hard: true\n";
+        let err = parse_patch_str(entry, Path::new("test.yaml"), &IdMap::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to parse changes for Spell.dbc entry 2 (test.yaml:4)");
+    }
+
+    #[test]
+    fn documents_of_a_stream_are_sections() {
+        let text = "---\nvars: { icon: 2 }\nSpell.dbc:\n  - type: update\n    key: 133\n    values: { SpellIconID: \"${icon}\" }\n\
+                    ---\nSpell.dbc:\n  - type: delete\n    key: 134\n...\n---\nSpellIcon.dbc:\n- type: delete\n  key: 1\n---\n";
+        let pfs = parse_patch_str(text, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        assert_eq!(pfs.iter().map(|pf| pf.dbc.as_str()).collect::<Vec<_>>(), ["Spell.dbc", "Spell.dbc", "SpellIcon.dbc"]);
+        assert_eq!(pfs[0].changes[0].values().unwrap()["SpellIconID"], ValueType::Int(2));
+        assert_eq!(pfs.iter().map(|pf| pf.entry_lines.clone()).collect::<Vec<_>>(), [vec![4], vec![9], vec![14]]);
+    }
+
+    #[test]
+    fn entries_keep_their_notes() {
+        let text = "Spell.dbc:\n  - type: update\n    key: 133\n    description: Four Horsemen marks\n    issue: 42\n    values: {}\n\
+                    \x20 - type: delete\n    key: 134\nSpellIcon.dbc:\n  - type: delete\n    key: 1\n";
+        let pfs = parse_patch_str(text, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        let note = pfs[0].entry_note(0).unwrap();
+        assert_eq!(note.description.as_deref(), Some("Four Horsemen marks"));
+        assert_eq!(note.issue.as_deref(), Some("42"));
+        assert!(pfs[0].entry_note(1).is_none());
+        assert!(pfs[1].notes.is_empty());
+    }
+
+    #[test]
+    fn symbols_without_ids_only_pass_as_values() {
+        let text = "Spell.dbc:\n  - type: update\n    key: 133\n    values: { Description_lang_0: \"$d\" }\n";
+        let pfs = parse_patch_str(text, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        assert_eq!(pfs[0].changes[0].values().unwrap()["Description_lang_0"], ValueType::String("$d".to_string()));
+        let key = "Spell.dbc:\n  - type: update\n    key: $unallocated\n    values: {}\n";
+        assert!(parse_patch_str(key, Path::new("test.yaml"), &IdMap::new()).is_err());
+    }
+
+    #[test]
+    fn json_patches_parse_like_yaml() {
+        let yaml = "Spell.dbc:\n  - type: update\n    key: 133\n    values: { SpellIconID: 1 }\nSpell.dbc:\n  - type: delete\n    key: 134\n";
+        let json = r#"{"Spell.dbc": [{"type": "update", "key": 133, "values": {"SpellIconID": 1}}],
+                       "Spell.dbc": [{"type": "delete", "key": 134}]}"#;
+        let from_yaml = parse_patch_str(yaml, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        let from_json = parse_patch_str(json, Path::new("test.json"), &IdMap::new()).unwrap();
+        assert_eq!(from_json.len(), 2);
+        for (a, b) in from_yaml.iter().zip(&from_json) {
+            assert_eq!((&a.dbc, &a.changes), (&b.dbc, &b.changes));
+        }
+    }
+
+    #[test]
+    fn tables_take_directives_as_a_mapping() {
+        let text = "Spell.dbc:\n  truncate: true\n  changes:\n    - type: delete_where\n      where: { SpellIconID: 1 }\n";
+        let pfs = parse_patch_str(text, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        assert!(pfs[0].truncate);
+        assert!(matches!(&pfs[0].changes[0], PatchEntry::DeleteWhere { hard: false, .. }));
+        let unknown = "Spell.dbc:\n  truncated: true\n";
+        assert!(parse_patch_str(unknown, Path::new("test.yaml"), &IdMap::new()).is_err());
+    }
+
+    /// A source without tables, for states that never consult one.
+    struct NoTables;
+
+    impl DbcSource for NoTables {
+        fn read_table(&self, path: &Path) -> Result<DbcTable> {
+            anyhow::bail!("no table {:?} in this test", path)
+        }
+    }
+
+    /// A table of `records` with `schema`, for checking conditions.
+    fn table(schema: Option<Schema>, records: Vec<Vec<u32>>) -> TableState<'static> {
+        TableState {
+            file_name: "Spell.dbc".to_string(),
+            field_count: records.first().map_or(0, |r| r.len() as u32),
+            records,
+            string_block: vec![0],
+            string_map: build_string_map(&[0]),
+            new_strings: Vec::new(),
+            schema,
+            locales: vec![0],
+            includes_dir: PathBuf::new(),
+            dbc_dir: PathBuf::new(),
+            source: &NoTables,
+            stats: TableStats::default(),
+            current_entry: String::new(),
+            provenance: None,
+            touched: BTreeSet::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn unsigned_ranges_take_high_bit_values() {
+        // AttributesEx declared `type: uint`, holding a mask with the high bit set
+        let mut schema = load_schema(Path::new(""), "Spell.dbc").unwrap();
+        let col = schema.column("AttributesEx").unwrap();
+        let field = schema.fields.iter_mut().find(|f| f.index == col).unwrap();
+        (field.kind, field.typed) = (FieldKind::Int, true);
+        let mut record = vec![0u32; 173];
+        record[col] = 0x8000_0010;
+        let state = table(Some(schema), vec![record.clone()]);
+        let range = |low, high| Condition::Range { range: [low, high] };
+        assert_eq!(condition_holds(&state, &record, col, &range(0.0, 4294967295.0)), Ok(true));
+        assert_eq!(condition_holds(&state, &record, col, &range(-2147483648.0, -1.0)), Ok(false));
+    }
+
+    #[test]
+    fn signed_ranges_take_negative_values() {
+        let schema = load_schema(Path::new(""), "Spell.dbc");
+        let col = resolve_field_index("EffectBasePoints_1", &schema).unwrap();
+        let mut record = vec![0u32; 173];
+        record[col] = -5i32 as u32;
+        let state = table(schema, vec![record.clone()]);
+        let range = |low, high| Condition::Range { range: [low, high] };
+        assert_eq!(condition_holds(&state, &record, col, &range(-10.0, 0.0)), Ok(true));
+        assert_eq!(condition_holds(&state, &record, col, &range(0.0, 4294967295.0)), Ok(false));
+    }
+
+    #[test]
+    fn values_in_effect_compare_floats_by_value() {
+        let schema = load_schema(Path::new(""), "Spell.dbc");
+        let speed = resolve_field_index("Speed", &schema).unwrap();
+        let mut record = vec![0u32; 173];
+        record[speed] = 24.0f32.to_bits();
+        let state = table(schema, vec![record.clone()]);
+        let values = |value| HashMap::from([("Speed".to_string(), value)]);
+        assert!(values_in_effect(&state, &record, &values(ValueType::Int(24))));
+        assert!(values_in_effect(&state, &record, &values(ValueType::Float(24.0))));
+        assert!(!values_in_effect(&state, &record, &values(ValueType::UInt(24.0f32.to_bits() as u64))));
+    }
+
+    #[test]
+    fn float_keys_match_by_value() {
+        assert_eq!(index_key(35.0f32.to_bits(), true), lookup_key(35, true));
+        assert_ne!(index_key(35.0f32.to_bits(), false), lookup_key(35, false));
+        assert_eq!(index_key(35, false), lookup_key(35, false));
+        assert_eq!(index_key((-0.0f32).to_bits(), true), lookup_key(0, true));
+    }
+
+    #[test]
+    fn requires_and_priority_order_patch_files() {
+        let settings = |priority: i64, requires: &[&str]| FileSettings {
+            priority,
+            requires: requires.iter().map(|s| s.to_string()).collect(),
+            ..FileSettings::default()
+        };
+        let files = [
+            (Path::new("patches/a.yaml"), settings(0, &["c.yaml"])),
+            (Path::new("patches/b.yaml"), settings(-1, &[])),
+            (Path::new("patches/c.yaml"), settings(5, &[])),
+            (Path::new("patches/d.yaml"), settings(0, &[])),
+        ];
+        assert_eq!(apply_order(&files).unwrap(), [1, 3, 2, 0]);
+
+        let cycle = [
+            (Path::new("a.yaml"), settings(0, &["b.yaml"])),
+            (Path::new("b.yaml"), settings(0, &["a.yaml"])),
+        ];
+        assert!(apply_order(&cycle).is_err());
+        let missing = [(Path::new("a.yaml"), settings(0, &["gone.yaml"]))];
+        assert!(apply_order(&missing).is_err());
+    }
+
+    #[test]
+    fn files_for_other_clients_are_excluded() {
+        let dir = std::env::temp_dir().join(format!("wow_dbc_patcher-clients-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let body = "Spell.dbc:\n  - type: update\n    key: 133\n    values: { SpellIconID: 1 }\n";
+        let files = [
+            ("any.yaml", ""),
+            ("old.yaml", "profile: 1.17\n"),
+            ("new.yaml", "profile: [\"1.18\", ptr]\n"),
+            ("recipes.yaml", "min_build: 5875\n"),
+            ("shifted.yaml", "min_build: 5000\nmax_build: 5874\n"),
+        ];
+        let paths: Vec<PathBuf> = files
+            .iter()
+            .map(|(name, settings)| {
+                let path = dir.join(name);
+                fs::write(&path, format!("{}{}", settings, body)).unwrap();
+                path
+            })
+            .collect();
+        let kept = |target: &ClientTarget| -> Vec<String> {
+            let kept = exclude_other_clients(paths.clone(), target).unwrap();
+            kept.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect()
+        };
+
+        // As the manifest's `defaults` name the target
+        let manifest: Manifest = serde_yaml::from_str("defaults:\n  profile: 1.18\n  client_build: 5875\n").unwrap();
+        let target = ClientTarget {
+            profile: manifest.defaults.profile,
+            build: manifest.defaults.client_build,
+        };
+        assert_eq!(kept(&target), ["any.yaml", "new.yaml", "recipes.yaml"]);
+        let target = ClientTarget {
+            profile: Some("PTR".to_string()),
+            build: Some(5874),
+        };
+        assert_eq!(kept(&target), ["any.yaml", "new.yaml", "shifted.yaml"]);
+        // Files that name a profile or builds need the target to name one
+        assert_eq!(kept(&ClientTarget::default()), ["any.yaml"]);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::stats::AppliedTable;
+
+/// A size in bytes, written in the manifest either as a plain number or
+/// with a `K`, `M` or `G` suffix (powers of 1024, e.g. `512K`, `20MB`).
+#[derive(Debug, Clone, Copy)]
+pub struct ByteSize(pub u64);
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(u64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Bytes(n) => Ok(ByteSize(n)),
+            Raw::Text(s) => parse_size(&s)
+                .map(ByteSize)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid size {:?}", s))),
+        }
+    }
+}
+
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim().to_ascii_uppercase();
+    let s = s.strip_suffix("IB").or_else(|| s.strip_suffix('B')).unwrap_or(&s);
+    let (digits, factor) = match s.chars().last()? {
+        'K' => (&s[..s.len() - 1], 1u64 << 10),
+        'M' => (&s[..s.len() - 1], 1 << 20),
+        'G' => (&s[..s.len() - 1], 1 << 30),
+        _ => (s, 1),
+    };
+    digits.trim().parse::<u64>().ok()?.checked_mul(factor)
+}
+
+/// Limits for one table.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct TableBudget {
+    /// Rows a patch run may add to the table.
+    pub max_inserted_rows: Option<usize>,
+    /// Bytes the table's string block may grow by.
+    pub max_string_growth: Option<ByteSize>,
+}
+
+/// Size budgets from the manifest.  Exceeding one prints a warning, or
+/// aborts the run when `fail` is set, so a patch cannot quietly bloat into
+/// something that slows down client loading.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Budgets {
+    /// Fail the run instead of warning.
+    pub fail: bool,
+    /// Largest MPQ `build` may produce.
+    pub max_archive_size: Option<ByteSize>,
+    /// Per‑table defaults.
+    #[serde(flatten)]
+    pub default: TableBudget,
+    /// Overrides for individual tables, keyed by file name.
+    pub tables: HashMap<String, TableBudget>,
+}
+
+impl Budgets {
+    fn for_table(&self, file_name: &str) -> TableBudget {
+        let own = self
+            .tables
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(file_name))
+            .map(|(_, b)| b.clone())
+            .unwrap_or_default();
+        TableBudget {
+            max_inserted_rows: own.max_inserted_rows.or(self.default.max_inserted_rows),
+            max_string_growth: own.max_string_growth.or(self.default.max_string_growth),
+        }
+    }
+
+    /// Check every applied table against its row and string budgets.
+    pub fn check_tables(&self, tables: &[AppliedTable]) -> Result<()> {
+        let mut exceeded = Vec::new();
+        for table in tables {
+            let budget = self.for_table(&table.file_name);
+            if let Some(max) = budget.max_inserted_rows {
+                if table.rows_added > max {
+                    exceeded.push(format!(
+                        "{} gained {} rows, budget is {}",
+                        table.file_name, table.rows_added, max
+                    ));
+                }
+            }
+            if let Some(ByteSize(max)) = budget.max_string_growth {
+                if table.string_growth > max {
+                    exceeded.push(format!(
+                        "{} string block grew by {} bytes, budget is {}",
+                        table.file_name, table.string_growth, max
+                    ));
+                }
+            }
+        }
+        self.report(&exceeded)
+    }
+
+    /// Check the size of a built archive.
+    pub fn check_archive(&self, size: u64) -> Result<()> {
+        let mut exceeded = Vec::new();
+        if let Some(ByteSize(max)) = self.max_archive_size {
+            if size > max {
+                exceeded.push(format!("archive is {} bytes, budget is {}", size, max));
+            }
+        }
+        self.report(&exceeded)
+    }
+
+    fn report(&self, exceeded: &[String]) -> Result<()> {
+        if exceeded.is_empty() {
+            return Ok(());
+        }
+        if self.fail {
+            bail!("Budget exceeded:\n  {}", exceeded.join("\n  "));
+        }
+        for msg in exceeded {
+            warning!("budget exceeded: {}", msg);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::capture;
+    use crate::stats::TableStats;
+    use std::path::PathBuf;
+
+    fn applied(file_name: &str, rows_added: usize, string_growth: u64) -> AppliedTable {
+        AppliedTable {
+            file_name: file_name.to_string(),
+            out_path: PathBuf::new(),
+            rows_added,
+            string_growth,
+            stats: TableStats::default(),
+            changelog: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn tables_are_checked_against_their_own_or_the_default_budget() {
+        let yaml = "max_inserted_rows: 10\nmax_string_growth: 1K\ntables:\n  spell.dbc: { max_inserted_rows: 100 }\n";
+        let mut budgets: Budgets = serde_yaml::from_str(yaml).unwrap();
+        let tables = [
+            // Within Spell.dbc's own row budget, over the default string one
+            applied("Spell.dbc", 50, 2000),
+            applied("SpellVisual.dbc", 11, 1024),
+        ];
+
+        let (result, captured) = capture(|| budgets.check_tables(&tables));
+        result.unwrap();
+        assert_eq!(
+            captured.warnings(),
+            [
+                "budget exceeded: Spell.dbc string block grew by 2000 bytes, budget is 1024",
+                "budget exceeded: SpellVisual.dbc gained 11 rows, budget is 10",
+            ]
+        );
+
+        budgets.fail = true;
+        let (result, captured) = capture(|| budgets.check_tables(&tables));
+        let err = result.unwrap_err().to_string();
+        assert!(err.starts_with("Budget exceeded:\n  Spell.dbc string block"), "{}", err);
+        assert!(captured.warnings().is_empty());
+        budgets.check_tables(&[applied("Spell.dbc", 100, 1024)]).unwrap();
+    }
+
+    #[test]
+    fn sizes_take_suffixes() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("512K"), Some(512 << 10));
+        assert_eq!(parse_size(" 20mb"), Some(20 << 20));
+        assert_eq!(parse_size("2GiB"), Some(2 << 30));
+        assert_eq!(parse_size("lots"), None);
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::patch::EntryNote;
+use crate::stats::AppliedTable;
+
+/// Keys listed for one table of a group before the rest are counted.
+const MAX_KEYS: usize = 12;
+
+/// A patch entry that has a `description` or an `issue`, with the records
+/// it changed.
+#[derive(Debug, Clone)]
+pub struct NotedChange {
+    pub note: EntryNote,
+    /// Where the entry comes from, as `patches/naxx.yaml:42`
+    pub entry: String,
+    /// Keys of the records it updated, inserted or deleted
+    pub keys: BTreeSet<u32>,
+}
+
+/// Changes of one description, in the order they were applied.
+struct Group<'a> {
+    description: Option<&'a str>,
+    issues: Vec<&'a str>,
+    /// Table with the keys changed in it, in the order first changed
+    tables: Vec<(&'a str, BTreeSet<u32>)>,
+    /// Entries that changed nothing
+    idle: Vec<&'a str>,
+}
+
+/// The changelog of `tables` as Markdown: a section for each description
+/// given to patch entries, in the order first applied, with its issues and
+/// the records it changed in each table.  Entries with an issue and no
+/// description come last, under "Other changes".
+pub fn changelog_markdown(tables: &[AppliedTable]) -> String {
+    let mut groups: Vec<Group> = Vec::new();
+    for table in tables {
+        for change in &table.changelog {
+            let description = change.note.description.as_deref();
+            let at = match groups.iter().position(|g| g.description == description) {
+                Some(at) => at,
+                None => {
+                    groups.push(Group {
+                        description,
+                        issues: Vec::new(),
+                        tables: Vec::new(),
+                        idle: Vec::new(),
+                    });
+                    groups.len() - 1
+                }
+            };
+            let group = &mut groups[at];
+            if let Some(issue) = change.note.issue.as_deref() {
+                if !group.issues.contains(&issue) {
+                    group.issues.push(issue);
+                }
+            }
+            if change.keys.is_empty() {
+                group.idle.push(&change.entry);
+                continue;
+            }
+            match group.tables.iter_mut().find(|(name, _)| *name == table.file_name) {
+                Some((_, keys)) => keys.extend(&change.keys),
+                None => group.tables.push((&table.file_name, change.keys.clone())),
+            }
+        }
+    }
+    // Undescribed changes go last
+    groups.sort_by_key(|g| g.description.is_none());
+
+    let mut out = String::from("# Changes\n");
+    if groups.is_empty() {
+        out.push_str("\nNo patch entry has a description or an issue.\n");
+    }
+    for group in &groups {
+        out.push_str(&format!("\n## {}\n\n", group.description.unwrap_or("Other changes")));
+        if !group.issues.is_empty() {
+            out.push_str(&format!("Issues: {}\n\n", group.issues.join(", ")));
+        }
+        for (table, keys) in &group.tables {
+            let mut listed: Vec<String> = keys.iter().take(MAX_KEYS).map(u32::to_string).collect();
+            if keys.len() > MAX_KEYS {
+                listed.push(format!("and {} more", keys.len() - MAX_KEYS));
+            }
+            out.push_str(&format!("- {}: {}\n", table, listed.join(", ")));
+        }
+        for entry in &group.idle {
+            out.push_str(&format!("- changed nothing: {}\n", entry));
+        }
+    }
+    out
+}
+
+/// Write the changelog of `tables` (see [`changelog_markdown`]) to `path`.
+pub fn write_changelog(tables: &[AppliedTable], path: &Path) -> Result<()> {
+    fs::write(path, changelog_markdown(tables)).with_context(|| format!("Failed to write changelog {:?}", path))?;
+    say!("Wrote changelog {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::apply::describe_entry;
+use crate::helpers::icon_name;
+use crate::includes::{select_includes, IncludeGlobs};
+use crate::manifest::Manifest;
+use crate::patch::{PatchEntry, PatchFile, ValueType};
+use crate::refs::walk_files;
+
+/// Extensions of client assets patches point at by path.
+const ASSET_EXTENSIONS: [&str; 7] = ["mdx", "m2", "blp", "wav", "mp3", "wmo", "skin"];
+
+/// The `Data` directory of a client given by its own directory or by
+/// `Data` itself.
+fn data_dir(dir: &Path) -> PathBuf {
+    if dir.join("Data").is_dir() {
+        dir.join("Data")
+    } else {
+        dir.to_path_buf()
+    }
+}
+
+/// The archives of a 1.12 client in the order the client loads them, lowest
+/// priority first: the base archives, then `patch.MPQ`, then
+/// `patch-2.MPQ`…`patch-9.MPQ` and `patch-A.MPQ`…`patch-Z.MPQ`.  `dir` may
+/// be the client directory or its `Data` directory.
+pub fn detect_patch_chain(dir: &Path) -> Result<Vec<PathBuf>> {
+    let data = data_dir(dir);
+    let mut archives: Vec<(u8, String, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(&data).with_context(|| format!("Failed to read client directory {:?}", data))? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()).map(|n| n.to_uppercase()) else {
+            continue;
+        };
+        let Some(stem) = name.strip_suffix(".MPQ") else {
+            continue;
+        };
+        let rank = if stem == "PATCH" {
+            (1, String::new())
+        } else if let Some(suffix) = stem.strip_prefix("PATCH-") {
+            // Digits sort before letters, which is the client's order
+            (2, suffix.to_string())
+        } else {
+            (0, stem.to_string())
+        };
+        archives.push((rank.0, rank.1, path));
+    }
+    if archives.is_empty() {
+        bail!("No MPQ archives found in {:?}", data);
+    }
+    archives.sort();
+    Ok(archives.into_iter().map(|(_, _, path)| path).collect())
+}
+
+/// Normalize `value` to a client path (backslashes) if it names an asset
+/// file by one of the extensions in `ASSET_EXTENSIONS`.
+fn asset_path(value: &str) -> Option<String> {
+    let path = value.trim().replace('/', "\\");
+    let (_, ext) = path.rsplit_once('.')?;
+    ASSET_EXTENSIONS
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(ext))
+        .then_some(path)
+}
+
+/// Record the asset paths `changes` refer to, keyed by lower‑cased path,
+/// with the spelling first seen and the entries using them.
+/// `item_display` icons are expanded to their `Interface\Icons` path.
+fn collect_assets(changes: &[PatchEntry], origin: &str, out: &mut BTreeMap<String, (String, Vec<String>)>) {
+    for change in changes {
+        if let PatchEntry::Zone { changes, .. } = change {
+            collect_assets(changes, origin, out);
+            continue;
+        }
+        let user = format!("{} {}", origin, describe_entry(change));
+        let note = |path: String, out: &mut BTreeMap<String, (String, Vec<String>)>| {
+            out.entry(path.to_lowercase())
+                .or_insert_with(|| (path, Vec::new()))
+                .1
+                .push(user.clone());
+        };
+        if let PatchEntry::ReplaceString { to, .. } = change {
+            if let Some(path) = asset_path(to) {
+                note(path, out);
+            }
+        }
+        if let PatchEntry::ItemDisplay { icon, .. } = change {
+            note(format!("Interface\\Icons\\{}.blp", icon_name(icon)), out);
+        }
+        for value in change.values().into_iter().flat_map(|v| v.values()) {
+            if let ValueType::String(text) = value {
+                if let Some(path) = asset_path(text) {
+                    note(path, out);
+                }
+            }
+        }
+    }
+}
+
+/// Paths a client may store an asset under.  Tables name models `.mdx`
+/// while 1.12 archives hold them as `.m2`.
+fn client_names(path: &str) -> Vec<String> {
+    let mut names = vec![path.to_string()];
+    match path.len().checked_sub(4) {
+        Some(cut) if path[cut..].eq_ignore_ascii_case(".mdx") => names.push(format!("{}.m2", &path[..cut])),
+        _ => {}
+    }
+    names
+}
+
+/// Check that every asset path the patches reference, and that the
+/// includes directory does not ship (see [`select_includes`]), exists in
+/// the client's archives.
+/// Prints the archive that provides each one (the highest priority archive
+/// holding it) and fails if any is missing.  Script output is not
+/// inspected.
+pub fn verify_client_refs_command(
+    patches_map: &HashMap<String, Vec<PatchFile>>,
+    client_dir: &Path,
+    includes_dir: &Path,
+    manifest: &Manifest,
+) -> Result<()> {
+    let mut assets: BTreeMap<String, (String, Vec<String>)> = BTreeMap::new();
+    for pfs in patches_map.values() {
+        for pf in pfs {
+            let origin = pf
+                .origin
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "<unknown>".to_string());
+            collect_assets(&pf.changes, &format!("{}: {}", origin, pf.dbc), &mut assets);
+        }
+    }
+
+    let shipped: Vec<String> = select_includes(includes_dir, manifest, &IncludeGlobs::default())?
+        .0
+        .iter()
+        .map(|entry| entry.archive_name.replace('/', "\\").to_lowercase())
+        .collect();
+
+    let archives = detect_patch_chain(client_dir)?;
+    let mut chain = wow_mpq::PatchChain::new();
+    for (priority, path) in archives.iter().enumerate() {
+        chain
+            .add_archive(path, priority as i32)
+            .with_context(|| format!("Failed to open client archive {:?}", path))?;
+    }
+    println!("Client patch chain ({} archives, highest priority last):", archives.len());
+    for path in &archives {
+        println!("  {}", path.display());
+    }
+
+    let (mut found, mut from_includes) = (0usize, 0usize);
+    let mut missing = Vec::new();
+    for (key, (path, users)) in &assets {
+        if client_names(key).iter().any(|name| shipped.contains(name)) {
+            from_includes += 1;
+            continue;
+        }
+        let provider = client_names(path)
+            .iter()
+            .find_map(|name| chain.find_file_archive(name).map(|archive| (name.clone(), archive.to_path_buf())));
+        match provider {
+            Some((name, archive)) => {
+                found += 1;
+                println!("{} -> {}", name, archive.display());
+            }
+            None => missing.push((path, users)),
+        }
+    }
+    for (path, users) in &missing {
+        println!("Missing: {} is in neither the includes nor the client, used by:", path);
+        for user in users.iter() {
+            println!("  {}", user);
+        }
+    }
+    println!(
+        "{} asset(s) found in the client, {} shipped in {:?}, {} missing",
+        found,
+        from_includes,
+        includes_dir,
+        missing.len()
+    );
+    if !missing.is_empty() {
+        bail!("{} referenced asset(s) are missing from the client", missing.len());
+    }
+    Ok(())
+}
+
+/// Copy `mpq` into the client's `Data` directory as `name` (its own file
+/// name by default).  The client keeps server data it was sent, such as
+/// spell and item records, in the `WDB` directory beside `Data`, and
+/// entries cached before the install can hide changed rows; with
+/// `clear_cache` every file there is deleted and listed, otherwise a
+/// non‑empty cache is warned about.
+pub fn install_command(mpq: &Path, client_dir: &Path, name: Option<&str>, clear_cache: bool) -> Result<()> {
+    let data = data_dir(client_dir);
+    if !data.is_dir() {
+        bail!("{:?} is not a client directory (no Data directory found)", client_dir);
+    }
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => mpq
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .with_context(|| format!("{:?} is not a file", mpq))?,
+    };
+    let target = data.join(&name);
+    let replaced = target.exists();
+    fs::copy(mpq, &target).with_context(|| format!("Failed to copy {:?} to {:?}", mpq, target))?;
+    println!("{} {}", if replaced { "Replaced" } else { "Installed" }, target.display());
+
+    let root = if data == client_dir { data.parent().unwrap_or(&data) } else { client_dir };
+    let cache = root.join("WDB");
+    let cached = walk_files(&cache).with_context(|| format!("Failed to read the client cache {:?}", cache))?;
+    if cached.is_empty() {
+        return Ok(());
+    }
+    if !clear_cache {
+        warning!(
+            "{} holds {} cached file(s); records the client cached before this install can hide changed rows until it is cleared (re-run with --clear-cache while the client is closed)",
+            cache.display(),
+            cached.len()
+        );
+        return Ok(());
+    }
+    for path in &cached {
+        fs::remove_file(path).with_context(|| format!("Failed to delete {:?} (is the client running?)", path))?;
+        println!("Removed {}", path.display());
+    }
+    println!("Cleared {} cached file(s) from {}", cached.len(), cache.display());
+    Ok(())
+}
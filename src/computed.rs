@@ -0,0 +1,222 @@
+use anyhow::{anyhow, bail, Result};
+use rhai::{Array, Dynamic, Engine, Scope, AST, FLOAT, INT};
+
+use crate::dbc::pending_string_at;
+use crate::schema::{FieldKind, Schema};
+
+/// Upper bound on the work one computed field's expression may do for one
+/// record.
+const MAX_OPERATIONS: u64 = 10_000;
+
+/// A field the schema computes, with its compiled expression.
+struct Computed {
+    name: String,
+    column: usize,
+    kind: FieldKind,
+    ast: AST,
+}
+
+/// The `computed` fields of a table's schema, ready to be evaluated on its
+/// records.  Each is a Rhai expression that sees every field of the record
+/// by name: numbers as integers (signed for `int32` fields), floats,
+/// booleans and the text of strings.  A `loc` field is also available by
+/// its bare name as an array of its texts, so
+/// `if Name.some(|text| text != "") { 0xFF } else { 0 }` sets a locale
+/// mask.  Fields are computed in the order the schema lists them, each
+/// seeing the ones before it.
+pub struct ComputedFields {
+    engine: Engine,
+    fields: Vec<Computed>,
+}
+
+impl ComputedFields {
+    /// Compile the `computed` expressions of `schema`, the schema of
+    /// `table`, or `None` when it declares none.
+    pub fn compile(schema: &Schema, table: &str) -> Result<Option<ComputedFields>> {
+        if schema.computed().is_empty() {
+            return Ok(None);
+        }
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.disable_symbol("eval");
+        let mut fields = Vec::new();
+        for (name, expression) in schema.computed() {
+            let Some(column) = schema.column(name) else {
+                continue;
+            };
+            // Under its current name, should the schema list a former one
+            let (name, kind) = schema
+                .field_at(column)
+                .map_or((name.clone(), FieldKind::Int), |f| (f.name.clone(), f.kind));
+            if kind == FieldKind::String {
+                bail!("{} of {} is a string field, which cannot be computed", name, table);
+            }
+            let ast = engine
+                .compile(expression)
+                .map_err(|e| anyhow!("Invalid expression for computed field {} of {}: {}", name, table, e))?;
+            fields.push(Computed {
+                name,
+                column,
+                kind,
+                ast,
+            });
+        }
+        Ok(Some(ComputedFields { engine, fields }))
+    }
+
+    /// Set the computed fields of `record`, whose strings are in
+    /// `string_block` or, past its end, `new_strings`.  Returns whether
+    /// any of them changed.
+    pub fn apply(
+        &self,
+        record: &mut [u32],
+        schema: &Schema,
+        string_block: &[u8],
+        new_strings: &[String],
+    ) -> Result<bool> {
+        let mut scope = record_scope(record, schema, string_block, new_strings);
+        let key = record.first().copied().unwrap_or_default();
+        let mut changed = false;
+        for field in &self.fields {
+            let Some(cell) = record.get_mut(field.column) else {
+                continue;
+            };
+            let result: Dynamic = self
+                .engine
+                .eval_ast_with_scope(&mut scope, &field.ast)
+                .map_err(|e| anyhow!("Computing {} of record {} failed: {}", field.name, key, e))?;
+            let value = to_cell(&result, field.kind)
+                .map_err(|e| anyhow!("Computing {} of record {} failed: {}", field.name, key, e))?;
+            changed |= *cell != value;
+            *cell = value;
+            scope.set_value(field.name.as_str(), result);
+        }
+        Ok(changed)
+    }
+}
+
+/// A scope holding every field of `record`, whose strings are in
+/// `string_block` or, past its end, `new_strings`, by name: numbers as
+/// integers (signed for `int32` fields), floats, booleans and the text of
+/// strings, and each `loc` field by its bare name as an array of its
+/// texts.
+pub(crate) fn record_scope(record: &[u32], schema: &Schema, string_block: &[u8], new_strings: &[String]) -> Scope<'static> {
+    let mut scope = Scope::new();
+    for field in &schema.fields {
+        let Some(&cell) = record.get(field.index) else {
+            continue;
+        };
+        let value = match field.kind {
+            FieldKind::Int => Dynamic::from(cell as INT),
+            FieldKind::Int32 => Dynamic::from(cell as i32 as INT),
+            FieldKind::Float => Dynamic::from(f32::from_bits(cell) as FLOAT),
+            FieldKind::Bool => Dynamic::from(cell != 0),
+            FieldKind::String => Dynamic::from(pending_string_at(string_block, new_strings, cell).unwrap_or_default()),
+        };
+        scope.push_dynamic(field.name.as_str(), value);
+        if let Some(bare) = field.name.strip_suffix("_lang_Mask") {
+            if let Some((first, mask)) = schema.loc_columns(bare) {
+                let texts: Array = record[first..mask]
+                    .iter()
+                    .map(|&offset| Dynamic::from(pending_string_at(string_block, new_strings, offset).unwrap_or_default()))
+                    .collect();
+                scope.push(bare.to_string(), texts);
+            }
+        }
+    }
+    scope
+}
+
+/// The value an expression gave, as a cell of a `kind` field.
+fn to_cell(result: &Dynamic, kind: FieldKind) -> Result<u32, String> {
+    if let Some(v) = result.clone().try_cast::<bool>() {
+        return Ok(v as u32);
+    }
+    if let Some(v) = result.clone().try_cast::<INT>() {
+        return match kind {
+            FieldKind::Float => Ok((v as f32).to_bits()),
+            _ if (i32::MIN as INT..=u32::MAX as INT).contains(&v) => Ok(v as u32),
+            _ => Err(format!("{} does not fit in 32 bits", v)),
+        };
+    }
+    if let Some(v) = result.clone().try_cast::<FLOAT>() {
+        return match kind {
+            FieldKind::Float => Ok((v as f32).to_bits()),
+            _ => Err(format!("the expression gave {}, not a whole number", v)),
+        };
+    }
+    Err(format!("the expression gave a {}", result.type_name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::load_schema_file;
+    use std::fs;
+
+    fn schema(name: &str, yaml: &str) -> Schema {
+        let path = std::env::temp_dir().join(format!("wow_dbc_patcher-{}-{}.dbc.yaml", std::process::id(), name));
+        fs::write(&path, yaml).unwrap();
+        let schema = load_schema_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+        schema
+    }
+
+    #[test]
+    fn computed_fields_see_the_record_and_earlier_results() {
+        let schema = schema(
+            "computed",
+            r#"
+fields:
+  - ID
+  - { name: Radius, type: float }
+  - { name: Count, type: int32 }
+  - { name: Name, type: loc }
+  - Doubled
+  - { name: Scaled, type: float }
+computed:
+  Name_lang_Mask: 'if Name.some(|text| text != "") { 0xFF } else { 0 }'
+  Doubled: Count * 2
+  Scaled: Doubled + Radius
+"#,
+        );
+        let computed = ComputedFields::compile(&schema, "Test.dbc").unwrap().unwrap();
+        let mut record = vec![0u32; 14];
+        record[0] = 7;
+        record[1] = 1.5f32.to_bits();
+        record[2] = -3i32 as u32;
+        // Name_lang_0 is a string added by the patch, past the block's end
+        record[3] = 1;
+        assert!(computed.apply(&mut record, &schema, b"\0", &["Frost".to_string()]).unwrap());
+        assert_eq!(record[11], 0xFF);
+        assert_eq!(record[12], -6i32 as u32);
+        assert_eq!(f32::from_bits(record[13]), -4.5);
+        // Already as computed
+        assert!(!computed.apply(&mut record, &schema, b"\0", &["Frost".to_string()]).unwrap());
+
+        record[3] = 0;
+        assert!(computed.apply(&mut record, &schema, b"\0", &[]).unwrap());
+        assert_eq!(record[11], 0);
+    }
+
+    #[test]
+    fn bad_expressions_and_results_are_errors() {
+        let strings = schema("strings", "fields: [ID, { name: Name, type: string }]\ncomputed:\n  Name: '\"x\"'\n");
+        assert!(ComputedFields::compile(&strings, "Test.dbc").is_err());
+
+        let invalid = schema("invalid", "fields: [ID, Count]\ncomputed:\n  Count: 'ID +'\n");
+        assert!(ComputedFields::compile(&invalid, "Test.dbc").is_err());
+
+        let fraction = schema("fraction", "fields: [ID, Count]\ncomputed:\n  Count: ID / 2.0\n");
+        let computed = ComputedFields::compile(&fraction, "Test.dbc").unwrap().unwrap();
+        let err = computed.apply(&mut [3, 0], &fraction, b"\0", &[]).unwrap_err();
+        assert!(err.to_string().contains("not a whole number"), "{}", err);
+
+        let endless = schema("endless", "fields: [ID, Count]\ncomputed:\n  Count: 'loop {}'\n");
+        let computed = ComputedFields::compile(&endless, "Test.dbc").unwrap().unwrap();
+        assert!(computed.apply(&mut [3, 0], &endless, b"\0", &[]).is_err());
+
+        let none = schema("none", "fields: [ID, Count]\n");
+        assert!(ComputedFields::compile(&none, "Test.dbc").unwrap().is_none());
+    }
+}
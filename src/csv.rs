@@ -0,0 +1,265 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::dbc::{dbc_file_name, find_dbc_file, read_dbc_with_schema, string_at, write_dbc, DbcFormat, DbcHeader, Endianness};
+use crate::schema::{load_schema, FieldKind, Schema};
+
+/// Quote a cell when it holds a separator, quote, line break or
+/// surrounding space, which spreadsheets would otherwise mangle.
+fn quote_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) || cell.trim() != cell {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Split CSV text into rows of cells, each row with the line it starts
+/// on.  Quoted cells may hold commas, doubled quotes and line breaks, as
+/// spreadsheets write them; blank lines are skipped.
+fn parse_rows(text: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut rows = Vec::new();
+    let (mut row, mut cell) = (Vec::new(), String::new());
+    let (mut line, mut row_line) = (1, 1);
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if cell.is_empty() => quoted = true,
+            '\n' if quoted => {
+                line += 1;
+                cell.push(c);
+            }
+            ',' if !quoted => row.push(std::mem::take(&mut cell)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut cell));
+                if row.len() > 1 || !row[0].is_empty() {
+                    rows.push((row_line, std::mem::take(&mut row)));
+                }
+                row.clear();
+                line += 1;
+                row_line = line;
+            }
+            _ => cell.push(c),
+        }
+    }
+    if quoted {
+        bail!("Quoted cell starting on line {} is never closed", row_line);
+    }
+    if !row.is_empty() || !cell.is_empty() {
+        row.push(cell);
+        rows.push((row_line, row));
+    }
+    Ok(rows)
+}
+
+/// Header of column `col`: the schema's field name, or `[N]`.
+fn column_header(schema: Option<&Schema>, col: usize) -> String {
+    schema
+        .and_then(|s| s.fields.iter().find(|f| f.index == col))
+        .map_or_else(|| format!("[{}]", col), |f| f.name.clone())
+}
+
+fn column_kind(schema: Option<&Schema>, col: usize) -> FieldKind {
+    schema
+        .and_then(|s| s.fields.iter().find(|f| f.index == col))
+        .map_or(FieldKind::Int, |f| f.kind)
+}
+
+/// Write every record of a DBC table to `out` as CSV, one row per record
+/// under a header row of schema field names.  Strings are written as
+/// text, floats as decimals and other columns as signed integers.
+pub fn export_csv_command(dbc_path: &Path, schema_dir: &Path, out: &Path) -> Result<()> {
+    let table = dbc_file_name(dbc_path)?;
+    let (header, records, string_block) =
+        read_dbc_with_schema(dbc_path, schema_dir).with_context(|| format!("Failed to read DBC file {:?}", dbc_path))?;
+    let schema = load_schema(schema_dir, &table);
+    if schema.is_none() {
+        warning!("no schema for {}, columns are written as [N] with raw values", table);
+    }
+    let schema = schema.as_ref();
+
+    let columns = header.field_count as usize;
+    let mut text = String::new();
+    let headers: Vec<String> = (0..columns).map(|col| quote_cell(&column_header(schema, col))).collect();
+    text.push_str(&headers.join(","));
+    text.push('\n');
+    for record in &records {
+        let cells: Vec<String> = record
+            .iter()
+            .enumerate()
+            .map(|(col, &value)| match column_kind(schema, col) {
+                FieldKind::String => quote_cell(&string_at(&string_block, value).unwrap_or_default()),
+                FieldKind::Float => format!("{:?}", f32::from_bits(value)),
+                FieldKind::Int | FieldKind::Int32 | FieldKind::Bool => (value as i32).to_string(),
+            })
+            .collect();
+        text.push_str(&cells.join(","));
+        text.push('\n');
+    }
+    fs::write(out, text).with_context(|| format!("Failed to write {:?}", out))?;
+    println!("Wrote {} record(s) of {} to {}", records.len(), table, out.display());
+    Ok(())
+}
+
+/// Parse one cell of column `col` into the value the record stores,
+/// interning strings into `string_block`.
+fn parse_cell(
+    cell: &str,
+    kind: FieldKind,
+    strings: &mut HashMap<String, u32>,
+    string_block: &mut Vec<u8>,
+) -> Result<u32, String> {
+    match kind {
+        FieldKind::String => {
+            if let Some(&offset) = strings.get(cell) {
+                return Ok(offset);
+            }
+            let offset = string_block.len() as u32;
+            string_block.extend_from_slice(cell.as_bytes());
+            string_block.push(0);
+            strings.insert(cell.to_string(), offset);
+            Ok(offset)
+        }
+        FieldKind::Float => cell
+            .trim()
+            .parse::<f32>()
+            .map(f32::to_bits)
+            .map_err(|_| format!("{:?} is not a number", cell)),
+        FieldKind::Bool if cell.trim().eq_ignore_ascii_case("true") => Ok(1),
+        FieldKind::Bool if cell.trim().eq_ignore_ascii_case("false") => Ok(0),
+        FieldKind::Int | FieldKind::Int32 | FieldKind::Bool => {
+            let cell = cell.trim();
+            cell.parse::<i32>()
+                .map(|v| v as u32)
+                .or_else(|_| cell.parse::<u32>())
+                .map_err(|_| format!("{:?} is not a 32‑bit integer", cell))
+        }
+    }
+}
+
+/// Read a CSV written by `export-csv`, or edited from one, and write it
+/// as the DBC `out` with a freshly built string block.  The header row
+/// must name the schema's fields in column order; when `dbc_dir` holds
+/// the stock table the column count must match it, and its byte order is
+/// kept.
+pub fn import_csv_command(csv_path: &Path, table: &str, dbc_dir: &Path, schema_dir: &Path, out: &Path) -> Result<()> {
+    let text = fs::read_to_string(csv_path).with_context(|| format!("Failed to read {:?}", csv_path))?;
+    let mut rows = parse_rows(&text)?.into_iter();
+    let Some((_, headers)) = rows.next() else {
+        bail!("{:?} is empty", csv_path);
+    };
+    let schema = load_schema(schema_dir, table);
+    let schema = schema.as_ref();
+
+    // The header row has to line up with the table layout
+    let stock_path = find_dbc_file(dbc_dir, table);
+    let stock = if stock_path.exists() {
+        Some(read_dbc_with_schema(&stock_path, schema_dir).with_context(|| format!("Failed to read DBC file {:?}", stock_path))?.0)
+    } else {
+        None
+    };
+    if let Some(stock) = &stock {
+        if headers.len() != stock.field_count as usize {
+            bail!(
+                "{:?} has {} column(s), but {} has {} fields",
+                csv_path,
+                headers.len(),
+                stock_path.display(),
+                stock.field_count
+            );
+        }
+    }
+    if let Some(last) = schema.and_then(|s| s.fields.iter().map(|f| f.index).max()) {
+        if headers.len() <= last {
+            bail!(
+                "{:?} has {} column(s), but the schema for {} has {}",
+                csv_path,
+                headers.len(),
+                table,
+                last + 1
+            );
+        }
+    }
+    for (col, name) in headers.iter().enumerate() {
+        let expected = column_header(schema, col);
+        if !name.trim().eq_ignore_ascii_case(&expected) {
+            bail!(
+                "Column {} of {:?} is {:?}, but {} names it {:?}",
+                col + 1,
+                csv_path,
+                name,
+                if schema.is_some() { "the schema" } else { "a table without a schema" },
+                expected
+            );
+        }
+    }
+
+    let mut string_block = vec![0u8];
+    let mut strings: HashMap<String, u32> = HashMap::from([(String::new(), 0)]);
+    let mut records = Vec::new();
+    for (line, cells) in rows {
+        if cells.len() != headers.len() {
+            bail!(
+                "Line {} of {:?} has {} cell(s), but the header has {}",
+                line,
+                csv_path,
+                cells.len(),
+                headers.len()
+            );
+        }
+        let mut record = Vec::with_capacity(cells.len());
+        for (col, cell) in cells.iter().enumerate() {
+            let value = parse_cell(cell, column_kind(schema, col), &mut strings, &mut string_block)
+                .map_err(|err| anyhow::anyhow!("Line {} of {:?}, {}: {}", line, csv_path, headers[col], err))?;
+            record.push(value);
+        }
+        records.push(record);
+    }
+
+    // A stock WDB2 table keeps its extra header fields
+    let format = stock.as_ref().map_or_else(DbcFormat::default, |h| h.format.clone());
+    let header = DbcHeader {
+        magic: if matches!(format, DbcFormat::Wdb2(_)) { *b"WDB2" } else { *b"WDBC" },
+        record_count: records.len() as u32,
+        field_count: headers.len() as u32,
+        record_size: headers.len() as u32 * 4,
+        string_block_size: string_block.len() as u32,
+        format,
+        endianness: stock.as_ref().map_or(Endianness::Little, |h| h.endianness),
+        layout: stock.and_then(|h| h.layout),
+    };
+    write_dbc(out, &header, &records, &string_block)
+        .with_context(|| format!("Failed to write output DBC for {}", table))?;
+    println!(
+        "Wrote {} record(s) and {} string(s) to {}",
+        records.len(),
+        strings.len() - 1,
+        out.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_cells_round_trip() {
+        let cells = ["7373", "Deals $s1, then \"more\"", "two\nlines", " padded", ""];
+        let line: Vec<String> = cells.iter().map(|c| quote_cell(c)).collect();
+        let text = format!("a,b,c,d,e\r\n{}\r\n\r\n1,2,3,4,5", line.join(","));
+        let rows = parse_rows(&text).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1], (2, cells.iter().map(|c| c.to_string()).collect()));
+        assert_eq!(rows[2].0, 5);
+    }
+}
@@ -3,10 +3,13 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::Path;
+use std::sync::OnceLock;
 
-/// Representation of a vanilla DBC header (WDBC).  The parser does not
-/// support the extended WDB2/WDB5 variants and assumes a simple fixed
-/// record layout.
+use crate::schema::{load_schema, FieldKind, Schema};
+
+/// Representation of a DBC header: vanilla WDBC, or the WDB2 of later
+/// clients with its extra fields in `format`.  The parser does not
+/// support WDB5 and later and assumes a simple fixed record layout.
 #[derive(Debug, Clone)]
 pub struct DbcHeader {
     pub magic: [u8; 4],
@@ -14,121 +17,698 @@ pub struct DbcHeader {
     pub field_count: u32,
     pub record_size: u32,
     pub string_block_size: u32,
+    /// Header variant, from the magic.
+    pub format: DbcFormat,
+    /// Byte order the file was read in, and will be written in.
+    pub endianness: Endianness,
+    /// Byte layout of records that are not `field_count` packed 4‑byte
+    /// cells.  `field_count` then also counts the padding columns the
+    /// layout adds after the fields.
+    pub layout: Option<RecordLayout>,
 }
 
 impl DbcHeader {
     /// Size of the header in bytes (magic + 4 u32s)
     pub const SIZE: usize = 4 + 4 * 4;
+
+    /// Number of columns that are fields of the table, leaving out the
+    /// padding columns of a layout.
+    pub fn table_fields(&self) -> usize {
+        self.layout
+            .as_ref()
+            .map_or(self.field_count as usize, |layout| layout.field_count as usize)
+    }
+}
+
+/// Header variant of a table, told apart by its magic.  `WDBC` is the
+/// default and the only one 1.12 clients read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DbcFormat {
+    #[default]
+    Wdbc,
+    /// `WDB2`, used by contributors testing against later clients.
+    Wdb2(Wdb2Header),
+}
+
+/// The fields a `WDB2` header has after those of `WDBC`.  They are kept
+/// as read and written back, except the key range and index, which
+/// follow the records and string block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Wdb2Header {
+    pub table_hash: u32,
+    pub build: u32,
+    pub timestamp: u32,
+    /// Lowest and highest key; both 0 when the file has no index.
+    pub min_id: u32,
+    pub max_id: u32,
+    pub locale: u32,
+    pub copy_table_size: u32,
+    /// Whether the file has an index of rows and string lengths by key.
+    pub indexed: bool,
+    /// The string columns, by the table's schema, whose lengths the index
+    /// sums for each row.  Without a schema the lengths are written as 0.
+    pub string_columns: Vec<usize>,
+}
+
+impl Wdb2Header {
+    /// Size of the extra fields in bytes.
+    const SIZE: usize = 7 * 4;
+
+    /// Widest key range an index is written for, so a stray huge key
+    /// cannot make one of gigabytes.
+    const MAX_INDEX_SPAN: u64 = 1 << 24;
+
+    /// Size of the index after the extra fields, for keys `min_id` to
+    /// `max_id`: a row number and a string length for each.
+    fn index_len(min_id: u32, max_id: u32) -> u64 {
+        if max_id == 0 {
+            return 0;
+        }
+        (max_id as u64).saturating_sub(min_id as u64).saturating_add(1) * 6
+    }
+
+    /// The extra fields and index of `records`, as written after the
+    /// `WDBC` fields.  Always little endian, as no later client is big
+    /// endian.
+    fn encode(&self, records: &[Vec<u32>], string_block: &[u8]) -> Result<Vec<u8>> {
+        let (mut min_id, mut max_id) = (0, 0);
+        let mut index = Vec::new();
+        let mut lengths = Vec::new();
+        if self.indexed {
+            let keys = records.iter().filter_map(|record| record.first().copied());
+            min_id = keys.clone().min().unwrap_or(0);
+            max_id = keys.max().unwrap_or(0);
+            let span = max_id as u64 - min_id as u64 + 1;
+            if max_id != 0 && span > Self::MAX_INDEX_SPAN {
+                bail!("Keys {} to {} are too far apart for a WDB2 index", min_id, max_id);
+            }
+            if max_id != 0 {
+                let mut rows = vec![0u32; span as usize];
+                let mut string_lengths = vec![0u16; span as usize];
+                for (row, record) in records.iter().enumerate() {
+                    if let Some(&key) = record.first() {
+                        rows[(key - min_id) as usize] = row as u32;
+                        string_lengths[(key - min_id) as usize] = self.string_length(record, string_block);
+                    }
+                }
+                index = rows.iter().flat_map(|row| row.to_le_bytes()).collect();
+                lengths = string_lengths.iter().flat_map(|length| length.to_le_bytes()).collect();
+            }
+        }
+        let mut bytes = Vec::with_capacity(Self::SIZE + index.len() + lengths.len());
+        for value in [self.table_hash, self.build, self.timestamp, min_id, max_id, self.locale, self.copy_table_size] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend(index);
+        bytes.extend(lengths);
+        Ok(bytes)
+    }
+
+    /// The total length in bytes of the strings `record` holds in
+    /// `string_columns`, without their null bytes.
+    fn string_length(&self, record: &[u32], string_block: &[u8]) -> u16 {
+        let total: usize = self
+            .string_columns
+            .iter()
+            .filter_map(|&col| string_block.get(*record.get(col)? as usize..))
+            .map(|text| text.iter().position(|&b| b == 0).unwrap_or(text.len()))
+            .sum();
+        total.min(u16::MAX as usize) as u16
+    }
+}
+
+/// Where one column lies in a record: its byte offset, its width in bytes
+/// and whether a narrower value is sign‑extended into its 32‑bit cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub offset: usize,
+    pub width: usize,
+    pub signed: bool,
+}
+
+/// Byte layout of records that are not packed 4‑byte fields, as in custom
+/// tables with byte or short columns or padding.  Every field is still
+/// read into a 32‑bit cell.  Bytes no field covers become padding columns
+/// after the fields, of up to 4 bytes each, so they are written back as
+/// they were read and copied along with their record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordLayout {
+    /// Field count as the file's header gives it.
+    pub field_count: u32,
+    pub record_size: u32,
+    /// The slots of the fields, then those of the padding columns.
+    slots: Vec<Slot>,
+}
+
+impl RecordLayout {
+    /// The layout of records of `record_size` bytes holding `fields`.
+    /// Fields may not overlap or run past the end of the record.
+    pub fn new(fields: &[Slot], record_size: u32) -> std::result::Result<RecordLayout, String> {
+        let mut covered = vec![false; record_size as usize];
+        for (col, slot) in fields.iter().enumerate() {
+            if !matches!(slot.width, 1 | 2 | 4) {
+                return Err(format!("column {} is {} bytes wide", col, slot.width));
+            }
+            let end = slot.offset + slot.width;
+            if end > covered.len() {
+                return Err(format!(
+                    "column {} ends at byte {}, past the end of the {} byte record",
+                    col, end, record_size
+                ));
+            }
+            if covered[slot.offset..end].iter().any(|&c| c) {
+                return Err(format!("column {} at byte {} overlaps another column", col, slot.offset));
+            }
+            covered[slot.offset..end].fill(true);
+        }
+        let mut slots = fields.to_vec();
+        let mut at = 0;
+        while at < covered.len() {
+            if covered[at] {
+                at += 1;
+                continue;
+            }
+            let width = covered[at..].iter().take(4).take_while(|&&c| !c).count();
+            slots.push(Slot {
+                offset: at,
+                width,
+                signed: false,
+            });
+            at += width;
+        }
+        Ok(RecordLayout {
+            field_count: fields.len() as u32,
+            record_size,
+            slots,
+        })
+    }
+
+    /// Number of columns, padding included.
+    pub fn columns(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+/// The layout of the records of the table at `path`, whose header gives
+/// `record_size` bytes for `field_count` fields rather than 4 bytes each.
+/// A `schema` declaring `width` or `offset` for its fields places them;
+/// otherwise the fields are packed 4‑byte cells followed by padding, which
+/// needs records larger than that.
+fn record_layout(path: &Path, field_count: u32, record_size: u32, schema: Option<&Schema>) -> Result<RecordLayout> {
+    let table = table_name(path);
+    let schema = schema.filter(|schema| schema.declares_layout());
+    if field_count > record_size {
+        bail!(
+            "Unsupported record layout in {:?}: {} fields do not fit records of {} bytes",
+            path,
+            field_count,
+            record_size
+        );
+    }
+    let mut fields = Vec::with_capacity(field_count as usize);
+    let mut next = 0;
+    for col in 0..field_count as usize {
+        let field = schema.as_ref().and_then(|schema| schema.field_at(col));
+        let slot = Slot {
+            offset: field.and_then(|f| f.offset).unwrap_or(next),
+            width: field.map_or(4, |f| f.width),
+            signed: field.is_some_and(|f| f.kind == FieldKind::Int32),
+        };
+        next = slot.offset + slot.width;
+        fields.push(slot);
+    }
+    if schema.is_none() && (record_size as u64) < field_count as u64 * 4 {
+        bail!(
+            "Unsupported record layout in {:?}: record size {} is smaller than {} fields of 4 bytes; \
+             declare the `width` of its byte or short columns in its schema",
+            path,
+            record_size,
+            field_count
+        );
+    }
+    RecordLayout::new(&fields, record_size)
+        .map_err(|err| anyhow::anyhow!("Schema for {} does not fit its {} byte records: {}", table, record_size, err))
+}
+
+/// Decode one record of `bytes` into its cells, by `header`'s layout.
+fn decode_record(bytes: &[u8], header: &DbcHeader) -> Vec<u32> {
+    match &header.layout {
+        None => bytes
+            .chunks_exact(4)
+            .take(header.field_count as usize)
+            .map(|c| header.endianness.read_u32([c[0], c[1], c[2], c[3]]))
+            .collect(),
+        Some(layout) => layout
+            .slots
+            .iter()
+            .map(|slot| header.endianness.read_uint(&bytes[slot.offset..slot.offset + slot.width], slot.signed))
+            .collect(),
+    }
+}
+
+/// Encode `record` by `header`'s layout.  A value too large for a narrow
+/// column is an error naming `row` and the column.
+fn encode_record(record: &[u32], header: &DbcHeader, row: usize) -> Result<Vec<u8>> {
+    let Some(layout) = &header.layout else {
+        return Ok(record.iter().flat_map(|&value| header.endianness.write_u32(value)).collect());
+    };
+    let mut bytes = vec![0u8; layout.record_size as usize];
+    for (col, (slot, &value)) in layout.slots.iter().zip(record).enumerate() {
+        let Some(encoded) = header.endianness.write_uint(value, slot.width) else {
+            bail!(
+                "Value {} of column {} in record {} does not fit the column's {} byte(s)",
+                value,
+                col,
+                row,
+                slot.width
+            );
+        };
+        bytes[slot.offset..slot.offset + slot.width].copy_from_slice(&encoded);
+    }
+    Ok(bytes)
+}
+
+/// The file name of a DBC path (e.g. `Spell.dbc`), used as the table name
+/// for schema lookup and patch matching.
+pub fn dbc_file_name(path: &Path) -> Result<String> {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow::anyhow!("Invalid DBC file path: {:?}", path))
+}
+
+/// Locate the file for table `name` in `dir`, matching the file name
+/// case‑insensitively.  Falls back to `dir/name` when nothing matches so the
+/// caller's error mentions the expected path.
+pub fn find_dbc_file(dir: &Path, name: &str) -> std::path::PathBuf {
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if let Some(file_name) = entry.file_name().to_str() {
+                if file_name.eq_ignore_ascii_case(name) {
+                    return entry.path();
+                }
+            }
+        }
+    }
+    dir.join(name)
+}
+
+/// Byte order of the integer data in a DBC.  PC clients use little
+/// endian; data extracted from old PowerPC Mac installs is big endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    fn read_u32(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn write_u32(self, value: u32) -> [u8; 4] {
+        match self {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        }
+    }
+
+    /// Read a value of 1 to 4 bytes, sign‑extending it when `signed`.
+    fn read_uint(self, bytes: &[u8], signed: bool) -> u32 {
+        let mut word = [0u8; 4];
+        match self {
+            Endianness::Little => word[..bytes.len()].copy_from_slice(bytes),
+            Endianness::Big => word[4 - bytes.len()..].copy_from_slice(bytes),
+        }
+        let value = u32::from_le_bytes(match self {
+            Endianness::Little => word,
+            Endianness::Big => [word[3], word[2], word[1], word[0]],
+        });
+        let unused = 32 - 8 * bytes.len() as u32;
+        if signed && unused > 0 {
+            (((value << unused) as i32) >> unused) as u32
+        } else {
+            value
+        }
+    }
+
+    /// Write `value` in `width` bytes, if it fits either as an unsigned or
+    /// as a sign‑extended value.
+    fn write_uint(self, value: u32, width: usize) -> Option<Vec<u8>> {
+        let unused = 32 - 8 * width as u32;
+        let fits = unused == 0
+            || value >> (32 - unused) == 0
+            || (((value << unused) as i32) >> unused) as u32 == value;
+        if !fits {
+            return None;
+        }
+        let bytes = self.write_u32(value);
+        Some(match self {
+            Endianness::Little => bytes[..width].to_vec(),
+            Endianness::Big => bytes[4 - width..].to_vec(),
+        })
+    }
+}
+
+/// Byte order forced by `--endianness`; `None` means detect per file.
+static ENDIANNESS_OVERRIDE: OnceLock<Option<Endianness>> = OnceLock::new();
+
+/// Force the byte order used by `read_dbc` for every file instead of
+/// detecting it.  Only the first call has an effect.
+pub fn set_endianness_override(endianness: Option<Endianness>) {
+    let _ = ENDIANNESS_OVERRIDE.set(endianness);
+}
+
+/// Decide the byte order of a file from its header.  The header whose
+/// counts add up to the actual file length wins; when neither does, a
+/// byte‑swapped `CBDW` magic still marks a big‑endian file.
+fn detect_endianness(header_bytes: &[u8; DbcHeader::SIZE], file_len: u64, path: &Path) -> Endianness {
+    if let Some(Some(forced)) = ENDIANNESS_OVERRIDE.get() {
+        return *forced;
+    }
+    let expected_len = |e: Endianness| {
+        let word = |i: usize| {
+            e.read_u32([header_bytes[i], header_bytes[i + 1], header_bytes[i + 2], header_bytes[i + 3]]) as u64
+        };
+        DbcHeader::SIZE as u64 + word(4) * word(12) + word(16)
+    };
+    let little = expected_len(Endianness::Little) == file_len;
+    let big = expected_len(Endianness::Big) == file_len;
+    match (little, big) {
+        (true, false) => Endianness::Little,
+        (false, true) => Endianness::Big,
+        (true, true) if &header_bytes[..4] == b"CBDW" => Endianness::Big,
+        (true, true) => {
+            if expected_len(Endianness::Little) != expected_len(Endianness::Big) {
+                warning!(
+                    "byte order of {:?} is ambiguous, assuming little endian (use --endianness to override)",
+                    path
+                );
+            }
+            Endianness::Little
+        }
+        (false, false) if &header_bytes[..4] == b"CBDW" => Endianness::Big,
+        (false, false) => Endianness::Little,
+    }
 }
 
 /// Read a DBC file from disk.  Returns the parsed header, a vector of
 /// records (each record is a vector of 32‑bit values) and the raw string
-/// block.
+/// block.  The byte order is detected from the header (see
+/// `set_endianness_override`) and recorded in the returned header so
+/// `write_dbc` writes the file back the same way.  Tables that are not
+/// packed 4‑byte fields are laid out by the schemas in `schema`; see
+/// `read_dbc_with_schema` to use another directory.
 pub fn read_dbc<P: AsRef<Path>>(path: P) -> Result<(DbcHeader, Vec<Vec<u32>>, Vec<u8>)> {
-    let mut file = File::open(&path)
+    read_dbc_with_schema(path, Path::new("schema"))
+}
+
+/// Like `read_dbc`, with the table's schema in `schema_dir` laying out
+/// records that are not packed 4‑byte fields and naming the string
+/// columns checked against the string block.
+pub fn read_dbc_with_schema<P: AsRef<Path>>(path: P, schema_dir: &Path) -> Result<(DbcHeader, Vec<Vec<u32>>, Vec<u8>)> {
+    let file = File::open(&path)
         .with_context(|| format!("Failed to open DBC file {:?}", path.as_ref()))?;
+    let file_len = file
+        .metadata()
+        .with_context(|| format!("Failed to stat DBC file {:?}", path.as_ref()))?
+        .len();
+    read_dbc_from(file, file_len, path, schema_dir)
+}
 
+/// Read a DBC table of `file_len` bytes from `file`, e.g. a file read out
+/// of an MPQ archive, as [`read_dbc`] does.  `path` names the table in
+/// messages.
+pub fn read_dbc_from<R: Read, P: AsRef<Path>>(
+    mut file: R,
+    file_len: u64,
+    path: P,
+    schema_dir: &Path,
+) -> Result<(DbcHeader, Vec<Vec<u32>>, Vec<u8>)> {
     // Read header
     let mut header_bytes = [0u8; DbcHeader::SIZE];
     file.read_exact(&mut header_bytes)
         .with_context(|| "Failed to read DBC header")?;
     let magic = [header_bytes[0], header_bytes[1], header_bytes[2], header_bytes[3]];
-    let record_count = u32::from_le_bytes([
+    let endianness = match &magic {
+        b"WDB2" => Endianness::Little,
+        _ => detect_endianness(&header_bytes, file_len, path.as_ref()),
+    };
+    let record_count = endianness.read_u32([
         header_bytes[4], header_bytes[5], header_bytes[6], header_bytes[7],
     ]);
-    let field_count = u32::from_le_bytes([
+    let field_count = endianness.read_u32([
         header_bytes[8], header_bytes[9], header_bytes[10], header_bytes[11],
     ]);
-    let record_size = u32::from_le_bytes([
+    let record_size = endianness.read_u32([
         header_bytes[12], header_bytes[13], header_bytes[14], header_bytes[15],
     ]);
-    let string_block_size = u32::from_le_bytes([
+    let string_block_size = endianness.read_u32([
         header_bytes[16], header_bytes[17], header_bytes[18], header_bytes[19],
     ]);
-    let header = DbcHeader {
+    let mut header = DbcHeader {
         magic,
         record_count,
         field_count,
         record_size,
         string_block_size,
+        format: DbcFormat::Wdbc,
+        endianness,
+        layout: None,
     };
 
-    // Sanity check: record size should equal field_count * 4 for vanilla WDBC
-    if header.record_size != header.field_count * 4 {
-        // We allow it but warn; some DBCs contain floats/arrays but still use
-        // 4 bytes per field.
-        // bail!("Unsupported record size: {} (field_count {})", header.record_size, header.field_count);
+    // WDB5 and later tables and unrelated files would otherwise be read
+    // as garbage
+    let mut header_len = DbcHeader::SIZE as u64;
+    match &magic {
+        b"WDBC" | b"CBDW" => {}
+        b"WDB2" => {
+            let wdb2 = read_wdb2_header(&mut file, file_len, path.as_ref())?;
+            header_len += Wdb2Header::SIZE as u64 + Wdb2Header::index_len(wdb2.min_id, wdb2.max_id);
+            header.format = DbcFormat::Wdb2(wdb2);
+        }
+        _ => bail!(
+            "{:?} is not a DBC file (magic {:?}, expected \"WDBC\" or \"WDB2\")",
+            path.as_ref(),
+            String::from_utf8_lossy(&magic)
+        ),
+    }
+
+    // Refuse headers that describe more data than the file holds instead of
+    // allocating for them; a wrong byte order produces absurd counts.
+    let data_len = header.record_count as u64 * header.record_size as u64
+        + header.string_block_size as u64;
+    if header_len + data_len > file_len {
+        bail!(
+            "Header of {:?} describes {} bytes of data but the file is only {} bytes (truncated, or wrong --endianness?)",
+            path.as_ref(),
+            data_len,
+            file_len
+        );
+    }
+    if header_len + data_len < file_len {
+        warning!(
+            "{:?} has {} byte(s) after its string block, which are ignored",
+            path.as_ref(),
+            file_len - header_len - data_len
+        );
+    }
+    // Empty records of which there are billions pass the length check
+    if header.record_size == 0 && header.record_count > 0 {
+        bail!(
+            "Header of {:?} describes {} records of 0 bytes",
+            path.as_ref(),
+            header.record_count
+        );
+    }
+
+    // Read once, for the layout and the string columns
+    let schema = load_schema(schema_dir, &table_name(path.as_ref()));
+
+    // Records that are not packed 4-byte fields, from byte or short
+    // columns or padding, are read by a layout.  An empty table has no
+    // records to misread, and its record size may be anything.
+    if header.record_size as u64 != header.field_count as u64 * 4 && header.record_count > 0 {
+        let layout = record_layout(path.as_ref(), header.field_count, header.record_size, schema.as_ref())?;
+        debug!(
+            "{:?} has {} byte records for {} fields, read as {} columns",
+            path.as_ref(),
+            header.record_size,
+            header.field_count,
+            layout.columns()
+        );
+        header.field_count = layout.columns() as u32;
+        header.layout = Some(layout);
     }
 
     // Read record data
     let mut records: Vec<Vec<u32>> = Vec::with_capacity(header.record_count as usize);
+    let mut record_bytes = vec![0u8; header.record_size as usize];
     for _ in 0..header.record_count {
-        let mut record_bytes = vec![0u8; header.record_size as usize];
         file.read_exact(&mut record_bytes)
             .with_context(|| "Failed to read record")?;
-        // Split into u32 values
-        let mut values: Vec<u32> = Vec::with_capacity(header.field_count as usize);
-        for i in 0..header.field_count as usize {
-            let start = i * 4;
-            // let end = start + 4;
-            let val = u32::from_le_bytes([
-                record_bytes[start],
-                record_bytes[start + 1],
-                record_bytes[start + 2],
-                record_bytes[start + 3],
-            ]);
-            values.push(val);
-        }
-        records.push(values);
+        records.push(decode_record(&record_bytes, &header));
     }
 
     // Read string block
     let mut string_block = vec![0u8; header.string_block_size as usize];
     file.read_exact(&mut string_block)
         .with_context(|| "Failed to read string block")?;
+    if let Some(schema) = &schema {
+        warn_broken_strings(path.as_ref(), &records, &string_block, schema);
+        if let DbcFormat::Wdb2(wdb2) = &mut header.format {
+            wdb2.string_columns = schema.string_fields().map(|f| f.index).collect();
+        }
+    }
 
     Ok((header, records, string_block))
 }
 
-/// Write a DBC file to disk.  Takes the header for field count/record size,
-/// the records to write and the final string block.  The record count and
-/// string block size are recomputed automatically.
+/// The table name at the end of `path`, which may also name a file in an
+/// archive (`patch.MPQ:DBFilesClient\Spell.dbc`).
+fn table_name(path: &Path) -> String {
+    let shown = path.to_string_lossy();
+    shown.rsplit(['/', '\\', ':']).next().unwrap_or_default().to_string()
+}
+
+/// Warn about the records of the table at `path` whose string columns, by
+/// its `schema`, hold an offset past the end of `string_block` or one whose
+/// text runs off its end without a null byte.  The client shows garbage
+/// or crashes on them, and patches built on them carry them along.
+fn warn_broken_strings(path: &Path, records: &[Vec<u32>], string_block: &[u8], schema: &Schema) {
+    let columns: Vec<(usize, &str)> = schema.string_fields().map(|f| (f.index, f.name.as_str())).collect();
+    let broken = broken_strings(records, string_block, &columns);
+    if broken.is_empty() {
+        return;
+    }
+    let mut listed: Vec<String> = broken
+        .iter()
+        .take(10)
+        .map(|(key, field, offset)| format!("{} {} (offset {})", key, field, offset))
+        .collect();
+    if broken.len() > 10 {
+        listed.push(format!("and {} more", broken.len() - 10));
+    }
+    warning!(
+        "{:?} has string offsets outside its {} byte string block or at text without a null byte, in records {}",
+        path,
+        string_block.len(),
+        listed.join(", ")
+    );
+}
+
+/// The key, field name and offset of each string of `records` in the
+/// `columns` given by index and name that does not lie within
+/// `string_block` with a null byte ending it.
+pub fn broken_strings<'a>(records: &[Vec<u32>], string_block: &[u8], columns: &[(usize, &'a str)]) -> Vec<(u32, &'a str, u32)> {
+    let mut broken = Vec::new();
+    for record in records {
+        for &(col, name) in columns {
+            let Some(&offset) = record.get(col) else {
+                continue;
+            };
+            let terminated = string_block
+                .get(offset as usize..)
+                .is_some_and(|text| !text.is_empty() && text.contains(&0));
+            if !terminated {
+                broken.push((record.first().copied().unwrap_or(0), name, offset));
+            }
+        }
+    }
+    broken
+}
+
+/// Read the extra fields of a `WDB2` header of a `file_len` byte file, and
+/// skip the index after them, from `file`, positioned after the `WDBC`
+/// fields.  Writing recomputes the index from the records.
+fn read_wdb2_header<R: Read>(file: &mut R, file_len: u64, path: &Path) -> Result<Wdb2Header> {
+    let mut bytes = [0u8; Wdb2Header::SIZE];
+    file.read_exact(&mut bytes)
+        .with_context(|| format!("Failed to read WDB2 header of {:?}", path))?;
+    let word = |i: usize| u32::from_le_bytes([bytes[4 * i], bytes[4 * i + 1], bytes[4 * i + 2], bytes[4 * i + 3]]);
+    let mut header = Wdb2Header {
+        table_hash: word(0),
+        build: word(1),
+        timestamp: word(2),
+        min_id: word(3),
+        max_id: word(4),
+        locale: word(5),
+        copy_table_size: word(6),
+        indexed: false,
+        string_columns: Vec::new(),
+    };
+    let index_len = Wdb2Header::index_len(header.min_id, header.max_id);
+    if header.max_id == 0 {
+        return Ok(header);
+    }
+    if header.min_id > header.max_id || (DbcHeader::SIZE + Wdb2Header::SIZE) as u64 + index_len > file_len {
+        bail!(
+            "WDB2 header of {:?} gives keys {} to {}, an index the file does not hold",
+            path,
+            header.min_id,
+            header.max_id
+        );
+    }
+    let mut index = vec![0u8; index_len as usize];
+    file.read_exact(&mut index)
+        .with_context(|| format!("Failed to read WDB2 index of {:?}", path))?;
+    header.indexed = true;
+    Ok(header)
+}
+
+/// Write a DBC file to disk.  Takes the header for field count, byte
+/// order and record layout, the records to write and the final string
+/// block.  The record count, record size and string block size are
+/// recomputed automatically.
 pub fn write_dbc<P: AsRef<Path>>(
     path: P,
     header: &DbcHeader,
     records: &[Vec<u32>],
     string_block: &[u8],
 ) -> Result<()> {
-    let mut file = File::create(&path)
-        .with_context(|| format!("Failed to create output DBC file {:?}", path.as_ref()))?;
     // Recalculate header fields
     let record_count = records.len() as u32;
-    let field_count = header.field_count;
-    let record_size = header.record_size;
+    let columns = header.field_count;
+    let (field_count, record_size) = match &header.layout {
+        Some(layout) => (layout.field_count, layout.record_size),
+        None => (
+            columns,
+            columns
+                .checked_mul(4)
+                .with_context(|| format!("Field count {} is too large", columns))?,
+        ),
+    };
     let string_block_size = string_block.len() as u32;
+    let mut file = File::create(&path)
+        .with_context(|| format!("Failed to create output DBC file {:?}", path.as_ref()))?;
 
     // Write header
     file.write_all(&header.magic)
         .context("Failed to write DBC magic")?;
-    file.write_all(&record_count.to_le_bytes())
+    file.write_all(&header.endianness.write_u32(record_count))
         .context("Failed to write record count")?;
-    file.write_all(&field_count.to_le_bytes())
+    file.write_all(&header.endianness.write_u32(field_count))
         .context("Failed to write field count")?;
-    file.write_all(&record_size.to_le_bytes())
+    file.write_all(&header.endianness.write_u32(record_size))
         .context("Failed to write record size")?;
-    file.write_all(&string_block_size.to_le_bytes())
+    file.write_all(&header.endianness.write_u32(string_block_size))
         .context("Failed to write string block size")?;
+    if let DbcFormat::Wdb2(wdb2) = &header.format {
+        file.write_all(&wdb2.encode(records, string_block)?)
+            .context("Failed to write WDB2 header")?;
+    }
 
     // Write records
-    for record in records {
+    for (row, record) in records.iter().enumerate() {
         // Ensure the record has the correct number of fields
-        if record.len() != field_count as usize {
-            bail!("Record length mismatch: expected {} fields, got {}", field_count, record.len());
-        }
-        for &value in record {
-            file.write_all(&value.to_le_bytes())
-                .context("Failed to write record field")?;
+        if record.len() != columns as usize {
+            bail!("Record length mismatch: expected {} fields, got {}", columns, record.len());
         }
+        file.write_all(&encode_record(record, header, row)?)
+            .context("Failed to write record")?;
     }
 
     // Write string block
@@ -137,9 +717,115 @@ pub fn write_dbc<P: AsRef<Path>>(
     Ok(())
 }
 
+/// Re-read the DBC [`write_dbc`] wrote to `path` from `header`, `records`
+/// and `string_block`, and check it is a file the client will load: the
+/// header agrees with the file length and with what was written, the
+/// records and string block read back unchanged, the block ends in a null
+/// byte, and every offset in `string_columns` lies inside the block.
+/// Returns an error listing the problems found.
+pub fn check_written_dbc<P: AsRef<Path>>(
+    path: P,
+    header: &DbcHeader,
+    records: &[Vec<u32>],
+    string_block: &[u8],
+    string_columns: &[usize],
+) -> Result<()> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to re-read DBC file {:?}", path))?;
+    if bytes.len() < DbcHeader::SIZE {
+        bail!("{:?} is {} bytes, shorter than a DBC header", path, bytes.len());
+    }
+    let word = |at: usize| header.endianness.read_u32([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+    let mut problems = Vec::new();
+    if bytes[..4] != header.magic {
+        problems.push(format!("magic is {:?}", String::from_utf8_lossy(&bytes[..4])));
+    }
+    let (record_count, field_count, record_size, block_size) = (word(4), word(8), word(12), word(16));
+    let mut header_len = DbcHeader::SIZE;
+    if let DbcFormat::Wdb2(wdb2) = &header.format {
+        let extra = wdb2.encode(records, string_block)?;
+        header_len += extra.len();
+        if bytes.get(DbcHeader::SIZE..header_len) != Some(&extra[..]) {
+            problems.push("WDB2 header does not read back as written".to_string());
+        }
+    }
+    if record_count as usize != records.len() {
+        problems.push(format!("header counts {} records, {} were written", record_count, records.len()));
+    }
+    let expected_fields = header.table_fields() as u32;
+    if field_count != expected_fields {
+        problems.push(format!("header counts {} fields, expected {}", field_count, expected_fields));
+    }
+    let expected_size = header.layout.as_ref().map_or(field_count as u64 * 4, |l| l.record_size as u64);
+    if record_size as u64 != expected_size {
+        problems.push(format!("record size {} does not match {} fields", record_size, field_count));
+    }
+    if block_size as usize != string_block.len() {
+        problems.push(format!(
+            "header gives a string block of {} bytes, {} were written",
+            block_size,
+            string_block.len()
+        ));
+    }
+    let expected_len = header_len as u64 + record_count as u64 * record_size as u64 + block_size as u64;
+    if bytes.len() as u64 != expected_len {
+        problems.push(format!("file is {} bytes, its header describes {}", bytes.len(), expected_len));
+    }
+    if !problems.is_empty() {
+        bail!("{:?} failed the self-check: {}", path, problems.join("; "));
+    }
+
+    let block_start = bytes.len() - string_block.len();
+    let read_back: Vec<Vec<u32>> = match record_size as usize {
+        0 => Vec::new(),
+        size => bytes[header_len..block_start]
+            .chunks_exact(size)
+            .map(|record| decode_record(record, header))
+            .collect(),
+    };
+    if read_back != records {
+        problems.push("records do not read back as written".to_string());
+    }
+    if bytes[block_start..] != *string_block {
+        problems.push("string block does not read back as written".to_string());
+    }
+    if string_block.last().is_some_and(|&b| b != 0) {
+        problems.push("string block does not end in a null byte".to_string());
+    }
+    for (row, record) in records.iter().enumerate() {
+        for &col in string_columns {
+            let Some(&offset) = record.get(col) else {
+                continue;
+            };
+            if offset as usize >= string_block.len() {
+                problems.push(format!(
+                    "record {} (key {}) column {} has string offset {} outside the {} byte string block",
+                    row,
+                    record.first().copied().unwrap_or(0),
+                    col,
+                    offset,
+                    string_block.len()
+                ));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        let more = problems.len().saturating_sub(10);
+        problems.truncate(10);
+        if more > 0 {
+            problems.push(format!("and {} more", more));
+        }
+        bail!("{:?} failed the self-check: {}", path, problems.join("; "));
+    }
+    Ok(())
+}
+
 /// Build a mapping of strings to their offsets from an existing string block.
 /// Offsets are 0‑based relative to the start of the block.  The empty string
-/// at offset 0 is always included.
+/// at offset 0 is always included.  A string stored more than once maps to
+/// its first occurrence, so the offset reused for it does not depend on
+/// how many copies later builds appended.  A last string without a
+/// terminator is left out.
 pub fn build_string_map(block: &[u8]) -> HashMap<String, u32> {
     let mut map = HashMap::new();
     // let mut offset = 0u32;
@@ -150,14 +836,478 @@ pub fn build_string_map(block: &[u8]) -> HashMap<String, u32> {
             let end = start + pos;
             let string_bytes = &block[start..end];
             let s = String::from_utf8_lossy(string_bytes).to_string();
-            map.insert(s, start as u32);
+            map.entry(s).or_insert(start as u32);
             // Move past the terminator
             start = end + 1;
         } else {
+            // An unterminated last string is not reused: strings appended
+            // after it would run on from its end
             break;
         }
     }
     // Ensure empty string at offset 0
     map.entry(String::new()).or_insert(0);
     map
-}
\ No newline at end of file
+}
+
+/// Read the null‑terminated string starting at `offset` in a string block.
+/// Returns `None` if the offset lies outside the block.  A missing
+/// terminator yields the remainder of the block.
+pub fn string_at(block: &[u8], offset: u32) -> Option<String> {
+    let start = offset as usize;
+    if start >= block.len() {
+        return None;
+    }
+    let end = block[start..]
+        .iter()
+        .position(|&b| b == 0)
+        .map_or(block.len(), |pos| start + pos);
+    Some(String::from_utf8_lossy(&block[start..end]).to_string())
+}
+
+/// Return the offset of `s` in the string block, allocating it if needed.
+/// New strings are queued in `new_strings` and will be appended after the
+/// existing block of length `block_len`; `string_map` is updated so later
+/// writes of the same string reuse the allocation.
+pub fn intern_string(
+    s: &str,
+    string_map: &mut HashMap<String, u32>,
+    new_strings: &mut Vec<String>,
+    block_len: usize,
+) -> u32 {
+    if let Some(&off) = string_map.get(s) {
+        trace!("string {:?} reuses offset {}", s, off);
+        return off;
+    }
+    let offset =
+        (block_len + new_strings.iter().map(|ss| ss.len() + 1).sum::<usize>()) as u32;
+    string_map.insert(s.to_string(), offset);
+    new_strings.push(s.to_string());
+    trace!("string {:?} allocated at offset {}", s, offset);
+    offset
+}
+
+/// Like `string_at`, but also resolves offsets pointing at strings queued in
+/// `new_strings` that have not yet been appended to the block.
+pub fn pending_string_at(block: &[u8], new_strings: &[String], offset: u32) -> Option<String> {
+    if (offset as usize) < block.len() {
+        return string_at(block, offset);
+    }
+    let mut pos = block.len();
+    for s in new_strings {
+        if pos == offset as usize {
+            return Some(s.clone());
+        }
+        pos += s.len() + 1;
+    }
+    None
+}
+
+/// Rebuild a string block from only the strings `records` reference in
+/// `columns`, each stored once, and point those columns at the new block.
+/// Strings orphaned by updates, and the copies repeated builds appended,
+/// are dropped.  Returns the new block, or `None` without touching the
+/// records if some offset lies outside `block`.
+pub fn compact_string_block(records: &mut [Vec<u32>], block: &[u8], columns: &[usize]) -> Option<Vec<u8>> {
+    let in_range = |offset: u32| offset == 0 || (offset as usize) < block.len();
+    if !records
+        .iter()
+        .all(|record| columns.iter().all(|&col| record.get(col).is_none_or(|&v| in_range(v))))
+    {
+        return None;
+    }
+    let mut compacted = vec![0u8];
+    let mut offsets: HashMap<String, u32> = HashMap::new();
+    offsets.insert(String::new(), 0);
+    for record in records.iter_mut() {
+        for &col in columns {
+            let Some(cell) = record.get_mut(col) else {
+                continue;
+            };
+            let s = string_at(block, *cell).unwrap_or_default();
+            *cell = *offsets.entry(s).or_insert_with_key(|s| {
+                let offset = compacted.len() as u32;
+                compacted.extend_from_slice(s.as_bytes());
+                compacted.push(0);
+                offset
+            });
+        }
+    }
+    Some(compacted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A string block holding `strings` after the leading empty string.
+    fn block(strings: &[&str]) -> Vec<u8> {
+        let mut block = vec![0u8];
+        for s in strings {
+            block.extend_from_slice(s.as_bytes());
+            block.push(0);
+        }
+        block
+    }
+
+    #[test]
+    fn string_map_uses_first_occurrence() {
+        let block = block(&["Fire", "", "Frost", "Fire"]);
+        let map = build_string_map(&block);
+        assert_eq!(map[""], 0);
+        assert_eq!(map["Fire"], 1);
+        assert_eq!(map["Frost"], 7);
+    }
+
+    #[test]
+    fn existing_strings_are_not_reallocated() {
+        let block = block(&["Fire"]);
+        let mut map = build_string_map(&block);
+        let mut new_strings = Vec::new();
+        assert_eq!(intern_string("Fire", &mut map, &mut new_strings, block.len()), 1);
+        assert_eq!(intern_string("", &mut map, &mut new_strings, block.len()), 0);
+        assert!(new_strings.is_empty());
+    }
+
+    #[test]
+    fn repeated_new_strings_share_one_allocation() {
+        let block = block(&["Fire"]);
+        let mut map = build_string_map(&block);
+        let mut new_strings = Vec::new();
+        // Two patch files writing the same new string, with another between
+        let first = intern_string("Zone", &mut map, &mut new_strings, block.len());
+        let other = intern_string("Glow", &mut map, &mut new_strings, block.len());
+        let again = intern_string("Zone", &mut map, &mut new_strings, block.len());
+        assert_eq!(first, block.len() as u32);
+        assert_eq!(other, first + 5);
+        assert_eq!(again, first);
+        assert_eq!(new_strings, ["Zone", "Glow"]);
+        assert_eq!(pending_string_at(&block, &new_strings, other).as_deref(), Some("Glow"));
+    }
+
+    #[test]
+    fn compaction_keeps_only_referenced_strings() {
+        // "Old" was orphaned by an update and "Fire" appended twice
+        let block = block(&["Old", "Fire", "Frost", "Fire"]);
+        let mut records = vec![vec![1, 5, 7], vec![2, 16, 10], vec![3, 10, 0]];
+        let compacted = compact_string_block(&mut records, &block, &[1]).unwrap();
+        assert_eq!(compacted, b"\0Fire\0Frost\0");
+        // Column 2 is not a string column and keeps its value
+        assert_eq!(records, vec![vec![1, 1, 7], vec![2, 1, 10], vec![3, 6, 0]]);
+        // An offset past the block leaves the table as it was
+        let mut broken = vec![vec![1, 99]];
+        assert!(compact_string_block(&mut broken, &block, &[1]).is_none());
+        assert_eq!(broken, vec![vec![1, 99]]);
+    }
+
+    #[test]
+    fn appended_strings_keep_their_offsets() {
+        // Offsets handed out while queued must be where the strings land
+        // once appended, and a map rebuilt from the result must agree
+        let mut block = block(&["Fire"]);
+        let mut map = build_string_map(&block);
+        let mut new_strings = Vec::new();
+        let zone = intern_string("Zone", &mut map, &mut new_strings, block.len());
+        let glow = intern_string("Glow", &mut map, &mut new_strings, block.len());
+        for s in &new_strings {
+            block.extend_from_slice(s.as_bytes());
+            block.push(0);
+        }
+        assert_eq!(string_at(&block, zone).as_deref(), Some("Zone"));
+        assert_eq!(string_at(&block, glow).as_deref(), Some("Glow"));
+        let rebuilt = build_string_map(&block);
+        assert_eq!((rebuilt["Zone"], rebuilt["Glow"]), (zone, glow));
+    }
+
+    /// Deterministic xorshift generator, so a failing case can be replayed
+    /// from the seed in its message.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        fn u32(&mut self) -> u32 {
+            self.next() as u32
+        }
+    }
+
+    /// A temporary file path unique to this test run and `name`.
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wow_dbc_patcher-{}-{}.dbc", std::process::id(), name))
+    }
+
+    /// A random well‑formed table: records whose cells are random or point
+    /// at one of the strings, in either byte order.
+    fn random_table(rng: &mut Rng) -> (DbcHeader, Vec<Vec<u32>>, Vec<u8>) {
+        let mut block = vec![0u8];
+        let mut offsets = vec![0u32];
+        for _ in 0..rng.below(12) {
+            offsets.push(block.len() as u32);
+            for _ in 0..rng.below(20) {
+                block.push(b' ' + rng.below(95) as u8);
+            }
+            block.push(0);
+        }
+        let field_count = 1 + rng.below(24) as u32;
+        let records: Vec<Vec<u32>> = (0..rng.below(40))
+            .map(|_| {
+                (0..field_count)
+                    .map(|_| match rng.below(3) {
+                        0 => offsets[rng.below(offsets.len() as u64) as usize],
+                        1 => rng.below(100) as u32,
+                        _ => rng.u32(),
+                    })
+                    .collect()
+            })
+            .collect();
+        let big = rng.below(2) == 0;
+        let header = DbcHeader {
+            magic: if big { *b"CBDW" } else { *b"WDBC" },
+            record_count: records.len() as u32,
+            field_count,
+            record_size: field_count * 4,
+            string_block_size: block.len() as u32,
+            format: DbcFormat::Wdbc,
+            endianness: if big { Endianness::Big } else { Endianness::Little },
+            layout: None,
+        };
+        (header, records, block)
+    }
+
+    #[test]
+    fn random_tables_round_trip() {
+        let path = scratch("round-trip");
+        for seed in 1..=300u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let (header, records, block) = random_table(&mut rng);
+            write_dbc(&path, &header, &records, &block).unwrap();
+            let (read_header, read_records, read_block) = read_dbc(&path).unwrap();
+            assert_eq!(read_header.endianness, header.endianness, "seed {}", seed);
+            assert_eq!(
+                (read_header.record_count, read_header.field_count, read_header.record_size),
+                (header.record_count, header.field_count, header.record_size),
+                "seed {}",
+                seed
+            );
+            assert_eq!(read_records, records, "seed {}", seed);
+            assert_eq!(read_block, block, "seed {}", seed);
+        }
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn malformed_files_are_rejected_or_read_whole() {
+        let path = scratch("malformed");
+        for seed in 1..=2000u64 {
+            let mut rng = Rng(seed.wrapping_mul(0xD1B5_4A32_D192_ED03));
+            let (header, records, block) = random_table(&mut rng);
+            write_dbc(&path, &header, &records, &block).unwrap();
+            let mut bytes = fs::read(&path).unwrap();
+            // One or more of the ways third‑party extractors damage files
+            for _ in 0..1 + rng.below(3) {
+                match rng.below(5) {
+                    0 => bytes.truncate(rng.below(bytes.len() as u64 + 1) as usize),
+                    1 => bytes.extend((0..rng.below(64)).map(|_| rng.u32() as u8)),
+                    2 if !bytes.is_empty() => {
+                        let at = rng.below(bytes.len() as u64) as usize;
+                        bytes[at] = rng.u32() as u8;
+                    }
+                    3 if bytes.len() >= DbcHeader::SIZE => {
+                        // Header counts set to extreme or random values
+                        for at in [4, 8, 12, 16] {
+                            if rng.below(2) == 0 {
+                                let value = [0, rng.below(64) as u32, u32::MAX, u32::MAX / 4, rng.u32()][rng.below(5) as usize];
+                                bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+                            }
+                        }
+                    }
+                    _ => bytes = (0..rng.below(64)).map(|_| rng.u32() as u8).collect(),
+                }
+            }
+            fs::write(&path, &bytes).unwrap();
+            if let Ok((header, records, block)) = read_dbc(&path) {
+                assert_eq!(records.len(), header.record_count as usize, "seed {}", seed);
+                assert!(records.iter().all(|r| r.len() == header.field_count as usize), "seed {}", seed);
+                assert_eq!(block.len(), header.string_block_size as usize, "seed {}", seed);
+                let described = DbcHeader::SIZE as u64
+                    + header.record_count as u64 * header.record_size as u64
+                    + header.string_block_size as u64;
+                assert!(described <= bytes.len() as u64, "seed {}", seed);
+            }
+        }
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn odd_record_layouts_round_trip() {
+        let path = scratch("layout");
+        let block = block(&[]);
+        let slot = |offset, width, signed| Slot { offset, width, signed };
+        // An int, a signed short and a byte, then five bytes of padding
+        let layout = RecordLayout::new(&[slot(0, 4, false), slot(4, 2, true), slot(6, 1, false)], 12).unwrap();
+        assert_eq!(layout.columns(), 5);
+        let header = DbcHeader {
+            magic: *b"WDBC",
+            record_count: 1,
+            field_count: 5,
+            record_size: 12,
+            string_block_size: 1,
+            format: DbcFormat::Wdbc,
+            endianness: Endianness::Little,
+            layout: Some(layout),
+        };
+        let records = vec![vec![7, -2i32 as u32, 200, 0xAABB_CCDD, 0x11]];
+        write_dbc(&path, &header, &records, &block).unwrap();
+        assert_eq!(fs::read(&path).unwrap()[20..32], [7, 0, 0, 0, 0xFE, 0xFF, 200, 0xDD, 0xCC, 0xBB, 0xAA, 0x11]);
+        check_written_dbc(&path, &header, &records, &block, &[]).unwrap();
+
+        // Padding after packed fields is read as extra columns and kept
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[8..12].copy_from_slice(&2u32.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let (read_header, read_records, read_block) = read_dbc(&path).unwrap();
+        assert_eq!((read_header.field_count, read_header.table_fields()), (3, 2));
+        assert_eq!(read_records, vec![vec![7, 0xDDC8_FFFE, 0x11AA_BBCC]]);
+        write_dbc(&path, &read_header, &read_records, &read_block).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        // A value too large for its byte column
+        assert!(write_dbc(&path, &header, &[vec![7, 0, 256, 0, 0]], &block).is_err());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn self_check_catches_bad_offsets_and_truncation() {
+        let path = scratch("self-check");
+        let block = block(&["Fire"]);
+        let header = DbcHeader {
+            magic: *b"WDBC",
+            record_count: 2,
+            field_count: 2,
+            record_size: 8,
+            string_block_size: block.len() as u32,
+            format: DbcFormat::Wdbc,
+            endianness: Endianness::Little,
+            layout: None,
+        };
+        let records = vec![vec![1, 1], vec![2, 0]];
+        write_dbc(&path, &header, &records, &block).unwrap();
+        check_written_dbc(&path, &header, &records, &block, &[1]).unwrap();
+
+        let broken = vec![vec![1, 1], vec![2, 40]];
+        write_dbc(&path, &header, &broken, &block).unwrap();
+        let err = check_written_dbc(&path, &header, &broken, &block, &[1]).unwrap_err();
+        assert!(err.to_string().contains("record 1 (key 2) column 1"), "{}", err);
+
+        write_dbc(&path, &header, &records, &block).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(check_written_dbc(&path, &header, &records, &block, &[1]).is_err());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn broken_string_offsets_are_found() {
+        let mut block = block(&["Fire"]);
+        let records = vec![vec![1, 1], vec![2, 0], vec![3, 40]];
+        assert_eq!(broken_strings(&records, &block, &[(1, "Name")]), [(3, "Name", 40)]);
+        // Text that runs off the end of the block
+        block.pop();
+        assert_eq!(broken_strings(&records, &block, &[(1, "Name")]), [(1, "Name", 1), (3, "Name", 40)]);
+    }
+
+    #[test]
+    fn wdb2_header_and_index_round_trip() {
+        let path = scratch("wdb2");
+        // A schema naming the string column, beside the table
+        let schema_dir = std::env::temp_dir();
+        let schema_path = schema_dir.join(format!("{}.yaml", path.file_name().unwrap().to_string_lossy()));
+        fs::write(&schema_path, "fields:\n  - ID\n  - { name: Name, type: string }\n").unwrap();
+        let block = block(&["Fire"]);
+        let header = DbcHeader {
+            magic: *b"WDB2",
+            record_count: 2,
+            field_count: 2,
+            record_size: 8,
+            string_block_size: block.len() as u32,
+            format: DbcFormat::Wdb2(Wdb2Header {
+                table_hash: 0xDEAD_BEEF,
+                build: 15595,
+                locale: 1,
+                indexed: true,
+                string_columns: vec![1],
+                ..Default::default()
+            }),
+            endianness: Endianness::Little,
+            layout: None,
+        };
+        let records = vec![vec![5, 1], vec![3, 0]];
+        write_dbc(&path, &header, &records, &block).unwrap();
+        check_written_dbc(&path, &header, &records, &block, &[1]).unwrap();
+
+        let (read_header, read_records, read_block) = read_dbc_with_schema(&path, &schema_dir).unwrap();
+        assert_eq!((read_records, read_block), (records.clone(), block.clone()));
+        let DbcFormat::Wdb2(wdb2) = &read_header.format else {
+            panic!("read as {:?}", read_header.format);
+        };
+        assert_eq!((wdb2.table_hash, wdb2.build, wdb2.locale), (0xDEAD_BEEF, 15595, 1));
+        assert_eq!(wdb2.string_columns, [1]);
+        // The key range and index follow the records
+        assert_eq!((wdb2.min_id, wdb2.max_id), (3, 5));
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes[48..60], [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bytes[60..66], [0, 0, 0, 0, 4, 0]);
+
+        // A new record with a new string widens the range, and its
+        // length is counted from the final string block
+        let mut grown_block = block.clone();
+        grown_block.extend_from_slice(b"Frostbolt\0");
+        let grown = vec![vec![5, 1], vec![3, 0], vec![9, block.len() as u32]];
+        write_dbc(&path, &read_header, &grown, &grown_block).unwrap();
+        check_written_dbc(&path, &read_header, &grown, &grown_block, &[1]).unwrap();
+        let (read_header, read_records, _) = read_dbc_with_schema(&path, &schema_dir).unwrap();
+        assert_eq!(read_records, grown);
+        assert!(matches!(read_header.format, DbcFormat::Wdb2(Wdb2Header { min_id: 3, max_id: 9, .. })));
+        let bytes = fs::read(&path).unwrap();
+        // Rows of keys 3 to 9, then their string lengths
+        assert_eq!(bytes[76..90], [0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 9, 0]);
+        fs::remove_file(&path).ok();
+        fs::remove_file(&schema_path).ok();
+    }
+
+    #[test]
+    fn random_string_blocks_map_consistently() {
+        for seed in 1..=500u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x2545_F491_4F6C_DD1D));
+            // Mostly text with stray terminators and bytes that are not UTF‑8
+            let block: Vec<u8> = (0..rng.below(80))
+                .map(|_| match rng.below(8) {
+                    0 => 0,
+                    1 => 0x80 + rng.below(0x80) as u8,
+                    _ => b'a' + rng.below(26) as u8,
+                })
+                .collect();
+            // Offsets handed out for reuse must still read the same once
+            // new strings are appended
+            let mut grown = block.clone();
+            grown.extend_from_slice(b"New\0");
+            for (s, &offset) in &build_string_map(&block) {
+                if s.is_empty() {
+                    continue;
+                }
+                assert_eq!(string_at(&block, offset).as_deref(), Some(s.as_str()), "seed {}", seed);
+                assert_eq!(string_at(&grown, offset).as_deref(), Some(s.as_str()), "seed {}", seed);
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+/// A file to be stored in the archive under `archive_name`.
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub archive_name: String,
+}
+
+/// Group archive entries whose contents are byte‑identical.  Payloads are
+/// bucketed by size and content hash, and each bucket is confirmed by
+/// comparing bytes so a hash collision never reports a false duplicate.
+/// `payloads` holds the contents of `entries`, index for index.  Returns
+/// groups of indices into `entries`, each with two or more members.
+pub fn find_duplicates(entries: &[ArchiveEntry], payloads: &[Vec<u8>]) -> Vec<Vec<usize>> {
+    let mut buckets: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    for (i, data) in payloads.iter().enumerate() {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        buckets
+            .entry((data.len() as u64, hasher.finish()))
+            .or_default()
+            .push(i);
+    }
+    let mut groups = Vec::new();
+    for (_, indices) in buckets {
+        if indices.len() < 2 {
+            continue;
+        }
+        // Split the bucket into runs of truly identical content
+        let mut remaining = indices;
+        while let Some(first) = remaining.first().copied() {
+            let (same, rest): (Vec<usize>, Vec<usize>) = remaining
+                .into_iter()
+                .partition(|&i| payloads[i] == payloads[first]);
+            if same.len() > 1 {
+                groups.push(same);
+            }
+            remaining = rest;
+        }
+    }
+    for group in &mut groups {
+        group.sort_by(|&a, &b| entries[a].archive_name.cmp(&entries[b].archive_name));
+    }
+    groups.sort_by(|a, b| entries[a[0]].archive_name.cmp(&entries[b[0]].archive_name));
+    groups
+}
+
+/// Print each group of identical files and the bytes the extra copies add
+/// to the archive.  Returns the total number of wasted bytes.
+pub fn report_duplicates(entries: &[ArchiveEntry], payloads: &[Vec<u8>], groups: &[Vec<usize>]) -> u64 {
+    let mut wasted = 0u64;
+    for group in groups {
+        let size = payloads[group[0]].len() as u64;
+        let extra = size * (group.len() as u64 - 1);
+        wasted += extra;
+        let files: Vec<String> = group
+            .iter()
+            .map(|&i| format!("{} ({})", entries[i].archive_name, entries[i].path.display()))
+            .collect();
+        warning!(
+            "{} files have identical content ({} bytes each, {} bytes wasted): {}",
+            group.len(),
+            size,
+            extra,
+            files.join(", ")
+        );
+    }
+    if wasted > 0 {
+        say!(
+            "Duplicate content adds {} bytes to the archive; consider pointing the duplicates at one file",
+            wasted
+        );
+    }
+    wasted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_payloads_are_grouped_by_archive_name() {
+        let entries: Vec<ArchiveEntry> = ["b.blp", "a.blp", "c.blp", "e.blp", "d.blp"]
+            .iter()
+            .map(|name| ArchiveEntry {
+                path: PathBuf::from(name),
+                archive_name: name.to_string(),
+            })
+            .collect();
+        // "sane" shares a size with "same" but not its bytes
+        let payloads: Vec<Vec<u8>> = ["same", "same", "sane", "other", "other"]
+            .iter()
+            .map(|data| data.as_bytes().to_vec())
+            .collect();
+        assert_eq!(find_duplicates(&entries, &payloads), vec![vec![1, 0], vec![4, 3]]);
+    }
+}
@@ -0,0 +1,358 @@
+use anyhow::Result;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+/// Warnings printed so far in this run.
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Whether `--strict` turns the warnings of a run into a failure.
+static STRICT: OnceLock<bool> = OnceLock::new();
+
+/// Whether `--trace` logs each step of applying patch entries.
+static TRACE: OnceLock<bool> = OnceLock::new();
+
+/// How much `--quiet` and `--verbose` let through.
+static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
+
+/// Whether `--log-json` writes diagnostics as JSON objects.
+static JSON: OnceLock<bool> = OnceLock::new();
+
+thread_local! {
+    /// Output held back on this thread while `capture` runs.
+    static CAPTURED: RefCell<Option<Captured>> = const { RefCell::new(None) };
+    /// Where in the patches the work on this thread is, for diagnostics.
+    static LOCATION: RefCell<Location> = RefCell::new(Location::default());
+}
+
+/// Print a line of progress output, or hold it back while `capture` runs
+/// on this thread.  Takes the same arguments as `println!`.
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::diagnostics::say(format!($($arg)*))
+    };
+}
+
+/// Print `Warning: <message>` and remember it for `--strict`.  Takes the
+/// same arguments as `println!`.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::diagnostics::warn(format!($($arg)*))
+    };
+}
+
+/// Print `Debug: <message>` when `--verbose` is on.  Takes the same
+/// arguments as `println!`, which are not evaluated otherwise.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::diagnostics::verbose() {
+            $crate::diagnostics::log($crate::diagnostics::Level::Debug, format!($($arg)*));
+        }
+    };
+}
+
+/// Print `Trace: <message>` when `--trace` is on.  Takes the same
+/// arguments as `println!`, which are not evaluated otherwise.
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::diagnostics::tracing() {
+            $crate::diagnostics::log($crate::diagnostics::Level::Trace, format!($($arg)*));
+        }
+    };
+}
+
+/// How much output a run prints besides its results and warnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only warnings and errors
+    Quiet,
+    Normal,
+    /// Also `debug!` messages
+    Verbose,
+}
+
+/// Kind of a diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Progress output from `say!`
+    Info,
+    Warn,
+    Debug,
+    Trace,
+    Error,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+            Level::Error => "error",
+        }
+    }
+
+    /// What a line of this level starts with in plain output.
+    fn prefix(self) -> &'static str {
+        match self {
+            Level::Info => "",
+            Level::Warn => "Warning: ",
+            Level::Debug => "Debug: ",
+            Level::Trace => "Trace: ",
+            Level::Error => "Error: ",
+        }
+    }
+}
+
+/// Where a diagnostic arose: the table and patch file being applied and,
+/// within them, the line of the entry, its record and field, as far as
+/// they are known.
+#[derive(Debug, Clone, Default)]
+pub struct Location {
+    pub table: Option<String>,
+    pub patch_file: Option<String>,
+    pub line: Option<usize>,
+    pub key: Option<u32>,
+    pub field: Option<String>,
+}
+
+/// Report diagnostics of this thread as arising at `location` until it
+/// is set again or cleared.
+pub fn set_location(location: Location) {
+    LOCATION.set(location);
+}
+
+/// Narrow the location of this thread's diagnostics to `field`, or widen
+/// it back to the whole record.
+pub fn set_field(field: Option<&str>) {
+    LOCATION.with_borrow_mut(|location| location.field = field.map(str::to_string));
+}
+
+/// Forget the location set by `set_location`.
+pub fn clear_location() {
+    LOCATION.take();
+}
+
+/// One line of output, to standard output or, for JSON diagnostics,
+/// standard error.
+#[derive(Debug)]
+struct Line {
+    text: String,
+    to_stderr: bool,
+}
+
+impl Line {
+    fn print(&self) {
+        if self.to_stderr {
+            eprintln!("{}", self.text);
+        } else {
+            println!("{}", self.text);
+        }
+    }
+}
+
+/// `message` of `level` as a line: with its prefix, or with `--log-json`
+/// as a JSON object that also gives the location of this thread.
+fn format_line(level: Level, message: &str) -> Line {
+    if !JSON.get().copied().unwrap_or(false) {
+        return Line {
+            text: format!("{}{}", level.prefix(), message),
+            to_stderr: false,
+        };
+    }
+    let location = LOCATION.with_borrow(Location::clone);
+    let mut object = serde_json::Map::new();
+    object.insert("level".into(), level.name().into());
+    object.insert("message".into(), message.into());
+    if let Some(table) = location.table {
+        object.insert("table".into(), table.into());
+    }
+    if let Some(patch_file) = location.patch_file {
+        object.insert("patch_file".into(), patch_file.into());
+    }
+    if let Some(line) = location.line {
+        object.insert("line".into(), line.into());
+    }
+    if let Some(key) = location.key {
+        object.insert("key".into(), key.into());
+    }
+    if let Some(field) = location.field {
+        object.insert("field".into(), field.into());
+    }
+    Line {
+        text: serde_json::Value::Object(object).to_string(),
+        to_stderr: true,
+    }
+}
+
+/// Print a line, or hold it back while `capture` runs on this thread.
+fn emit(line: Line) {
+    CAPTURED.with_borrow_mut(|captured| match captured {
+        Some(captured) => captured.lines.push(line),
+        None => line.print(),
+    });
+}
+
+/// Print a diagnostic of `level`; see `debug!` and `trace!`.  Callers
+/// check first whether the level is shown.
+pub fn log(level: Level, message: String) {
+    emit(format_line(level, &message));
+}
+
+/// Print and record one warning; see `warning!`.
+pub fn warn(message: String) {
+    let line = format_line(Level::Warn, &message);
+    let held = CAPTURED.with_borrow_mut(|captured| match captured {
+        Some(captured) => {
+            captured.lines.push(line);
+            captured.warnings.push(message.clone());
+            None
+        }
+        None => Some(line),
+    });
+    if let Some(line) = held {
+        line.print();
+        WARNINGS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(message);
+    }
+}
+
+/// Print one line of output unless `--quiet` is on; see `say!`.
+pub fn say(line: String) {
+    if VERBOSITY.get().is_some_and(|&v| v == Verbosity::Quiet) {
+        return;
+    }
+    emit(format_line(Level::Info, &line));
+}
+
+/// Output and warnings held back by `capture`.
+#[derive(Debug, Default)]
+pub struct Captured {
+    lines: Vec<Line>,
+    warnings: Vec<String>,
+}
+
+impl Captured {
+    /// The warnings held back, without printing or recording them.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Print the held‑back output and record its warnings, as if it had
+    /// been printed when it was produced.
+    pub fn replay(self) {
+        for line in &self.lines {
+            line.print();
+        }
+        WARNINGS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .extend(self.warnings);
+    }
+}
+
+/// Run `f` with what `say!`, `warning!` and `trace!` print on this thread
+/// held back, so work done on parallel workers can be reported one piece
+/// after another, in a fixed order, with `Captured::replay`.
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Captured) {
+    CAPTURED.set(Some(Captured::default()));
+    let result = f();
+    (result, CAPTURED.take().unwrap_or_default())
+}
+
+/// Make `check_strict` fail on warnings.  Only the first call has an
+/// effect.
+pub fn set_strict(strict: bool) {
+    let _ = STRICT.set(strict);
+}
+
+/// Turn on `trace!` output.  Only the first call has an effect.
+pub fn set_trace(trace: bool) {
+    let _ = TRACE.set(trace);
+}
+
+/// Whether `trace!` prints anything.
+pub fn tracing() -> bool {
+    TRACE.get().copied().unwrap_or(false)
+}
+
+/// Set what `--quiet` or `--verbose` asked for.  Only the first call has
+/// an effect.
+pub fn set_verbosity(verbosity: Verbosity) {
+    let _ = VERBOSITY.set(verbosity);
+}
+
+/// Whether `debug!` prints anything.
+pub fn verbose() -> bool {
+    VERBOSITY.get().is_some_and(|&v| v == Verbosity::Verbose)
+}
+
+/// Write diagnostics as one JSON object per line on standard error, with
+/// `level`, `message` and what is known of `table`, `patch_file`, `line`,
+/// `key` and `field`.  Only the first call has an effect.
+pub fn set_log_json(json: bool) {
+    let _ = JSON.set(json);
+}
+
+/// The failure of a run that `--strict` stops because of its warnings,
+/// told apart from other errors by its exit code.
+#[derive(Debug)]
+pub struct StrictFailure {
+    pub warnings: usize,
+}
+
+impl fmt::Display for StrictFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} warning(s) in strict mode", self.warnings)
+    }
+}
+
+impl std::error::Error for StrictFailure {}
+
+/// Exit code for a run that failed with `err`: 2 when `--strict` stopped
+/// it because of warnings, 1 for any other error.
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    if err.downcast_ref::<StrictFailure>().is_some() {
+        2
+    } else {
+        1
+    }
+}
+
+/// Print the error a run failed with, with its causes, as `main` would,
+/// or as a JSON diagnostic with `--log-json`.
+pub fn report_error(err: &anyhow::Error) {
+    if JSON.get().copied().unwrap_or(false) {
+        format_line(Level::Error, &format!("{:#}", err)).print();
+    } else {
+        eprintln!("Error: {:?}", err);
+    }
+}
+
+/// With `--strict`, fail if anything was warned about so far, after
+/// listing the warnings again so they are not lost in the output.
+pub fn check_strict() -> Result<()> {
+    if !STRICT.get().copied().unwrap_or(false) {
+        return Ok(());
+    }
+    let warnings = WARNINGS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if warnings.is_empty() {
+        return Ok(());
+    }
+    println!();
+    println!("{} warning(s):", warnings.len());
+    for message in warnings.iter() {
+        println!("  {}", message);
+    }
+    Err(StrictFailure {
+        warnings: warnings.len(),
+    }
+    .into())
+}
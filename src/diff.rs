@@ -0,0 +1,592 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::apply::{
+    exclude_experimental, load_patches_with_ids, patch_paths_or_dir, patch_tables,
+};
+use crate::ids::IdMap;
+use crate::dbc::{dbc_file_name, find_dbc_file, string_at};
+use crate::l10n::Translations;
+use crate::schema::{load_schema, load_schema_file, FieldDef, FieldKind, Schema};
+use crate::source::{DbcSource, DbcTable, FileSource};
+use crate::units::Transformers;
+
+/// Write the files under `patch_dir` as of git revision `rev` into `dest`,
+/// keeping their paths relative to the working directory, and return where
+/// the patch directory ended up.  Only the patch tree is taken from the
+/// revision; DBCs, schemas and includes are the working copy's.
+pub fn checkout_patch_dir(rev: &str, patch_dir: &Path, dest: &Path) -> Result<PathBuf> {
+    let git = |args: &[&str]| -> Result<Vec<u8>> {
+        let output = Command::new("git")
+            .args(args)
+            .output()
+            .context("Failed to run git")?;
+        if !output.status.success() {
+            bail!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    };
+    let dir = patch_dir.to_string_lossy();
+    let listing = git(&["ls-tree", "-r", "--name-only", rev, "--", &dir])?;
+    let listing = String::from_utf8_lossy(&listing);
+    if listing.trim().is_empty() {
+        bail!("Revision {} has no files under {:?}", rev, patch_dir);
+    }
+    for file in listing.lines() {
+        let data = git(&["show", &format!("{}:./{}", rev, file)])?;
+        let path = dest.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, data).with_context(|| format!("Failed to write {:?}", path))?;
+    }
+    Ok(dest.join(patch_dir))
+}
+
+/// A column value as a reader would want to see it: strings resolved and
+/// floats decoded for fields the schema declares so, numbers otherwise,
+/// followed by their names when the schema names them.
+pub fn render_value(field: Option<&FieldDef>, value: u32, block: &[u8]) -> String {
+    match field {
+        Some(f) if f.kind == FieldKind::String => format!("{:?}", string_at(block, value).unwrap_or_default()),
+        Some(f) if f.kind == FieldKind::Float => format!("{:?}", f32::from_bits(value)),
+        Some(f) if f.kind == FieldKind::Int32 => (value as i32).to_string(),
+        Some(f) => match f.constants.as_ref().and_then(|c| c.describe(value)) {
+            Some(named) => format!("{} ({})", value, named),
+            None => value.to_string(),
+        },
+        None => value.to_string(),
+    }
+}
+
+/// Name of a column from the schema, or `[N]`.
+pub fn column_name(schema: Option<&Schema>, col: usize) -> (String, Option<&FieldDef>) {
+    let field = schema.and_then(|s| s.fields.iter().find(|f| f.index == col));
+    (field.map_or_else(|| format!("[{}]", col), |f| f.name.clone()), field)
+}
+
+/// The first non‑empty string field of a record, to tell readers which
+/// spell or effect a key is.
+pub fn record_label(schema: Option<&Schema>, record: &[u32], block: &[u8]) -> String {
+    schema
+        .into_iter()
+        .flat_map(|s| s.string_fields())
+        .filter_map(|f| record.get(f.index).and_then(|&v| string_at(block, v)))
+        .find(|s| !s.is_empty())
+        .map(|s| format!(" {:?}", s))
+        .unwrap_or_default()
+}
+
+/// Print how the records of one table differ between two builds, by key
+/// (column 0) and field, comparing strings by text so a reordered string
+/// block is not reported.  Returns whether anything differs.
+pub fn report_table_diff(name: &str, old: &DbcTable, new: &DbcTable, schema: Option<&Schema>) -> bool {
+    let index = |table: &DbcTable| -> BTreeMap<u32, usize> {
+        let mut keys = BTreeMap::new();
+        for (i, record) in table.records.iter().enumerate() {
+            if let Some(&key) = record.first() {
+                keys.entry(key).or_insert(i);
+            }
+        }
+        keys
+    };
+    let (old_keys, new_keys) = (index(old), index(new));
+
+    let mut lines = Vec::new();
+    let (mut changed, mut added, mut removed) = (0usize, 0usize, 0usize);
+    for (&key, &i) in &new_keys {
+        let record = &new.records[i];
+        let Some(&j) = old_keys.get(&key) else {
+            added += 1;
+            lines.push(format!("  + {}{}", key, record_label(schema, record, &new.string_block)));
+            for (col, &value) in record.iter().enumerate().skip(1) {
+                if value != 0 {
+                    let (field_name, field) = column_name(schema, col);
+                    lines.push(format!("      {}: {}", field_name, render_value(field, value, &new.string_block)));
+                }
+            }
+            continue;
+        };
+        let before = &old.records[j];
+        let mut fields = Vec::new();
+        for col in 1..record.len().max(before.len()) {
+            let (field_name, field) = column_name(schema, col);
+            let was = before.get(col).map(|&v| render_value(field, v, &old.string_block));
+            let now = record.get(col).map(|&v| render_value(field, v, &new.string_block));
+            if was != now {
+                fields.push(format!(
+                    "      {}: {} -> {}",
+                    field_name,
+                    was.unwrap_or_else(|| "-".to_string()),
+                    now.unwrap_or_else(|| "-".to_string())
+                ));
+            }
+        }
+        if !fields.is_empty() {
+            changed += 1;
+            lines.push(format!("  ~ {}{}", key, record_label(schema, record, &new.string_block)));
+            lines.extend(fields);
+        }
+    }
+    for (&key, &j) in &old_keys {
+        if !new_keys.contains_key(&key) {
+            removed += 1;
+            lines.push(format!("  - {}{}", key, record_label(schema, &old.records[j], &old.string_block)));
+        }
+    }
+
+    if changed + added + removed == 0 {
+        return false;
+    }
+    println!(
+        "{}: {} changed, {} added, {} removed",
+        name, changed, added, removed
+    );
+    for line in lines {
+        println!("{}", line);
+    }
+    true
+}
+
+/// Quote `s` as a double‑quoted YAML scalar, escaping what YAML requires.
+fn yaml_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A cell as a patch value for `field`: strings resolved, floats, bools and
+/// signed integers written as such, named values and flags by their names,
+/// everything else as the raw number so it round‑trips.
+fn patch_value(field: Option<&FieldDef>, value: u32, block: &[u8]) -> String {
+    if let Some(constants) = field.and_then(|f| f.constants.as_ref()) {
+        if constants.flags {
+            let (names, rest) = constants.flag_names(value);
+            if !names.is_empty() {
+                let mut items: Vec<String> = names.into_iter().map(yaml_quote).collect();
+                if rest != 0 {
+                    items.push(rest.to_string());
+                }
+                return format!("[{}]", items.join(", "));
+            }
+        } else if let Some(name) = constants.describe(value) {
+            return yaml_quote(&name);
+        }
+    }
+    match field.map(|f| f.kind) {
+        Some(FieldKind::String) => yaml_quote(&string_at(block, value).unwrap_or_default()),
+        Some(FieldKind::Float) => format!("{:?}", f32::from_bits(value)),
+        Some(FieldKind::Bool) if value <= 1 => (value == 1).to_string(),
+        Some(FieldKind::Int32) => (value as i32).to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// The key of a `values` mapping for column `col`: the schema name, or the
+/// column number.
+fn value_key(schema: Option<&Schema>, col: usize) -> String {
+    schema
+        .and_then(|s| s.field_at(col))
+        .map_or_else(|| format!("'{}'", col), |f| f.name.clone())
+}
+
+/// Compare two versions of a table by `key_col` and write the patch that
+/// turns the first into the second: `update` entries with the changed
+/// fields, `insert` entries with every non‑zero field of new records and
+/// `delete` entries for records that are gone.  Records are matched by
+/// their first row with a given key.  Returns the YAML section and the
+/// number of updates, inserts and deletes.
+fn patch_from_tables(
+    table_name: &str,
+    original: &DbcTable,
+    modified: &DbcTable,
+    schema: Option<&Schema>,
+    key_col: usize,
+) -> (String, [usize; 3]) {
+    let index = |table: &DbcTable| -> BTreeMap<u32, usize> {
+        let mut keys = BTreeMap::new();
+        for (i, record) in table.records.iter().enumerate() {
+            if let Some(&key) = record.get(key_col) {
+                keys.entry(key).or_insert(i);
+            }
+        }
+        keys
+    };
+    let (old_keys, new_keys) = (index(original), index(modified));
+    let key_column = match key_col {
+        0 => String::new(),
+        col => format!("    key_column: {}\n", value_key(schema, col)),
+    };
+    let field = |col: usize| schema.and_then(|s| s.field_at(col));
+
+    let mut yaml = format!("{}:\n", table_name);
+    let mut counts = [0usize; 3];
+    for (&key, &i) in &new_keys {
+        let record = &modified.records[i];
+        let (kind, changed): (&str, Vec<usize>) = match old_keys.get(&key) {
+            Some(&j) => {
+                let before = &original.records[j];
+                let changed = (0..record.len())
+                    .filter(|&col| col != key_col)
+                    .filter(|&col| {
+                        let was = before.get(col).map(|&v| patch_value(field(col), v, &original.string_block));
+                        Some(patch_value(field(col), record[col], &modified.string_block)) != was
+                    })
+                    .collect();
+                ("update", changed)
+            }
+            None => {
+                // A zero is only left out when the insert starts from it
+                let declared = |col: usize| {
+                    schema.zip(field(col)).is_some_and(|(s, f)| s.defaults().contains_key(&f.name))
+                };
+                let set = (0..record.len()).filter(|&col| col != key_col && (record[col] != 0 || declared(col)));
+                ("insert", set.collect())
+            }
+        };
+        if kind == "update" && changed.is_empty() {
+            continue;
+        }
+        counts[if kind == "update" { 0 } else { 1 }] += 1;
+        yaml.push_str(&format!("  - type: {}\n    key: {}\n{}", kind, key, key_column));
+        if changed.is_empty() {
+            yaml.push_str("    values: {}\n");
+            continue;
+        }
+        yaml.push_str("    values:\n");
+        for col in changed {
+            yaml.push_str(&format!(
+                "      {}: {}\n",
+                value_key(schema, col),
+                patch_value(field(col), record[col], &modified.string_block)
+            ));
+        }
+    }
+    for &key in old_keys.keys() {
+        if !new_keys.contains_key(&key) {
+            counts[2] += 1;
+            yaml.push_str(&format!("  - type: delete\n    key: {}\n{}", key, key_column));
+        }
+    }
+    if counts == [0; 3] {
+        yaml = format!("{}: []\n", table_name);
+    }
+    (yaml, counts)
+}
+
+/// Read the tables at `dbc_paths` as they are before `apply` writes
+/// anything, for [`write_revert_patch`].
+pub fn read_originals(dbc_paths: &[PathBuf], schema_dir: &Path) -> Result<Vec<(String, DbcTable)>> {
+    let source = FileSource::new(schema_dir);
+    dbc_paths
+        .iter()
+        .map(|path| Ok((dbc_file_name(path)?, source.read_table(path)?)))
+        .collect()
+}
+
+/// Write to `out` the patch that undoes an `apply`: for each of the
+/// `originals`, compare the table it wrote in `out_dir` with it (see
+/// `patch_from_tables`), so fields it changed are set back, records it
+/// inserted deleted and records it deleted inserted again.  Applied to
+/// that output, the patch restores the tables as they were read.
+pub fn write_revert_patch(originals: &[(String, DbcTable)], out_dir: &Path, schema_dir: &Path, out: &Path) -> Result<()> {
+    let mut yaml = format!(
+        "# Generated by `apply --emit-revert`; apply it to the DBCs written to {}\n\
+         # to restore them as they were before\n",
+        out_dir.display()
+    );
+    let mut totals = [0usize; 3];
+    for (table_name, original) in originals {
+        let written = FileSource::new(schema_dir).read_table(&out_dir.join(table_name))?;
+        let schema = load_schema(schema_dir, table_name);
+        let (section, counts) = patch_from_tables(table_name, &written, original, schema.as_ref(), 0);
+        if counts == [0; 3] {
+            continue;
+        }
+        yaml.push_str(&section);
+        for (total, count) in totals.iter_mut().zip(counts) {
+            *total += count;
+        }
+    }
+    fs::write(out, yaml).with_context(|| format!("Failed to write {:?}", out))?;
+    println!(
+        "Wrote revert patch {} ({} update(s), {} insert(s), {} delete(s))",
+        out.display(),
+        totals[0],
+        totals[1],
+        totals[2]
+    );
+    Ok(())
+}
+
+/// Capture the edits made to a table in an external editor as a patch:
+/// compare `original` and `modified` (see `patch_from_tables`) and write
+/// the result to `out`, or print it.  `schema` defaults to the table's
+/// built‑in schema.  Without one, string columns can only be written as
+/// raw offsets, which do not carry over to other builds of the table, and
+/// a warning says so.
+pub fn dbc_diff_command(
+    original: &Path,
+    modified: &Path,
+    schema: Option<&Path>,
+    key_column: Option<&str>,
+    out: Option<&Path>,
+) -> Result<()> {
+    let table_name = dbc_file_name(modified)?;
+    let source = FileSource::new(Path::new("schema"));
+    let read = |path: &Path| source.read_table(path);
+    let (original_table, modified_table) = (read(original)?, read(modified)?);
+    if original_table.header.field_count != modified_table.header.field_count {
+        bail!(
+            "{:?} has {} columns but {:?} has {}; a patch cannot change the layout",
+            original,
+            original_table.header.field_count,
+            modified,
+            modified_table.header.field_count
+        );
+    }
+    let schema = match schema {
+        Some(path) => Some(load_schema_file(path).with_context(|| format!("Failed to load schema {:?}", path))?),
+        None => load_schema(Path::new("schema"), &table_name),
+    };
+    if schema.is_none() {
+        warning!(
+            "no schema for {}; fields are written by column number and strings as raw offsets",
+            table_name
+        );
+    }
+    let key_col = match key_column {
+        None => 0,
+        Some(name) => match name.parse::<usize>() {
+            Ok(col) => col,
+            Err(_) => schema
+                .as_ref()
+                .and_then(|s| s.column(name))
+                .with_context(|| format!("Unknown key column {:?} in {}", name, table_name))?,
+        },
+    };
+
+    let (section, [updated, inserted, deleted]) =
+        patch_from_tables(&table_name, &original_table, &modified_table, schema.as_ref(), key_col);
+    let yaml = format!(
+        "# Generated by `diff` from {} to {}\n{}",
+        original.display(),
+        modified.display(),
+        section
+    );
+    match out {
+        Some(path) => {
+            fs::write(path, yaml).with_context(|| format!("Failed to write {:?}", path))?;
+            println!(
+                "Wrote {} ({} update(s), {} insert(s), {} delete(s))",
+                path.display(),
+                updated,
+                inserted,
+                deleted
+            );
+        }
+        None => print!("{}", yaml),
+    }
+    Ok(())
+}
+
+/// Patch both trees in memory from the same stock tables and report the
+/// differences between the results.  With `git`, `old` and `new` are
+/// revisions whose `patch_dir` is written to a temporary directory first.
+#[allow(clippy::too_many_arguments)]
+pub fn diff_builds_command(
+    old: &str,
+    new: &str,
+    git: bool,
+    patch_dir: &Path,
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    units: &Transformers,
+    ids: &IdMap,
+) -> Result<()> {
+    let scratch = std::env::temp_dir().join(format!("wow_dbc_patcher-diff-{}", std::process::id()));
+    let result = (|| -> Result<()> {
+        let mut sides = Vec::new();
+        for (label, spec) in [("old", old), ("new", new)] {
+            let dir = if git {
+                checkout_patch_dir(spec, patch_dir, &scratch.join(label))?
+            } else {
+                PathBuf::from(spec)
+            };
+            if !dir.is_dir() {
+                anyhow::bail!("Patch directory {:?} does not exist", dir);
+            }
+            sides.push(exclude_experimental(patch_paths_or_dir(&[], &dir)?, false)?);
+        }
+
+        // Every table either side patches, read from the same stock files
+        let mut names: Vec<String> = Vec::new();
+        for patch_paths in &sides {
+            for pfs in load_patches_with_ids(patch_paths, ids)?.values() {
+                if !names.iter().any(|n| n.eq_ignore_ascii_case(&pfs[0].dbc)) {
+                    names.push(pfs[0].dbc.clone());
+                }
+            }
+        }
+        names.sort_by_key(|n| n.to_lowercase());
+        let dbc_paths: Vec<PathBuf> = names.iter().map(|name| find_dbc_file(dbc_dir, name)).collect();
+
+        let mut builds = Vec::new();
+        for (label, patch_paths) in ["old", "new"].iter().zip(&sides) {
+            say!("Patching the {} tree ({} file(s))", label, patch_paths.len());
+            let (overlay, _) = patch_tables(
+                &dbc_paths,
+                std::slice::from_ref(patch_paths),
+                dbc_dir,
+                schema_dir,
+                includes_dir,
+                false,
+                None,
+                units,
+                ids,
+            )?;
+            builds.push(overlay);
+        }
+
+        println!();
+        let mut differing = 0;
+        for (name, path) in names.iter().zip(&dbc_paths) {
+            let old_table = builds[0].read_table(path)?;
+            let new_table = builds[1].read_table(path)?;
+            let schema = load_schema(schema_dir, name);
+            if report_table_diff(name, &old_table, &new_table, schema.as_ref()) {
+                differing += 1;
+            }
+        }
+        println!("{} of {} patched table(s) differ between {} and {}", differing, names.len(), old, new);
+        Ok(())
+    })();
+    if scratch.exists() {
+        fs::remove_dir_all(&scratch).ok();
+    }
+    result
+}
+
+/// Patch the tables in memory and print what the patches would change,
+/// without writing anything: every record updated, field by field with its
+/// old and new value, and every record added or removed.  Everything a
+/// real run checks is done, so its warnings and errors show up too.
+#[allow(clippy::too_many_arguments)]
+pub fn dry_run_command(
+    dbc_files: &[PathBuf],
+    patch_sets: &[Vec<PathBuf>],
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    translations: Option<&Translations>,
+    units: &Transformers,
+    ids: &IdMap,
+) -> Result<()> {
+    let (overlay, _) = patch_tables(
+        dbc_files,
+        patch_sets,
+        dbc_dir,
+        schema_dir,
+        includes_dir,
+        false,
+        translations,
+        units,
+        ids,
+    )?;
+    println!();
+    let mut changed = 0;
+    for path in dbc_files {
+        let name = dbc_file_name(path)?;
+        let stock = FileSource::new(schema_dir).read_table(path)?;
+        let patched = overlay.read_table(path)?;
+        let schema = load_schema(schema_dir, &name);
+        if report_table_diff(&name, &stock, &patched, schema.as_ref()) {
+            changed += 1;
+        }
+    }
+    println!(
+        "Dry run: {} of {} table(s) would change; nothing was written",
+        changed,
+        dbc_files.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apply::parse_patch_str;
+    use crate::dbc::{DbcFormat, DbcHeader, Endianness};
+
+    fn table(records: Vec<Vec<u32>>, string_block: &[u8]) -> DbcTable {
+        DbcTable {
+            header: DbcHeader {
+                magic: *b"WDBC",
+                record_count: records.len() as u32,
+                field_count: 4,
+                record_size: 16,
+                string_block_size: string_block.len() as u32,
+                format: DbcFormat::Wdbc,
+                endianness: Endianness::Little,
+                layout: None,
+            },
+            records,
+            string_block: string_block.to_vec(),
+        }
+    }
+
+    #[test]
+    fn patches_update_insert_and_delete_by_field_name() {
+        let path = std::env::temp_dir().join(format!("wow_dbc_patcher-{}-Test.dbc.yaml", std::process::id()));
+        fs::write(&path, "fields: [ID, { name: Name, type: string }, { name: Radius, type: float }, Flags]\n").unwrap();
+        let schema = load_schema_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        let radius = |r: f32| r.to_bits();
+        let original = table(
+            vec![vec![1, 1, radius(1.0), 0], vec![2, 3, radius(2.0), 5], vec![4, 3, 0, 0]],
+            b"\0A\0B\0",
+        );
+        // Record 2 keeps its text at another offset, which is no change
+        let modified = table(
+            vec![vec![1, 3, radius(1.0), 0], vec![2, 1, radius(2.0), 5], vec![3, 0, radius(0.5), 0]],
+            b"\0B\0C\0",
+        );
+        let (yaml, counts) = patch_from_tables("Test.dbc", &original, &modified, Some(&schema), 0);
+        assert_eq!(counts, [1, 1, 1]);
+        assert_eq!(
+            yaml,
+            "Test.dbc:\n\
+             \x20 - type: update\n    key: 1\n    values:\n      Name: \"C\"\n\
+             \x20 - type: insert\n    key: 3\n    values:\n      Radius: 0.5\n\
+             \x20 - type: delete\n    key: 4\n"
+        );
+        assert!(parse_patch_str(&yaml, Path::new("diff.yaml"), &IdMap::new()).is_ok());
+
+        // By another key column, and without a schema
+        let (yaml, counts) = patch_from_tables("Test.dbc", &original, &original, None, 3);
+        assert_eq!((yaml.as_str(), counts), ("Test.dbc: []\n", [0; 3]));
+        let (yaml, _) = patch_from_tables("Test.dbc", &original, &modified, None, 3);
+        assert!(yaml.starts_with("Test.dbc:\n  - type: update\n    key: 0\n    key_column: '3'\n"), "{}", yaml);
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::dbc::{find_dbc_file, read_dbc_with_schema, string_at};
+use crate::schema::{load_schema, FieldKind, Schema};
+
+/// A table loaded for display, together with its schema.
+struct LoadedTable {
+    records: Vec<Vec<u32>>,
+    string_block: Vec<u8>,
+    schema: Option<Schema>,
+}
+
+/// Lazily loads tables from a directory so joined views only read the
+/// tables they actually visit, and each of them once.
+struct TableCache {
+    dbc_dir: PathBuf,
+    schema_dir: PathBuf,
+    tables: HashMap<String, LoadedTable>,
+}
+
+impl TableCache {
+    fn get(&mut self, name: &str) -> Result<&LoadedTable> {
+        let key = name.to_lowercase();
+        if !self.tables.contains_key(&key) {
+            let path = find_dbc_file(&self.dbc_dir, name);
+            let (_header, records, string_block) = read_dbc_with_schema(&path, &self.schema_dir)
+                .with_context(|| format!("Failed to read DBC file {:?}", path))?;
+            let schema = load_schema(&self.schema_dir, name);
+            self.tables.insert(
+                key.clone(),
+                LoadedTable {
+                    records,
+                    string_block,
+                    schema,
+                },
+            );
+        }
+        Ok(&self.tables[&key])
+    }
+}
+
+/// Another reading of a column whose schema does not say what it holds:
+/// the float its bits encode, when that is a plausible number (integers
+/// below 2^23 decode to tiny denormals and are not), or else the signed
+/// integer when the value only makes sense negative.
+fn other_reading(value: u32) -> Option<String> {
+    let float = f32::from_bits(value);
+    if float.is_finite() && (1e-4..=1e7).contains(&float.abs()) {
+        return Some(format!("as float {:?}", float));
+    }
+    (value > i32::MAX as u32).then(|| format!("as signed {}", value as i32))
+}
+
+/// Render one record of `table` with field names and resolved strings.
+/// Named values show their names, and untyped columns also show what
+/// else their bits could mean (see `other_reading`).
+/// When `depth` is non‑zero, columns with a `ref:` declaration are followed
+/// (0 and -1 mean "none") and the referenced record is rendered beneath
+/// them, indented.  `seen` guards against reference cycles (e.g. spells
+/// triggering themselves).
+fn render_record(
+    cache: &mut TableCache,
+    table: &str,
+    key: u32,
+    depth: usize,
+    indent: usize,
+    seen: &mut HashSet<(String, u32)>,
+) -> Result<()> {
+    let pad = "  ".repeat(indent);
+    // Render the lines while the table is borrowed, remembering which
+    // references to follow afterwards.
+    let mut lines: Vec<(String, Option<(String, u32)>)> = Vec::new();
+    {
+        let loaded = cache.get(table)?;
+        let Some(record) = loaded.records.iter().find(|r| r.first() == Some(&key)) else {
+            println!("{}{} [{}]  (no such record)", pad, table, key);
+            return Ok(());
+        };
+        for (col, &value) in record.iter().enumerate() {
+            let field = loaded
+                .schema
+                .as_ref()
+                .and_then(|s| s.fields.iter().find(|f| f.index == col));
+            let name = field.map_or_else(|| format!("[{}]", col), |f| f.name.clone());
+            match field {
+                Some(f) if f.kind == FieldKind::String => {
+                    let s = string_at(&loaded.string_block, value).unwrap_or_default();
+                    lines.push((format!("{}: {:?}", name, s), None));
+                }
+                Some(f) if f.kind == FieldKind::Float => {
+                    lines.push((format!("{}: {:?}", name, f32::from_bits(value)), None));
+                }
+                Some(f) if f.kind == FieldKind::Int32 => {
+                    lines.push((format!("{}: {}", name, value as i32), None));
+                }
+                Some(f) if f.reference.is_some() && value != 0 && value != u32::MAX => {
+                    let target = f.reference.clone().unwrap_or_default();
+                    lines.push((
+                        format!("{}: {} -> {}", name, value, target),
+                        Some((target, value)),
+                    ));
+                }
+                Some(f) if f.constants.is_some() => {
+                    let line = match f.constants.as_ref().and_then(|c| c.describe(value)) {
+                        Some(named) => format!("{}: {} ({})", name, value, named),
+                        None => format!("{}: {}", name, value),
+                    };
+                    lines.push((line, None));
+                }
+                Some(f) if f.typed => lines.push((format!("{}: {}", name, value), None)),
+                _ => {
+                    let line = match other_reading(value) {
+                        Some(other) => format!("{}: {} ({})", name, value, other),
+                        None => format!("{}: {}", name, value),
+                    };
+                    lines.push((line, None));
+                }
+            }
+        }
+    }
+    seen.insert((table.to_lowercase(), key));
+
+    println!("{}{} [{}]", pad, table, key);
+    for (line, follow) in lines {
+        println!("{}  {}", pad, line);
+        if let Some((target, value)) = follow {
+            if depth > 0 && !seen.contains(&(target.to_lowercase(), value)) {
+                render_record(cache, &target, value, depth - 1, indent + 2, seen)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print a record by key.  With `join_depth` above zero, declared foreign
+/// keys are followed that many levels deep, so e.g. a spell is shown
+/// together with its SpellVisual, the visual's kits and the kits' effect
+/// names in one block.
+pub fn dump_command(
+    table: &str,
+    key: u32,
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    join_depth: usize,
+) -> Result<()> {
+    let mut cache = TableCache {
+        dbc_dir: dbc_dir.to_path_buf(),
+        schema_dir: schema_dir.to_path_buf(),
+        tables: HashMap::new(),
+    };
+    let mut seen = HashSet::new();
+    render_record(&mut cache, table, key, join_depth, 0, &mut seen)
+}
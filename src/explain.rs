@@ -0,0 +1,111 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::dbc::find_dbc_file;
+use crate::diff::{column_name, record_label, render_value};
+use crate::apply::patch_tables;
+use crate::ids::IdMap;
+use crate::schema::{load_schema, Schema};
+use crate::source::{DbcSource, FileSource};
+use crate::units::Transformers;
+
+/// The fields `after` differs from `before` in, as `Name: old -> new`, or
+/// every non‑empty field of `after` when there is no `before`.
+fn changed_fields(schema: Option<&Schema>, before: Option<&[u32]>, after: &[u32], block: &[u8]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (col, &value) in after.iter().enumerate() {
+        let (name, field) = column_name(schema, col);
+        let new = render_value(field, value, block);
+        match before.and_then(|b| b.get(col)) {
+            Some(&old) => {
+                let old = render_value(field, old, block);
+                if old != new {
+                    lines.push(format!("{}: {} -> {}", name, old, new));
+                }
+            }
+            None if value != 0 && new != "\"\"" => lines.push(format!("{}: {}", name, new)),
+            None => {}
+        }
+    }
+    lines
+}
+
+/// Patch `table` in memory with provenance tracking and print every
+/// entry that touched the record whose key (column 0) is `key`, in the
+/// order they were applied, each with the fields it changed relative to
+/// the stock record or the entry before.
+#[allow(clippy::too_many_arguments)]
+pub fn explain_row_command(
+    table: &str,
+    key: u32,
+    patch_sets: &[Vec<PathBuf>],
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    units: &Transformers,
+    ids: &IdMap,
+) -> Result<()> {
+    let dbc_path = find_dbc_file(dbc_dir, table);
+    let stock = FileSource::new(schema_dir)
+        .read_table(&dbc_path)
+        .with_context(|| format!("Failed to read DBC file {:?}", dbc_path))?;
+    let (overlay, progress) = patch_tables(
+        std::slice::from_ref(&dbc_path),
+        patch_sets,
+        dbc_dir,
+        schema_dir,
+        includes_dir,
+        true,
+        None,
+        units,
+        ids,
+    )?;
+    let result = overlay.read_table(&dbc_path)?;
+    let schema = load_schema(schema_dir, table);
+    let schema = schema.as_ref();
+    let block = &result.string_block;
+
+    let stock_record = stock.records.iter().find(|r| r.first() == Some(&key));
+    let final_record = result.records.iter().find(|r| r.first() == Some(&key));
+    let label = final_record
+        .or(stock_record)
+        .map(|r| record_label(schema, r, block))
+        .unwrap_or_default();
+    println!();
+    println!("{} record {}{}", table, key, label);
+    println!(
+        "  {}",
+        if stock_record.is_some() { "in the stock table" } else { "not in the stock table" }
+    );
+
+    let steps = progress
+        .get(&table.to_lowercase())
+        .and_then(|p| p.provenance.as_ref())
+        .and_then(|p| p.get(&key));
+    let Some(steps) = steps else {
+        println!("  no patch entry touched it");
+        return Ok(());
+    };
+    let mut before: Option<&[u32]> = stock_record.map(Vec::as_slice);
+    for (i, step) in steps.iter().enumerate() {
+        println!("  {}. {} ({})", i + 1, step.entry, step.action);
+        if step.removed {
+            println!("       deleted");
+            before = None;
+            continue;
+        }
+        let lines = changed_fields(schema, before, &step.record, block);
+        if lines.is_empty() {
+            println!("       (no change)");
+        }
+        for line in lines {
+            println!("       {}", line);
+        }
+        before = Some(&step.record);
+    }
+    println!(
+        "  {}",
+        if final_record.is_some() { "in the built table" } else { "not in the built table" }
+    );
+    Ok(())
+}
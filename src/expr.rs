@@ -0,0 +1,68 @@
+use rhai::{Dynamic, Engine, Scope, FLOAT, INT};
+
+use crate::patch::ValueType;
+
+/// Upper bound on the work one value expression may do.
+const MAX_OPERATIONS: u64 = 10_000;
+
+thread_local! {
+    /// Engine value expressions are evaluated with, made once per thread.
+    static ENGINE: Engine = {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.disable_symbol("eval");
+        engine
+    };
+}
+
+/// What a patch value written as text stands for.  Text after a leading
+/// `=` is a Rhai expression, as in `Radius: "=${base} * 0.5"`; a leading
+/// `==` writes text that starts with `=`.  Other text is itself.
+pub enum TextValue<'a> {
+    Literal(&'a str),
+    Expression(&'a str),
+}
+
+impl TextValue<'_> {
+    pub fn of(text: &str) -> TextValue<'_> {
+        match text.strip_prefix('=') {
+            Some(rest) if rest.starts_with('=') => TextValue::Literal(rest),
+            Some(expression) => TextValue::Expression(expression),
+            None => TextValue::Literal(text),
+        }
+    }
+}
+
+/// Whether any of `values` is an expression.
+pub fn has_expression<'a>(mut values: impl Iterator<Item = &'a ValueType>) -> bool {
+    values.any(|value| matches!(value, ValueType::String(text) if text.starts_with('=')))
+}
+
+/// Check that `expression` parses, without evaluating it.
+pub fn check_expression(expression: &str) -> Result<(), String> {
+    ENGINE
+        .with(|engine| engine.compile_expression(expression))
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Evaluate `expression` with the fields of `scope` (see
+/// `computed::record_scope`).  A whole number is an integer unless
+/// `float` says the field holds floats; the caller checks the result
+/// against the field's type as it does a written value.
+pub fn eval_value(expression: &str, scope: &mut Scope, float: bool) -> Result<ValueType, String> {
+    let result: Dynamic = ENGINE
+        .with(|engine| engine.eval_with_scope(scope, expression))
+        .map_err(|e| e.to_string())?;
+    if let Some(v) = result.clone().try_cast::<INT>() {
+        Ok(if float { ValueType::Float(v as f64) } else { ValueType::Int(v) })
+    } else if let Some(v) = result.clone().try_cast::<FLOAT>() {
+        Ok(if !float && v.fract() == 0.0 && v.abs() < 1e15 { ValueType::Int(v as i64) } else { ValueType::Float(v) })
+    } else if let Some(v) = result.clone().try_cast::<bool>() {
+        Ok(ValueType::Bool(v))
+    } else if result.is_string() {
+        result.into_string().map(ValueType::String).map_err(|t| format!("unexpected {}", t))
+    } else {
+        Err(format!("the expression gave a {}", result.type_name()))
+    }
+}
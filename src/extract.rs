@@ -0,0 +1,203 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ownership::wildcard_match;
+
+/// A file `extract_files` wrote: where, its size and the index of the
+/// archive it came from.
+struct Extracted {
+    path: PathBuf,
+    size: usize,
+    archive: usize,
+}
+
+/// Copy the files matching `filter` out of client archives into `out_dir`,
+/// flattened to their file names the way the `dbc` directory holds them.
+/// `mpqs` are read in order and a file found in several of them is taken
+/// from the last, so passing `dbc.MPQ` then `patch.MPQ` extracts the
+/// tables as the client sees them.  The filter is matched case‑insensitively
+/// against archive paths, with `/` read as `\` and `*` matching any run of
+/// characters.  Returns how many files of each archive matched, and the
+/// files written.
+fn extract_files(mpqs: &[PathBuf], filter: &str, out_dir: &Path) -> Result<(Vec<usize>, Vec<Extracted>)> {
+    let filter = filter.replace('/', "\\");
+    // File name (lower‑cased) -> archive index and archive path
+    let mut chosen: BTreeMap<String, (usize, String)> = BTreeMap::new();
+    let mut archives = Vec::new();
+    let mut matches = Vec::new();
+    for (index, mpq) in mpqs.iter().enumerate() {
+        let mut archive = wow_mpq::Archive::open(mpq)
+            .with_context(|| format!("Failed to open archive {:?}", mpq))?;
+        let entries = archive
+            .list()
+            .with_context(|| format!("Failed to list the files of {:?} (does it have a listfile?)", mpq))?;
+        let mut matched = 0;
+        for entry in entries {
+            if !wildcard_match(&filter, &entry.name.replace('/', "\\")) {
+                continue;
+            }
+            let file_name = entry.name.rsplit(['\\', '/']).next().unwrap_or(&entry.name).to_string();
+            if let Some((earlier, path)) = chosen.get(&file_name.to_lowercase()) {
+                if *earlier == index && !path.eq_ignore_ascii_case(&entry.name) {
+                    warning!(
+                        "{} and {} in {} share a file name; keeping {}",
+                        path,
+                        entry.name,
+                        mpq.display(),
+                        path
+                    );
+                    continue;
+                }
+            }
+            chosen.insert(file_name.to_lowercase(), (index, entry.name));
+            matched += 1;
+        }
+        matches.push(matched);
+        archives.push(archive);
+    }
+    if chosen.is_empty() {
+        bail!("No files matching {} in the given archive(s)", filter);
+    }
+
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create output directory {:?}", out_dir))?;
+    let mut extracted = Vec::new();
+    for (index, name) in chosen.into_values() {
+        let data = archives[index]
+            .read_file(&name)
+            .with_context(|| format!("Failed to read {} from {:?}", name, mpqs[index]))?;
+        let file_name = name.rsplit(['\\', '/']).next().unwrap_or(&name);
+        let path = out_dir.join(file_name);
+        fs::write(&path, &data).with_context(|| format!("Failed to write {:?}", path))?;
+        extracted.push(Extracted {
+            path,
+            size: data.len(),
+            archive: index,
+        });
+    }
+    Ok((matches, extracted))
+}
+
+/// Extract the files matching `filter` from `mpqs` into `out_dir` (see
+/// `extract_files`) and list what was taken from where.
+pub fn extract_command(mpqs: &[PathBuf], filter: &str, out_dir: &Path) -> Result<()> {
+    let (matches, extracted) = extract_files(mpqs, filter, out_dir)?;
+    for (mpq, matched) in mpqs.iter().zip(matches) {
+        println!("{}: {} file(s) match {}", mpq.display(), matched, filter.replace('/', "\\"));
+    }
+    for file in extracted {
+        println!(
+            "Extracted {} ({} bytes) from {}",
+            file.path.display(),
+            file.size,
+            mpqs[file.archive].display()
+        );
+    }
+    Ok(())
+}
+
+/// Directory under the output directory that `--dbc-source` extracts the
+/// tables into.
+pub const DBC_SOURCE_DIR: &str = "dbc-source";
+
+/// Read the tables of `mpqs` (`DBFilesClient\*.dbc`, later archives
+/// replacing earlier ones) into a fresh `dbc-source` directory under
+/// `out_dir`, for `--dbc-source`, and return it for use as the DBC
+/// directory.  The directory is emptied first, so it only ever holds what
+/// the archives hold now.
+pub fn stage_dbc_source(mpqs: &[PathBuf], out_dir: &Path) -> Result<PathBuf> {
+    let dir = out_dir.join(DBC_SOURCE_DIR);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).with_context(|| format!("Failed to empty {:?}", dir))?;
+    }
+    let (_, extracted) = extract_files(mpqs, "DBFilesClient\\*.dbc", &dir)?;
+    for file in &extracted {
+        let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+        debug!("{} taken from {}", name, mpqs[file.archive].display());
+    }
+    say!(
+        "Read {} table(s) from {}",
+        extracted.len(),
+        mpqs.iter().map(|mpq| mpq.display().to_string()).collect::<Vec<_>>().join(", ")
+    );
+    Ok(dir)
+}
+
+/// Block table flags worth showing, with the names `list` prints for them.
+const BLOCK_FLAGS: [(u32, &str); 8] = [
+    (0x0000_0100, "imploded"),
+    (0x0000_0200, "compressed"),
+    (0x0001_0000, "encrypted"),
+    (0x0002_0000, "fix-key"),
+    (0x0010_0000, "patch"),
+    (0x0100_0000, "single-unit"),
+    (0x0200_0000, "deleted"),
+    (0x0400_0000, "sector-crc"),
+];
+
+/// The names of the `BLOCK_FLAGS` set in `flags`, or `stored`.
+fn describe_flags(flags: u32) -> String {
+    let names: Vec<&str> = BLOCK_FLAGS
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() {
+        "stored".to_string()
+    } else {
+        names.join(",")
+    }
+}
+
+/// Print the files of `mpq` with their sizes, sizes in the archive and
+/// block flags, sorted by path, optionally only those matching `filter`
+/// (as in [`extract_command`]).  Names come from the archive's listfile;
+/// without one the files are listed under the placeholder names the hash
+/// table yields.
+pub fn list_command(mpq: &Path, filter: Option<&str>) -> Result<()> {
+    let mut archive = wow_mpq::Archive::open(mpq).with_context(|| format!("Failed to open archive {:?}", mpq))?;
+    let mut entries = match archive.list() {
+        Ok(entries) if !entries.is_empty() => entries,
+        _ => {
+            warning!("{} has no listfile; files are listed without their names", mpq.display());
+            archive
+                .list_all()
+                .with_context(|| format!("Failed to list the files of {:?}", mpq))?
+        }
+    };
+    if let Some(filter) = filter {
+        let filter = filter.replace('/', "\\");
+        entries.retain(|entry| wildcard_match(&filter, &entry.name.replace('/', "\\")));
+    }
+    entries.sort_by_key(|entry| entry.name.to_lowercase());
+
+    println!("{:>12} {:>12} {:>6}  {:<24} name", "size", "packed", "ratio", "flags");
+    let (mut size, mut packed) = (0u64, 0u64);
+    for entry in &entries {
+        println!(
+            "{:>12} {:>12} {:>5}%  {:<24} {}",
+            entry.size,
+            entry.compressed_size,
+            ratio(entry.compressed_size, entry.size),
+            describe_flags(entry.flags),
+            entry.name
+        );
+        size += entry.size;
+        packed += entry.compressed_size;
+    }
+    println!(
+        "{:>12} {:>12} {:>5}%  {} file(s)",
+        size,
+        packed,
+        ratio(packed, size),
+        entries.len()
+    );
+    Ok(())
+}
+
+/// `packed` as a whole percentage of `size`.
+fn ratio(packed: u64, size: u64) -> u64 {
+    (packed * 100).checked_div(size).unwrap_or(100)
+}
@@ -0,0 +1,243 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::dbc::{dbc_file_name, read_dbc_with_schema, string_at};
+use crate::schema::{load_schema, FieldKind, Schema};
+
+/// Case handling for `grep`.  Matching is a plain substring test.
+fn matches(haystack: &str, needle: &str, ignore_case: bool) -> bool {
+    if ignore_case {
+        haystack.to_lowercase().contains(&needle.to_lowercase())
+    } else {
+        haystack.contains(needle)
+    }
+}
+
+/// Start offsets of every string in the block that contains `pattern`.
+fn matching_offsets(block: &[u8], pattern: &str, ignore_case: bool) -> HashSet<u32> {
+    let mut offsets = HashSet::new();
+    let mut start = 0usize;
+    while start < block.len() {
+        let end = block[start..]
+            .iter()
+            .position(|&b| b == 0)
+            .map_or(block.len(), |pos| start + pos);
+        let s = String::from_utf8_lossy(&block[start..end]);
+        if !s.is_empty() && matches(&s, pattern, ignore_case) {
+            offsets.insert(start as u32);
+        }
+        start = end + 1;
+    }
+    offsets
+}
+
+/// What `search` looks for.
+pub enum SearchQuery {
+    /// Text within the strings of string fields (a substring).
+    Text(String),
+    /// A number stored in a column: compared as a float in columns the
+    /// schema types `float`, and as a whole number (negative ones as
+    /// stored, in two's complement) in the others.
+    Value {
+        int: Option<u32>,
+        float: f32,
+    },
+}
+
+impl SearchQuery {
+    /// A `--value` such as `4930`, `-1`, `0x80` or `1.5`.
+    pub fn value(text: &str) -> Result<SearchQuery> {
+        let text = text.trim();
+        let not_a_number = || anyhow!("{:?} is not a number", text);
+        // Hexadecimal is taken as the bits, also of a float
+        if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            let bits = u32::from_str_radix(hex, 16).map_err(|_| not_a_number())?;
+            return Ok(SearchQuery::Value {
+                int: Some(bits),
+                float: f32::from_bits(bits),
+            });
+        }
+        let float = text.parse::<f32>().map_err(|_| not_a_number())?;
+        let int = text
+            .parse::<i64>()
+            .ok()
+            .filter(|&v| (i32::MIN as i64..=u32::MAX as i64).contains(&v))
+            .map(|v| v as u32);
+        Ok(SearchQuery::Value { int, float })
+    }
+}
+
+/// Whether the field at column `index`, named `name` by the schema if it
+/// names it, is the column `search --column` asked for: a field name
+/// (case‑insensitive), the name of an array whose element it is, or a
+/// column number.
+fn column_selected(column: Option<&str>, index: usize, name: Option<&str>) -> bool {
+    let Some(column) = column else {
+        return true;
+    };
+    if let Ok(wanted) = column.parse::<usize>() {
+        return wanted == index;
+    }
+    let Some(name) = name else {
+        return false;
+    };
+    if name.eq_ignore_ascii_case(column) {
+        return true;
+    }
+    name.len() > column.len() + 1
+        && name.is_char_boundary(column.len())
+        && name[..column.len()].eq_ignore_ascii_case(column)
+        && name[column.len()..].strip_prefix('_').is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Search the string data of every given DBC for `pattern` and print each
+/// matching record with its table, key (column 0) and field.  Columns the
+/// schema declares as `string` are checked directly.  Tables without any
+/// string‑typed schema fields fall back to a heuristic: a column is
+/// reported when its value is exactly the offset of a matching string.
+/// Such hits are marked as untyped since the column might be a plain
+/// integer that happens to coincide with an offset.
+pub fn grep_command(
+    pattern: &str,
+    dbc_files: &[PathBuf],
+    schema_dir: &Path,
+    ignore_case: bool,
+) -> Result<()> {
+    search_command(&SearchQuery::Text(pattern.to_string()), None, dbc_files, schema_dir, ignore_case)
+}
+
+/// Search every given DBC for `query` and print each hit with its table,
+/// key (column 0) and field, named by the schema or as `column N`.  Text
+/// is looked for as `grep` does.  A value is matched against every
+/// column but string ones, the key included, so a search for an ID finds
+/// the record that has it as well as those pointing at it.  `column`
+/// limits either search to one field (see `column_selected`); tables
+/// without it are skipped.
+pub fn search_command(
+    query: &SearchQuery,
+    column: Option<&str>,
+    dbc_files: &[PathBuf],
+    schema_dir: &Path,
+    ignore_case: bool,
+) -> Result<()> {
+    let mut total = 0usize;
+    let mut tables = 0usize;
+    for dbc_path in dbc_files {
+        let file_name = dbc_file_name(dbc_path)?;
+        // A directory scan sweeps in tables with layouts we cannot parse;
+        // skip those rather than aborting the whole search.
+        let (_header, records, string_block) = match read_dbc_with_schema(dbc_path, schema_dir) {
+            Ok(t) => t,
+            Err(err) => {
+                warning!("skipping {}: {:#}", file_name, err);
+                continue;
+            }
+        };
+        let schema = load_schema(schema_dir, &file_name);
+        let hits = match query {
+            SearchQuery::Text(pattern) => {
+                text_hits(&file_name, &records, &string_block, schema.as_ref(), pattern, column, ignore_case)
+            }
+            SearchQuery::Value { int, float } => {
+                value_hits(&file_name, &records, schema.as_ref(), *int, *float, column)
+            }
+        };
+        if hits > 0 {
+            tables += 1;
+            total += hits;
+        }
+    }
+    println!("{} match(es) in {} table(s)", total, tables);
+    Ok(())
+}
+
+/// Print the records of one table whose strings contain `pattern` (see
+/// `grep_command`) and return how many fields matched.
+fn text_hits(
+    file_name: &str,
+    records: &[Vec<u32>],
+    string_block: &[u8],
+    schema: Option<&Schema>,
+    pattern: &str,
+    column: Option<&str>,
+    ignore_case: bool,
+) -> usize {
+    let string_fields: Vec<_> = schema
+        .map(|s| s.string_fields().cloned().collect())
+        .unwrap_or_default();
+
+    let mut hits = 0usize;
+    if !string_fields.is_empty() {
+        let string_fields: Vec<_> = string_fields
+            .into_iter()
+            .filter(|f| column_selected(column, f.index, Some(&f.name)))
+            .collect();
+        for record in records {
+            let key = record.first().copied().unwrap_or(0);
+            for field in &string_fields {
+                let Some(&offset) = record.get(field.index) else {
+                    continue;
+                };
+                if let Some(s) = string_at(string_block, offset) {
+                    if !s.is_empty() && matches(&s, pattern, ignore_case) {
+                        println!("{}  key {}  {}: {:?}", file_name, key, field.name, s);
+                        hits += 1;
+                    }
+                }
+            }
+        }
+    } else {
+        let offsets = matching_offsets(string_block, pattern, ignore_case);
+        if offsets.is_empty() {
+            return 0;
+        }
+        for record in records {
+            let key = record.first().copied().unwrap_or(0);
+            for (col, value) in record.iter().enumerate().skip(1) {
+                if offsets.contains(value) && column_selected(column, col, None) {
+                    let s = string_at(string_block, *value).unwrap_or_default();
+                    println!("{}  key {}  column {} (untyped): {:?}", file_name, key, col, s);
+                    hits += 1;
+                }
+            }
+        }
+    }
+    hits
+}
+
+/// Print the fields of one table that hold the value `int` (or `float`,
+/// in float columns) and return how many did.
+fn value_hits(
+    file_name: &str,
+    records: &[Vec<u32>],
+    schema: Option<&Schema>,
+    int: Option<u32>,
+    float: f32,
+    column: Option<&str>,
+) -> usize {
+    let mut hits = 0usize;
+    for record in records {
+        let key = record.first().copied().unwrap_or(0);
+        for (col, &cell) in record.iter().enumerate() {
+            let field = schema.and_then(|s| s.field_at(col));
+            if !column_selected(column, col, field.map(|f| f.name.as_str())) {
+                continue;
+            }
+            let kind = field.map_or(FieldKind::Int, |f| f.kind);
+            let shown = match kind {
+                FieldKind::String => continue,
+                FieldKind::Float if f32::from_bits(cell) == float => format!("{:?}", float),
+                FieldKind::Int32 if int == Some(cell) => (cell as i32).to_string(),
+                FieldKind::Int | FieldKind::Bool if int == Some(cell) => cell.to_string(),
+                _ => continue,
+            };
+            match field {
+                Some(field) => println!("{}  key {}  {}: {}", file_name, key, field.name, shown),
+                None => println!("{}  key {}  column {}: {}", file_name, key, col, shown),
+            }
+            hits += 1;
+        }
+    }
+    hits
+}
@@ -0,0 +1,265 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::apply::patch_paths_or_dir;
+
+/// A calendar date, as given to `--as-of` and in group ranges
+/// (`YYYY-MM-DD`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// Today's date in UTC.
+    pub fn today() -> Date {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        // Days since 1970-01-01 to a civil date (proleptic Gregorian)
+        let z = (secs / 86_400) as i64 + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+        Date { year, month, day }
+    }
+}
+
+/// Parse `MM-DD`, checking the ranges.
+fn month_day(text: &str) -> Option<(u32, u32)> {
+    let (month, day) = text.trim().split_once('-')?;
+    let (month, day) = (month.parse().ok()?, day.parse().ok()?);
+    ((1..=12).contains(&month) && (1..=31).contains(&day)).then_some((month, day))
+}
+
+impl FromStr for Date {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Date> {
+        let parsed = text
+            .trim()
+            .split_once('-')
+            .and_then(|(year, rest)| Some((year.parse().ok()?, month_day(rest)?)));
+        match parsed {
+            Some((year, (month, day))) => Ok(Date { year, month, day }),
+            None => bail!("invalid date {:?}, expected YYYY-MM-DD", text),
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// A yearly window such as a holiday event, given as `MM-DD` days.  The
+/// window may wrap over the new year (`from: 12-15`, `to: 01-02`).
+#[derive(Debug, Deserialize)]
+pub struct Season {
+    pub from: String,
+    pub to: String,
+}
+
+/// Patch files that are only built while their season or date range is
+/// on, e.g. holiday visuals.  Outside it they are left out even when they
+/// sit in the patch directory; inside it they are added even when they
+/// sit elsewhere.
+#[derive(Debug, Deserialize)]
+pub struct PatchGroup {
+    pub name: String,
+    /// Patch files or directories of patch files, relative to the manifest.
+    pub patches: Vec<PathBuf>,
+    /// Name of a season from the manifest's `seasons`.
+    #[serde(default)]
+    pub season: Option<String>,
+    /// First day the group is on (`YYYY-MM-DD`).
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Last day the group is on (`YYYY-MM-DD`).
+    #[serde(default)]
+    pub to: Option<String>,
+}
+
+impl PatchGroup {
+    /// Whether the group is on at `date`, with a description of its window.
+    fn active(&self, seasons: &BTreeMap<String, Season>, date: Date) -> Result<(bool, String)> {
+        match (&self.season, &self.from, &self.to) {
+            (Some(name), None, None) => {
+                let season = seasons
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map(|(_, s)| s)
+                    .with_context(|| format!("patch group {:?} names unknown season {:?}", self.name, name))?;
+                let (Some(from), Some(to)) = (month_day(&season.from), month_day(&season.to)) else {
+                    bail!("season {:?} needs `from` and `to` as MM-DD", name);
+                };
+                let today = (date.month, date.day);
+                let on = if from <= to {
+                    from <= today && today <= to
+                } else {
+                    today >= from || today <= to
+                };
+                Ok((on, format!("season {}, {} to {}", name, season.from, season.to)))
+            }
+            (None, Some(from), Some(to)) => {
+                let (from, to): (Date, Date) = (from.parse()?, to.parse()?);
+                Ok((from <= date && date <= to, format!("{} to {}", from, to)))
+            }
+            _ => bail!("patch group {:?} needs either `season` or both `from` and `to`", self.name),
+        }
+    }
+
+    /// The group's patch files, with directories expanded.
+    fn files(&self, base_dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for path in &self.patches {
+            let path = base_dir.join(path);
+            if path.is_dir() {
+                files.extend(patch_paths_or_dir(&[], &path)?);
+            } else if path.exists() {
+                files.push(path);
+            } else {
+                bail!("patch group {:?} lists {:?}, which does not exist", self.name, path);
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Path used to compare patch files named through different directories.
+fn identity(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Drop the patch files of groups that are off at `date` and, with
+/// `add_enabled`, add those of groups that are on; printing the state of
+/// every group.  Explicitly chosen patch lists pass `add_enabled: false`
+/// so groups can only narrow them.
+pub fn select_groups(
+    groups: &[PatchGroup],
+    seasons: &BTreeMap<String, Season>,
+    base_dir: &Path,
+    patch_paths: Vec<PathBuf>,
+    add_enabled: bool,
+    date: Date,
+) -> Result<Vec<PathBuf>> {
+    if groups.is_empty() {
+        return Ok(patch_paths);
+    }
+    // A file stays in when any group listing it is on
+    let mut listed = Vec::new();
+    let mut enabled = Vec::new();
+    for group in groups {
+        let (on, window) = group.active(seasons, date)?;
+        let files = group.files(base_dir)?;
+        say!(
+            "Patch group {} ({}): {} as of {} ({} file(s))",
+            group.name,
+            window,
+            if on { "enabled" } else { "disabled" },
+            date,
+            files.len()
+        );
+        for file in files {
+            let id = identity(&file);
+            if on && !enabled.iter().any(|(e, _)| *e == id) {
+                enabled.push((id.clone(), file));
+            }
+            listed.push(id);
+        }
+    }
+    let mut paths = patch_paths;
+    if add_enabled {
+        paths.retain(|p| !listed.contains(&identity(p)));
+        paths.extend(enabled.into_iter().map(|(_, file)| file));
+    } else {
+        paths.retain(|p| {
+            let id = identity(p);
+            !listed.contains(&id) || enabled.iter().any(|(e, _)| *e == id)
+        });
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> Date {
+        text.parse().unwrap()
+    }
+
+    fn group(name: &str, patch: &str, season: Option<&str>, range: Option<(&str, &str)>) -> PatchGroup {
+        PatchGroup {
+            name: name.to_string(),
+            patches: vec![PathBuf::from(patch)],
+            season: season.map(str::to_string),
+            from: range.map(|(from, _)| from.to_string()),
+            to: range.map(|(_, to)| to.to_string()),
+        }
+    }
+
+    #[test]
+    fn seasons_wrap_over_the_new_year() {
+        let seasons = BTreeMap::from([(
+            "Winter Veil".to_string(),
+            Season {
+                from: "12-15".to_string(),
+                to: "01-02".to_string(),
+            },
+        )]);
+        let winter = group("winter", "winter.yaml", Some("winter veil"), None);
+        for (day, on) in [("2025-12-14", false), ("2025-12-15", true), ("2026-01-02", true), ("2026-01-03", false)] {
+            assert_eq!(winter.active(&seasons, date(day)).unwrap().0, on, "{}", day);
+        }
+        assert!(group("other", "x.yaml", Some("Harvest"), None).active(&seasons, date("2026-01-01")).is_err());
+        assert!(group("neither", "x.yaml", None, None).active(&seasons, date("2026-01-01")).is_err());
+    }
+
+    #[test]
+    fn groups_narrow_or_extend_the_patch_list() {
+        let dir = std::env::temp_dir().join(format!("wow_dbc_patcher-groups-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["base.yaml", "event.yaml", "old.yaml"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let groups = [
+            group("event", "event.yaml", None, Some(("2026-10-01", "2026-10-31"))),
+            group("old", "old.yaml", None, Some(("2025-10-01", "2025-10-31"))),
+        ];
+        let seasons = BTreeMap::new();
+        let names = |paths: Vec<PathBuf>| -> Vec<String> {
+            paths.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect()
+        };
+
+        // From the patch directory: groups that are off are dropped, and
+        // those that are on are added even when not already listed
+        let all = vec![dir.join("base.yaml"), dir.join("old.yaml")];
+        let selected = select_groups(&groups, &seasons, &dir, all, true, date("2026-10-16")).unwrap();
+        assert_eq!(names(selected), ["base.yaml", "event.yaml"]);
+
+        // Chosen explicitly: nothing is added, only dropped
+        let chosen = vec![dir.join("old.yaml"), dir.join("base.yaml")];
+        let selected = select_groups(&groups, &seasons, &dir, chosen, false, date("2026-10-16")).unwrap();
+        assert_eq!(names(selected), ["base.yaml"]);
+
+        let chosen = vec![dir.join("event.yaml")];
+        let selected = select_groups(&groups, &seasons, &dir, chosen, false, date("2026-11-01")).unwrap();
+        assert!(selected.is_empty());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,270 @@
+use anyhow::{bail, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::patch::{PatchEntry, ValueType};
+use crate::refs::walk_files;
+
+/// Column of the display ID in ItemDisplayInfo.dbc.
+const ITEM_DISPLAY_ID_COLUMN: usize = 0;
+/// Column of the inventory icon name in ItemDisplayInfo.dbc.
+const ITEM_DISPLAY_ICON_COLUMN: usize = 5;
+
+/// Columns of SkillLineAbility.dbc written by `learn_spell`.
+const SKILL_ABILITY_SKILL_LINE_COLUMN: usize = 1;
+const SKILL_ABILITY_SPELL_COLUMN: usize = 2;
+const SKILL_ABILITY_RACE_MASK_COLUMN: usize = 3;
+const SKILL_ABILITY_CLASS_MASK_COLUMN: usize = 4;
+const SKILL_ABILITY_MIN_RANK_COLUMN: usize = 7;
+const SKILL_ABILITY_ACQUIRE_METHOD_COLUMN: usize = 9;
+/// Column of SkillLineAbility.dbc naming the next rank of the spell.
+const SKILL_ABILITY_SUPERSEDED_BY_COLUMN: usize = 8;
+
+/// Columns of Spell.dbc used to find the ranks of a spell.
+const SPELL_ICON_COLUMN: usize = 117;
+const SPELL_NAME_COLUMN: usize = 120;
+
+/// Columns of Talent.dbc written by `talent`.
+const TALENT_TAB_COLUMN: usize = 1;
+const TALENT_TIER_COLUMN: usize = 2;
+const TALENT_COLUMN_INDEX_COLUMN: usize = 3;
+const TALENT_FIRST_RANK_COLUMN: usize = 4;
+const TALENT_MAX_RANKS: usize = 9;
+const TALENT_PREREQ_TALENT_COLUMN: usize = 13;
+const TALENT_PREREQ_RANK_COLUMN: usize = 16;
+
+/// Reduce an icon reference to the form ItemDisplayInfo stores: the file
+/// name under `Interface\Icons` without directory or `.blp` extension.
+/// Accepts either that form or a full client path.
+pub fn icon_name(icon: &str) -> String {
+    let name = icon.rsplit(['\\', '/']).next().unwrap_or(icon);
+    match name.len().checked_sub(4) {
+        Some(cut) if name[cut..].eq_ignore_ascii_case(".blp") => name[..cut].to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Locate `Interface/Icons/<icon>.blp` under `includes_dir`, ignoring case
+/// the way the client does.
+pub fn find_icon_blp(includes_dir: &Path, icon: &str) -> Result<Option<PathBuf>> {
+    let wanted = format!("interface/icons/{}.blp", icon.to_lowercase());
+    Ok(walk_files(includes_dir)?.into_iter().find(|path| {
+        path.strip_prefix(includes_dir)
+            .map(|rel| rel.to_string_lossy().replace('\\', "/").to_lowercase() == wanted)
+            .unwrap_or(false)
+    }))
+}
+
+/// Expand an `item_display` entry into the plain entry that creates the
+/// row: a copy of `base` when given, otherwise an insert that starts from
+/// zeros.  The ID and icon are written by column so the expansion does not
+/// depend on the schema's field names; `values` is applied on top.
+pub fn expand_item_display(
+    id: u32,
+    icon: &str,
+    base: Option<u32>,
+    values: &HashMap<String, ValueType>,
+) -> PatchEntry {
+    let mut values = values.clone();
+    values.insert(ITEM_DISPLAY_ID_COLUMN.to_string(), ValueType::UInt(id as u64));
+    values.insert(ITEM_DISPLAY_ICON_COLUMN.to_string(), ValueType::String(icon_name(icon)));
+    match base {
+        Some(key) => PatchEntry::Copy {
+            key,
+            key_column: None,
+            new_key: None,
+            values,
+        },
+        None => PatchEntry::Insert {
+            key: Some(id),
+            key_column: None,
+            template: None,
+            values,
+        },
+    }
+}
+
+/// The lowest ID above every existing key, for helpers whose new row needs
+/// an ID the patch did not spell out.
+pub fn next_free_id(records: &[Vec<u32>]) -> u32 {
+    records
+        .iter()
+        .filter_map(|r| r.first().copied())
+        .max()
+        .map_or(1, |max| max.saturating_add(1))
+}
+
+/// Set column `col` unless the patch's own `values` already name it.
+fn set_default(values: &mut HashMap<String, ValueType>, col: usize, value: u32) {
+    values
+        .entry(col.to_string())
+        .or_insert(ValueType::UInt(value as u64));
+}
+
+/// Expand a `learn_spell` entry into a SkillLineAbility.dbc insert that
+/// makes `spell` learnable under `skill_line`.  Masks of 0 allow every
+/// race or class.  `id` defaults to the next free ID in `records`.
+#[allow(clippy::too_many_arguments)]
+pub fn expand_learn_spell(
+    id: Option<u32>,
+    spell: u32,
+    skill_line: u32,
+    race_mask: u32,
+    class_mask: u32,
+    min_skill_rank: u32,
+    acquire_method: u32,
+    values: &HashMap<String, ValueType>,
+    records: &[Vec<u32>],
+) -> PatchEntry {
+    let mut values = values.clone();
+    set_default(&mut values, SKILL_ABILITY_SKILL_LINE_COLUMN, skill_line);
+    set_default(&mut values, SKILL_ABILITY_SPELL_COLUMN, spell);
+    set_default(&mut values, SKILL_ABILITY_RACE_MASK_COLUMN, race_mask);
+    set_default(&mut values, SKILL_ABILITY_CLASS_MASK_COLUMN, class_mask);
+    set_default(&mut values, SKILL_ABILITY_MIN_RANK_COLUMN, min_skill_rank);
+    set_default(&mut values, SKILL_ABILITY_ACQUIRE_METHOD_COLUMN, acquire_method);
+    PatchEntry::Insert {
+        key: Some(id.unwrap_or_else(|| next_free_id(records))),
+        key_column: None,
+        template: None,
+        values,
+    }
+}
+
+/// Expand a `talent` entry into a Talent.dbc insert placing `ranks` (one
+/// spell per rank, at most nine) at `tier`/`column` of talent tab `tab`.
+/// `requires` names a prerequisite talent and the number of points it
+/// needs; the DBC stores that as a zero‑based rank index.
+#[allow(clippy::too_many_arguments)]
+pub fn expand_talent(
+    id: Option<u32>,
+    tab: u32,
+    tier: u32,
+    column: u32,
+    ranks: &[u32],
+    requires: Option<(u32, u32)>,
+    values: &HashMap<String, ValueType>,
+    records: &[Vec<u32>],
+) -> Result<PatchEntry> {
+    if ranks.is_empty() || ranks.len() > TALENT_MAX_RANKS {
+        bail!("a talent needs between 1 and {} rank spells, got {}", TALENT_MAX_RANKS, ranks.len());
+    }
+    let mut values = values.clone();
+    set_default(&mut values, TALENT_TAB_COLUMN, tab);
+    set_default(&mut values, TALENT_TIER_COLUMN, tier);
+    set_default(&mut values, TALENT_COLUMN_INDEX_COLUMN, column);
+    for (i, &spell) in ranks.iter().enumerate() {
+        set_default(&mut values, TALENT_FIRST_RANK_COLUMN + i, spell);
+    }
+    if let Some((talent, points)) = requires {
+        if points == 0 {
+            bail!("a prerequisite talent needs at least 1 point");
+        }
+        set_default(&mut values, TALENT_PREREQ_TALENT_COLUMN, talent);
+        set_default(&mut values, TALENT_PREREQ_RANK_COLUMN, points - 1);
+    }
+    Ok(PatchEntry::Insert {
+        key: Some(id.unwrap_or_else(|| next_free_id(records))),
+        key_column: None,
+        template: None,
+        values,
+    })
+}
+
+/// The rank family of `spell` in Spell.dbc (`records`).  The client only
+/// partly links ranks (SkillLineAbility.dbc's SupercededBySpell), so the
+/// family is those links followed in either direction, plus every spell
+/// with the same enUS name that is listed under one of the family's skill
+/// lines.  A spell no skill line lists, like a boss ability, takes in its
+/// unlisted namesakes with the same icon instead.  With `same_name` every
+/// namesake joins, which also catches NPC copies of player spells.
+/// `string_at` resolves a string offset of the table.  Returned in ID
+/// order.
+pub fn spell_family(
+    spell: u32,
+    same_name: bool,
+    records: &[Vec<u32>],
+    abilities: &[Vec<u32>],
+    string_at: impl Fn(u32) -> Option<String>,
+) -> Vec<u32> {
+    let mut links: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut skill_lines: HashMap<u32, BTreeSet<u32>> = HashMap::new();
+    for row in abilities {
+        let (Some(&line), Some(&from), Some(&to)) = (
+            row.get(SKILL_ABILITY_SKILL_LINE_COLUMN),
+            row.get(SKILL_ABILITY_SPELL_COLUMN),
+            row.get(SKILL_ABILITY_SUPERSEDED_BY_COLUMN),
+        ) else {
+            continue;
+        };
+        skill_lines.entry(from).or_default().insert(line);
+        if from != 0 && to != 0 {
+            links.entry(from).or_default().push(to);
+            links.entry(to).or_default().push(from);
+        }
+    }
+    let mut family = BTreeSet::from([spell]);
+    let mut queue = vec![spell];
+    while let Some(current) = queue.pop() {
+        for &next in links.get(&current).into_iter().flatten() {
+            if family.insert(next) {
+                queue.push(next);
+            }
+        }
+    }
+
+    let name_of = |row: &[u32]| row.get(SPELL_NAME_COLUMN).and_then(|&offset| string_at(offset));
+    let Some(origin) = records.iter().find(|r| r.first() == Some(&spell)) else {
+        return family.into_iter().collect();
+    };
+    let name = name_of(origin).unwrap_or_default();
+    if name.is_empty() {
+        return family.into_iter().collect();
+    }
+    let icon = origin.get(SPELL_ICON_COLUMN).copied();
+    let lines: BTreeSet<u32> = family
+        .iter()
+        .filter_map(|id| skill_lines.get(id))
+        .flatten()
+        .copied()
+        .collect();
+    for row in records {
+        let Some(&id) = row.first() else {
+            continue;
+        };
+        if family.contains(&id) || name_of(row).as_deref() != Some(name.as_str()) {
+            continue;
+        }
+        let own_lines = skill_lines.get(&id);
+        let related = if same_name {
+            true
+        } else if lines.is_empty() {
+            own_lines.is_none() && row.get(SPELL_ICON_COLUMN).copied() == icon
+        } else {
+            own_lines.is_some_and(|own| !own.is_disjoint(&lines))
+        };
+        if related {
+            family.insert(id);
+        }
+    }
+    family.into_iter().collect()
+}
+
+/// Expand a `spell_ranks` entry into one Spell.dbc update per member of
+/// `family` that exists in `records`, each setting `values`.
+pub fn expand_spell_ranks(
+    family: &[u32],
+    values: &HashMap<String, ValueType>,
+    records: &[Vec<u32>],
+) -> Vec<PatchEntry> {
+    let existing: BTreeSet<u32> = records.iter().filter_map(|r| r.first().copied()).collect();
+    family
+        .iter()
+        .filter(|id| existing.contains(id))
+        .map(|&key| PatchEntry::Update {
+            key,
+            key_column: None,
+            values: values.clone(),
+        })
+        .collect()
+}
@@ -0,0 +1,396 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::apply::{apply_order, patch_documents};
+use crate::dbc::{find_dbc_file, read_dbc_with_schema};
+use crate::schema::{load_schema, Schema};
+
+/// IDs given to symbolic keys, by table and then symbol, as the map file
+/// stores them.  Read by `load_id_map`, extended by `assign_ids` and
+/// handed to the functions that parse patch files, which replace symbols
+/// with their IDs.
+pub type IdMap = BTreeMap<String, BTreeMap<String, u32>>;
+
+/// The manifest's `ids` section: where the IDs allocated to symbolic keys
+/// (`key: $horsemen_mark_vis`) are kept, and the range of IDs each table
+/// allocates from.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct IdSettings {
+    /// Map file, relative to the manifest.  Defaults to `ids.yaml`.
+    pub map: PathBuf,
+    /// Lowest and highest ID to allocate, by table, e.g.
+    /// `SpellVisual.dbc: [50000, 50999]`.
+    pub ranges: BTreeMap<String, [u32; 2]>,
+}
+
+impl Default for IdSettings {
+    fn default() -> Self {
+        IdSettings {
+            map: PathBuf::from("ids.yaml"),
+            ranges: BTreeMap::new(),
+        }
+    }
+}
+
+impl IdSettings {
+    /// The allocation range of `table`, compared case‑insensitively.
+    fn range(&self, table: &str) -> Option<[u32; 2]> {
+        self.ranges
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(table))
+            .map(|(_, range)| *range)
+    }
+}
+
+/// The symbol a patch value names, without the `$`: a string such as
+/// `$horsemen_mark_vis` made of letters, digits and underscores.
+pub(crate) fn symbol_name(s: &str) -> Option<&str> {
+    let name = s.strip_prefix('$')?;
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some(name)
+}
+
+/// The table and ID of `symbol` in `ids`.
+fn lookup<'a>(ids: &'a IdMap, symbol: &str) -> Option<(&'a str, u32)> {
+    ids.iter()
+        .find_map(|(table, symbols)| symbols.get(symbol).map(|&id| (table.as_str(), id)))
+}
+
+/// Read the map file of `settings`, relative to `base_dir`.  A missing
+/// file is an empty map.
+pub fn load_id_map(settings: &IdSettings, base_dir: &Path) -> Result<IdMap> {
+    let path = base_dir.join(&settings.map);
+    let mut ids = IdMap::new();
+    if path.exists() {
+        let content = fs::read_to_string(&path).with_context(|| format!("Failed to read ID map {:?}", path))?;
+        if !content.trim().is_empty() {
+            ids = serde_yaml::from_str(&content).with_context(|| format!("Failed to parse ID map {:?}", path))?;
+        }
+        let mut seen: HashMap<&str, &str> = HashMap::new();
+        for (table, symbols) in &ids {
+            for symbol in symbols.keys() {
+                if let Some(other) = seen.insert(symbol, table) {
+                    bail!("Symbol ${} is listed under both {} and {} in {:?}", symbol, other, table, path);
+                }
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// The table and entries of a patch in the `dbc:`/`changes:` object form.
+fn object_table(value: &Value) -> Option<(&str, &[Value])> {
+    Some((value.get("dbc")?.as_str()?, value.get("changes")?.as_sequence()?.as_slice()))
+}
+
+/// The tables a patch document names, each with its entries, in any of
+/// the forms `parse_patch_file` accepts.
+fn document_tables(value: &Value) -> Vec<(&str, &[Value])> {
+    match value {
+        Value::Sequence(items) => items.iter().filter_map(object_table).collect(),
+        Value::Mapping(_) if value.get("dbc").is_some() && value.get("changes").is_some() => {
+            object_table(value).into_iter().collect()
+        }
+        Value::Mapping(map) => map
+            .iter()
+            .filter_map(|(table, entries)| {
+                // A list of changes, or a mapping with `truncate` and `changes`
+                let changes = entries.as_sequence().or_else(|| entries.get("changes")?.as_sequence())?;
+                Some((table.as_str()?, changes.as_slice()))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `field` of a `copy` entry's values is the key column the copy
+/// is given its new key in.
+fn is_key_field(field: &str, key_column: Option<&str>, schema: Option<&Schema>) -> bool {
+    let column = |name: &str| {
+        name.parse::<usize>()
+            .ok()
+            .or_else(|| schema.and_then(|s| s.column(name)))
+    };
+    let key = key_column.map_or(Some(0), column);
+    key.is_some() && column(field) == key
+}
+
+/// The key of each record `entries` create: the `key` of an `insert`, the
+/// `id` of a helper entry, or the `new_key` of a `copy` or the key column
+/// in its values, including copies within a `zone`.
+fn new_keys<'a>(entries: &'a [Value], schema: Option<&Schema>, out: &mut Vec<&'a Value>) {
+    for entry in entries {
+        match entry.get("type").and_then(Value::as_str) {
+            Some("insert") => out.extend(entry.get("key")),
+            Some("item_display" | "learn_spell" | "talent") => out.extend(entry.get("id")),
+            Some("copy") => {
+                out.extend(entry.get("new_key"));
+                let key_column = entry.get("key_column").and_then(Value::as_str);
+                if let Some(Value::Mapping(values)) = entry.get("values") {
+                    out.extend(values.iter().filter_map(|(field, value)| {
+                        is_key_field(field.as_str()?, key_column, schema).then_some(value)
+                    }));
+                }
+            }
+            Some("zone") => {
+                if let Some(changes) = entry.get("changes").and_then(Value::as_sequence) {
+                    new_keys(changes, schema, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Give every symbol that a patch file in `patch_paths` uses as the key
+/// of a new record (see `new_keys`) an ID, unless the map already has
+/// one in `ids`.  IDs come from the table's range in `settings`, skipping
+/// IDs the table in `dbc_dir` already has, IDs patches write literally
+/// and IDs of the map, and are added to `ids` and the map file so later
+/// builds, and other contributors, get the same ones.  Unless `save` is
+/// set the map file is left alone.
+pub fn assign_ids(
+    ids: &mut IdMap,
+    patch_paths: &[PathBuf],
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    settings: &IdSettings,
+    base_dir: &Path,
+    save: bool,
+) -> Result<()> {
+    let mut documents = Vec::with_capacity(patch_paths.len());
+    let mut file_settings = Vec::with_capacity(patch_paths.len());
+    for path in patch_paths {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read patch file {:?}", path))?;
+        let (docs, settings) = patch_documents(&content, path)?;
+        documents.push(docs);
+        file_settings.push((path.as_path(), settings));
+    }
+
+    let mut wanted: Vec<(String, String)> = Vec::new();
+    let mut defined_in: HashMap<String, (String, PathBuf)> = HashMap::new();
+    let mut literal: HashMap<String, HashSet<u32>> = HashMap::new();
+    // Files in the order `load_patches` applies them, so symbols are
+    // numbered in the order their records are created
+    for i in apply_order(&file_settings)? {
+        let path = &patch_paths[i];
+        for document in &documents[i] {
+            for (table, entries) in document_tables(document) {
+                let schema = load_schema(schema_dir, table);
+                let mut keys = Vec::new();
+                new_keys(entries, schema.as_ref(), &mut keys);
+                for key in keys {
+                    if let Some(id) = key.as_u64().and_then(|id| u32::try_from(id).ok()) {
+                        literal.entry(table.to_lowercase()).or_default().insert(id);
+                        continue;
+                    }
+                    let Some(symbol) = key.as_str().and_then(symbol_name) else {
+                        continue;
+                    };
+                    match defined_in.get(symbol) {
+                        Some((other, other_path)) if !other.eq_ignore_ascii_case(table) => bail!(
+                            "Symbol ${} is the key of new records in both {} ({:?}) and {} ({:?})",
+                            symbol,
+                            other,
+                            other_path,
+                            table,
+                            path
+                        ),
+                        Some(_) => {}
+                        None => {
+                            defined_in.insert(symbol.to_string(), (table.to_string(), path.clone()));
+                            wanted.push((table.to_string(), symbol.to_string()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let map_path = base_dir.join(&settings.map);
+    let mut stock: HashMap<String, HashSet<u32>> = HashMap::new();
+    let mut allocated = 0;
+    for (table, symbol) in wanted {
+        if let Some((mapped, id)) = lookup(ids, &symbol) {
+            if !mapped.eq_ignore_ascii_case(&table) {
+                bail!(
+                    "Symbol ${} has ID {} in {} according to {:?}, but is now the key of a new record in {}; remove it from the map to allocate a new ID",
+                    symbol,
+                    id,
+                    mapped,
+                    map_path,
+                    table
+                );
+            }
+            continue;
+        }
+        let Some([low, high]) = settings.range(&table) else {
+            bail!(
+                "No ID range for {} to allocate ${} from; add one under `ids: ranges:` in the manifest",
+                table,
+                symbol
+            );
+        };
+        let key = table.to_lowercase();
+        if !stock.contains_key(&key) {
+            let path = find_dbc_file(dbc_dir, &table);
+            let existing = if path.exists() {
+                read_dbc_with_schema(&path, schema_dir)?.1.iter().filter_map(|r| r.first().copied()).collect()
+            } else {
+                HashSet::new()
+            };
+            stock.insert(key.clone(), existing);
+        }
+        let mapped: HashSet<u32> = ids
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(&table))
+            .flat_map(|(_, symbols)| symbols.values().copied())
+            .collect();
+        let used = |id: &u32| {
+            stock[&key].contains(id) || literal.get(&key).is_some_and(|l| l.contains(id)) || mapped.contains(id)
+        };
+        let Some(id) = (low..=high).find(|id| !used(id)) else {
+            bail!("No free ID left in {}'s range {}-{} for ${}", table, low, high, symbol);
+        };
+        say!("Allocated ID {} in {} for ${}", id, table, symbol);
+        let listed = ids.keys().find(|name| name.eq_ignore_ascii_case(&table)).cloned();
+        ids.entry(listed.unwrap_or(table)).or_default().insert(symbol, id);
+        allocated += 1;
+    }
+    if allocated > 0 && !save {
+        say!("Not writing {} new ID(s) to {}", allocated, map_path.display());
+    } else if allocated > 0 {
+        let mut out = String::from(
+            "# IDs allocated to symbolic keys, by table.  Keep this file with the\n\
+             # patches so every build, and every contributor, uses the same IDs.\n",
+        );
+        for (table, symbols) in ids.iter() {
+            let _ = writeln!(out, "{}:", table);
+            for (symbol, id) in symbols {
+                let _ = writeln!(out, "  {}: {}", symbol, id);
+            }
+        }
+        fs::write(&map_path, out).with_context(|| format!("Failed to write ID map {:?}", map_path))?;
+        say!("Wrote {} new ID(s) to {}", allocated, map_path.display());
+    }
+    Ok(())
+}
+
+/// Replace the symbols in a patch document with their IDs in `ids`.
+/// Outside `values` and `where` every symbol must have one; within them a
+/// string that only looks like a symbol, such as the spell text `$d`, is
+/// left as it is.
+pub(crate) fn resolve_symbols(value: &mut Value, ids: &IdMap, path: &Path) -> Result<()> {
+    resolve_in(value, ids, false, path)
+}
+
+fn resolve_in(value: &mut Value, ids: &IdMap, in_values: bool, path: &Path) -> Result<()> {
+    match value {
+        Value::String(s) => {
+            let Some(symbol) = symbol_name(s) else {
+                return Ok(());
+            };
+            match lookup(ids, symbol) {
+                Some((_, id)) => *value = Value::Number(id.into()),
+                None if !in_values => bail!(
+                    "Symbol ${} in {:?} has no ID yet; `apply` and `build` allocate one for the entry that creates its record",
+                    symbol,
+                    path
+                ),
+                None => {}
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                resolve_in(item, ids, in_values, path)?;
+            }
+        }
+        Value::Mapping(map) => {
+            for (field, item) in map.iter_mut() {
+                let inner = in_values || matches!(field.as_str(), Some("values" | "where"));
+                resolve_in(item, ids, inner, path)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbc::{write_dbc, DbcFormat, DbcHeader, Endianness};
+
+    /// A directory unique to this test run and `name`, holding a
+    /// one‑column Test.dbc with IDs 1 and 2 and a patch inserting `$a`,
+    /// the literal 3 and `$b`.
+    fn project(name: &str) -> (PathBuf, Vec<PathBuf>) {
+        let dir = std::env::temp_dir().join(format!("wow_dbc_patcher-ids-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let header = DbcHeader {
+            magic: *b"WDBC",
+            record_count: 2,
+            field_count: 1,
+            record_size: 4,
+            string_block_size: 1,
+            format: DbcFormat::Wdbc,
+            endianness: Endianness::Little,
+            layout: None,
+        };
+        write_dbc(dir.join("Test.dbc"), &header, &[vec![1], vec![2]], &[0]).unwrap();
+        let patch = dir.join("patch.yaml");
+        let mut content = String::from("Test.dbc:\n");
+        for key in ["$a", "3", "$b"] {
+            let _ = writeln!(content, "  - type: insert\n    key: {}\n    values: {{}}", key);
+        }
+        fs::write(&patch, content).unwrap();
+        (dir, vec![patch])
+    }
+
+    fn settings(low: u32, high: u32) -> IdSettings {
+        IdSettings {
+            ranges: BTreeMap::from([("test.dbc".to_string(), [low, high])]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn allocation_skips_stock_literal_and_mapped_ids() {
+        let (dir, patches) = project("skip");
+        fs::write(dir.join("ids.yaml"), "Test.dbc:\n  c: 4\n").unwrap();
+        let mut ids = load_id_map(&settings(1, 6), &dir).unwrap();
+        assign_ids(&mut ids, &patches, &dir, &dir, &settings(1, 6), &dir, true).unwrap();
+        let table = &ids["Test.dbc"];
+        assert_eq!((table["a"], table["b"], table["c"]), (5, 6, 4));
+
+        // Saved, so the next run reads the same IDs back
+        assert_eq!(load_id_map(&settings(1, 6), &dir).unwrap(), ids);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unsaved_ids_are_not_written() {
+        let (dir, patches) = project("unsaved");
+        let mut ids = IdMap::new();
+        assign_ids(&mut ids, &patches, &dir, &dir, &settings(10, 20), &dir, false).unwrap();
+        assert_eq!((ids["Test.dbc"]["a"], ids["Test.dbc"]["b"]), (10, 11));
+        assert!(!dir.join("ids.yaml").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exhausted_ranges_are_errors() {
+        let (dir, patches) = project("exhausted");
+        let mut ids = IdMap::new();
+        let err = assign_ids(&mut ids, &patches, &dir, &dir, &settings(1, 4), &dir, false).unwrap_err();
+        assert!(err.to_string().contains("No free ID left in Test.dbc's range 1-4 for $b"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
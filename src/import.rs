@@ -0,0 +1,240 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Number of locale slots of a localized string in 1.12 tables; a mask
+/// column follows them.
+const LOCALE_SLOTS: usize = 8;
+
+/// Column definition formats `import-schema` understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DefinitionFormat {
+    /// WDBX Editor definition XML (`<Table Name=".." Build=".."><Field .. />`)
+    Wdbx,
+    /// CSV with a header row naming `name`, `type` and optionally `array`
+    /// and `ref` columns, one field per row
+    Csv,
+}
+
+/// One column group of an imported definition, before array and locale
+/// expansion.
+struct ImportedField {
+    name: String,
+    kind: String,
+    array_size: usize,
+    reference: Option<String>,
+}
+
+/// Parse the attributes of a tag body such as `Field Name="ID" Type="int"`.
+fn tag_attributes(body: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+    let mut rest = body;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].split_whitespace().last().unwrap_or("").to_lowercase();
+        let after = rest[eq + 1..].trim_start();
+        let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(end) = after[1..].find(quote) else {
+            break;
+        };
+        attrs.insert(key, after[1..1 + end].to_string());
+        rest = &after[end + 2..];
+    }
+    attrs
+}
+
+/// Read the tables of a WDBX Editor definition file.  Only tables without a
+/// `Build` attribute or with the requested build are kept.
+fn parse_wdbx(content: &str, build: &str) -> Vec<(String, Vec<ImportedField>)> {
+    let mut tables: Vec<(String, Vec<ImportedField>)> = Vec::new();
+    let mut current: Option<(String, Vec<ImportedField>)> = None;
+    let mut rest = content;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim();
+        rest = &rest[start + end + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        match name.as_str() {
+            "table" if tag.starts_with('/') => {
+                if let Some(table) = current.take() {
+                    tables.push(table);
+                }
+            }
+            "table" => {
+                let attrs = tag_attributes(tag);
+                let wanted = attrs.get("build").is_none_or(|b| b == build);
+                current = if wanted {
+                    attrs.get("name").map(|n| (n.clone(), Vec::new()))
+                } else {
+                    None
+                };
+            }
+            "field" => {
+                if let Some((_, fields)) = current.as_mut() {
+                    let attrs = tag_attributes(tag);
+                    if let Some(field_name) = attrs.get("name") {
+                        fields.push(ImportedField {
+                            name: field_name.clone(),
+                            kind: attrs.get("type").cloned().unwrap_or_else(|| "int".to_string()),
+                            array_size: attrs
+                                .get("arraysize")
+                                .and_then(|s| s.parse().ok())
+                                .unwrap_or(1),
+                            reference: None,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    tables
+}
+
+/// Read a CSV definition for a single table.
+fn parse_csv(content: &str) -> Result<Vec<ImportedField>> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+    let header: Vec<String> = lines
+        .next()
+        .context("CSV definition is empty")?
+        .split(',')
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+    let Some(name_col) = column(&["name", "field"]) else {
+        bail!("CSV definition needs a `name` column");
+    };
+    let type_col = column(&["type"]);
+    let array_col = column(&["array", "arraysize", "count"]);
+    let ref_col = column(&["ref", "reference"]);
+
+    let mut fields = Vec::new();
+    for line in lines {
+        let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+        let cell = |col: Option<usize>| col.and_then(|c| cells.get(c)).filter(|s| !s.is_empty());
+        let Some(name) = cell(Some(name_col)) else {
+            continue;
+        };
+        fields.push(ImportedField {
+            name: name.to_string(),
+            kind: cell(type_col).map_or_else(|| "int".to_string(), |s| s.to_string()),
+            array_size: cell(array_col).and_then(|s| s.parse().ok()).unwrap_or(1),
+            reference: cell(ref_col).map(|s| s.to_string()),
+        });
+    }
+    Ok(fields)
+}
+
+/// Render imported fields as schema YAML lines, expanding arrays to
+/// `_1.._N` suffixes and localized strings to `_lang_0.._lang_7` plus a
+/// `_lang_Mask` column, the way the built‑in schemas spell them.
+fn schema_lines(fields: &[ImportedField]) -> Vec<String> {
+    let entry = |name: &str, kind: Option<&str>, reference: Option<&str>| match (kind, reference) {
+        (None, None) => format!("  - {}", name),
+        (Some(k), None) => format!("  - {{ name: {}, type: {} }}", name, k),
+        (None, Some(r)) => format!("  - {{ name: {}, ref: {} }}", name, r),
+        (Some(k), Some(r)) => format!("  - {{ name: {}, type: {}, ref: {} }}", name, k, r),
+    };
+    let mut lines = Vec::new();
+    for f in fields {
+        let kind = f.kind.to_lowercase();
+        let names: Vec<String> = if f.array_size > 1 {
+            (1..=f.array_size).map(|i| format!("{}_{}", f.name, i)).collect()
+        } else {
+            vec![f.name.clone()]
+        };
+        for name in names {
+            match kind.as_str() {
+                "loc" => {
+                    for slot in 0..LOCALE_SLOTS {
+                        lines.push(entry(&format!("{}_lang_{}", name, slot), Some("string"), None));
+                    }
+                    lines.push(entry(&format!("{}_lang_Mask", name), None, None));
+                }
+                "string" => lines.push(entry(&name, Some("string"), f.reference.as_deref())),
+                "float" | "single" => lines.push(entry(&name, Some("float"), f.reference.as_deref())),
+                "bool" | "boolean" => lines.push(entry(&name, Some("bool"), f.reference.as_deref())),
+                // Other kinds are 32‑bit integers; they stay untyped so
+                // patches may keep writing raw cells to them
+                _ => lines.push(entry(&name, None, f.reference.as_deref())),
+            }
+        }
+    }
+    lines
+}
+
+/// Convert community column definitions into schema files in `schema_dir`.
+/// WDBX Editor files may hold many tables; each one matching `build` (and
+/// `table`, when given) is written as `<Table>.dbc.yaml`.  A CSV file holds
+/// one table, named by `table` or else by the file stem.  Existing schema
+/// files are kept unless `force` is set.
+pub fn import_schema_command(
+    source: &Path,
+    format: DefinitionFormat,
+    table: Option<&str>,
+    build: &str,
+    schema_dir: &Path,
+    force: bool,
+) -> Result<()> {
+    let content = fs::read_to_string(source)
+        .with_context(|| format!("Failed to read definition file {:?}", source))?;
+    let tables: Vec<(String, Vec<ImportedField>)> = match format {
+        DefinitionFormat::Wdbx => parse_wdbx(&content, build)
+            .into_iter()
+            .filter(|(name, _)| table.is_none_or(|t| t.trim_end_matches(".dbc").eq_ignore_ascii_case(name)))
+            .collect(),
+        DefinitionFormat::Csv => {
+            let name = match table {
+                Some(t) => t.to_string(),
+                None => source
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .context("Cannot derive a table name from the file name; pass --table")?
+                    .to_string(),
+            };
+            vec![(name, parse_csv(&content)?)]
+        }
+    };
+    if tables.is_empty() {
+        bail!("No matching table definitions in {:?}", source);
+    }
+
+    fs::create_dir_all(schema_dir)
+        .with_context(|| format!("Failed to create schema directory {:?}", schema_dir))?;
+    for (name, fields) in tables {
+        let table_file = format!("{}.dbc", name.trim_end_matches(".dbc"));
+        let out = schema_dir.join(format!("{}.yaml", table_file));
+        if out.exists() && !force {
+            println!("Skipping {}: {} exists (use --force to overwrite)", table_file, out.display());
+            continue;
+        }
+        let lines = schema_lines(&fields);
+        let mut yaml = format!(
+            "# Schema for {} imported from {}\n#\n# Review string types and add `ref:` annotations by hand.\nfields:\n",
+            table_file,
+            source.display()
+        );
+        for line in &lines {
+            yaml.push_str(line);
+            yaml.push('\n');
+        }
+        fs::write(&out, yaml).with_context(|| format!("Failed to write {:?}", out))?;
+        println!("Wrote {} ({} columns)", out.display(), lines.len());
+    }
+    Ok(())
+}
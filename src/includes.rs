@@ -0,0 +1,294 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::dedup::ArchiveEntry;
+use crate::manifest::Manifest;
+use crate::ownership::wildcard_match;
+use crate::refs::walk_files;
+
+/// File in the root of the includes directory that picks, leaves out and
+/// renames the files going into the archive.  It is never archived itself.
+pub const INCLUDES_MANIFEST: &str = "includes.yaml";
+
+/// The contents of `includes.yaml`.  Paths are relative to the includes
+/// directory, with either slash; patterns use `*` for any run of
+/// characters, and one without a slash also matches bare file names, so
+/// `*.psd` leaves out Photoshop sources in every directory.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IncludeManifest {
+    /// Files to archive; when empty, every file is.
+    pub files: Vec<String>,
+    /// Files to leave out, even when `files` lists them.
+    pub exclude: Vec<String>,
+    /// Archive paths of files stored under another path than they have in
+    /// the directory, keyed by their path there.
+    pub rename: BTreeMap<String, String>,
+}
+
+impl IncludeManifest {
+    /// Read `includes.yaml` from `includes_dir`, or an empty manifest when
+    /// there is none.
+    pub fn load(includes_dir: &Path) -> Result<IncludeManifest> {
+        let path = includes_dir.join(INCLUDES_MANIFEST);
+        if !path.is_file() {
+            return Ok(IncludeManifest::default());
+        }
+        let text = fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        if text.trim().is_empty() {
+            return Ok(IncludeManifest::default());
+        }
+        serde_yaml::from_str(&text).with_context(|| format!("Failed to parse {:?}", path))
+    }
+}
+
+/// Patterns given on the command line with `--include-glob` and
+/// `--exclude-glob`, in the syntax of `includes.yaml`.
+#[derive(Debug, Clone, Default)]
+pub struct IncludeGlobs {
+    /// When not empty, only files matching one of these are archived.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// Whether the include at `rel` (forward slashes) matches `pattern`.
+fn pattern_matches(pattern: &str, rel: &str) -> bool {
+    let pattern = pattern.replace('\\', "/");
+    if wildcard_match(&pattern, rel) {
+        return true;
+    }
+    let file_name = rel.rsplit('/').next().unwrap_or(rel);
+    !pattern.contains('/') && wildcard_match(&pattern, file_name)
+}
+
+/// Whether `rel` matches any of `patterns`.
+fn any_matches(patterns: &[String], rel: &str) -> bool {
+    patterns.iter().any(|pattern| pattern_matches(pattern, rel))
+}
+
+/// The files under `includes_dir` that go into the archive, each with the
+/// path it is stored under (forward slashes), and how many were left out.
+/// A file is left out when the manifest's `exclude_includes`, the
+/// `exclude` of `includes.yaml` or an `--exclude-glob` matches it, or when
+/// `files` or `--include-glob` are given and none of them matches it.
+/// Entries of `files` and `rename` that match no file are warned about,
+/// and two files renamed to the same archive path are an error.
+pub fn select_includes(includes_dir: &Path, manifest: &Manifest, globs: &IncludeGlobs) -> Result<(Vec<ArchiveEntry>, usize)> {
+    let listed = IncludeManifest::load(includes_dir)?;
+    let renames: HashMap<String, &String> = listed
+        .rename
+        .iter()
+        .map(|(from, to)| (from.replace('\\', "/").to_lowercase(), to))
+        .collect();
+    let mut entries: Vec<ArchiveEntry> = Vec::new();
+    let mut excluded = 0usize;
+    let mut seen_files = vec![false; listed.files.len()];
+    let mut seen_renames: Vec<String> = Vec::new();
+    let mut stored: HashMap<String, String> = HashMap::new();
+    for path in walk_files(includes_dir)? {
+        let rel_path = path.strip_prefix(includes_dir).unwrap_or(&path);
+        let rel = rel_path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if rel.eq_ignore_ascii_case(INCLUDES_MANIFEST) {
+            continue;
+        }
+        for (seen, pattern) in seen_files.iter_mut().zip(&listed.files) {
+            *seen |= pattern_matches(pattern, &rel);
+        }
+        let picked = (listed.files.is_empty() || any_matches(&listed.files, &rel))
+            && (globs.include.is_empty() || any_matches(&globs.include, &rel));
+        if !picked
+            || manifest.excludes_include(&rel)
+            || any_matches(&listed.exclude, &rel)
+            || any_matches(&globs.exclude, &rel)
+        {
+            excluded += 1;
+            continue;
+        }
+        let archive_name = match renames.get(&rel.to_lowercase()) {
+            Some(to) => {
+                seen_renames.push(rel.to_lowercase());
+                to.replace('\\', "/")
+            }
+            None => rel.clone(),
+        };
+        if let Some(earlier) = stored.insert(archive_name.to_lowercase(), rel.clone()) {
+            bail!(
+                "Both {} and {} would be stored as {} (see {})",
+                earlier,
+                rel,
+                archive_name,
+                INCLUDES_MANIFEST
+            );
+        }
+        entries.push(ArchiveEntry { path, archive_name });
+    }
+    for (seen, pattern) in seen_files.iter().zip(&listed.files) {
+        if !seen {
+            warning!("{:?} under `files` in {} matches no include file", pattern, INCLUDES_MANIFEST);
+        }
+    }
+    for from in listed.rename.keys() {
+        if !seen_renames.contains(&from.replace('\\', "/").to_lowercase()) {
+            warning!("{:?} under `rename` in {} is not an archived include file", from, INCLUDES_MANIFEST);
+        }
+    }
+    Ok((entries, excluded))
+}
+
+/// Directories of the client's archives in the casing it writes them, so
+/// `--normalize-paths` stores `dbfilesclient/spell.dbc` as
+/// `DBFilesClient\spell.dbc`.  Other directories keep the casing of the
+/// first file stored under them.
+const CANONICAL_DIRS: &[&str] = &[
+    "AddOns", "Ambience", "Buttons", "Cameras", "Character", "Creature", "Cursor", "DBFilesClient", "Doodads",
+    "Dungeons", "Environments", "Fonts", "FrameXML", "Glues", "Icons", "Interface", "Item", "Minimap", "Music",
+    "ObjectComponents", "Particles", "Shield", "Sound", "Spells", "TargetingFrame", "TextureComponents", "Textures",
+    "Tooltips", "Weapon", "World", "WorldMap",
+];
+
+/// Rewrite the archive paths of `entries` with backslashes and one casing
+/// per directory: the client's for the directories it has (see
+/// `CANONICAL_DIRS`), otherwise that of the first entry under it.  File
+/// names are left as they are.  Returns how many paths changed.
+pub fn normalize_archive_paths(entries: &mut [ArchiveEntry]) -> usize {
+    // Lower-cased directory path -> the name its last directory is given
+    let mut spellings: HashMap<String, String> = HashMap::new();
+    let mut changed = 0;
+    for entry in entries.iter_mut() {
+        let parts: Vec<&str> = entry.archive_name.split(['/', '\\']).filter(|p| !p.is_empty()).collect();
+        let Some((file_name, dirs)) = parts.split_last() else {
+            continue;
+        };
+        let mut normalized: Vec<String> = Vec::new();
+        let mut prefix = String::new();
+        for dir in dirs {
+            prefix.push_str(&dir.to_lowercase());
+            prefix.push('\\');
+            let spelled = spellings.entry(prefix.clone()).or_insert_with(|| {
+                CANONICAL_DIRS
+                    .iter()
+                    .find(|canonical| canonical.eq_ignore_ascii_case(dir))
+                    .map_or_else(|| dir.to_string(), |canonical| canonical.to_string())
+            });
+            normalized.push(spelled.clone());
+        }
+        normalized.push(file_name.to_string());
+        let normalized = normalized.join("\\");
+        if normalized != entry.archive_name {
+            debug!("Storing {} as {}", entry.archive_name, normalized);
+            entry.archive_name = normalized;
+            changed += 1;
+        }
+    }
+    changed
+}
+
+/// Warn about archive paths of `entries` that differ only by case: files
+/// the client would load as one while MPQ readers that compare case see
+/// two, and directories written in several casings, which such readers
+/// extract as separate directories.
+pub fn warn_case_collisions(entries: &[ArchiveEntry]) {
+    let mut files: HashMap<String, &ArchiveEntry> = HashMap::new();
+    // Lower-cased directory path -> first spelling, and the file it came from
+    let mut dirs: HashMap<String, (String, &ArchiveEntry)> = HashMap::new();
+    let mut reported: HashSet<String> = HashSet::new();
+    for entry in entries {
+        let name = entry.archive_name.replace('/', "\\");
+        if let Some(earlier) = files.insert(name.to_lowercase(), entry) {
+            if earlier.archive_name == entry.archive_name {
+                warning!(
+                    "{} and {} are both stored as {}",
+                    earlier.path.display(),
+                    entry.path.display(),
+                    entry.archive_name
+                );
+            } else {
+                warning!(
+                    "{} ({}) and {} ({}) are the same archive path apart from case",
+                    earlier.archive_name,
+                    earlier.path.display(),
+                    entry.archive_name,
+                    entry.path.display()
+                );
+            }
+            continue;
+        }
+        let mut end = 0;
+        while let Some(at) = name[end..].find('\\') {
+            end += at;
+            let dir = &name[..end];
+            end += 1;
+            match dirs.get(&dir.to_lowercase()) {
+                Some((spelled, earlier)) if spelled != dir && reported.insert(dir.to_string()) => {
+                    warning!(
+                        "directory {} of {} is written {} by {}; use one casing (see --normalize-paths)",
+                        dir,
+                        entry.path.display(),
+                        spelled,
+                        earlier.path.display()
+                    );
+                }
+                Some(_) => {}
+                None => {
+                    dirs.insert(dir.to_lowercase(), (dir.to_string(), entry));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::capture;
+    use std::path::PathBuf;
+
+    fn entries(names: &[&str]) -> Vec<ArchiveEntry> {
+        names
+            .iter()
+            .map(|name| ArchiveEntry {
+                path: PathBuf::from(name.replace('\\', "/")),
+                archive_name: name.to_string(),
+            })
+            .collect()
+    }
+
+    fn names(entries: &[ArchiveEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.archive_name.as_str()).collect()
+    }
+
+    #[test]
+    fn client_directories_take_its_casing() {
+        let mut entries = entries(&["dbfilesclient/Spell.dbc", "DBFILESCLIENT\\SpellVisual.dbc", "Spells\\Fire.m2"]);
+        assert_eq!(normalize_archive_paths(&mut entries), 2);
+        assert_eq!(
+            names(&entries),
+            ["DBFilesClient\\Spell.dbc", "DBFilesClient\\SpellVisual.dbc", "Spells\\Fire.m2"]
+        );
+    }
+
+    #[test]
+    fn other_directories_keep_their_first_casing() {
+        let mut entries = entries(&["Spells\\MyMod\\a.blp", "spells/mymod/B.blp", "SPELLS\\MYMOD\\Sub\\c.blp"]);
+        assert_eq!(normalize_archive_paths(&mut entries), 2);
+        assert_eq!(
+            names(&entries),
+            ["Spells\\MyMod\\a.blp", "Spells\\MyMod\\B.blp", "Spells\\MyMod\\Sub\\c.blp"]
+        );
+    }
+
+    #[test]
+    fn files_differing_only_by_case_collide() {
+        let entries = entries(&["Spells\\Fire.blp", "Spells\\fire.BLP", "Spells\\Ice.blp"]);
+        let ((), captured) = capture(|| warn_case_collisions(&entries));
+        assert_eq!(captured.warnings().len(), 1, "{:?}", captured.warnings());
+        assert!(captured.warnings()[0].contains("apart from case"), "{:?}", captured.warnings());
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::dbc::{dbc_file_name, find_dbc_file, read_dbc_with_schema, string_at};
+use crate::schema::LOCALE_SLOTS;
+
+/// Floats outside this magnitude are taken for integers that happen to
+/// have a valid bit pattern: the small IDs and counts tables are full of
+/// read as denormals far below it.  Colours packed as floats reach well
+/// into the millions, hence the wide upper bound.
+const FLOAT_RANGE: (f32, f32) = (1e-6, 1e12);
+
+/// How many distinct values of a column the draft lists as a hint.
+const SAMPLES: usize = 3;
+
+/// What the values of a column look like.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Guess {
+    /// Zero in every record, so anything could be stored there.
+    Empty,
+    /// Every value is the offset of a string in the string block.
+    String,
+    /// Every non‑zero value reads as a float of sensible magnitude.
+    Float,
+    Int,
+}
+
+/// Whether `offset` is where a string of `block` starts.
+fn string_start(block: &[u8], offset: u32) -> bool {
+    let offset = offset as usize;
+    offset < block.len() && (offset == 0 || block[offset - 1] == 0)
+}
+
+/// Guess what column `col` holds from its values in `records`.  Small
+/// integers are string offsets too (offset 1 is always a string start),
+/// so a column only counts as strings when some value points further
+/// into the block.
+fn guess_column(records: &[Vec<u32>], block: &[u8], col: usize) -> Guess {
+    let values: Vec<u32> = records.iter().filter_map(|r| r.get(col).copied()).collect();
+    if values.iter().all(|&v| v == 0) {
+        return Guess::Empty;
+    }
+    if values.iter().all(|&v| string_start(block, v)) && values.iter().any(|&v| v > 1) {
+        return Guess::String;
+    }
+    let float = |v: u32| {
+        let f = f32::from_bits(v).abs();
+        v == 0 || (f.is_finite() && f >= FLOAT_RANGE.0 && f <= FLOAT_RANGE.1)
+    };
+    if values.iter().all(|&v| float(v)) {
+        return Guess::Float;
+    }
+    Guess::Int
+}
+
+/// A few distinct non‑zero values of column `col`, rendered as its guess
+/// reads them, for the comment beside its draft field.
+fn samples(records: &[Vec<u32>], block: &[u8], col: usize, guess: Guess) -> String {
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    for &value in records.iter().filter_map(|r| r.get(col)) {
+        if value == 0 || !seen.insert(value) {
+            continue;
+        }
+        out.push(match guess {
+            Guess::String => {
+                let text: String = string_at(block, value).unwrap_or_default().chars().take(30).collect();
+                format!("{:?}", text)
+            }
+            Guess::Float => f32::from_bits(value).to_string(),
+            Guess::Int | Guess::Empty => value.to_string(),
+        });
+        if out.len() == SAMPLES {
+            break;
+        }
+    }
+    out.join(", ")
+}
+
+/// The schema lines of a draft for a table with `field_count` columns.
+/// Columns are named `Field_<index>` for the author to rename, except an
+/// `ID` column 0 whose values are unique.  A string column followed by
+/// seven string or empty columns and a mask, and not preceded by another
+/// string column, is written as a localized string.
+fn draft_lines(records: &[Vec<u32>], block: &[u8], field_count: usize) -> Vec<String> {
+    let guesses: Vec<Guess> = (0..field_count).map(|col| guess_column(records, block, col)).collect();
+    let mut lines = Vec::new();
+    let mut col = 0;
+    while col < field_count {
+        // A longer run of string columns is an array, such as the files
+        // of a sound, rather than locales
+        let is_loc = guesses[col] == Guess::String
+            && (col == 0 || guesses[col - 1] != Guess::String)
+            && col + LOCALE_SLOTS < field_count
+            && guesses[col + 1..col + LOCALE_SLOTS]
+                .iter()
+                .all(|&g| g == Guess::String || g == Guess::Empty)
+            && guesses[col + LOCALE_SLOTS] != Guess::String;
+        if is_loc {
+            let name = format!("Field_{}", col);
+            let hint = samples(records, block, col, Guess::String);
+            lines.push(format!("  - {{ name: {}_lang_0, type: string }}  # {}", name, hint));
+            for slot in 1..LOCALE_SLOTS {
+                lines.push(format!("  - {{ name: {}_lang_{}, type: string }}", name, slot));
+            }
+            lines.push(format!("  - {}_lang_Mask", name));
+            col += LOCALE_SLOTS + 1;
+            continue;
+        }
+        let name = if col == 0 {
+            let ids: BTreeSet<u32> = records.iter().filter_map(|r| r.first().copied()).collect();
+            if ids.len() == records.len() {
+                "ID".to_string()
+            } else {
+                "Field_0".to_string()
+            }
+        } else {
+            format!("Field_{}", col)
+        };
+        let line = match guesses[col] {
+            Guess::String => format!("  - {{ name: {}, type: string }}", name),
+            Guess::Float => format!("  - {{ name: {}, type: float }}", name),
+            // Integers stay untyped, as in the built‑in schemas
+            Guess::Int | Guess::Empty => format!("  - {}", name),
+        };
+        let hint = match guesses[col] {
+            Guess::Empty => "always 0".to_string(),
+            guess => samples(records, block, col, guess),
+        };
+        lines.push(format!("{}  # {}", line, hint));
+        col += 1;
+    }
+    lines
+}
+
+/// Write a draft schema for `table` in `dbc_dir` to `schema_dir`, with
+/// column types guessed from its records: string offsets that point at
+/// strings of the string block, float bit patterns and plain integers.
+/// An existing schema file is kept unless `force` is set.
+pub fn schema_gen_command(table: &str, dbc_dir: &Path, schema_dir: &Path, force: bool) -> Result<()> {
+    let path = if Path::new(table).is_file() {
+        Path::new(table).to_path_buf()
+    } else {
+        find_dbc_file(dbc_dir, table)
+    };
+    let table_file = dbc_file_name(&path)?;
+    let out = schema_dir.join(format!("{}.yaml", table_file));
+    if out.exists() && !force {
+        bail!("{} exists; pass --force to overwrite it", out.display());
+    }
+    let (header, records, block) = read_dbc_with_schema(&path, schema_dir)?;
+    let lines = draft_lines(&records, &block, header.field_count as usize);
+
+    let mut yaml = format!(
+        "# Draft schema for {} inferred by `schema-gen` from {} records.\n\
+         # Column types are guesses; rename the fields, check the types and\n\
+         # add `ref:` annotations before relying on it.\n\
+         fields:\n",
+        table_file,
+        records.len()
+    );
+    for line in &lines {
+        yaml.push_str(line);
+        yaml.push('\n');
+    }
+    fs::create_dir_all(schema_dir)
+        .with_context(|| format!("Failed to create schema directory {:?}", schema_dir))?;
+    fs::write(&out, yaml).with_context(|| format!("Failed to write {:?}", out))?;
+    println!("Wrote {} ({} columns)", out.display(), header.field_count);
+    Ok(())
+}
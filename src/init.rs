@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::client::detect_patch_chain;
+use crate::extract::extract_command;
+use crate::lock::LOCK_FILE;
+use crate::manifest::DEFAULT_MANIFEST;
+
+/// Directories a project keeps its tables, patches, schemas, shipped
+/// files and output in, as the other commands default to them.
+const PROJECT_DIRS: [&str; 5] = ["dbc", "patches", "schema", "includes", "build"];
+
+/// Starter patch written to `patches/`.  It is marked experimental, so a
+/// fresh project builds the stock tables until the author changes it.
+const EXAMPLE_PATCH: &str = "\
+# An example patch.  Each top-level key names a table in dbc/, and its
+# entries change records there; see \"Patch format\" in the README.
+#
+# This file is marked experimental, so `apply` and `build` skip it unless
+# --include-experimental is given.  Remove the flag once it does what you
+# want, or delete the file.
+experimental: true
+
+SpellVisual.dbc:
+  # A copy of visual 329 under a new ID
+  - type: copy
+    key: 329
+    values:
+      ID: 50000
+
+Spell.dbc:
+  # Point Rain of Fire (Rank 1) at the copy
+  - type: update
+    key: 5740
+    values:
+      SpellVisualID_1: 50000
+";
+
+/// Starter manifest.  Every section is optional, so it only shows some.
+const EXAMPLE_MANIFEST: &str = "\
+# Project settings; see \"Project manifest\" in the README.  Every section
+# is optional.
+
+# Values for options not given on the command line, relative to this file
+defaults:
+  dbc_dir: dbc
+  patch_dir: patches
+  schema_dir: schema
+  includes_dir: includes
+  out_dir: build
+  # mpq: build/patch-O.mpq
+  # locales: [enUS]
+  # profile: \"1.18\"
+  # strict: true
+
+# Shell commands to run around `apply` and `build`
+hooks:
+  post_build: []
+  #   - cp \"$WOWDBC_MPQ\" \"/path/to/WoW/Data/patch-O.mpq\"
+
+# Files under includes/ to leave out of the archive
+exclude_includes: []
+";
+
+/// Write `contents` to `path` unless a file is already there.
+fn write_new(path: &Path, contents: &str) -> Result<()> {
+    if path.exists() {
+        println!("Keeping existing {}", path.display());
+        return Ok(());
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {:?}", path))?;
+    println!("Created {}", path.display());
+    Ok(())
+}
+
+/// Set up a patch project in `dir`: the directories the other commands
+/// default to, a starter patch, a manifest and a `.gitignore`.  Existing
+/// files are left alone, so running it again only fills in what is
+/// missing.  With `client_dir` the stock DBC tables are extracted from
+/// the client's archives, in its load order, into `dbc/`.
+pub fn init_command(dir: &Path, client_dir: Option<&Path>) -> Result<()> {
+    for name in PROJECT_DIRS {
+        let path = dir.join(name);
+        if path.is_dir() {
+            println!("Keeping existing {}", path.display());
+        } else {
+            fs::create_dir_all(&path).with_context(|| format!("Failed to create directory {:?}", path))?;
+            println!("Created {}", path.display());
+        }
+    }
+    write_new(&dir.join("patches").join("0-example.yaml"), EXAMPLE_PATCH)?;
+    write_new(&dir.join(DEFAULT_MANIFEST), EXAMPLE_MANIFEST)?;
+    // Build output and the lock of a running build are not tracked
+    write_new(&dir.join(".gitignore"), &format!("/build/\n/{}\n", LOCK_FILE))?;
+
+    let dbc_dir = dir.join("dbc");
+    match client_dir {
+        Some(client_dir) => {
+            let chain = detect_patch_chain(client_dir)?;
+            extract_command(&chain, "DBFilesClient\\*.dbc", &dbc_dir)?;
+        }
+        None if fs::read_dir(&dbc_dir)?.next().is_none() => {
+            println!(
+                "{} is empty; copy the client's DBFilesClient tables into it, or run `init --client-dir <WoW>` to extract them",
+                dbc_dir.display()
+            );
+        }
+        None => {}
+    }
+    Ok(())
+}
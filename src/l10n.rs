@@ -0,0 +1,306 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::apply::describe_entry;
+use crate::patch::{PatchEntry, PatchFile, ValueType};
+use crate::schema::{load_schema, Schema};
+
+/// Client locales of 1.12 in the order of their `_lang_N` slots.
+pub const LOCALES: [&str; 8] = ["enUS", "koKR", "frFR", "deDE", "zhCN", "zhTW", "esES", "esMX"];
+
+/// Suffix of the slot patches write their source text to.
+const SOURCE_SUFFIX: &str = "_lang_0";
+
+/// One translatable string in a translations file.  The `used_by` list
+/// written next to it is informational and not read back.
+#[derive(Debug, Deserialize)]
+struct Message {
+    /// The enUS text as written in the patches.
+    source: String,
+    /// Translated text by locale name.  Empty strings are untranslated.
+    #[serde(default)]
+    translations: BTreeMap<String, String>,
+}
+
+fn read_messages(path: &Path) -> Result<Vec<Message>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read translations {:?}", path))?;
+    let messages: Option<Vec<Message>> = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse translations {:?}", path))?;
+    Ok(messages.unwrap_or_default())
+}
+
+/// Slot index of a locale name such as `deDE` (case‑insensitive).
+fn locale_slot(name: &str) -> Option<usize> {
+    LOCALES.iter().position(|l| l.eq_ignore_ascii_case(name))
+}
+
+/// Whether `value` is a text written per locale, such as
+/// `{enUS: "...", deDE: "..."}`, rather than an amount with a unit: some
+/// key of the mapping is a locale name.
+pub fn is_per_locale(value: &ValueType) -> bool {
+    matches!(value, ValueType::Unit(map) if map.keys().any(|name| locale_slot(name).is_some()))
+}
+
+/// The texts of a value written per locale with the slot of each, or the
+/// key that is not a locale name.
+pub fn per_locale_texts(texts: &BTreeMap<String, ValueType>) -> Result<Vec<(usize, &ValueType)>, String> {
+    texts
+        .iter()
+        .map(|(name, text)| match locale_slot(name) {
+            Some(slot) => Ok((slot, text)),
+            None => Err(format!("unknown locale '{}'; known locales are {}", name, LOCALES.join(", "))),
+        })
+        .collect()
+}
+
+/// Base name of a localized source field, e.g. `Name` for `Name_lang_0`,
+/// or for `Name` itself when `schema` has it as a `loc` field.
+fn source_base<'a>(field: &'a str, schema: Option<&Schema>) -> Option<&'a str> {
+    if schema.is_some_and(|s| s.column(field).is_none() && s.loc_columns(field).is_some()) {
+        return Some(field);
+    }
+    let split = field.len().checked_sub(SOURCE_SUFFIX.len())?;
+    field
+        .get(split..)
+        .filter(|suffix| suffix.eq_ignore_ascii_case(SOURCE_SUFFIX))
+        .map(|_| &field[..split])
+}
+
+/// Translations loaded from a file written by `extract-schema-strings`,
+/// keyed by source text the way gettext catalogs are, and the client
+/// locales a build is for.
+#[derive(Debug, Default)]
+pub struct Translations {
+    by_source: HashMap<String, BTreeMap<usize, String>>,
+    /// Slots of the locales given with `--locale`
+    wanted: Vec<usize>,
+}
+
+impl Translations {
+    /// No translations, for a build for `locales` (by name, e.g. `deDE`).
+    pub fn for_locales(locales: &[String]) -> Result<Translations> {
+        let mut wanted = Vec::new();
+        for name in locales {
+            match locale_slot(name) {
+                Some(slot) => wanted.push(slot),
+                None => bail!("Unknown locale {:?}; expected one of {}", name, LOCALES.join(", ")),
+            }
+        }
+        Ok(Translations {
+            by_source: HashMap::new(),
+            wanted,
+        })
+    }
+
+    /// Load a translations file.  When `locales` is not empty only those
+    /// locales are used, which gives a build for one client language.
+    pub fn load(path: &Path, locales: &[String]) -> Result<Translations> {
+        let Translations { wanted, .. } = Translations::for_locales(locales)?;
+        let mut by_source = HashMap::new();
+        for message in read_messages(path)? {
+            let mut slots = BTreeMap::new();
+            for (name, text) in message.translations {
+                let Some(slot) = locale_slot(&name) else {
+                    warning!("unknown locale {:?} in translations {}", name, path.display());
+                    continue;
+                };
+                if slot == 0 || text.is_empty() || (!wanted.is_empty() && !wanted.contains(&slot)) {
+                    continue;
+                }
+                slots.insert(slot, text);
+            }
+            if !slots.is_empty() {
+                by_source.insert(message.source, slots);
+            }
+        }
+        Ok(Translations { by_source, wanted })
+    }
+
+    /// Slots that values of `loc` fields written by their bare name go to:
+    /// those of the locales built for, or enUS's.
+    pub fn slots(&self) -> &[usize] {
+        if self.wanted.is_empty() {
+            &[0]
+        } else {
+            &self.wanted
+        }
+    }
+
+    /// Fill the other locale slots of every translated `_lang_0` or bare
+    /// `loc` value.  Slots a patch sets explicitly are left alone.
+    fn translate_values(&self, values: &mut HashMap<String, ValueType>, schema: Option<&Schema>) {
+        let mut additions = Vec::new();
+        for (field, value) in values.iter() {
+            let (Some(base), ValueType::String(text)) = (source_base(field, schema), value) else {
+                continue;
+            };
+            let Some(slots) = self.by_source.get(text) else {
+                continue;
+            };
+            for (slot, translated) in slots {
+                let target = format!("{}_lang_{}", base, slot);
+                if !values.keys().any(|k| k.eq_ignore_ascii_case(&target)) {
+                    additions.push((target, ValueType::String(translated.clone())));
+                }
+            }
+        }
+        values.extend(additions);
+    }
+
+    fn translate_entries(&self, changes: &mut [PatchEntry], schema: Option<&Schema>) {
+        for change in changes {
+            match change {
+                PatchEntry::Update { values, .. }
+                | PatchEntry::UpdateWhere { values, .. }
+                | PatchEntry::Insert { values, .. }
+                | PatchEntry::Copy { values, .. }
+                | PatchEntry::ItemDisplay { values, .. }
+                | PatchEntry::LearnSpell { values, .. }
+                | PatchEntry::Talent { values, .. }
+                | PatchEntry::SpellRanks { values, .. } => self.translate_values(values, schema),
+                PatchEntry::Zone { changes, .. } => self.translate_entries(changes, schema),
+                PatchEntry::Delete { .. }
+                | PatchEntry::DeleteWhere { .. }
+                | PatchEntry::Disable { .. }
+                | PatchEntry::ReplaceString { .. }
+                | PatchEntry::Script { .. } => {}
+            }
+        }
+    }
+
+    /// Add translations to the entries of loaded patch files of the table
+    /// `schema` describes.
+    pub fn translate_patches(&self, patches: &mut [PatchFile], schema: Option<&Schema>) {
+        for pf in patches {
+            self.translate_entries(&mut pf.changes, schema);
+        }
+    }
+}
+
+/// Record the `_lang_0` and bare `loc` strings of `changes` as (source,
+/// user) pairs.
+fn collect_strings(
+    changes: &[PatchEntry],
+    origin: &str,
+    table: &str,
+    schema: Option<&Schema>,
+    out: &mut Vec<(String, String)>,
+) {
+    for change in changes {
+        if let PatchEntry::Zone { changes, .. } = change {
+            collect_strings(changes, origin, table, schema, out);
+            continue;
+        }
+        let Some(values) = change.values() else {
+            continue;
+        };
+        let mut fields: Vec<(&String, &ValueType)> = values.iter().collect();
+        fields.sort_by(|a, b| a.0.cmp(b.0));
+        for (field, value) in fields {
+            if let (Some(base), ValueType::String(text)) = (source_base(field, schema), value) {
+                if !text.is_empty() {
+                    let user = format!("{}: {} {} {}", origin, table, describe_entry(change), base);
+                    out.push((text.clone(), user));
+                }
+            }
+        }
+    }
+}
+
+/// Write every localized string the patches add (values of `_lang_0`
+/// fields, and of fields the schemas in `schema_dir` have as `loc` when
+/// written by their bare name) to a translations file, one entry per
+/// distinct text with an empty slot for each locale.  Translations already
+/// in `out` are kept; strings no longer used by any patch are kept at the
+/// end with an empty `used_by` so finished work is not lost.
+pub fn extract_strings_command(
+    patches_map: &HashMap<String, Vec<PatchFile>>,
+    schema_dir: &Path,
+    out: &Path,
+) -> Result<()> {
+    let mut existing: HashMap<String, BTreeMap<String, String>> = HashMap::new();
+    let mut previous_order = Vec::new();
+    if out.exists() {
+        for message in read_messages(out)? {
+            previous_order.push(message.source.clone());
+            existing.insert(message.source, message.translations);
+        }
+    }
+
+    let mut tables: Vec<&Vec<PatchFile>> = patches_map.values().collect();
+    tables.sort_by(|a, b| a[0].dbc.to_lowercase().cmp(&b[0].dbc.to_lowercase()));
+    let mut found = Vec::new();
+    for pfs in tables {
+        let schema = load_schema(schema_dir, &pfs[0].dbc);
+        for pf in pfs {
+            let origin = pf
+                .origin
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "<unknown>".to_string());
+            collect_strings(&pf.changes, &origin, &pf.dbc, schema.as_ref(), &mut found);
+        }
+    }
+
+    let mut order: Vec<String> = Vec::new();
+    let mut users: HashMap<String, Vec<String>> = HashMap::new();
+    for (source, user) in found {
+        let list = users.entry(source.clone()).or_default();
+        if list.is_empty() {
+            order.push(source);
+        }
+        list.push(user);
+    }
+    let obsolete: Vec<String> = previous_order
+        .into_iter()
+        .filter(|source| !users.contains_key(source))
+        .collect();
+
+    let mut text = String::from(
+        "# Localized strings added by patches.  Fill in the locales you translate and\n\
+         # pass this file to `apply`/`build` with --translations; re-run\n\
+         # extract-schema-strings after editing patches to pick up new strings.\n",
+    );
+    let mut untranslated = 0;
+    for source in order.iter().chain(&obsolete) {
+        let known = existing.remove(source).unwrap_or_default();
+        writeln!(text, "- source: {:?}", source)?;
+        let used_by = users.get(source).map(Vec::as_slice).unwrap_or_default();
+        if used_by.is_empty() {
+            writeln!(text, "  used_by: []")?;
+        } else {
+            writeln!(text, "  used_by:")?;
+            for user in used_by {
+                writeln!(text, "    - {:?}", user)?;
+            }
+        }
+        writeln!(text, "  translations:")?;
+        let mut complete = true;
+        for locale in &LOCALES[1..] {
+            let value = known
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(locale))
+                .map(|(_, v)| v.as_str())
+                .unwrap_or("");
+            complete &= !value.is_empty();
+            writeln!(text, "    {}: {:?}", locale, value)?;
+        }
+        if !complete {
+            untranslated += 1;
+        }
+    }
+    fs::write(out, text).with_context(|| format!("Failed to write {:?}", out))?;
+    println!(
+        "Wrote {} string(s) to {} ({} not fully translated, {} no longer used)",
+        order.len() + obsolete.len(),
+        out.display(),
+        untranslated,
+        obsolete.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Reading, patching and packing vanilla (1.12) WoW DBC tables.  The
+//! `wow_dbc_patcher` binary is a command line front‑end over this library;
+//! build scripts can use the same pieces directly:
+//!
+//! - [`read_dbc`] (or [`read_dbc_with_schema`]) and [`write_dbc`] for the
+//!   WDBC (and WDB2) file format;
+//! - [`load_schema`] for the field names and types of a schema directory
+//!   or the built‑in 1.12 schemas;
+//! - [`load_patches`] (or [`load_patches_with_ids`], for symbolic keys) to
+//!   parse patch YAML into [`PatchFile`]s;
+//! - [`patch_tables`] to apply patch sets in memory, [`apply_command`] to
+//!   also write the results, and [`build_command`] to pack them into an MPQ.
+//!
+//! ```no_run
+//! use std::path::{Path, PathBuf};
+//! use wow_dbc_patcher::{patch_tables, Transformers};
+//! use wow_dbc_patcher::ids::IdMap;
+//! use wow_dbc_patcher::source::DbcSource;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let spell = PathBuf::from("dbc/Spell.dbc");
+//! let patches = vec![PathBuf::from("patches/1-blizzard.yaml")];
+//! let (tables, _) = patch_tables(
+//!     &[spell.clone()],
+//!     &[patches],
+//!     Path::new("dbc"),
+//!     Path::new("schema"),
+//!     Path::new("includes"),
+//!     false,
+//!     None,
+//!     &Transformers::builtin(),
+//!     &IdMap::new(),
+//! )?;
+//! println!("{} records", tables.read_table(&spell)?.records.len());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Warnings are printed as they happen and collected for
+//! [`diagnostics::check_strict`].
+
+// Declared first so its `warning!` macro is visible in every other module
+#[macro_use]
+pub mod diagnostics;
+pub mod apply;
+pub mod budget;
+pub mod changelog;
+pub mod client;
+pub mod computed;
+pub mod csv;
+pub mod dbc;
+pub mod dedup;
+pub mod diff;
+pub mod dump;
+pub mod explain;
+pub mod expr;
+pub mod extract;
+pub mod grep;
+pub mod groups;
+pub mod helpers;
+pub mod ids;
+pub mod import;
+pub mod includes;
+pub mod infer;
+pub mod init;
+pub mod l10n;
+pub mod lint;
+pub mod lock;
+pub mod manifest;
+pub mod migrate;
+pub mod mpq;
+pub mod ownership;
+pub mod pack;
+pub mod parallel;
+pub mod patch;
+pub mod prune;
+pub mod refs;
+pub mod rewrite;
+pub mod schema;
+pub mod script;
+pub mod source;
+pub mod spelltext;
+pub mod sql;
+pub mod stats;
+pub mod templates;
+pub mod units;
+pub mod verify;
+pub mod watch;
+pub mod wizard;
+pub mod workspace;
+pub mod zones;
+
+pub use apply::{apply_command, load_patches, load_patches_with_ids, parse_patch_file, patch_tables};
+pub use dbc::{read_dbc, read_dbc_with_schema, write_dbc, DbcHeader, Endianness};
+pub use pack::build_command;
+pub use patch::{PatchEntry, PatchFile, ValueType};
+pub use schema::{load_schema, Schema};
+pub use units::Transformers;
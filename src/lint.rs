@@ -0,0 +1,599 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::apply::{apply_values_to_record, parse_patch_str, resolve_field_index};
+use crate::dbc::{find_dbc_file, read_dbc_with_schema};
+use crate::diagnostics::capture;
+use crate::expr::{check_expression, TextValue};
+use crate::helpers::{expand_item_display, expand_learn_spell, expand_talent};
+use crate::ids::IdMap;
+use crate::ownership::OwnershipRule;
+use crate::patch::{PatchEntry, PatchFile, ValueType};
+use crate::schema::{load_schema, Schema};
+use crate::script::run_script;
+use crate::units::Transformers;
+
+/// One problem found in a patch file.
+pub struct Finding {
+    pub origin: String,
+    /// 1‑based line the entry starts on, when the loader could tell
+    pub line: Option<usize>,
+    pub table: String,
+    /// 1‑based position of the entry in its patch section; 0 for problems
+    /// with the file as a whole
+    pub entry: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.origin)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if self.entry > 0 {
+            write!(f, ": {} entry {}", self.table, self.entry)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Print `findings` and fail when there are any.
+fn report_findings(findings: &[Finding]) -> Result<()> {
+    for f in findings {
+        println!("{}", f);
+    }
+    if !findings.is_empty() {
+        bail!("lint found {} problem(s)", findings.len());
+    }
+    println!("No problems found");
+    Ok(())
+}
+
+/// What lint needs to know about one table.
+struct LintTable {
+    name: String,
+    schema: Option<Schema>,
+    /// Stock records and string block, loaded when a script needs them
+    data: Option<(Vec<Vec<u32>>, Vec<u8>)>,
+}
+
+impl LintTable {
+    /// The schema name of a values key, which may be a name or a column
+    /// number.  A `loc` field written by its bare name is taken as its
+    /// enUS column.
+    fn field_name(&self, key: &str) -> String {
+        let column = key
+            .parse::<usize>()
+            .ok()
+            .or_else(|| self.schema.as_ref()?.column(key))
+            .or_else(|| Some(self.schema.as_ref()?.loc_columns(key)?.0));
+        column
+            .and_then(|c| self.schema.as_ref()?.fields.iter().find(|f| f.index == c))
+            .map_or_else(|| key.to_string(), |f| f.name.clone())
+    }
+}
+
+/// Names of the fields an entry may change.  Scripts are run against the
+/// stock table and their generated entries inspected; `replace_string` may
+/// change any string field.  Problems that prevent the inspection are
+/// returned as `Err` messages.
+fn touched_fields(
+    table: &mut LintTable,
+    entry: &PatchEntry,
+    origin: &str,
+    dbc_dir: &Path,
+    schema_dir: &Path,
+) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    match entry {
+        PatchEntry::Update { values, .. }
+        | PatchEntry::UpdateWhere { values, .. }
+        | PatchEntry::Insert { values, .. }
+        | PatchEntry::Copy { values, .. }
+        | PatchEntry::SpellRanks { values, .. } => {
+            fields.extend(values.keys().map(|k| table.field_name(k)));
+            if let PatchEntry::Insert { template: Some(name), .. } = entry {
+                if let Some(defaults) = table.schema.as_ref().and_then(|s| s.template(name)) {
+                    fields.extend(defaults.keys().map(|k| table.field_name(k)));
+                }
+            }
+        }
+        PatchEntry::ReplaceString { .. } => {
+            if let Some(schema) = &table.schema {
+                fields.extend(schema.string_fields().map(|f| f.name.clone()));
+            }
+        }
+        // Removing a row takes every field with it, disabling it only the
+        // tombstone fields
+        PatchEntry::Delete { hard, .. } | PatchEntry::DeleteWhere { hard, .. }
+            if *hard || table.schema.as_ref().is_none_or(|s| s.tombstone().is_none()) =>
+        {
+            if let Some(schema) = &table.schema {
+                fields.extend(schema.fields.iter().map(|f| f.name.clone()));
+            }
+        }
+        PatchEntry::Delete { .. } | PatchEntry::DeleteWhere { .. } | PatchEntry::Disable { .. } => {
+            if let Some(tombstone) = table.schema.as_ref().and_then(|s| s.tombstone()) {
+                fields.extend(tombstone.keys().map(|k| table.field_name(k)));
+            }
+        }
+        PatchEntry::Script { code, file } => {
+            let source = match (code, file) {
+                (Some(code), None) => code.clone(),
+                (None, Some(file)) => {
+                    let path = Path::new(origin).parent().unwrap_or_else(|| Path::new("")).join(file);
+                    fs::read_to_string(&path).map_err(|e| format!("cannot read script {:?}: {}", path, e))?
+                }
+                _ => return Err("script entry needs exactly one of `code` or `file`".to_string()),
+            };
+            let (records, block) = match table.data.take() {
+                Some(data) => data,
+                None => {
+                    let path = find_dbc_file(dbc_dir, &table.name);
+                    let (_header, records, block) = read_dbc_with_schema(&path, schema_dir)
+                        .map_err(|e| format!("cannot run script, {:?} is unreadable: {:#}", path, e))?;
+                    (records, block)
+                }
+            };
+            let result = run_script(&source, &table.name, &records, &block, &[], &table.schema);
+            table.data = Some((records, block));
+            let generated = result.map_err(|e| format!("script failed: {}", e))?;
+            for generated_entry in &generated {
+                fields.extend(touched_fields(table, generated_entry, origin, dbc_dir, schema_dir)?);
+            }
+        }
+        PatchEntry::Zone { changes, .. } => {
+            for change in changes {
+                fields.extend(touched_fields(table, change, origin, dbc_dir, schema_dir)?);
+            }
+        }
+        PatchEntry::ItemDisplay { id, icon, base, values } => {
+            let expanded = expand_item_display(*id, icon, *base, values);
+            fields.extend(touched_fields(table, &expanded, origin, dbc_dir, schema_dir)?);
+        }
+        PatchEntry::LearnSpell {
+            id,
+            spell,
+            skill_line,
+            race_mask,
+            class_mask,
+            min_skill_rank,
+            acquire_method,
+            values,
+        } => {
+            let expanded = expand_learn_spell(
+                *id,
+                *spell,
+                *skill_line,
+                *race_mask,
+                *class_mask,
+                *min_skill_rank,
+                *acquire_method,
+                values,
+                &[],
+            );
+            fields.extend(touched_fields(table, &expanded, origin, dbc_dir, schema_dir)?);
+        }
+        PatchEntry::Talent {
+            id,
+            tab,
+            tier,
+            column,
+            ranks,
+            requires,
+            values,
+        } => {
+            let expanded = expand_talent(
+                *id,
+                *tab,
+                *tier,
+                *column,
+                ranks,
+                requires.as_ref().map(|r| (r.talent, r.points)),
+                values,
+                &[],
+            )
+            .map_err(|e| e.to_string())?;
+            fields.extend(touched_fields(table, &expanded, origin, dbc_dir, schema_dir)?);
+        }
+    }
+    Ok(fields)
+}
+
+/// Check every patch entry against the ownership rules and report entries
+/// that change a field owned by the server or another mod.  Fails when
+/// anything is found, so it can gate a build or a commit.
+pub fn lint_command(
+    patches_map: &HashMap<String, Vec<PatchFile>>,
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    rules: &[OwnershipRule],
+) -> Result<()> {
+    let mut findings: Vec<Finding> = Vec::new();
+    let mut keys: Vec<&String> = patches_map.keys().collect();
+    keys.sort();
+    for key in keys {
+        let pfs = &patches_map[key];
+        let name = pfs.first().map_or(key.clone(), |pf| pf.dbc.clone());
+        let mut table = LintTable {
+            schema: load_schema(schema_dir, &name),
+            name,
+            data: None,
+        };
+        for pf in pfs {
+            let origin = pf
+                .origin
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "<unknown>".to_string());
+            for (i, entry) in pf.changes.iter().enumerate() {
+                let mut report = |message: String| {
+                    findings.push(Finding {
+                        origin: origin.clone(),
+                        line: pf.entry_lines.get(i).copied(),
+                        table: pf.dbc.clone(),
+                        entry: i + 1,
+                        message,
+                    })
+                };
+                let fields = match touched_fields(&mut table, entry, &origin, dbc_dir, schema_dir) {
+                    Ok(fields) => fields,
+                    Err(message) => {
+                        report(message);
+                        continue;
+                    }
+                };
+                for field in fields {
+                    if let Some(rule) = rules.iter().find(|r| r.covers(&table.name, &field)) {
+                        report(format!(
+                            "changes {}, which is owned by {}{}",
+                            field,
+                            rule.owner,
+                            rule.reason.as_ref().map(|r| format!(" ({})", r)).unwrap_or_default()
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    report_findings(&findings)
+}
+
+/// The column a `key_column` names: a column number, or a field the
+/// schema has.  Column 0 when there is none.
+fn key_column_index(key_column: &Option<String>, schema: &Option<Schema>) -> Option<usize> {
+    match key_column {
+        Some(name) => name.parse().ok().or_else(|| schema.as_ref()?.column(name)),
+        None => Some(0),
+    }
+}
+
+/// The problem with an entry's `key_column`, if it names no column.
+fn check_key_column(key_column: &Option<String>, schema: &Option<Schema>, table: &str, problems: &mut Vec<String>) {
+    if key_column_index(key_column, schema).is_some() {
+        return;
+    }
+    let name = key_column.as_deref().unwrap_or_default();
+    problems.push(match schema {
+        Some(_) => format!("unknown key column '{}'", name),
+        None => format!("no schema for {} to resolve key column '{}'", table, name),
+    });
+}
+
+/// The problems with the fields a `where` names.
+fn check_conditions<'a>(
+    names: impl Iterator<Item = &'a String>,
+    schema: &Option<Schema>,
+    table: &str,
+    problems: &mut Vec<String>,
+) {
+    for name in names {
+        let localized = schema.as_ref().is_some_and(|s| s.loc_columns(name).is_some());
+        if resolve_field_index(name, schema).is_none() && !localized {
+            problems.push(format!("unknown field '{}' in `where` of {}", name, table));
+        }
+    }
+}
+
+/// The problems `apply` would report writing `values` to a record of
+/// `table`: each is written to a zeroed record the way `apply` writes
+/// it and the warnings and error collected, so one bad value does not
+/// hide the next.  Expressions are only parsed, as what they give
+/// depends on the record.
+fn check_values(
+    values: &HashMap<String, ValueType>,
+    schema: &Option<Schema>,
+    table: &str,
+    origin: &str,
+    key: u32,
+    problems: &mut Vec<String>,
+) {
+    // Long enough for every column the schema or the values name
+    let columns = schema
+        .iter()
+        .flat_map(|s| s.fields.iter().map(|f| f.index + 1))
+        .chain(values.keys().filter_map(|name| name.parse::<usize>().ok()).map(|i| i + 1))
+        .max()
+        .unwrap_or(1);
+    let block = [0u8];
+    // The findings already name the file
+    let unplaced = |message: &str| message.replace(&format!(" (patch file: {})", origin), "");
+    let mut names: Vec<&String> = values.keys().collect();
+    names.sort();
+    for name in names {
+        let value = &values[name];
+        if let ValueType::String(text) = value {
+            if let TextValue::Expression(expression) = TextValue::of(text) {
+                if let Err(err) = check_expression(expression) {
+                    problems.push(format!("invalid expression for '{}': {}", name, err));
+                }
+                let localized = schema.as_ref().is_some_and(|s| s.loc_columns(name).is_some());
+                if resolve_field_index(name, schema).is_none() && !localized {
+                    problems.push(format!("unknown field '{}' in {}", name, table));
+                }
+                continue;
+            }
+        }
+        let single = HashMap::from([(name.clone(), value.clone())]);
+        let (result, captured) = capture(|| {
+            apply_values_to_record(
+                &single,
+                &mut vec![0; columns],
+                schema,
+                &[0],
+                &mut HashMap::new(),
+                &mut Vec::new(),
+                &block,
+                table,
+                origin,
+                key,
+            )
+        });
+        problems.extend(captured.warnings().iter().map(|warning| unplaced(warning)));
+        if let Err(err) = result {
+            problems.push(unplaced(&format!("{:#}", err)));
+        }
+    }
+}
+
+/// Check one entry of `table` from the patch file at `path` without any
+/// DBC, adding what is wrong with it to `problems`.
+fn check_entry(entry: &PatchEntry, table: &str, schema: &Option<Schema>, path: &Path, problems: &mut Vec<String>) {
+    let origin = path.display().to_string();
+    let relative = |file: &Path| path.parent().unwrap_or_else(|| Path::new("")).join(file);
+    match entry {
+        PatchEntry::Update { key, key_column, values } => {
+            check_key_column(key_column, schema, table, problems);
+            check_values(values, schema, table, &origin, *key, problems);
+        }
+        PatchEntry::UpdateWhere { conditions, values } => {
+            check_conditions(conditions.keys(), schema, table, problems);
+            check_values(values, schema, table, &origin, 0, problems);
+        }
+        PatchEntry::Insert {
+            key,
+            key_column,
+            template,
+            values,
+        } => {
+            check_key_column(key_column, schema, table, problems);
+            if let Some(name) = template {
+                if schema.as_ref().and_then(|s| s.template(name)).is_none() {
+                    problems.push(format!("no template named {} in the schema for {}", name, table));
+                }
+            }
+            check_values(values, schema, table, &origin, key.unwrap_or(0), problems);
+        }
+        PatchEntry::Copy {
+            key,
+            key_column,
+            new_key,
+            values,
+        } => {
+            check_key_column(key_column, schema, table, problems);
+            check_values(values, schema, table, &origin, new_key.unwrap_or(*key), problems);
+        }
+        PatchEntry::Delete { key_column, .. } => check_key_column(key_column, schema, table, problems),
+        PatchEntry::Disable { key_column, .. } => {
+            check_key_column(key_column, schema, table, problems);
+            if schema.as_ref().is_none_or(|s| s.tombstone().is_none()) {
+                problems.push(format!("the schema for {} declares no tombstone, so nothing can be disabled", table));
+            }
+        }
+        PatchEntry::DeleteWhere { conditions, .. } => check_conditions(conditions.keys(), schema, table, problems),
+        PatchEntry::ReplaceString { .. } => {
+            if schema.as_ref().is_none_or(|s| s.string_fields().next().is_none()) {
+                problems.push(format!("replace_string needs a schema declaring the string fields of {}", table));
+            }
+        }
+        PatchEntry::Script { code, file } => match (code, file) {
+            (Some(_), None) => {}
+            (None, Some(file)) if !relative(file).is_file() => {
+                problems.push(format!("script file {:?} does not exist", relative(file)))
+            }
+            (None, Some(_)) => {}
+            _ => problems.push("script entry needs exactly one of `code` or `file`".to_string()),
+        },
+        PatchEntry::Zone { members, changes, .. } => {
+            if !relative(members).is_file() {
+                problems.push(format!("members file {:?} does not exist", relative(members)));
+            }
+            for change in changes {
+                match change {
+                    PatchEntry::Update { .. } | PatchEntry::Copy { .. } | PatchEntry::Script { .. } => {
+                        check_entry(change, table, schema, path, problems)
+                    }
+                    _ => problems.push("zone filters only apply to update, copy and script entries".to_string()),
+                }
+            }
+        }
+        PatchEntry::ItemDisplay { id, icon, base, values } => {
+            let expanded = expand_item_display(*id, icon, *base, values);
+            check_entry(&expanded, table, schema, path, problems);
+        }
+        PatchEntry::LearnSpell {
+            id,
+            spell,
+            skill_line,
+            race_mask,
+            class_mask,
+            min_skill_rank,
+            acquire_method,
+            values,
+        } => {
+            let expanded = expand_learn_spell(
+                *id,
+                *spell,
+                *skill_line,
+                *race_mask,
+                *class_mask,
+                *min_skill_rank,
+                *acquire_method,
+                values,
+                &[],
+            );
+            check_entry(&expanded, table, schema, path, problems);
+        }
+        PatchEntry::Talent {
+            id,
+            tab,
+            tier,
+            column,
+            ranks,
+            requires,
+            values,
+        } => {
+            let expanded = expand_talent(
+                *id,
+                *tab,
+                *tier,
+                *column,
+                ranks,
+                requires.as_ref().map(|r| (r.talent, r.points)),
+                values,
+                &[],
+            );
+            match expanded {
+                Ok(expanded) => check_entry(&expanded, table, schema, path, problems),
+                Err(err) => problems.push(err.to_string()),
+            }
+        }
+        PatchEntry::SpellRanks { spell, values, .. } => check_values(values, schema, table, &origin, *spell, problems),
+    }
+}
+
+/// The file and line of each entry creating or deleting a record, and
+/// whether it creates it.
+type KeyUses = Vec<(String, Option<usize>, bool)>;
+
+/// The records an entry creates or deletes by key, as key column, key
+/// and whether it creates it.  Updates are left out, as is the record a
+/// `copy` reads from: several files may change one record, but only one
+/// may create it, and none should delete a record another creates.
+fn named_keys(entry: &PatchEntry, schema: &Option<Schema>, keys: &mut Vec<(usize, u32, bool)>) {
+    let mut push = |key_column: &Option<String>, key: u32, creates: bool| {
+        if let Some(column) = key_column_index(key_column, schema) {
+            keys.push((column, key, creates));
+        }
+    };
+    match entry {
+        PatchEntry::Delete { key, key_column, .. } => push(key_column, *key, false),
+        PatchEntry::Insert {
+            key: Some(key),
+            key_column,
+            ..
+        }
+        | PatchEntry::Copy {
+            new_key: Some(key),
+            key_column,
+            ..
+        } => push(key_column, *key, true),
+        PatchEntry::ItemDisplay { id, .. }
+        | PatchEntry::LearnSpell { id: Some(id), .. }
+        | PatchEntry::Talent { id: Some(id), .. } => push(&None, *id, true),
+        PatchEntry::Zone { changes, .. } => {
+            for change in changes {
+                named_keys(change, schema, keys);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Check patch files on their own, without any DBC: every file parses,
+/// key columns and field names resolve against the schemas, values suit
+/// the types of their fields, and no two files create the same record by
+/// key, or delete one another creates.  Problems are reported by file and
+/// line, and fail the run, so it can serve as a pre‑commit hook.
+pub fn lint_patches_command(
+    patch_paths: &[PathBuf],
+    schema_dir: &Path,
+    units: &Transformers,
+    ids: &IdMap,
+) -> Result<()> {
+    let mut paths = patch_paths.to_vec();
+    paths.sort();
+    let mut findings: Vec<Finding> = Vec::new();
+    let mut schemas: HashMap<String, Option<Schema>> = HashMap::new();
+    // The entries creating or deleting each record, by table, key column
+    // and key
+    let mut named: HashMap<(String, usize, u32), KeyUses> = HashMap::new();
+    for path in &paths {
+        let origin = path.display().to_string();
+        let parsed = fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| parse_patch_str(&content, path, ids))
+            .and_then(|mut pfs| units.resolve_patches(&mut pfs).map(|_| pfs));
+        let pfs = match parsed {
+            Ok(pfs) => pfs,
+            Err(err) => {
+                findings.push(Finding {
+                    origin,
+                    line: None,
+                    table: String::new(),
+                    entry: 0,
+                    message: format!("{:#}", err),
+                });
+                continue;
+            }
+        };
+        for pf in &pfs {
+            let table = pf.dbc.to_lowercase();
+            let schema = schemas
+                .entry(table.clone())
+                .or_insert_with(|| load_schema(schema_dir, &pf.dbc));
+            for (i, entry) in pf.changes.iter().enumerate() {
+                let line = pf.entry_lines.get(i).copied();
+                let mut problems = Vec::new();
+                check_entry(entry, &pf.dbc, schema, path, &mut problems);
+                let mut keys = Vec::new();
+                named_keys(entry, schema, &mut keys);
+                for (column, key, creates) in keys {
+                    let entries = named.entry((table.clone(), column, key)).or_default();
+                    let clash = entries
+                        .iter()
+                        .find(|(other, _, other_creates)| *other != origin && (creates || *other_creates));
+                    if let Some((other, other_line, other_creates)) = clash {
+                        let at = other_line.map_or_else(|| other.clone(), |l| format!("{}:{}", other, l));
+                        let verb = if *other_creates { "created" } else { "deleted" };
+                        problems.push(format!("key {} is also {} by {}", key, verb, at));
+                    }
+                    entries.push((origin.clone(), line, creates));
+                }
+                findings.extend(problems.into_iter().map(|message| Finding {
+                    origin: origin.clone(),
+                    line,
+                    table: pf.dbc.clone(),
+                    entry: i + 1,
+                    message,
+                }));
+            }
+        }
+    }
+    report_findings(&findings)
+}
@@ -0,0 +1,143 @@
+use anyhow::{bail, Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// File name of the lock a build holds in the project directory.
+pub const LOCK_FILE: &str = ".wowdbc.lock";
+
+/// How long `--wait` sleeps between attempts to take the lock.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Exclusive hold on a project for a run that writes its output directory
+/// or archive, so two builds (a watcher and a manual run, or parallel CI
+/// jobs) cannot interleave their writes.  The lock file holds the process
+/// ID of its owner and is removed when the lock is dropped.
+pub struct ProjectLock {
+    path: PathBuf,
+}
+
+impl ProjectLock {
+    /// Take the lock in `dir`.  When another run holds it this fails, or
+    /// with `wait` retries until that run is done.  A lock left behind by
+    /// a process that no longer runs is removed.
+    pub fn acquire(dir: &Path, wait: bool) -> Result<ProjectLock> {
+        let path = dir.join(LOCK_FILE);
+        let mut waiting = false;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(file, "{}", std::process::id())
+                        .with_context(|| format!("Failed to write lock file {:?}", path))?;
+                    return Ok(ProjectLock { path });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err).with_context(|| format!("Failed to create lock file {:?}", path)),
+            }
+            // The owner may have just released it; try again straight away
+            let Ok(holder) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let owner = holder.trim().parse::<u32>().ok();
+            if let Some(pid) = owner.filter(|&pid| !process_running(pid)) {
+                say!("Removing stale lock {} left by process {}", path.display(), pid);
+                // Only if no other run replaced it in the meantime
+                if fs::read_to_string(&path).is_ok_and(|now| now == holder) {
+                    let _ = fs::remove_file(&path);
+                }
+                continue;
+            }
+            let owner = owner.map_or_else(|| "another process".to_string(), |pid| format!("process {}", pid));
+            if !wait {
+                bail!(
+                    "Another build is in progress ({} holds {}); pass --wait to wait for it, or delete the file if no build is running",
+                    owner,
+                    path.display()
+                );
+            }
+            if !waiting {
+                say!("Waiting for another build to finish ({} holds {})", owner, path.display());
+                waiting = true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether a process with ID `pid` is running.  Only Linux can tell
+/// cheaply; elsewhere every owner is assumed alive and a stale lock has
+/// to be deleted by hand.
+fn process_running(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new("/proc").join(pid.to_string()).exists()
+    } else {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn project(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wow_dbc_patcher-lock-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn a_held_lock_fails_other_runs_until_dropped() {
+        let dir = project("held");
+        let lock = ProjectLock::acquire(&dir, false).unwrap();
+        let holder = fs::read_to_string(dir.join(LOCK_FILE)).unwrap();
+        assert_eq!(holder.trim(), std::process::id().to_string());
+        let err = ProjectLock::acquire(&dir, false).err().unwrap();
+        assert!(err.to_string().contains("--wait"), "{}", err);
+
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE).exists());
+        drop(ProjectLock::acquire(&dir, false).unwrap());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn waiting_runs_take_the_lock_once_it_is_released() {
+        let dir = project("wait");
+        let lock = ProjectLock::acquire(&dir, false).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let waiter = {
+            let dir = dir.clone();
+            thread::spawn(move || {
+                let lock = ProjectLock::acquire(&dir, true).unwrap();
+                sender.send(()).unwrap();
+                drop(lock);
+            })
+        };
+        assert!(receiver.recv_timeout(POLL_INTERVAL * 2).is_err());
+        drop(lock);
+        receiver.recv_timeout(POLL_INTERVAL * 10).unwrap();
+        waiter.join().unwrap();
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn stale_locks_are_removed() {
+        if !cfg!(target_os = "linux") {
+            return;
+        }
+        let dir = project("stale");
+        // No process has the largest ID
+        fs::write(dir.join(LOCK_FILE), format!("{}\n", u32::MAX)).unwrap();
+        drop(ProjectLock::acquire(&dir, false).unwrap());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,16 +1,49 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use serde_yaml;
-use std::collections::{HashMap, HashSet};
+use clap::{Arg, Command, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::collections::BTreeSet;
+use std::env;
 use std::fs;
-use std::fs::File;
 use std::path::{Path, PathBuf};
 
-mod dbc;
-mod patch;
-
-use dbc::{build_string_map, read_dbc, write_dbc};
-use patch::{PatchEntry, PatchFile, ValueType};
+use wow_dbc_patcher::apply::{
+    apply_command, exclude_experimental, exclude_other_clients, list_dbc_files, load_patches_with_ids, patch_paths_or_dir,
+    patch_tables, validate_live_command, ClientTarget,
+};
+use wow_dbc_patcher::changelog::write_changelog;
+use wow_dbc_patcher::client::{detect_patch_chain, install_command, verify_client_refs_command};
+use wow_dbc_patcher::csv::{export_csv_command, import_csv_command};
+use wow_dbc_patcher::dbc::{dbc_file_name, find_dbc_file, set_endianness_override, Endianness};
+use wow_dbc_patcher::diagnostics::{
+    check_strict, exit_code, report_error, set_log_json, set_strict, set_trace, set_verbosity, Verbosity,
+};
+use wow_dbc_patcher::diff::{dbc_diff_command, diff_builds_command, dry_run_command, read_originals, write_revert_patch};
+use wow_dbc_patcher::dump::dump_command;
+use wow_dbc_patcher::explain::explain_row_command;
+use wow_dbc_patcher::extract::{extract_command, list_command, stage_dbc_source};
+use wow_dbc_patcher::grep::{grep_command, search_command, SearchQuery};
+use wow_dbc_patcher::ids::{assign_ids, load_id_map, IdMap};
+use wow_dbc_patcher::groups::{select_groups, Date};
+use wow_dbc_patcher::import::{import_schema_command, DefinitionFormat};
+use wow_dbc_patcher::includes::IncludeGlobs;
+use wow_dbc_patcher::infer::schema_gen_command;
+use wow_dbc_patcher::init::init_command;
+use wow_dbc_patcher::l10n::{extract_strings_command, Translations};
+use wow_dbc_patcher::lint::{lint_command, lint_patches_command};
+use wow_dbc_patcher::lock::ProjectLock;
+use wow_dbc_patcher::manifest::{run_hooks, Defaults, HookContext, Manifest};
+use wow_dbc_patcher::migrate::migrate_command;
+use wow_dbc_patcher::pack::{build_command, merge_mpq_command, Compression, OnConflict};
+use wow_dbc_patcher::prune::prune_command;
+use wow_dbc_patcher::refs::{check_refs_command, where_used_command};
+use wow_dbc_patcher::rewrite::rewrite_strings_command;
+use wow_dbc_patcher::source::DbcSource;
+use wow_dbc_patcher::sql::{default_sql_table, export_sql_command, SqlDialect};
+use wow_dbc_patcher::units::Transformers;
+use wow_dbc_patcher::verify::verify_command;
+use wow_dbc_patcher::watch::watch_command;
+use wow_dbc_patcher::warning;
+use wow_dbc_patcher::wizard::wizard_command;
+use wow_dbc_patcher::workspace::workspace_command;
 
 /// Command line interface for the WoW DBC manager.  Supports applying
 /// patches to one or more DBC files and optionally packaging them into an
@@ -18,6 +51,47 @@ use patch::{PatchEntry, PatchFile, ValueType};
 #[derive(Debug, Parser)]
 #[command(name = "wow_dbc_manager_rs", about = "Vanilla WoW DBC patcher and MPQ packer")] 
 struct Cli {
+    /// Project manifest to read (hooks and other project settings).
+    /// Defaults to `patchproject.yaml` in the working directory when it
+    /// exists.
+    #[arg(long = "manifest", global = true)]
+    manifest: Option<PathBuf>,
+    /// Byte order of the DBC files to read.  Detected per file when
+    /// omitted; set it for files extracted from old Mac clients whose
+    /// layout is ambiguous.  Files are written back in the order they
+    /// were read.
+    #[arg(long = "endianness", global = true, value_enum)]
+    endianness: Option<Endianness>,
+    /// Fail `apply` and `build` when anything was warned about (unknown
+    /// fields, missing keys, values out of range, ...), after listing the
+    /// warnings, instead of writing the result.
+    #[arg(long = "strict", visible_alias = "warnings-as-errors", global = true)]
+    strict: bool,
+    /// Log every step of applying patch entries: key column resolution,
+    /// record lookups, each value written (floats with their bit
+    /// pattern) and every string allocated or reused, with its offset.
+    #[arg(long = "trace", global = true)]
+    trace: bool,
+    /// Print only results, warnings and errors, leaving out progress
+    /// messages such as the tables being processed.
+    #[arg(short = 'q', long = "quiet", global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Also print debug messages, such as the order patch files are
+    /// applied in and where each table's schema comes from.
+    #[arg(short = 'v', long = "verbose", global = true)]
+    verbose: bool,
+    /// Write warnings, progress and debug messages and the final error as
+    /// JSON objects, one per line, on standard error, with the message
+    /// and, where known, the table, patch file, record key and field.
+    /// Results stay on standard output.  A run that `--strict` stops
+    /// because of warnings exits with code 2, other errors with 1.
+    #[arg(long = "log-json", global = true)]
+    log_json: bool,
+    /// Wait for another `apply`, `build` or `workspace` run in the same
+    /// project to finish instead of failing.  Only one of them may write
+    /// at a time.
+    #[arg(long = "wait", global = true)]
+    wait: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -36,8 +110,23 @@ enum Commands {
         /// `--dbc-files` is not specified.  Defaults to `dbc`.
         #[arg(long = "dbc-dir", default_value = "dbc")]
         dbc_dir: PathBuf,
-        /// YAML patch files to apply.  If omitted the tool will load all
-        /// `.yaml` and `.yml` files from the default patch directory (see
+        /// Client archive to read the source tables from instead of
+        /// `--dbc-dir`, e.g. `dbc.MPQ`.  Repeat it for a patch chain, in
+        /// load order; a table in a later archive replaces the earlier
+        /// ones.  The tables are extracted afresh on every run into
+        /// `dbc-source` under the output directory.
+        #[arg(long = "dbc-source", conflicts_with = "dbc_files")]
+        dbc_source: Vec<PathBuf>,
+        /// Client directory, or its `Data` directory, to read the source
+        /// tables from: its archives are found and read in the client's
+        /// load order, base archives, `patch.MPQ`, then `patch-2.MPQ`…
+        /// `patch-Z.MPQ`, so each table is the version the client loads,
+        /// with the rows later patches such as Turtle WoW's add (see
+        /// `--dbc-source`)
+        #[arg(long = "client-dir", conflicts_with_all = ["dbc_files", "dbc_source"])]
+        client_dir: Option<PathBuf>,
+        /// YAML or JSON patch files to apply.  If omitted the tool will load
+        /// all `.yaml`, `.yml` and `.json` files from the default patch directory (see
         /// `--patch-dir`).  Patches targeting unknown tables are ignored
         /// with a warning.
         #[arg(short = 'p', long = "patches")]
@@ -54,9 +143,85 @@ enum Commands {
         /// Directory containing schema definitions (YAML files listing field
         /// names in order).  For each DBC file `Foo.dbc` the tool looks
         /// for `schema_dir/Foo.dbc.yaml` and uses it to map field names to
-        /// column indices.  Defaults to `schema`.
+        /// column indices.  Defaults to `schema`.  Tables without a file
+        /// there use the 1.12 schemas built into the tool.
         #[arg(long = "schema-dir", default_value = "schema")]
         schema_dir: PathBuf,
+        /// Directory of files that will ship alongside the DBCs.  Used to
+        /// check that assets referenced by helper entries (such as
+        /// `item_display` icons) exist.  Defaults to `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+        /// Also write `<Table>.dbc.provenance.yaml` next to each output
+        /// DBC, listing the patch entries that produced every modified or
+        /// inserted record.
+        #[arg(long = "provenance")]
+        provenance: bool,
+        /// Rebuild each string block from only the strings its table still
+        /// references, dropping strings orphaned by updates and copies
+        /// appended by earlier builds.  Tables whose schema does not name
+        /// every column are left as they are.
+        #[arg(long = "compact-strings")]
+        compact_strings: bool,
+        /// Read each written DBC back and check it before going on: the
+        /// header agrees with the file length, records and string block
+        /// read back as written, the block is null-terminated and every
+        /// string column points inside it.  A file failing the check fails
+        /// the run.
+        #[arg(long = "self-check")]
+        self_check: bool,
+        /// Also write a patch that undoes this run to this file: applied to
+        /// the output DBCs it sets back every field the patches changed,
+        /// deletes the records they inserted and restores those they
+        /// deleted, e.g. to remove the mod cleanly again
+        #[arg(long = "emit-revert", conflicts_with = "dry_run")]
+        emit_revert: Option<PathBuf>,
+        /// Also write a Markdown changelog to this file: the `description`
+        /// of every patch entry that has one, as a heading, with the
+        /// `issue`s given with it and the records it changed in each
+        /// table, for release notes
+        #[arg(long = "changelog", conflicts_with = "dry_run")]
+        changelog: Option<PathBuf>,
+        /// Apply the patches in memory and print what they would change,
+        /// record by record and field by field, without writing any DBC,
+        /// the ID map or running hooks.  Meant for reviewing patches.
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+        /// Also apply patch files marked `experimental: true`.  They are
+        /// left out by default so unfinished work cannot reach a release.
+        #[arg(long = "include-experimental")]
+        include_experimental: bool,
+        /// Translations file written by `extract-schema-strings`.  Each
+        /// translated text is written to its locale's `_lang_N` slot of
+        /// every entry whose `_lang_0` value, or bare `loc` value, matches
+        /// it.
+        #[arg(long = "translations")]
+        translations: Option<PathBuf>,
+        /// Client locale to build for (e.g. `deDE`); repeat for several.
+        /// Values of `loc` fields written by their bare name go to these
+        /// locales' slots (`enUS` by default), and only these locales are
+        /// taken from `--translations` (all by default).
+        #[arg(long = "locale")]
+        locales: Vec<String>,
+        /// Further patch directory applied on top of the in‑memory result
+        /// of the patches before it, e.g. guild‑specific tweaks over the
+        /// base visuals.  Repeat to stack more layers, in order.
+        #[arg(long = "layer")]
+        layers: Vec<PathBuf>,
+        /// Date (`YYYY-MM-DD`) at which the manifest's seasonal patch
+        /// groups are evaluated.  Defaults to today; pin it to reproduce
+        /// a build.
+        #[arg(long = "as-of")]
+        as_of: Option<Date>,
+        /// Client profile to build for, e.g. `1.18`.  Patch files that
+        /// declare a `profile` are only applied when it is one of theirs.
+        #[arg(long = "profile")]
+        profile: Option<String>,
+        /// Build number of the client to build for.  Patch files that
+        /// declare `min_build` or `max_build` are only applied when it
+        /// lies in their range.
+        #[arg(long = "client-build")]
+        client_build: Option<u32>,
     },
     /// Apply patches and then build an MPQ archive containing the
     /// resulting DBC files.  The MPQ will contain files under
@@ -71,8 +236,23 @@ enum Commands {
         /// `--dbc-files` is not specified.  Defaults to `dbc`.
         #[arg(long = "dbc-dir", default_value = "dbc")]
         dbc_dir: PathBuf,
-        /// Patch files in YAML format.  If omitted the tool will load all
-        /// `.yaml` and `.yml` files from the default patch directory (see
+        /// Client archive to read the source tables from instead of
+        /// `--dbc-dir`, e.g. `dbc.MPQ`.  Repeat it for a patch chain, in
+        /// load order; a table in a later archive replaces the earlier
+        /// ones.  The tables are extracted afresh on every run into
+        /// `dbc-source` under the output directory.
+        #[arg(long = "dbc-source", conflicts_with = "dbc_files")]
+        dbc_source: Vec<PathBuf>,
+        /// Client directory, or its `Data` directory, to read the source
+        /// tables from: its archives are found and read in the client's
+        /// load order, base archives, `patch.MPQ`, then `patch-2.MPQ`…
+        /// `patch-Z.MPQ`, so each table is the version the client loads,
+        /// with the rows later patches such as Turtle WoW's add (see
+        /// `--dbc-source`)
+        #[arg(long = "client-dir", conflicts_with_all = ["dbc_files", "dbc_source"])]
+        client_dir: Option<PathBuf>,
+        /// Patch files in YAML or JSON format.  If omitted the tool will load
+        /// all `.yaml`, `.yml` and `.json` files from the default patch directory (see
         /// `--patch-dir`).
         #[arg(short = 'p', long = "patches")]
         patches: Vec<PathBuf>,
@@ -90,126 +270,959 @@ enum Commands {
         /// MPQ format version (1, 2, 3 or 4).  Defaults to 2.
         #[arg(long = "mpq-version", default_value_t = 2)]
         mpq_version: u8,
+        /// How files are compressed in the archive: `zlib`, `bzip2` or
+        /// `none`.  The manifest's `compression` section overrides it for
+        /// the extensions it lists.  Defaults to `zlib`.
+        #[arg(long = "compression", value_enum, default_value = "zlib")]
+        compression: Compression,
+        /// Existing archive to start from, such as a server's
+        /// `patch-O.mpq`.  Its files are kept unless the build replaces
+        /// them, and the result is written to `--mpq`, which may be the
+        /// same path.
+        #[arg(long = "base-mpq")]
+        base_mpq: Option<PathBuf>,
+        /// Leave the `(listfile)` and `(attributes)` blocks out of the
+        /// archive.  Without them other MPQ tools cannot list its files
+        /// or check them.
+        #[arg(long = "no-listfile")]
+        no_listfile: bool,
         /// Directory containing schema definitions (see `apply`)
         #[arg(long = "schema-dir", default_value = "schema")]
         schema_dir: PathBuf,
         /// Directory containing additional files to include in the MPQ.
-        /// All files under this directory will be added to the archive
-        /// preserving their relative paths.  Defaults to `includes`.
+        /// Files under this directory are added to the archive under their
+        /// relative paths, unless an `includes.yaml` in it picks, leaves
+        /// out or renames them.  Defaults to `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+        /// Only archive include files matching this pattern (`*` matches
+        /// any run of characters; a pattern without a slash also matches
+        /// bare file names).  May be given more than once.
+        #[arg(long = "include-glob", value_name = "PATTERN")]
+        include_globs: Vec<String>,
+        /// Leave include files matching this pattern out of the archive,
+        /// e.g. `*.psd`.  May be given more than once.
+        #[arg(long = "exclude-glob", value_name = "PATTERN")]
+        exclude_globs: Vec<String>,
+        /// Write provenance files next to the output DBCs (see `apply`).
+        /// They are not added to the archive.
+        #[arg(long = "provenance")]
+        provenance: bool,
+        /// Compact the string blocks of the output DBCs (see `apply`)
+        #[arg(long = "compact-strings")]
+        compact_strings: bool,
+        /// Check each written DBC before packing it (see `apply`)
+        #[arg(long = "self-check")]
+        self_check: bool,
+        /// Also write `manifest.json` next to the archive, with the size
+        /// and SHA‑256 of the archive, of every file in it and of the
+        /// patch files, the tool version and the project's git revision
+        #[arg(long = "release-manifest")]
+        release_manifest: bool,
+        /// Also write a Markdown changelog of the patch entries' notes to
+        /// this file (see `apply`)
+        #[arg(long = "changelog")]
+        changelog: Option<PathBuf>,
+        /// Store files under backslashed archive paths with one casing per
+        /// directory: the client's own, such as `DBFilesClient` and
+        /// `Spells`, for the directories it has, otherwise that of the
+        /// first file under it.  For MPQ readers that compare case.
+        #[arg(long = "normalize-paths")]
+        normalize_paths: bool,
+        /// Also apply patch files marked `experimental: true` (see `apply`).
+        #[arg(long = "include-experimental")]
+        include_experimental: bool,
+        /// Translations file to apply (see `apply`)
+        #[arg(long = "translations")]
+        translations: Option<PathBuf>,
+        /// Client locale to build for (see `apply`)
+        #[arg(long = "locale")]
+        locales: Vec<String>,
+        /// Further patch directories layered over the patches (see `apply`)
+        #[arg(long = "layer")]
+        layers: Vec<PathBuf>,
+        /// Date at which seasonal patch groups are evaluated (see `apply`)
+        #[arg(long = "as-of")]
+        as_of: Option<Date>,
+        /// Client profile to build for (see `apply`)
+        #[arg(long = "profile")]
+        profile: Option<String>,
+        /// Build number of the client to build for (see `apply`)
+        #[arg(long = "client-build")]
+        client_build: Option<u32>,
+    },
+    /// Check that the changes patches make actually landed in a built
+    /// archive: the patches are applied in memory, the tables they target
+    /// are read out of the MPQ, and every entry is reported as present,
+    /// missing (with the fields that differ) or a no‑op.  Exits with an
+    /// error when any entry is missing.
+    Verify {
+        /// Archive to check, e.g. the output of `build`
+        #[arg(long = "mpq")]
+        mpq: PathBuf,
+        /// YAML patch files the archive was built from.  Defaults to every
+        /// file in `--patch-dir`, as `build` selects them.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Further patch directories layered over the patches (see `apply`)
+        #[arg(long = "layer")]
+        layers: Vec<PathBuf>,
+        /// Also apply patch files marked `experimental: true` (see `apply`).
+        #[arg(long = "include-experimental")]
+        include_experimental: bool,
+        /// Date at which seasonal patch groups are evaluated (see `apply`)
+        #[arg(long = "as-of")]
+        as_of: Option<Date>,
+        /// Client profile to build for (see `apply`)
+        #[arg(long = "profile")]
+        profile: Option<String>,
+        /// Build number of the client to build for (see `apply`)
+        #[arg(long = "client-build")]
+        client_build: Option<u32>,
+        /// Directory containing the stock DBC files.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory of files that ship alongside the DBCs.  Defaults to
+        /// `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+        /// Also write the per‑entry results and a summary to this file as
+        /// YAML, for scripts and CI
+        #[arg(long = "report")]
+        report: Option<PathBuf>,
+    },
+    /// Search the string fields of DBC tables for a piece of text and
+    /// print every matching record with its table, key and field.
+    Grep {
+        /// Text to search for (plain substring match)
+        pattern: String,
+        /// DBC files to search.  If omitted every `.dbc` file in
+        /// `--dbc-dir` is searched.
+        #[arg(short = 'd', long = "dbc-files")]
+        dbc_files: Vec<PathBuf>,
+        /// Directory containing DBC files.  Defaults to `dbc`; point it
+        /// at `build` to search patched output instead.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Match without regard to case
+        #[arg(short = 'i', long = "ignore-case")]
+        ignore_case: bool,
+    },
+    /// Search every table for a piece of text or a number and print each
+    /// hit with its table, key and field.
+    Search {
+        /// Text to search string fields for (plain substring match)
+        #[arg(long = "string", conflicts_with = "value", required_unless_present = "value")]
+        string: Option<String>,
+        /// Number to search columns for, e.g. `4930`, `-1`, `0x80` or
+        /// `1.5`.  Float columns are compared as floats; string columns
+        /// are skipped.
+        #[arg(long = "value", allow_hyphen_values = true)]
+        value: Option<String>,
+        /// Only search this field: a name (an array's name covers its
+        /// elements) or a column number
+        #[arg(long = "column")]
+        column: Option<String>,
+        /// DBC files to search.  If omitted every `.dbc` file in
+        /// `--dbc-dir` is searched.
+        #[arg(short = 'd', long = "dbc-files")]
+        dbc_files: Vec<PathBuf>,
+        /// Directory containing DBC files.  Defaults to `dbc`; point it
+        /// at `build` to search patched output instead.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Match text without regard to case
+        #[arg(short = 'i', long = "ignore-case")]
+        ignore_case: bool,
+    },
+    /// Replace a string value in every string‑typed field of the selected
+    /// tables, e.g. to repoint references after renaming a model, and
+    /// write the changed tables to the output directory.
+    RewriteStrings {
+        /// The exact string value to replace
+        #[arg(long = "from")]
+        from: String,
+        /// The replacement string
+        #[arg(long = "to")]
+        to: String,
+        /// DBC files to rewrite.  If omitted every `.dbc` file in
+        /// `--dbc-dir` with string fields in its schema is considered.
+        #[arg(short = 'd', long = "dbc-files")]
+        dbc_files: Vec<PathBuf>,
+        /// Directory containing source DBC files.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory where changed DBCs will be written.  Defaults to
+        /// `build`.
+        #[arg(short = 'o', long = "out-dir", default_value = "build")]
+        out_dir: PathBuf,
+        /// Compare without regard to ASCII case
+        #[arg(short = 'i', long = "ignore-case")]
+        ignore_case: bool,
+        /// List the affected rows without writing anything
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// List every row referencing an ID or asset path.  IDs are matched
+    /// through foreign keys declared with `ref:` in the schemas; paths are
+    /// matched against string fields and against files in the includes
+    /// directory that embed them.
+    WhereUsed {
+        /// Record ID (e.g. `7373`) or asset path/name (e.g.
+        /// `DangerAreaBlue.blp`)
+        target: String,
+        /// Only follow references into this table (e.g. `SpellVisual.dbc`)
+        #[arg(short = 't', long = "table")]
+        table: Option<String>,
+        /// DBC files to search.  If omitted every `.dbc` file in
+        /// `--dbc-dir` is searched.
+        #[arg(short = 'd', long = "dbc-files")]
+        dbc_files: Vec<PathBuf>,
+        /// Directory containing DBC files.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory of additional archive files to scan for embedded
+        /// asset paths.  Defaults to `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+    },
+    /// Apply the patches in memory and report foreign keys (fields the
+    /// schemas declare with `ref:`) that point at no record, such as a
+    /// spell naming a SpellVisual that was never inserted.  Exits with an
+    /// error when any are found.
+    CheckRefs {
+        /// YAML patch files to apply.  Defaults to every file in
+        /// `--patch-dir`, as `build` selects them.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Further patch directories layered over the patches (see `apply`)
+        #[arg(long = "layer")]
+        layers: Vec<PathBuf>,
+        /// Also apply patch files marked `experimental: true` (see `apply`).
+        #[arg(long = "include-experimental")]
+        include_experimental: bool,
+        /// Date at which seasonal patch groups are evaluated (see `apply`)
+        #[arg(long = "as-of")]
+        as_of: Option<Date>,
+        /// Client profile to build for (see `apply`)
+        #[arg(long = "profile")]
+        profile: Option<String>,
+        /// Build number of the client to build for (see `apply`)
+        #[arg(long = "client-build")]
+        client_build: Option<u32>,
+        /// Directory containing the stock DBC files.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory of files that ship alongside the DBCs.  Defaults to
+        /// `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+        /// Check every record, including dangling references the stock
+        /// tables already have, not only those the patches set or break
+        #[arg(long = "all")]
+        all: bool,
+    },
+    /// Write every record of a DBC table to a CSV file, with the schema's
+    /// field names as column headers, for editing in a spreadsheet.
+    ExportCsv {
+        /// DBC file to export
+        dbc_file: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// CSV file to write.  Defaults to the table name with `.csv` in
+        /// the working directory.
+        #[arg(short = 'o', long = "out")]
+        out: Option<PathBuf>,
+    },
+    /// Write every record of a DBC table, such as one `apply` produced, as
+    /// SQL statements that create and fill a table, so a server's world
+    /// database can be loaded with the same edits and compared.
+    ExportSql {
+        /// DBC file to export
+        dbc_file: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// SQL table to fill.  Defaults to `dbc_` and the lower‑cased
+        /// table name, e.g. `dbc_spell`.
+        #[arg(short = 't', long = "table")]
+        table: Option<String>,
+        /// Database the statements are written for
+        #[arg(long = "dialect", value_enum, default_value = "mysql")]
+        dialect: SqlDialect,
+        /// SQL file to write.  Defaults to the SQL table name with `.sql`
+        /// in the working directory.
+        #[arg(short = 'o', long = "out")]
+        out: Option<PathBuf>,
+    },
+    /// Turn a CSV file in the `export-csv` layout back into a DBC table,
+    /// rebuilding its string block.
+    ImportCsv {
+        /// CSV file to read
+        csv_file: PathBuf,
+        /// Table the CSV holds (e.g. `Spell.dbc`).  Defaults to the CSV
+        /// file name with `.dbc`.
+        #[arg(short = 't', long = "table")]
+        table: Option<String>,
+        /// Directory containing the stock DBC files, whose field count the
+        /// CSV must match.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory the table is written to.  Defaults to `build`.
+        #[arg(short = 'o', long = "out-dir", default_value = "build")]
+        out_dir: PathBuf,
+    },
+    /// Print a record with field names and resolved strings, optionally
+    /// joined with the records its foreign keys point at.
+    Dump {
+        /// Table to read (e.g. `Spell.dbc`)
+        table: String,
+        /// Value of column 0 of the record to print
+        #[arg(short = 'k', long = "key")]
+        key: u32,
+        /// Directory containing DBC files.  Defaults to `dbc`; point it
+        /// at `build` to inspect patched output.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Follow `ref:` foreign keys this many levels deep and render the
+        /// referenced records inline.  `--join` alone means 3 levels, enough
+        /// for Spell -> SpellVisual -> SpellVisualKit -> SpellVisualEffectName.
+        #[arg(short = 'j', long = "join", num_args = 0..=1, default_value_t = 0, default_missing_value = "3")]
+        join: usize,
+    },
+    /// List the patch entries that touched one record, in the order they
+    /// are applied, with what each changed, like `git blame` for a row.
+    ExplainRow {
+        /// Table the record is in (e.g. `Spell.dbc`)
+        table: String,
+        /// Value of column 0 of the record
+        #[arg(short = 'k', long = "key")]
+        key: u32,
+        /// YAML patch files to apply.  Defaults to every file in
+        /// `--patch-dir`, as `build` selects them.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Further patch directories layered over the patches (see `apply`)
+        #[arg(long = "layer")]
+        layers: Vec<PathBuf>,
+        /// Also apply patch files marked `experimental: true` (see `apply`).
+        #[arg(long = "include-experimental")]
+        include_experimental: bool,
+        /// Date at which seasonal patch groups are evaluated (see `apply`)
+        #[arg(long = "as-of")]
+        as_of: Option<Date>,
+        /// Client profile to build for (see `apply`)
+        #[arg(long = "profile")]
+        profile: Option<String>,
+        /// Build number of the client to build for (see `apply`)
+        #[arg(long = "client-build")]
+        client_build: Option<u32>,
+        /// Directory containing the stock DBC files.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory of files that ship alongside the DBCs.  Defaults to
+        /// `includes`.
         #[arg(long = "includes-dir", default_value = "includes")]
         includes_dir: PathBuf,
     },
+    /// Interactively build an `insert` entry by walking a table's schema
+    /// fields, with a search picker for foreign keys.
+    Wizard {
+        /// Table to insert into.  Prompted for when omitted.
+        table: Option<String>,
+        /// Directory containing DBC files, used for ID suggestions and the
+        /// foreign‑key picker.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Patch file to append the generated entry to.  The entry is
+        /// always printed.
+        #[arg(short = 'o', long = "out")]
+        out: Option<PathBuf>,
+    },
+    /// Create schema files from column definitions of community tools
+    /// (WDBX Editor XML or CSV).
+    ImportSchema {
+        /// Definition file to import
+        source: PathBuf,
+        /// Format of the definition file
+        #[arg(short = 'f', long = "format", value_enum, default_value = "wdbx")]
+        format: DefinitionFormat,
+        /// Only import this table (WDBX), or name the table (CSV; defaults
+        /// to the file stem)
+        #[arg(short = 't', long = "table")]
+        table: Option<String>,
+        /// Client build whose WDBX definitions to import.  Defaults to
+        /// 5875 (1.12.1).
+        #[arg(long = "build", default_value = "5875")]
+        build: String,
+        /// Directory to write schema files to.  Defaults to `schema`.
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Overwrite existing schema files
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// Write a draft schema for a table that has none, guessing from its
+    /// records which columns hold strings, floats and integers.  Fields
+    /// are named by column for you to rename.
+    SchemaGen {
+        /// Table to read (e.g. `Foo.dbc`), or the path of a DBC file
+        table: String,
+        /// Directory containing DBC files.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory to write the schema file to.  Defaults to `schema`.
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Overwrite an existing schema file
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// Check patch files for entries that change fields the manifest's
+    /// ownership rules protect.  Exits with an error when problems are
+    /// found.
+    Lint {
+        /// YAML patch files to check.  Defaults to every file in
+        /// `--patch-dir`.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Directory containing stock DBC files, which script entries are
+        /// run against.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+    },
+    /// Check patch files on their own, without reading the tables they
+    /// patch: every file parses, key columns and field names resolve
+    /// against the schemas, values suit their fields' types, no two files
+    /// create the same record by key and none deletes one another creates.  Problems are reported with
+    /// their file and line, and make the command fail, so it can run as a
+    /// pre‑commit hook.
+    LintPatches {
+        /// YAML patch files to check.  Defaults to every file in
+        /// `--patch-dir`.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory containing stock DBC files.  Only read to number
+        /// symbolic keys that have no ID yet, as `apply --dry-run` does,
+        /// and not needed otherwise.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+    },
+    /// Rewrite patch files from deprecated forms (the `dbc:`/`changes:`
+    /// object form, field names a schema lists under `renamed`) to the
+    /// current ones, keeping comments and layout.
+    Migrate {
+        /// YAML patch files to migrate.  Defaults to every file in
+        /// `--patch-dir`.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Only list what would change, and fail if anything would, e.g.
+        /// in CI
+        #[arg(long = "check")]
+        check: bool,
+    },
+    /// Check whether patch entries would find their target rows in a
+    /// user's installed, possibly already modified client tables, and
+    /// report entries that would be no‑ops or conflict with rows there.
+    /// Nothing is written.
+    ValidatePatchAgainstLive {
+        /// Directory holding the user's client DBCs (e.g. extracted from
+        /// their installed patches)
+        #[arg(short = 'l', long = "live-dir")]
+        live_dir: PathBuf,
+        /// YAML patch files to check.  Defaults to every file in
+        /// `--patch-dir`.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Directory containing stock DBC files, for entries that consult
+        /// other tables.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory of files that will ship alongside the DBCs (see
+        /// `apply`).  Defaults to `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+    },
+    /// Check that the stock models, textures and sounds patches point at,
+    /// and that the includes directory does not ship, exist in a client's
+    /// archives, and print which archive provides each.  Exits with an
+    /// error when any is missing.
+    VerifyReferencesIntoClient {
+        /// Client directory, or its `Data` directory, whose MPQ archives
+        /// are searched in the client's load order
+        #[arg(short = 'c', long = "client-dir")]
+        client_dir: PathBuf,
+        /// YAML patch files to check.  Defaults to every file in
+        /// `--patch-dir`.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Directory of files that will ship alongside the DBCs; assets
+        /// found there are not looked up in the client.  Defaults to
+        /// `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+    },
+    /// Copy a built archive into a client's `Data` directory and check
+    /// the client's `WDB` cache, whose stale entries can hide changed rows
+    Install {
+        /// Archive to install, as written by `build`
+        mpq: PathBuf,
+        /// Client directory, or its `Data` directory
+        #[arg(short = 'c', long = "client-dir")]
+        client_dir: PathBuf,
+        /// File name to give the archive in `Data` (e.g. `patch-O.MPQ`).
+        /// Defaults to the archive's own name.
+        #[arg(long = "as")]
+        name: Option<String>,
+        /// Delete every file in the client's `WDB` cache after
+        /// installing, listing each one.  Close the client first.
+        #[arg(long = "clear-cache")]
+        clear_cache: bool,
+    },
+    /// Collect the localized strings patches add (values of `_lang_0`
+    /// fields, or of `loc` fields written by their bare name) into a
+    /// translations file for `--translations`.  Existing
+    /// translations in the file are kept.
+    ExtractSchemaStrings {
+        /// YAML patch files to scan.  Defaults to every file in
+        /// `--patch-dir`.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Translations file to create or update.  Defaults to
+        /// `translations.yaml`.
+        #[arg(short = 'o', long = "out", default_value = "translations.yaml")]
+        out: PathBuf,
+        /// Directory of schemas, to recognize `loc` fields written by their
+        /// bare name
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+    },
+    /// Apply two patch trees (or the patch directory at two git revisions)
+    /// in memory and report how the built tables would differ, record by
+    /// record and field by field.  Nothing is written.
+    DiffBuilds {
+        /// The old patch directory, or a git revision with `--git`
+        old: String,
+        /// The new patch directory, or a git revision with `--git`
+        new: String,
+        /// Treat `old` and `new` as git revisions of `--patch-dir`
+        #[arg(long = "git")]
+        git: bool,
+        /// Directory containing patch YAML files, read at each revision
+        /// with `--git`.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Directory containing stock DBC files both builds start from.
+        /// Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory of files that will ship alongside the DBCs (see
+        /// `apply`).  Defaults to `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+    },
+    /// Copy files, by default the DBC tables, out of client MPQ archives,
+    /// e.g. to fill the `dbc` directory on a new machine.
+    Extract {
+        /// Archive to read.  Repeat to read a patch chain in load order;
+        /// files in later archives replace those in earlier ones.
+        #[arg(short = 'm', long = "mpq", required = true)]
+        mpqs: Vec<PathBuf>,
+        /// Archive paths to extract; `*` matches any run of characters
+        #[arg(long = "filter", default_value = "DBFilesClient\\*.dbc")]
+        filter: String,
+        /// Directory to write the files to, flattened to their file names.
+        /// Defaults to `dbc`.
+        #[arg(short = 'o', long = "out", default_value = "dbc")]
+        out_dir: PathBuf,
+    },
+    /// Print the files of an MPQ archive with their sizes, sizes in the
+    /// archive and block flags, e.g. to check what a built or downloaded
+    /// `patch-O.mpq` actually holds.
+    List {
+        /// Archive to list
+        mpq: PathBuf,
+        /// Only list archive paths matching this; `*` matches any run of
+        /// characters
+        #[arg(long = "filter")]
+        filter: Option<String>,
+    },
+    /// Combine several MPQ archives, such as patches from different
+    /// community sources, into one, listing the files they overlap in.
+    MergeMpq {
+        /// Archives to merge, in load order: with `--on-conflict
+        /// last-wins` a file in a later archive replaces the same file in
+        /// earlier ones
+        #[arg(required = true)]
+        mpqs: Vec<PathBuf>,
+        /// Path of the merged archive
+        #[arg(short = 'o', long = "out", required = true)]
+        out: PathBuf,
+        /// What to do with a file the archives hold with different
+        /// contents: keep the last archive's copy, stop, or ask for each.
+        /// Defaults to `last-wins`.
+        #[arg(long = "on-conflict", value_enum, default_value = "last-wins")]
+        on_conflict: OnConflict,
+        /// MPQ format version (1, 2, 3 or 4).  Defaults to 2.
+        #[arg(long = "mpq-version", default_value_t = 2)]
+        mpq_version: u8,
+        /// How files are compressed in the merged archive (see `build`)
+        #[arg(long = "compression", value_enum, default_value = "zlib")]
+        compression: Compression,
+    },
+    /// Turn the differences between two versions of a DBC file, e.g. one
+    /// edited in an external editor, into a patch with update, insert and
+    /// delete entries.
+    Diff {
+        /// The unmodified table
+        original: PathBuf,
+        /// The edited table
+        modified: PathBuf,
+        /// Schema file naming the fields.  Defaults to the table's schema
+        /// in `schema`.
+        #[arg(long = "schema")]
+        schema: Option<PathBuf>,
+        /// Column records are matched by, as a field name or number.
+        /// Defaults to column 0.
+        #[arg(long = "key-column")]
+        key_column: Option<String>,
+        /// Patch file to write.  Printed when omitted.
+        #[arg(short = 'o', long = "out")]
+        out: Option<PathBuf>,
+    },
+    /// List files in the includes directory that no table string (after
+    /// patching) and no referenced include points at, and offer to
+    /// exclude them from the archive or delete them.
+    Prune {
+        /// YAML patch files to apply first.  Defaults to every file in
+        /// `--patch-dir`, experimental ones included.
+        #[arg(short = 'p', long = "patches")]
+        patches: Vec<PathBuf>,
+        /// Directory containing patch YAML files.  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Directory containing the DBC files, all of which are searched.
+        /// Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory of files that ship alongside the DBCs.  Defaults to
+        /// `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+        /// Delete the unreferenced files, after asking
+        #[arg(long = "delete")]
+        delete: bool,
+        /// Do not ask before deleting
+        #[arg(short = 'y', long = "yes", requires = "delete")]
+        yes: bool,
+    },
+    /// Build the patch projects listed in the manifest's `workspace`
+    /// section, each into its own archive or all into one, after checking
+    /// that no two of them set a record field differently or ship
+    /// different files under the same path.
+    Workspace {
+        /// Member to build, by name; repeat for several.  Defaults to
+        /// every member.
+        #[arg(long = "member")]
+        members: Vec<String>,
+        /// Build the members into this one archive, applied in workspace
+        /// order, instead of one archive each
+        #[arg(long = "combined")]
+        combined: Option<PathBuf>,
+        /// Build the combined archive despite conflicts; later members
+        /// win
+        #[arg(long = "allow-conflicts", requires = "combined")]
+        allow_conflicts: bool,
+        /// Only check the members for conflicts
+        #[arg(long = "check", conflicts_with = "combined")]
+        check: bool,
+        /// Directory containing source DBC files.  Defaults to `dbc`.
+        #[arg(long = "dbc-dir", default_value = "dbc")]
+        dbc_dir: PathBuf,
+        /// Directory containing schema definitions (see `apply`)
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Directory where modified DBCs will be written, in a
+        /// subdirectory per member unless `--combined`.  Defaults to
+        /// `build`.
+        #[arg(short = 'o', long = "out-dir", default_value = "build")]
+        out_dir: PathBuf,
+        /// MPQ format version (1, 2, 3 or 4).  Defaults to 2.
+        #[arg(long = "mpq-version", default_value_t = 2)]
+        mpq_version: u8,
+        /// How files are compressed in the archives (see `build`)
+        #[arg(long = "compression", value_enum, default_value = "zlib")]
+        compression: Compression,
+        /// Also apply patch files marked `experimental: true` (see `apply`).
+        #[arg(long = "include-experimental")]
+        include_experimental: bool,
+        /// Date at which seasonal patch groups are evaluated (see `apply`)
+        #[arg(long = "as-of")]
+        as_of: Option<Date>,
+    },
+    /// Run `apply`, or the command given after `--` such as `build`, and
+    /// run it again whenever a patch, schema or include file changes, with
+    /// how long each run took.  Stop it with Ctrl+C.
+    Watch {
+        /// Patch directory to watch (see `apply`).  Defaults to `patches`.
+        #[arg(long = "patch-dir", default_value = "patches")]
+        patch_dir: PathBuf,
+        /// Schema directory to watch (see `apply`).  Defaults to `schema`.
+        #[arg(long = "schema-dir", default_value = "schema")]
+        schema_dir: PathBuf,
+        /// Includes directory to watch (see `apply`).  Defaults to
+        /// `includes`.
+        #[arg(long = "includes-dir", default_value = "includes")]
+        includes_dir: PathBuf,
+        /// Further directory to watch, e.g. a `--layer`; repeat for several
+        #[arg(long = "also-watch")]
+        also_watch: Vec<PathBuf>,
+        /// Command to run, with its options.  Defaults to `apply`.  The
+        /// global options of `watch` (`--manifest`, `--strict` and so on)
+        /// are passed on, and each run waits for the project lock.
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+    /// Create the directories a patch project uses (`dbc`, `patches`,
+    /// `schema`, `includes`, `build`) with a starter patch, a manifest and
+    /// a `.gitignore`.  Existing files are kept.
+    Init {
+        /// Directory to set the project up in.  Defaults to the current
+        /// directory.
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// Client directory, or its `Data` directory, to extract the stock
+        /// DBC tables from into `dbc`, in the client's load order
+        #[arg(short = 'c', long = "client-dir")]
+        client_dir: Option<PathBuf>,
+    },
 }
 
-/// Resolves a key column name or index to a numeric index
-fn resolve_key_column_index(
-    key_column: &Option<String>,
-    schema_map: &Option<HashMap<String, usize>>,
-    file_name: &str,
-    pf_origin: &str,
-) -> usize {
-    match key_column {
-        Some(ref col_name) => {
-            // Try to parse as a number first
-            if let Ok(idx) = col_name.parse::<usize>() {
-                idx
-            } else {
-                // Look up by name in schema map
-                if let Some(ref schema) = schema_map {
-                    if let Some(&idx) = schema.get(&col_name.to_lowercase()) {
-                        idx
-                    } else {
-                        println!(
-                            "Warning: unknown key column '{}' in {} (patch file: {}) – defaulting to 0",
-                            col_name, file_name, pf_origin
-                        );
-                        0
-                    }
-                } else {
-                    println!(
-                        "Warning: no schema for {} (patch file: {}), cannot resolve key column '{}', defaulting to 0",
-                        file_name, pf_origin, col_name
-                    );
-                    0
-                }
-            }
+/// The `--manifest` argument, read from the raw arguments since the
+/// manifest's defaults are needed to parse them.
+fn manifest_arg() -> Option<PathBuf> {
+    let mut args = env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--manifest" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|a| a.strip_prefix("--manifest=")) {
+            return Some(PathBuf::from(path));
         }
-        None => 0,
     }
+    None
 }
 
-/// Resolves a field name or index to a numeric index
-fn resolve_field_index(
-    field_name: &str,
-    schema_map: &Option<HashMap<String, usize>>,
-) -> Option<usize> {
-    // Try parse as number
-    if let Ok(idx) = field_name.parse::<usize>() {
-        Some(idx)
-    } else {
-        schema_map
-            .as_ref()
-            .and_then(|schema| schema.get(&field_name.to_lowercase()).cloned())
+/// `arg` with `value` as its default instead of the built-in one, and no
+/// longer required.
+fn default_to(arg: Arg, value: Option<&PathBuf>) -> Arg {
+    match value {
+        Some(value) => arg.required(false).default_value(value.as_os_str().to_os_string()),
+        None => arg,
     }
 }
 
-/// Applies values to a record, handling string allocation
-fn apply_values_to_record(
-    values: &HashMap<String, ValueType>,
-    record: &mut Vec<u32>,
-    schema_map: &Option<HashMap<String, usize>>,
-    string_map: &mut HashMap<String, u32>,
-    new_strings: &mut Vec<String>,
-    string_block: &[u8],
-    file_name: &str,
-    pf_origin: &str,
-    record_key: u32,
-) {
-    for (field_name, value) in values {
-        let field_idx = match resolve_field_index(field_name, schema_map) {
-            Some(i) => i,
-            None => {
-                println!(
-                    "Warning: unknown field '{}' in {} (patch file: {}) – skipping",
-                    field_name, file_name, pf_origin
-                );
-                continue;
-            }
-        };
-        
-        if field_idx >= record.len() {
-            println!(
-                "Warning: field {} out of range for record with key {} in {} (patch file: {})",
-                field_idx, record_key, file_name, pf_origin
-            );
-            continue;
-        }
-        
-        match value {
-            ValueType::String(s) => {
-                // Check if string already exists
-                let offset = if let Some(&off) = string_map.get(s) {
-                    off
-                } else {
-                    let offset = (string_block.len()
-                        + new_strings.iter().map(|ss| ss.len() + 1).sum::<usize>()) as u32;
-                    string_map.insert(s.clone(), offset);
-                    new_strings.push(s.clone());
-                    offset
-                };
-                record[field_idx] = offset;
+/// `command` with the options the manifest's `defaults` cover defaulting
+/// to its values, so flags given on the command line still win and
+/// `--help` shows the project's values.  `extract` writes to the DBC
+/// directory, so its `--out` follows `dbc_dir`.
+fn with_project_defaults(command: Command, defaults: &Defaults) -> Command {
+    command.mut_subcommands(|sub| {
+        sub.mut_args(|arg| {
+            let built_in = arg.get_default_values().first().and_then(|v| v.to_str()).map(str::to_string);
+            match (arg.get_id().as_str(), built_in.as_deref()) {
+                ("dbc_dir", _) | ("out_dir", Some("dbc")) => default_to(arg, defaults.dbc_dir.as_ref()),
+                ("out_dir", Some("build")) => default_to(arg, defaults.out_dir.as_ref()),
+                ("patch_dir", _) => default_to(arg, defaults.patch_dir.as_ref()),
+                ("schema_dir", _) => default_to(arg, defaults.schema_dir.as_ref()),
+                ("includes_dir", _) => default_to(arg, defaults.includes_dir.as_ref()),
+                ("mpq" | "mpq_path", _) => default_to(arg, defaults.mpq.as_ref()),
+                ("mpq_version", _) => match defaults.mpq_version {
+                    Some(version) => arg.default_value(version.to_string()),
+                    None => arg,
+                },
+                ("compression", _) => match defaults.compression.and_then(|c| c.to_possible_value()) {
+                    Some(value) => arg.default_value(value.get_name().to_string()),
+                    None => arg,
+                },
+                ("profile", _) => match &defaults.profile {
+                    Some(profile) => arg.default_value(profile.clone()),
+                    None => arg,
+                },
+                ("client_build", _) => match defaults.client_build {
+                    Some(build) => arg.default_value(build.to_string()),
+                    None => arg,
+                },
+                ("locales", _) if !defaults.locales.is_empty() => arg.default_values(defaults.locales.clone()),
+                _ => arg,
             }
-            _ => {
-                if let Some(int_val) = value.as_u32() {
-                    record[field_idx] = int_val;
-                }
-            }
-        }
+        })
+    })
+}
+
+/// The patch files a command applies, as the sets `patch_tables` takes:
+/// the `patches` given, or the files in `patch_dir` that the manifest's
+/// groups select `as_of` a date, then each of `layers`, without the
+/// experimental files unless asked for or the files for other clients.
+fn select_patch_sets(
+    manifest: &Manifest,
+    patches: &[PathBuf],
+    patch_dir: &Path,
+    layers: &[PathBuf],
+    as_of: Option<Date>,
+    include_experimental: bool,
+    target: &ClientTarget,
+) -> Result<Vec<Vec<PathBuf>>> {
+    let patch_paths = select_groups(
+        &manifest.groups,
+        &manifest.seasons,
+        &manifest.base_dir,
+        patch_paths_or_dir(patches, patch_dir)?,
+        patches.is_empty(),
+        as_of.unwrap_or_else(Date::today),
+    )?;
+    let mut patch_sets = vec![exclude_other_clients(exclude_experimental(patch_paths, include_experimental)?, target)?];
+    for layer in layers {
+        patch_sets.push(exclude_other_clients(
+            exclude_experimental(patch_paths_or_dir(&[], layer)?, include_experimental)?,
+            target,
+        )?);
+    }
+    Ok(patch_sets)
+}
+
+/// The DBC files a command writes: `dbc_files` if any were given, else
+/// the tables `patch_paths` patch, found in `dbc_dir` by case‑insensitive
+/// match.
+fn infer_dbc_paths(
+    dbc_files: &[PathBuf],
+    patch_paths: &[PathBuf],
+    dbc_dir: &Path,
+    ids: &IdMap,
+) -> Result<Vec<PathBuf>> {
+    if !dbc_files.is_empty() {
+        return Ok(dbc_files.to_vec());
     }
+    let names: BTreeSet<String> = load_patches_with_ids(patch_paths, ids)?.into_keys().collect();
+    Ok(names.iter().map(|name| find_dbc_file(dbc_dir, name)).collect())
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn main() {
+    if let Err(err) = run() {
+        report_error(&err);
+        std::process::exit(exit_code(&err));
+    }
+}
+
+fn run() -> Result<()> {
+    let manifest = Manifest::load(manifest_arg().as_deref())?;
+    let matches = with_project_defaults(Cli::command(), &manifest.defaults).get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    set_log_json(cli.log_json);
+    set_verbosity(if cli.quiet {
+        Verbosity::Quiet
+    } else if cli.verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    });
+    let units = Transformers::with_custom(&manifest.units)?.with_school_colors(&manifest.school_colors)?;
+    let mut ids = load_id_map(&manifest.ids, &manifest.base_dir)?;
+    set_endianness_override(cli.endianness);
+    set_strict(cli.strict || manifest.defaults.strict);
+    set_trace(cli.trace);
+    // Runs that write an output directory, an archive, or files a build
+    // reads hold the project lock until they return
+    let _lock = match cli.command {
+        Commands::Apply { dry_run: false, .. }
+        | Commands::Build { .. }
+        | Commands::Workspace { .. }
+        | Commands::RewriteStrings { dry_run: false, .. }
+        | Commands::ImportCsv { .. }
+        | Commands::MergeMpq { .. }
+        | Commands::Extract { .. }
+        | Commands::Prune { delete: true, .. } => Some(ProjectLock::acquire(&manifest.base_dir, cli.wait)?),
+        _ => None,
+    };
     match cli.command {
         Commands::Apply {
             dbc_files,
@@ -217,60 +1230,110 @@ fn main() -> Result<()> {
             out_dir,
             schema_dir,
             dbc_dir,
+            dbc_source,
+            client_dir,
             patch_dir,
+            includes_dir,
+            provenance,
+            compact_strings,
+            self_check,
+            emit_revert,
+            changelog,
+            dry_run,
+            include_experimental,
+            translations,
+            locales,
+            layers,
+            as_of,
+            profile,
+            client_build,
         } => {
-            // Determine which patch files to use.  If none were specified,
-            // read all .yaml and .yml files from the patch_dir.
-            let patch_paths: Vec<PathBuf> = if patches.is_empty() {
-                let mut files = Vec::new();
-                if patch_dir.exists() {
-                    for entry in fs::read_dir(&patch_dir)? {
-                        let entry = entry?;
-                        let path = entry.path();
-                        if path.extension().map_or(false, |ext| {
-                            let ext = ext.to_string_lossy().to_lowercase();
-                            ext == "yaml" || ext == "yml"
-                        }) {
-                            files.push(path);
-                        }
-                    }
-                }
-                files
+            let target = ClientTarget { profile, build: client_build };
+            let dbc_source = match &client_dir {
+                Some(dir) => detect_patch_chain(dir)?,
+                None => dbc_source,
+            };
+            // A dry run writes nothing under the output directory, and does
+            // not hold the lock, so it reads the archives into a scratch
+            // directory instead
+            let stage_dir = if dry_run {
+                env::temp_dir().join(format!("wow_dbc_patcher-dry-run-{}", std::process::id()))
+            } else {
+                out_dir.clone()
+            };
+            let dbc_dir = if dbc_source.is_empty() {
+                dbc_dir
             } else {
-                patches.clone()
+                stage_dbc_source(&dbc_source, &stage_dir)?
             };
+            // Determine which patch files to use.  If none were specified,
+            // read all .yaml, .yml and .json files from the patch_dir.
+            let patch_sets = select_patch_sets(&manifest, &patches, &patch_dir, &layers, as_of, include_experimental, &target)?;
+            let patch_paths = patch_sets.concat();
+            assign_ids(&mut ids, &patch_paths, &dbc_dir, &schema_dir, &manifest.ids, &manifest.base_dir, !dry_run)?;
             // Determine which DBC files to process.  If the user did not
             // explicitly specify any, infer them from the patch files and
             // load them from the dbc_dir directory.
-            let dbc_paths: Vec<PathBuf> = if dbc_files.is_empty() {
-                let patch_map = load_patches(&patch_paths)?;
-                let mut set: HashSet<String> = HashSet::new();
-                for key in patch_map.keys() {
-                    set.insert(key.clone());
-                }
-                let mut paths = Vec::new();
-                for name in set {
-                    // Attempt to resolve the file in dbc_dir by case‑insensitive match.
-                    let mut found_path: Option<PathBuf> = None;
-                    if dbc_dir.exists() {
-                        for entry in fs::read_dir(&dbc_dir)? {
-                            let entry = entry?;
-                            if let Some(file_name) = entry.file_name().to_str() {
-                                if file_name.to_lowercase() == name {
-                                    found_path = Some(entry.path());
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                    let path = found_path.unwrap_or_else(|| dbc_dir.join(&name));
-                    paths.push(path);
+            let dbc_paths = infer_dbc_paths(&dbc_files, &patch_paths, &dbc_dir, &ids)?;
+            let hook_ctx = HookContext {
+                dbc_dir: dbc_dir.clone(),
+                patch_dir: patch_dir.clone(),
+                schema_dir: schema_dir.clone(),
+                out_dir: out_dir.clone(),
+                includes_dir: Some(includes_dir.clone()),
+                ..Default::default()
+            };
+            let translations = match translations {
+                Some(path) => Translations::load(&path, &locales)?,
+                None => Translations::for_locales(&locales)?,
+            };
+            if dry_run {
+                let result = dry_run_command(
+                    &dbc_paths,
+                    &patch_sets,
+                    &dbc_dir,
+                    &schema_dir,
+                    &includes_dir,
+                    Some(&translations),
+                    &units,
+                    &ids,
+                );
+                if stage_dir.exists() {
+                    fs::remove_dir_all(&stage_dir).ok();
                 }
-                paths
+                result?;
+                check_strict()?;
             } else {
-                dbc_files.clone()
-            };
-            apply_command(&dbc_paths, &patch_paths, &out_dir, &schema_dir)?;
+                run_hooks(&manifest.hooks.pre_apply, "pre_apply", &hook_ctx)?;
+                // Read before the output may overwrite them
+                let originals = match &emit_revert {
+                    Some(_) => read_originals(&dbc_paths, &schema_dir)?,
+                    None => Vec::new(),
+                };
+                let applied = apply_command(
+                    &dbc_paths,
+                    &patch_sets,
+                    &out_dir,
+                    &dbc_dir,
+                    &schema_dir,
+                    &includes_dir,
+                    provenance,
+                    Some(&translations),
+                    &units,
+                    &ids,
+                    compact_strings,
+                    self_check,
+                )?;
+                manifest.budgets.check_tables(&applied)?;
+                check_strict()?;
+                if let Some(path) = &emit_revert {
+                    write_revert_patch(&originals, &out_dir, &schema_dir, path)?;
+                }
+                if let Some(path) = &changelog {
+                    write_changelog(&applied, path)?;
+                }
+                run_hooks(&manifest.hooks.post_apply, "post_apply", &hook_ctx)?;
+            }
         }
         Commands::Build {
             dbc_files,
@@ -278,626 +1341,523 @@ fn main() -> Result<()> {
             out_dir,
             mpq_path,
             mpq_version,
+            compression,
+            base_mpq,
+            no_listfile,
             schema_dir,
             dbc_dir,
+            dbc_source,
+            client_dir,
             patch_dir,
             includes_dir,
+            include_globs,
+            exclude_globs,
+            provenance,
+            compact_strings,
+            self_check,
+            release_manifest,
+            changelog,
+            normalize_paths,
+            include_experimental,
+            translations,
+            locales,
+            layers,
+            as_of,
+            profile,
+            client_build,
         } => {
-            // Determine which patch files to use.
-            let patch_paths: Vec<PathBuf> = if patches.is_empty() {
-                let mut files = Vec::new();
-                if patch_dir.exists() {
-                    for entry in fs::read_dir(&patch_dir)? {
-                        let entry = entry?;
-                        let path = entry.path();
-                        if path.extension().map_or(false, |ext| {
-                            let ext = ext.to_string_lossy().to_lowercase();
-                            ext == "yaml" || ext == "yml"
-                        }) {
-                            files.push(path);
-                        }
-                    }
-                }
-                files
+            let target = ClientTarget { profile, build: client_build };
+            let dbc_source = match &client_dir {
+                Some(dir) => detect_patch_chain(dir)?,
+                None => dbc_source,
+            };
+            let dbc_dir = if dbc_source.is_empty() {
+                dbc_dir
             } else {
-                patches.clone()
+                stage_dbc_source(&dbc_source, &out_dir)?
             };
+            // Determine which patch files to use.
+            let patch_sets = select_patch_sets(&manifest, &patches, &patch_dir, &layers, as_of, include_experimental, &target)?;
+            let patch_paths = patch_sets.concat();
+            assign_ids(&mut ids, &patch_paths, &dbc_dir, &schema_dir, &manifest.ids, &manifest.base_dir, true)?;
             // Determine input DBC files for building.  Same logic as apply.
-            let dbc_paths: Vec<PathBuf> = if dbc_files.is_empty() {
-                let patch_map = load_patches(&patch_paths)?;
-                let mut set: HashSet<String> = HashSet::new();
-                for key in patch_map.keys() {
-                    set.insert(key.clone());
-                }
-                let mut paths = Vec::new();
-                for name in set {
-                    let mut found_path: Option<PathBuf> = None;
-                    if dbc_dir.exists() {
-                        for entry in fs::read_dir(&dbc_dir)? {
-                            let entry = entry?;
-                            if let Some(file_name) = entry.file_name().to_str() {
-                                if file_name.to_lowercase() == name {
-                                    found_path = Some(entry.path());
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                    let path = found_path.unwrap_or_else(|| dbc_dir.join(&name));
-                    paths.push(path);
-                }
-                paths
-            } else {
-                dbc_files.clone()
+            let dbc_paths = infer_dbc_paths(&dbc_files, &patch_paths, &dbc_dir, &ids)?;
+            let hook_ctx = HookContext {
+                dbc_dir: dbc_dir.clone(),
+                patch_dir: patch_dir.clone(),
+                schema_dir: schema_dir.clone(),
+                out_dir: out_dir.clone(),
+                includes_dir: Some(includes_dir.clone()),
+                mpq_path: Some(mpq_path.clone()),
+            };
+            let translations = match translations {
+                Some(path) => Translations::load(&path, &locales)?,
+                None => Translations::for_locales(&locales)?,
             };
+            run_hooks(&manifest.hooks.pre_build, "pre_build", &hook_ctx)?;
             build_command(
                 &dbc_paths,
-                &patch_paths,
+                &patch_sets,
                 &out_dir,
                 &mpq_path,
                 mpq_version,
+                base_mpq.as_deref(),
+                !no_listfile,
+                &dbc_dir,
                 &schema_dir,
                 &includes_dir,
+                &IncludeGlobs {
+                    include: include_globs,
+                    exclude: exclude_globs,
+                },
+                provenance,
+                Some(&translations),
+                &units,
+                &ids,
+                compact_strings,
+                self_check,
+                compression,
+                release_manifest,
+                changelog.as_deref(),
+                normalize_paths || manifest.defaults.normalize_paths,
+                &manifest,
+                &hook_ctx,
             )?;
+            check_strict()?;
+            run_hooks(&manifest.hooks.post_build, "post_build", &hook_ctx)?;
         }
-    }
-    Ok(())
-}
-
-/// Read and parse all patch files.  Returns a vector of `PatchFile` and a
-/// map from lower‑cased DBC file name to patches.  A DBC file may have
-/// multiple patch files targeting it.
-/// Parse a YAML document into one or more `PatchFile` values.  A patch
-/// document can take several forms:
-///
-/// 1. A single patch object with fields `dbc` and `changes`.
-/// 2. A sequence of patch objects as described above.
-/// 3. A mapping of DBC file names to arrays of changes.  In this case
-///    the key becomes the `dbc` field of a new `PatchFile` and the value
-///    must be a sequence of change objects.
-fn parse_patch_value(value: serde_yaml::Value, path: &Path) -> Result<Vec<PatchFile>> {
-    use serde_yaml::Value;
-    let mut patch_files = Vec::new();
-    match value {
-        // An empty document (null) or an empty mapping yields no patches.  This
-        // allows YAML files with only comments or whitespace to be ignored.
-        Value::Null => {
-            return Ok(patch_files);
-        }
-        Value::Mapping(ref map) if map.is_empty() => {
-            return Ok(patch_files);
-        }
-        Value::Sequence(seq) => {
-            for item in seq {
-                // Try to parse each element as a PatchFile
-                let pf: PatchFile = serde_yaml::from_value(item.clone()).with_context(|| {
-                    format!("Failed to parse patch entry in {:?}", path)
-                })?;
-                patch_files.push(pf);
-            }
+        Commands::Verify {
+            mpq,
+            patches,
+            patch_dir,
+            layers,
+            include_experimental,
+            as_of,
+            profile,
+            client_build,
+            dbc_dir,
+            schema_dir,
+            includes_dir,
+            report,
+        } => {
+            let target = ClientTarget { profile, build: client_build };
+            let patch_sets = select_patch_sets(&manifest, &patches, &patch_dir, &layers, as_of, include_experimental, &target)?;
+            verify_command(&mpq, &patch_sets, &dbc_dir, &schema_dir, &includes_dir, &units, &ids, report.as_deref())?;
         }
-        Value::Mapping(map) => {
-            // Heuristic: if the mapping contains keys "dbc" and "changes", treat
-            // it as a single patch file
-            let has_dbc = map.contains_key(&Value::String("dbc".to_string()));
-            let has_changes = map.contains_key(&Value::String("changes".to_string()));
-            if has_dbc && has_changes {
-                let pf: PatchFile = serde_yaml::from_value(Value::Mapping(map)).with_context(|| {
-                    format!("Failed to parse patch file {:?}", path)
-                })?;
-                patch_files.push(pf);
+        Commands::Grep {
+            pattern,
+            dbc_files,
+            dbc_dir,
+            schema_dir,
+            ignore_case,
+        } => {
+            let dbc_paths = if dbc_files.is_empty() {
+                list_dbc_files(&dbc_dir)?
             } else {
-                // Otherwise treat the mapping as a collection of DBC name to changes
-                for (k, v) in map {
-                    // Key must be a string representing the DBC name
-                    let dbc_name = match k {
-                        Value::String(s) => s,
-                        _ => {
-                            return Err(
-                                anyhow::anyhow!("Invalid DBC key in patch file {:?}: {:?}", path, k)
-                            );
-                        }
-                    };
-                    // Value must be a sequence of changes
-                    let changes: Vec<PatchEntry> = serde_yaml::from_value(v).with_context(|| {
-                        format!("Failed to parse changes for {} in {:?}", dbc_name, path)
-                    })?;
-                    let pf = PatchFile {
-                        dbc: dbc_name,
-                        changes,
-                        origin: None,
-                    };
-                    patch_files.push(pf);
-                }
-            }
+                dbc_files
+            };
+            grep_command(&pattern, &dbc_paths, &schema_dir, ignore_case)?;
         }
-        _ => {
-            return Err(anyhow::anyhow!("Unexpected YAML structure in patch file {:?}", path));
+        Commands::Search {
+            string,
+            value,
+            column,
+            dbc_files,
+            dbc_dir,
+            schema_dir,
+            ignore_case,
+        } => {
+            // Exactly one of them is given
+            let query = match string {
+                Some(text) => SearchQuery::Text(text),
+                None => SearchQuery::value(value.as_deref().unwrap_or_default())?,
+            };
+            let dbc_paths = if dbc_files.is_empty() {
+                list_dbc_files(&dbc_dir)?
+            } else {
+                dbc_files
+            };
+            search_command(&query, column.as_deref(), &dbc_paths, &schema_dir, ignore_case)?;
         }
-    }
-    Ok(patch_files)
-}
-
-/// Split a patch file into multiple YAML sections based on repeated top‑level DBC keys.
-/// This allows users to specify the same DBC name multiple times in a single file
-/// (e.g. `SpellVisual.dbc:` followed by another `SpellVisual.dbc:`).  We scan the
-/// file line by line; whenever we encounter a line with no leading indentation
-/// and ending in `.dbc:`, we treat that as the start of a new section.  Each
-/// section is parsed independently via `parse_patch_value` and aggregated.
-fn parse_patch_file(path: &Path) -> Result<Vec<PatchFile>> {
-    use std::fs;
-    let content = fs::read_to_string(path)
-        .with_context(|| format!("Failed to read patch file {:?}", path))?;
-    // Split into sections by top‑level DBC keys
-    let mut sections: Vec<String> = Vec::new();
-    let mut current = String::new();
-    for line in content.lines() {
-        // If the line has no leading indentation and ends with `.dbc:`, start a new section
-        let trimmed = line.trim_start();
-        let indent = line.len() - trimmed.len();
-        if indent == 0 && trimmed.ends_with(".dbc:") {
-            if !current.trim().is_empty() {
-                sections.push(current);
-                current = String::new();
-            }
+        Commands::RewriteStrings {
+            from,
+            to,
+            dbc_files,
+            dbc_dir,
+            schema_dir,
+            out_dir,
+            ignore_case,
+            dry_run,
+        } => {
+            let dbc_paths = if dbc_files.is_empty() {
+                list_dbc_files(&dbc_dir)?
+            } else {
+                dbc_files
+            };
+            rewrite_strings_command(
+                &from,
+                &to,
+                &dbc_paths,
+                &schema_dir,
+                &out_dir,
+                ignore_case,
+                dry_run,
+            )?;
         }
-        current.push_str(line);
-        current.push('\n');
-    }
-    if !current.trim().is_empty() {
-        sections.push(current);
-    }
-    // If no sections were detected, treat the whole file as a single section
-    if sections.is_empty() {
-        sections.push(content);
-    }
-    let mut pfs_all = Vec::new();
-    for section in sections {
-        // Parse each section as YAML
-        let value: serde_yaml::Value = serde_yaml::from_str(&section).with_context(|| {
-            format!("Failed to parse YAML section in {:?}", path)
-        })?;
-        let mut pfs = parse_patch_value(value, path)?;
-        // Set the origin on each patch file to the current path
-        for pf in &mut pfs {
-            pf.origin = Some(path.to_path_buf());
-        }
-        pfs_all.append(&mut pfs);
-    }
-    Ok(pfs_all)
-}
-
-fn load_patches(patch_paths: &[PathBuf]) -> Result<HashMap<String, Vec<PatchFile>>> {
-    let mut patches_map: HashMap<String, Vec<PatchFile>> = HashMap::new();
-    // Sort patch paths alphabetically by their file name to enforce deterministic ordering
-    let mut sorted: Vec<&PathBuf> = patch_paths.iter().collect();
-    sorted.sort_by(|a, b| {
-        let a_name = a
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("");
-        let b_name = b
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("");
-        a_name.cmp(b_name)
-    });
-    for path in sorted {
-        let pfs = parse_patch_file(path)?;
-        for pf in pfs {
-            let key = pf.dbc.to_lowercase();
-            patches_map.entry(key).or_default().push(pf);
+        Commands::WhereUsed {
+            target,
+            table,
+            dbc_files,
+            dbc_dir,
+            schema_dir,
+            includes_dir,
+        } => {
+            let dbc_paths = if dbc_files.is_empty() {
+                list_dbc_files(&dbc_dir)?
+            } else {
+                dbc_files
+            };
+            where_used_command(
+                &target,
+                table.as_deref(),
+                &dbc_paths,
+                &schema_dir,
+                &includes_dir,
+            )?;
         }
-    }
-    Ok(patches_map)
-}
-
-/// Load a schema mapping for a given DBC file.  The schema directory must
-/// contain a YAML file whose name is derived from the DBC file name with
-/// `.yaml` appended (for example `Spell.dbc.yaml`).  The YAML can be either
-/// a sequence of strings representing field names in order, or a mapping with
-/// a `fields` entry that is such a sequence.  Field names are converted to
-/// lowercase for case‑insensitive lookup.  Returns `None` if the file
-/// doesn't exist or cannot be parsed.
-fn load_schema_map(schema_dir: &Path, dbc_file_name: &str) -> Option<HashMap<String, usize>> {
-    use std::io::BufReader;
-    // Attempt to load a YAML file for this DBC from the provided
-    // schema directory.  If it does not exist there, fall back to
-    // the built‑in defaults under `schema` in the project
-    // root.  This allows shipping canonical 1.12 definitions with
-    // the tool while still permitting overrides via --schema-dir.
-    let yaml_name = format!("{}.yaml", dbc_file_name);
-    let candidate_dirs = [schema_dir, Path::new("schema")];
-    for dir in &candidate_dirs {
-        let path = dir.join(&yaml_name);
-        if !path.exists() {
-            continue;
-        }
-        let file = match File::open(&path) {
-            Ok(f) => f,
-            Err(_) => continue,
-        };
-        let reader = BufReader::new(file);
-        let value: serde_yaml::Value = match serde_yaml::from_reader(reader) {
-            Ok(v) => v,
-            Err(err) => {
-                println!("Warning: failed to parse schema {}: {}", path.display(), err);
-                continue;
-            }
-        };
-        let mut mapping: HashMap<String, usize> = HashMap::new();
-        match value {
-            serde_yaml::Value::Sequence(seq) => {
-                for (i, item) in seq.iter().enumerate() {
-                    if let serde_yaml::Value::String(name) = item {
-                        mapping.insert(name.to_lowercase(), i);
-                    }
-                }
-                return Some(mapping);
-            }
-            serde_yaml::Value::Mapping(map) => {
-                // Try to find "fields" entry
-                let mut found_fields = false;
-                for (k, v) in map.iter() {
-                    if let serde_yaml::Value::String(ref key_name) = k {
-                        if key_name == "fields" {
-                            if let serde_yaml::Value::Sequence(seq) = v {
-                                for (i, item) in seq.iter().enumerate() {
-                                    if let serde_yaml::Value::String(name) = item {
-                                        mapping.insert(name.to_lowercase(), i);
-                                    }
-                                }
-                                found_fields = true;
-                                break;
-                            }
-                        }
-                    }
-                }
-                if found_fields {
-                    return Some(mapping);
-                }
-                // Fallback: treat mapping keys as names and values as indices
-                let mut ok = false;
-                for (k, v) in map.iter() {
-                    if let (serde_yaml::Value::String(name), serde_yaml::Value::Number(num)) = (k, v) {
-                        if let Some(i) = num.as_u64() {
-                            mapping.insert(name.to_lowercase(), i as usize);
-                            ok = true;
-                        }
-                    }
-                }
-                if ok {
-                    return Some(mapping);
-                }
-            }
-            _ => {}
+        Commands::CheckRefs {
+            patches,
+            patch_dir,
+            layers,
+            include_experimental,
+            as_of,
+            profile,
+            client_build,
+            dbc_dir,
+            schema_dir,
+            includes_dir,
+            all,
+        } => {
+            let target = ClientTarget { profile, build: client_build };
+            let patch_sets = select_patch_sets(&manifest, &patches, &patch_dir, &layers, as_of, include_experimental, &target)?;
+            let dbc_paths = list_dbc_files(&dbc_dir)?;
+            check_refs_command(&dbc_paths, &patch_sets, &dbc_dir, &schema_dir, &includes_dir, &units, &ids, all)?;
         }
-    }
-    None
-}
-
-/// Apply patches to the given DBC files and write modified versions into
-/// the output directory.  Returns the list of paths written.  Called by
-/// both the `apply` and `build` subcommands.
-fn apply_command(
-    dbc_files: &[PathBuf],
-    patch_files: &[PathBuf],
-    out_dir: &Path,
-    schema_dir: &Path,
-) -> Result<Vec<PathBuf>> {
-    // Ensure output directory exists
-    fs::create_dir_all(out_dir)
-        .with_context(|| format!("Failed to create output directory {:?}", out_dir))?;
-
-    // Load patch files and group them by DBC name
-    let patches_map = load_patches(patch_files)?;
-
-    // Keep track of written paths
-    let mut written = Vec::new();
-
-    for dbc_path in dbc_files {
-        let file_name = dbc_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .map(|s| s.to_string())
-            .ok_or_else(|| anyhow::anyhow!("Invalid DBC file path: {:?}", dbc_path))?;
-        println!("Processing {}", file_name);
-
-        // Read the DBC
-        let (header, mut records, mut string_block) = read_dbc(dbc_path)
-            .with_context(|| format!("Failed to read DBC file {:?}", dbc_path))?;
-
-        // Build string offset map for existing strings
-        let mut string_map = build_string_map(&string_block);
-        // Keep track of new strings appended (in order)
-        let mut new_strings: Vec<String> = Vec::new();
-
-        // Load a schema mapping for this DBC (if available)
-        let schema_map = load_schema_map(schema_dir, &file_name);
-
-        // Apply all patches matching this DBC name (case insensitive)
-        let mut any_patch_applied = false;
-        if let Some(patches_for_file) = patches_map.get(&file_name.to_lowercase()) {
-            for pf in patches_for_file {
-                // Determine the origin of this patch file for warnings
-                let pf_origin = pf
-                    .origin
-                    .as_ref()
-                    .map(|p| p.display().to_string())
-                    .unwrap_or_else(|| "<unknown>".to_string());
-                for change in &pf.changes {
-                    match change {
-                        PatchEntry::Update {
-                            key,
-                            key_column,
-                            values,
-                        } => {
-                            let key_col_index = resolve_key_column_index(key_column, &schema_map, &file_name, &pf_origin);
-
-                            // Find the record with matching key
-                            let mut found = false;
-                            for record in &mut records {
-                                if key_col_index >= record.len() {
-                                    continue;
-                                }
-                                if record[key_col_index] == *key {
-                                    found = true;
-                                    apply_values_to_record(
-                                        values,
-                                        record,
-                                        &schema_map,
-                                        &mut string_map,
-                                        &mut new_strings,
-                                        &string_block,
-                                        &file_name,
-                                        &pf_origin,
-                                        *key,
-                                    );
-                                    break;
-                                }
-                            }
-                            if !found {
-                                println!(
-                                    "Warning: no record found with key {} in {} (patch file: {})",
-                                    key,
-                                    file_name,
-                                    pf_origin
-                                );
-                            }
-                        }
-                        PatchEntry::Insert { key, key_column, values } => {
-                            let key_col_index = resolve_key_column_index(key_column, &schema_map, &file_name, &pf_origin);
-
-                            // Create new record filled with zeros
-                            let mut new_record = vec![0u32; header.field_count as usize];
-
-                            // If a key is provided and the field is not explicitly set in values, write it to the key column
-                            if let Some(k) = key {
-                                let provided_key = values.keys().any(|field_name| {
-                                    // Determine if this field matches the key column
-                                    if let Ok(idx) = field_name.parse::<usize>() {
-                                        idx == key_col_index
-                                    } else {
-                                        schema_map
-                                            .as_ref()
-                                            .and_then(|schema| schema.get(&field_name.to_lowercase()))
-                                            .map_or(false, |&idx| idx == key_col_index)
-                                    }
-                                });
-                                if key_col_index < new_record.len() && !provided_key {
-                                    // `key` is a reference when matching on &PatchEntry; dereference it
-                                    new_record[key_col_index] = *k;
-                                }
-                            }
-
-                            // Fill in specified fields from the values map
-                            let effective_key = key.unwrap_or(0); // Use a default key for apply_values_to_record
-                            apply_values_to_record(
-                                values,
-                                &mut new_record,
-                                &schema_map,
-                                &mut string_map,
-                                &mut new_strings,
-                                &string_block,
-                                &file_name,
-                                &pf_origin,
-                                effective_key,
-                            );
-
-                            // Check for duplicate keys: if the key value in the new record already exists in the
-                            // records list at the same key column, warn and skip this insert.
-                            if key_col_index < new_record.len() {
-                                let new_key_val = new_record[key_col_index];
-                                if records.iter().any(|r| {
-                                    if key_col_index < r.len() {
-                                        r[key_col_index] == new_key_val
-                                    } else {
-                                        false
-                                    }
-                                }) {
-                                    println!(
-                                        "Warning: record with key {} already exists in {} (patch file: {}) – skipping insert",
-                                        new_key_val,
-                                        file_name,
-                                        pf_origin
-                                    );
-                                    // Do not push the duplicate record
-                                } else {
-                                    records.push(new_record);
-                                }
-                            } else {
-                                // If the key column is out of bounds, just append the record (no duplicate check)
-                                records.push(new_record);
-                            }
-                        }
-                        PatchEntry::Copy {
-                            key,
-                            key_column,
-                            values,
-                        } => {
-                            let key_col_index = resolve_key_column_index(key_column, &schema_map, &file_name, &pf_origin);
-                            // Find the record to copy
-                            let mut found = false;
-                            for record in &records {
-                                if key_col_index >= record.len() {
-                                    continue;
-                                }
-                                if record[key_col_index] == *key {
-                                    found = true;
-                                    // Clone the existing record
-                                    let mut new_record = record.clone();
-                                    // Apply updates to the new record
-                                    apply_values_to_record(
-                                        values,
-                                        &mut new_record,
-                                        &schema_map,
-                                        &mut string_map,
-                                        &mut new_strings,
-                                        &string_block,
-                                        &file_name,
-                                        &pf_origin,
-                                        *key,
-                                    );
-                                    // After applying updates, ensure we are not duplicating the key.  Use the
-                                    // resolved key column to retrieve the new key value and check against
-                                    // existing records.  If a duplicate is found, skip adding the new record and
-                                    // warn.  Otherwise, push it to the list.
-                                    if key_col_index < new_record.len() {
-                                        let new_key_val = new_record[key_col_index];
-                                        if records.iter().any(|r| {
-                                            if key_col_index < r.len() {
-                                                r[key_col_index] == new_key_val
-                                            } else {
-                                                false
-                                            }
-                                        }) {
-                                            println!(
-                                                "Warning: record with key {} already exists in {} (patch file: {}) – skipping copy",
-                                                new_key_val,
-                                                file_name,
-                                                pf_origin
-                                            );
-                                        } else {
-                                            records.push(new_record);
-                                        }
-                                    } else {
-                                        // If the key column is out of bounds, append without duplicate check
-                                        records.push(new_record);
-                                    }
-                                    break;
-                                }
-                            }
-                            if !found {
-                                println!(
-                                    "Warning: no record found with key {} in {} (patch file: {}) to copy",
-                                    key,
-                                    file_name,
-                                    pf_origin
-                                );
-                            }
-                        }
-                    }
+        Commands::ExportCsv {
+            dbc_file,
+            schema_dir,
+            out,
+        } => {
+            let out = match out {
+                Some(out) => out,
+                None => PathBuf::from(dbc_file_name(&dbc_file)?).with_extension("csv"),
+            };
+            export_csv_command(&dbc_file, &schema_dir, &out)?;
+        }
+        Commands::ExportSql {
+            dbc_file,
+            schema_dir,
+            table,
+            dialect,
+            out,
+        } => {
+            let table = match table {
+                Some(table) => table,
+                None => default_sql_table(&dbc_file_name(&dbc_file)?),
+            };
+            let out = out.unwrap_or_else(|| PathBuf::from(format!("{}.sql", table)));
+            export_sql_command(&dbc_file, &schema_dir, &table, dialect, &out)?;
+        }
+        Commands::ImportCsv {
+            csv_file,
+            table,
+            dbc_dir,
+            schema_dir,
+            out_dir,
+        } => {
+            let table = match table {
+                Some(table) => table,
+                None => {
+                    let stem = csv_file
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .ok_or_else(|| anyhow::anyhow!("Invalid file name {:?}", csv_file))?;
+                    format!("{}.dbc", stem)
                 }
-            }
-            any_patch_applied = true;
+            };
+            fs::create_dir_all(&out_dir)
+                .with_context(|| format!("Failed to create output directory {:?}", out_dir))?;
+            import_csv_command(&csv_file, &table, &dbc_dir, &schema_dir, &out_dir.join(&table))?;
         }
-
-        // Build final string block by appending new strings
-        if any_patch_applied {
-            // Append all new strings to the original block
-            for s in &new_strings {
-                // Strings are stored as bytes followed by a null terminator
-                string_block.extend_from_slice(s.as_bytes());
-                string_block.push(0);
+        Commands::ExplainRow {
+            table,
+            key,
+            patches,
+            patch_dir,
+            layers,
+            include_experimental,
+            as_of,
+            profile,
+            client_build,
+            dbc_dir,
+            schema_dir,
+            includes_dir,
+        } => {
+            let target = ClientTarget { profile, build: client_build };
+            let patch_sets = select_patch_sets(&manifest, &patches, &patch_dir, &layers, as_of, include_experimental, &target)?;
+            explain_row_command(&table, key, &patch_sets, &dbc_dir, &schema_dir, &includes_dir, &units, &ids)?;
+        }
+        Commands::Dump {
+            table,
+            key,
+            dbc_dir,
+            schema_dir,
+            join,
+        } => {
+            dump_command(&table, key, &dbc_dir, &schema_dir, join)?;
+        }
+        Commands::Wizard {
+            table,
+            dbc_dir,
+            schema_dir,
+            out,
+        } => {
+            wizard_command(table.as_deref(), &dbc_dir, &schema_dir, out.as_deref())?;
+        }
+        Commands::ImportSchema {
+            source,
+            format,
+            table,
+            build,
+            schema_dir,
+            force,
+        } => {
+            import_schema_command(&source, format, table.as_deref(), &build, &schema_dir, force)?;
+        }
+        Commands::SchemaGen {
+            table,
+            dbc_dir,
+            schema_dir,
+            force,
+        } => {
+            schema_gen_command(&table, &dbc_dir, &schema_dir, force)?;
+        }
+        Commands::Lint {
+            patches,
+            patch_dir,
+            dbc_dir,
+            schema_dir,
+        } => {
+            let patch_paths = patch_paths_or_dir(&patches, &patch_dir)?;
+            let rules = manifest.ownership.load_rules(&manifest.base_dir)?;
+            lint_command(&load_patches_with_ids(&patch_paths, &ids)?, &dbc_dir, &schema_dir, &rules)?;
+        }
+        Commands::LintPatches {
+            patches,
+            patch_dir,
+            schema_dir,
+            dbc_dir,
+        } => {
+            let patch_paths = patch_paths_or_dir(&patches, &patch_dir)?;
+            // New symbols get IDs for this run only.  When that fails the
+            // lint reports why file by file: the file does not parse, or
+            // its symbols have no ID
+            let _ = assign_ids(&mut ids, &patch_paths, &dbc_dir, &schema_dir, &manifest.ids, &manifest.base_dir, false);
+            lint_patches_command(&patch_paths, &schema_dir, &units, &ids)?;
+        }
+        Commands::Migrate {
+            patches,
+            patch_dir,
+            schema_dir,
+            check,
+        } => {
+            let patch_paths = patch_paths_or_dir(&patches, &patch_dir)?;
+            migrate_command(&patch_paths, &schema_dir, &ids, check)?;
+        }
+        Commands::ValidatePatchAgainstLive {
+            live_dir,
+            patches,
+            patch_dir,
+            dbc_dir,
+            schema_dir,
+            includes_dir,
+        } => {
+            let patch_paths = patch_paths_or_dir(&patches, &patch_dir)?;
+            // New symbols get IDs for this run only, as for `lint-patches`
+            assign_ids(&mut ids, &patch_paths, &dbc_dir, &schema_dir, &manifest.ids, &manifest.base_dir, false)?;
+            validate_live_command(&live_dir, &patch_paths, &dbc_dir, &schema_dir, &includes_dir, &units, &ids)?;
+        }
+        Commands::VerifyReferencesIntoClient {
+            client_dir,
+            patches,
+            patch_dir,
+            includes_dir,
+        } => {
+            let patch_paths = patch_paths_or_dir(&patches, &patch_dir)?;
+            verify_client_refs_command(&load_patches_with_ids(&patch_paths, &ids)?, &client_dir, &includes_dir, &manifest)?;
+        }
+        Commands::Install {
+            mpq,
+            client_dir,
+            name,
+            clear_cache,
+        } => {
+            install_command(&mpq, &client_dir, name.as_deref(), clear_cache)?;
+        }
+        Commands::ExtractSchemaStrings {
+            patches,
+            patch_dir,
+            out,
+            schema_dir,
+        } => {
+            let patch_paths = patch_paths_or_dir(&patches, &patch_dir)?;
+            extract_strings_command(&load_patches_with_ids(&patch_paths, &ids)?, &schema_dir, &out)?;
+        }
+        Commands::DiffBuilds {
+            old,
+            new,
+            git,
+            patch_dir,
+            dbc_dir,
+            schema_dir,
+            includes_dir,
+        } => {
+            diff_builds_command(
+                &old,
+                &new,
+                git,
+                &patch_dir,
+                &dbc_dir,
+                &schema_dir,
+                &includes_dir,
+                &units,
+                &ids,
+            )?;
+        }
+        Commands::Extract { mpqs, filter, out_dir } => {
+            extract_command(&mpqs, &filter, &out_dir)?;
+        }
+        Commands::List { mpq, filter } => {
+            list_command(&mpq, filter.as_deref())?;
+        }
+        Commands::MergeMpq {
+            mpqs,
+            out,
+            on_conflict,
+            mpq_version,
+            compression,
+        } => {
+            merge_mpq_command(&mpqs, &out, on_conflict, mpq_version, compression)?;
+        }
+        Commands::Diff {
+            original,
+            modified,
+            schema,
+            key_column,
+            out,
+        } => {
+            dbc_diff_command(&original, &modified, schema.as_deref(), key_column.as_deref(), out.as_deref())?;
+        }
+        Commands::Prune {
+            patches,
+            patch_dir,
+            dbc_dir,
+            schema_dir,
+            includes_dir,
+            delete,
+            yes,
+        } => {
+            // Every table, as patched, since includes may replace stock
+            // assets that only unpatched tables name
+            let patch_paths = patch_paths_or_dir(&patches, &patch_dir)?;
+            let dbc_paths = list_dbc_files(&dbc_dir)?;
+            let (overlay, _) = patch_tables(
+                &dbc_paths,
+                &[patch_paths],
+                &dbc_dir,
+                &schema_dir,
+                &includes_dir,
+                false,
+                None,
+                &units,
+                &ids,
+            )?;
+            let mut tables = Vec::new();
+            for path in &dbc_paths {
+                match overlay.read_table(path) {
+                    Ok(table) => tables.push(table),
+                    Err(err) => warning!("skipping {}: {:#}", path.display(), err),
+                }
             }
+            prune_command(&tables, &includes_dir, &manifest, delete, yes)?;
         }
-
-        // Build output path
-        let out_path = out_dir.join(&file_name);
-        write_dbc(&out_path, &header, &records, &string_block)
-            .with_context(|| format!("Failed to write output DBC for {}", file_name))?;
-        println!("Wrote {}", out_path.display());
-        written.push(out_path);
-    }
-
-    Ok(written)
-}
-
-/// Build an MPQ archive after applying patches.  First calls
-/// `apply_command` to produce the modified DBCs and then uses the
-/// `wow_mpq` crate to create an archive.  If MPQ creation fails the
-/// modified DBCs remain in the output directory.
-fn build_command(
-    dbc_files: &[PathBuf],
-    patch_files: &[PathBuf],
-    out_dir: &Path,
-    mpq_path: &Path,
-    mpq_version: u8,
-    schema_dir: &Path,
-    includes_dir: &Path,
-) -> Result<()> {
-    // Apply patches first.  The modified DBCs will be written into out_dir.
-    let modified_paths = apply_command(dbc_files, patch_files, out_dir, schema_dir)?;
-
-    // Collect the file names and archive paths
-    // Start building the archive
-    let mut builder = wow_mpq::ArchiveBuilder::new();
-    // Set the version if provided
-    let version = match mpq_version {
-        1 => wow_mpq::FormatVersion::V1,
-        2 => wow_mpq::FormatVersion::V2,
-        3 => wow_mpq::FormatVersion::V3,
-        4 => wow_mpq::FormatVersion::V4,
-        _ => {
-            println!("Warning: unknown MPQ version {}, defaulting to 2", mpq_version);
-            wow_mpq::FormatVersion::V2
+        Commands::Workspace {
+            members,
+            combined,
+            allow_conflicts,
+            check,
+            dbc_dir,
+            schema_dir,
+            out_dir,
+            mpq_version,
+            compression,
+            include_experimental,
+            as_of,
+        } => {
+            workspace_command(
+                &manifest,
+                &members,
+                combined.as_deref(),
+                allow_conflicts,
+                check,
+                &dbc_dir,
+                &schema_dir,
+                &out_dir,
+                mpq_version,
+                compression,
+                include_experimental,
+                as_of.unwrap_or_else(Date::today),
+            )?;
+            check_strict()?;
         }
-    };
-    builder = builder.version(version);
-
-    // Add modified DBC files under DBFilesClient/
-    for path in modified_paths {
-        let file_name = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid file name for {:?}", path))?;
-        let archive_name = format!("DBFilesClient/{}", file_name);
-        builder = builder.add_file(&path, &archive_name);
-    }
-
-    // Include additional files from includes_dir, preserving relative paths
-    if includes_dir.exists() {
-        // Recursively gather all files
-        let mut stack: Vec<PathBuf> = vec![includes_dir.to_path_buf()];
-        while let Some(dir) = stack.pop() {
-            for entry in fs::read_dir(&dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    stack.push(path);
-                } else {
-                    // Determine archive name by stripping the includes_dir prefix
-                    let rel = path
-                        .strip_prefix(includes_dir)
-                        .unwrap_or(&path);
-                    let mut dest = String::new();
-                    for component in rel.components() {
-                        let part = component.as_os_str().to_string_lossy();
-                        if !dest.is_empty() {
-                            dest.push('/');
-                        }
-                        dest.push_str(&part);
-                    }
-                    builder = builder.add_file(&path, &dest);
+        Commands::Watch {
+            patch_dir,
+            schema_dir,
+            includes_dir,
+            also_watch,
+            command,
+        } => {
+            let mut args = Vec::new();
+            if let Some(manifest) = &cli.manifest {
+                args.extend(["--manifest".to_string(), manifest.display().to_string()]);
+            }
+            if let Some(endianness) = cli.endianness.and_then(|e| e.to_possible_value()) {
+                args.extend(["--endianness".to_string(), endianness.get_name().to_string()]);
+            }
+            let flags = [
+                (cli.strict, "--strict"),
+                (cli.trace, "--trace"),
+                (cli.quiet, "--quiet"),
+                (cli.verbose, "--verbose"),
+                (cli.log_json, "--log-json"),
+                (true, "--wait"),
+            ];
+            for (set, flag) in flags {
+                if set {
+                    args.push(flag.to_string());
                 }
             }
+            if command.is_empty() {
+                args.push("apply".to_string());
+            }
+            args.extend(command);
+            let mut dirs = vec![patch_dir, schema_dir, includes_dir];
+            dirs.extend(also_watch);
+            watch_command(&dirs, &args)?;
+        }
+        Commands::Init { dir, client_dir } => {
+            init_command(&dir, client_dir.as_deref())?;
         }
     }
-
-    // Build the archive
-    builder
-        .build(mpq_path)
-        .with_context(|| format!("Failed to create MPQ at {:?}", mpq_path))?;
-    println!("Created MPQ {}", mpq_path.display());
     Ok(())
-}
\ No newline at end of file
+}
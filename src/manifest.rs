@@ -0,0 +1,223 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::budget::Budgets;
+use crate::groups::{PatchGroup, Season};
+use crate::ids::IdSettings;
+use crate::ownership::{wildcard_match, Ownership};
+use crate::pack::Compression;
+use crate::patch::ValueType;
+use crate::workspace::Workspace;
+
+/// File name of the project manifest looked up in the working directory
+/// when `--manifest` is not given.
+pub const DEFAULT_MANIFEST: &str = "patchproject.yaml";
+
+/// Project manifest.  Holds settings that belong to the patch project
+/// rather than to a single invocation.  Every section is optional, so an
+/// absent manifest behaves like an empty one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    /// Values for command line options not given on the command line.
+    pub defaults: Defaults,
+    /// Shell commands to run around `apply` and `build`.
+    pub hooks: Hooks,
+    /// Limits on table and archive growth.
+    pub budgets: Budgets,
+    /// Fields our patches must not touch.
+    pub ownership: Ownership,
+    /// Named yearly windows patch groups can refer to.
+    pub seasons: BTreeMap<String, Season>,
+    /// Patch files built only during a season or date range.
+    pub groups: Vec<PatchGroup>,
+    /// Files under the includes directory to leave out of the archive,
+    /// relative to it; `*` matches any run of characters.
+    pub exclude_includes: Vec<String>,
+    /// Files under the includes directory `prune` must never report,
+    /// such as replacements for sounds that stock models play and no table
+    /// names.  Same syntax as `exclude_includes`.
+    pub keep_includes: Vec<String>,
+    /// Compression of archive files by extension (e.g. `mp3: none`),
+    /// overriding `--compression`.
+    pub compression: BTreeMap<String, Compression>,
+    /// Units for patch values beyond the built‑in ones, each a Rhai
+    /// expression of `value` giving the stored value.
+    pub units: BTreeMap<String, String>,
+    /// Warning colour for each spell school, written in patches as
+    /// `{school_color: fire}`.
+    pub school_colors: BTreeMap<String, ValueType>,
+    /// Where the IDs allocated to symbolic keys are kept, and the range
+    /// each table allocates from.
+    pub ids: IdSettings,
+    /// Patch projects built together by the `workspace` command.
+    pub workspace: Workspace,
+    /// Directory the manifest was loaded from; relative paths in it are
+    /// resolved against this.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+/// Whether an include path matches one of `patterns`, comparing with
+/// either slash.
+fn include_listed(patterns: &[String], path: &str) -> bool {
+    let path = path.replace('/', "\\");
+    patterns
+        .iter()
+        .any(|pattern| wildcard_match(&pattern.replace('/', "\\"), &path))
+}
+
+/// Commands run at fixed points of a run.  `build` applies patches too, so
+/// it runs `pre_build`, `pre_apply`, `post_apply` and `post_build` in that
+/// order.  Each command runs through the platform shell with the build
+/// context exported as `WOWDBC_*` environment variables; a failing
+/// command aborts the run.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    pub pre_apply: Vec<String>,
+    pub post_apply: Vec<String>,
+    pub pre_build: Vec<String>,
+    pub post_build: Vec<String>,
+}
+
+/// The manifest's `defaults` section: the directories, archive, locales
+/// and strictness every command uses unless its own flags say otherwise,
+/// so a project need not repeat `--dbc-dir`, `--mpq` and the rest on
+/// every run.  Paths are relative to the manifest.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Defaults {
+    pub dbc_dir: Option<PathBuf>,
+    pub patch_dir: Option<PathBuf>,
+    pub schema_dir: Option<PathBuf>,
+    pub includes_dir: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    /// Archive `build` writes, and `verify` and `install` read.
+    pub mpq: Option<PathBuf>,
+    pub mpq_version: Option<u8>,
+    pub compression: Option<Compression>,
+    pub locales: Vec<String>,
+    /// Client profile and build to select patch files for.
+    pub profile: Option<String>,
+    pub client_build: Option<u32>,
+    /// Behave as if `--strict` were given.
+    pub strict: bool,
+    /// Behave as if `build --normalize-paths` were given.
+    pub normalize_paths: bool,
+}
+
+/// Values exported to hook commands.
+#[derive(Debug, Default)]
+pub struct HookContext {
+    pub dbc_dir: PathBuf,
+    pub patch_dir: PathBuf,
+    pub schema_dir: PathBuf,
+    pub out_dir: PathBuf,
+    pub includes_dir: Option<PathBuf>,
+    pub mpq_path: Option<PathBuf>,
+}
+
+impl Manifest {
+    /// Whether `exclude_includes` leaves out the include at `path`
+    /// (relative to the includes directory, either slash).
+    pub fn excludes_include(&self, path: &str) -> bool {
+        include_listed(&self.exclude_includes, path)
+    }
+
+    /// Whether `keep_includes` protects the include at `path` from `prune`.
+    pub fn keeps_include(&self, path: &str) -> bool {
+        include_listed(&self.keep_includes, path)
+    }
+
+    /// How the archive file `name` is compressed: as its extension is
+    /// listed under `compression`, else with `default`.
+    pub fn compression_for(&self, name: &str, default: Compression) -> Compression {
+        let Some((_, extension)) = name.rsplit_once('.') else {
+            return default;
+        };
+        self.compression
+            .iter()
+            .find(|(listed, _)| listed.trim_start_matches('.').eq_ignore_ascii_case(extension))
+            .map_or(default, |(_, &compression)| compression)
+    }
+
+    /// Load the manifest from `path`, or from `patchproject.yaml` in the
+    /// working directory when no path is given.  A missing default
+    /// manifest yields an empty one; a missing explicit one is an error.
+    pub fn load(path: Option<&Path>) -> Result<Manifest> {
+        let (path, explicit) = match path {
+            Some(p) => (p.to_path_buf(), true),
+            None => (PathBuf::from(DEFAULT_MANIFEST), false),
+        };
+        if !path.exists() {
+            if explicit {
+                bail!("Manifest {:?} does not exist", path);
+            }
+            return Ok(Manifest::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read manifest {:?}", path))?;
+        let mut manifest: Manifest = if content.trim().is_empty() {
+            Manifest::default()
+        } else {
+            serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to parse manifest {:?}", path))?
+        };
+        manifest.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let defaults = &mut manifest.defaults;
+        for dir in [
+            &mut defaults.dbc_dir,
+            &mut defaults.patch_dir,
+            &mut defaults.schema_dir,
+            &mut defaults.includes_dir,
+            &mut defaults.out_dir,
+            &mut defaults.mpq,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *dir = manifest.base_dir.join(&*dir);
+        }
+        Ok(manifest)
+    }
+}
+
+/// Run the hook commands for `stage` (e.g. `pre_build`) in order.
+pub fn run_hooks(commands: &[String], stage: &str, ctx: &HookContext) -> Result<()> {
+    for cmd in commands {
+        say!("Running {} hook: {}", stage, cmd);
+        let mut command = if cfg!(windows) {
+            let mut c = Command::new("cmd");
+            c.arg("/C").arg(cmd);
+            c
+        } else {
+            let mut c = Command::new("sh");
+            c.arg("-c").arg(cmd);
+            c
+        };
+        command
+            .env("WOWDBC_STAGE", stage)
+            .env("WOWDBC_DBC_DIR", &ctx.dbc_dir)
+            .env("WOWDBC_PATCH_DIR", &ctx.patch_dir)
+            .env("WOWDBC_SCHEMA_DIR", &ctx.schema_dir)
+            .env("WOWDBC_OUT_DIR", &ctx.out_dir);
+        if let Some(dir) = &ctx.includes_dir {
+            command.env("WOWDBC_INCLUDES_DIR", dir);
+        }
+        if let Some(mpq) = &ctx.mpq_path {
+            command.env("WOWDBC_MPQ", mpq);
+        }
+        let status = command
+            .status()
+            .with_context(|| format!("Failed to run {} hook {:?}", stage, cmd))?;
+        if !status.success() {
+            bail!("{} hook {:?} failed with {}", stage, cmd, status);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,381 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::apply::{is_document_marker, parse_patch_str};
+use crate::ids::IdMap;
+use crate::patch::{PatchEntry, PatchFile};
+use crate::schema::{load_schema, Schema};
+
+/// A deprecated form used in a patch file, with what replaces it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deprecation {
+    /// The deprecated form, e.g. `field SpellVisual_1 of Spell.dbc`
+    pub found: String,
+    /// What to write instead
+    pub replacement: String,
+}
+
+/// The field names `changes` use: `values` keys, key columns and
+/// `update_where` and `delete_where` conditions, including those of nested
+/// `zone` entries.
+fn field_names(changes: &[PatchEntry], out: &mut Vec<String>) {
+    for change in changes {
+        match change {
+            PatchEntry::Update { key_column, .. }
+            | PatchEntry::Insert { key_column, .. }
+            | PatchEntry::Copy { key_column, .. }
+            | PatchEntry::Delete { key_column, .. }
+            | PatchEntry::Disable { key_column, .. } => out.extend(key_column.iter().cloned()),
+            PatchEntry::UpdateWhere { conditions, .. } | PatchEntry::DeleteWhere { conditions, .. } => {
+                out.extend(conditions.keys().cloned())
+            }
+            PatchEntry::Zone { changes, .. } => field_names(changes, out),
+            _ => {}
+        }
+        if let Some(values) = change.values() {
+            out.extend(values.keys().cloned());
+        }
+    }
+}
+
+/// Replace former field names in `changes` with the current ones.
+fn rename_fields(changes: &mut [PatchEntry], schema: &Schema) {
+    let rename = |name: &mut String| {
+        if let Some(current) = schema.renamed(name) {
+            *name = current.to_string();
+        }
+    };
+    for change in changes.iter_mut() {
+        match change {
+            PatchEntry::Update { key_column, .. }
+            | PatchEntry::Insert { key_column, .. }
+            | PatchEntry::Copy { key_column, .. }
+            | PatchEntry::Delete { key_column, .. }
+            | PatchEntry::Disable { key_column, .. } => key_column.iter_mut().for_each(rename),
+            PatchEntry::UpdateWhere { conditions, .. } | PatchEntry::DeleteWhere { conditions, .. } => {
+                *conditions = std::mem::take(conditions)
+                    .into_iter()
+                    .map(|(mut name, condition)| {
+                        rename(&mut name);
+                        (name, condition)
+                    })
+                    .collect();
+            }
+            PatchEntry::Zone { changes, .. } => rename_fields(changes, schema),
+            _ => {}
+        }
+        if let Some(values) = change.values_mut() {
+            *values = std::mem::take(values)
+                .into_iter()
+                .map(|(mut name, value)| {
+                    rename(&mut name);
+                    (name, value)
+                })
+                .collect();
+        }
+    }
+}
+
+/// The deprecated forms in the patch files loaded from one file: the
+/// object form, and field names the table's schema lists under
+/// `renamed`.
+pub fn deprecations(pfs: &[&PatchFile], schema_dir: &Path) -> Vec<Deprecation> {
+    let mut found = Vec::new();
+    if pfs.iter().any(|pf| pf.legacy_form) {
+        found.push(Deprecation {
+            found: "the `dbc:`/`changes:` object form".to_string(),
+            replacement: "a `Table.dbc:` mapping".to_string(),
+        });
+    }
+    for pf in pfs {
+        let Some(schema) = load_schema(schema_dir, &pf.dbc) else {
+            continue;
+        };
+        let mut names = Vec::new();
+        field_names(&pf.changes, &mut names);
+        names.sort();
+        for name in names {
+            if let Some(current) = schema.renamed(&name) {
+                let deprecation = Deprecation {
+                    found: format!("field {} of {}", name, pf.dbc),
+                    replacement: current.to_string(),
+                };
+                if !found.contains(&deprecation) {
+                    found.push(deprecation);
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Warn about every deprecated form in the loaded patches, once per patch
+/// file, pointing at its replacement.
+pub fn warn_deprecations(patches_map: &HashMap<String, Vec<PatchFile>>, schema_dir: &Path) {
+    let mut by_file: BTreeMap<String, Vec<&PatchFile>> = BTreeMap::new();
+    for pf in patches_map.values().flatten() {
+        let origin = pf
+            .origin
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "<unknown>".to_string());
+        by_file.entry(origin).or_default().push(pf);
+    }
+    for (origin, pfs) in by_file {
+        for deprecation in deprecations(&pfs, schema_dir) {
+            warning!(
+                "{} is deprecated, write {} instead (patch file: {}); `migrate` rewrites it",
+                deprecation.found,
+                deprecation.replacement,
+                origin
+            );
+        }
+    }
+}
+
+/// The table an object-form patch names with `dbc:`.
+fn object_table(lines: &[String]) -> Option<String> {
+    lines.iter().find_map(|line| {
+        let value = line.strip_prefix("dbc:")?;
+        let value = value.split(" #").next().unwrap_or(value);
+        Some(value.trim().trim_matches(['"', '\'']).to_string())
+    })
+}
+
+/// Rewrite one object-form patch as a `Table.dbc:` section: drop the
+/// `dbc:` line and put the table name in place of `changes:`.
+fn object_section(lines: &[String], out: &mut String) {
+    let Some(table) = object_table(lines) else {
+        lines.iter().for_each(|line| out.push_str(&format!("{}\n", line)));
+        return;
+    };
+    for line in lines {
+        if line.starts_with("dbc:") {
+            continue;
+        }
+        match line.strip_prefix("changes:") {
+            Some(rest) => out.push_str(&format!("{}:{}\n", table, rest)),
+            None => out.push_str(&format!("{}\n", line)),
+        }
+    }
+}
+
+/// Rewrite the object form, or a sequence of such objects, as
+/// `Table.dbc:` sections, document by document of a `---` separated
+/// stream.  Comments and the layout of the changes are kept; items of a
+/// sequence are moved two columns left.
+fn object_form_to_mapping(content: &str) -> String {
+    let mut out = String::new();
+    let mut document = String::new();
+    for line in content.lines() {
+        if is_document_marker(line) {
+            out.push_str(&document_to_mapping(&document));
+            out.push_str(&format!("{}\n", line));
+            document.clear();
+        } else {
+            document.push_str(&format!("{}\n", line));
+        }
+    }
+    out.push_str(&document_to_mapping(&document));
+    out
+}
+
+/// Rewrite one YAML document of the object form (see
+/// `object_form_to_mapping`).
+fn document_to_mapping(content: &str) -> String {
+    let mut out = String::new();
+    if !content.lines().any(|line| line.starts_with("- ") || line == "-") {
+        let lines: Vec<String> = content.lines().map(str::to_string).collect();
+        object_section(&lines, &mut out);
+        return out;
+    }
+    let mut item: Option<Vec<String>> = None;
+    for line in content.lines() {
+        if let Some(rest) = line.strip_prefix("- ").or((line == "-").then_some("")) {
+            if let Some(lines) = item.take() {
+                object_section(&lines, &mut out);
+            }
+            item = Some(vec![rest.to_string()]);
+            continue;
+        }
+        match item.as_mut() {
+            Some(lines) => lines.push(line.strip_prefix("  ").unwrap_or(line).to_string()),
+            None => out.push_str(&format!("{}\n", line)),
+        }
+    }
+    if let Some(lines) = item {
+        object_section(&lines, &mut out);
+    }
+    out
+}
+
+/// Replace `old` with `new` in `line` where it is written as a mapping key
+/// (`Old: 1`, `{ Old: 1 }`) or as the value of `key_column`, ignoring
+/// ASCII case as field names do.
+fn rename_in_line(line: &str, old: &str, new: &str) -> String {
+    let lower = line.to_ascii_lowercase();
+    let old_lower = old.to_ascii_lowercase();
+    let (mut out, mut last, mut from) = (String::new(), 0, 0);
+    while let Some(found) = lower[from..].find(&old_lower) {
+        let start = from + found;
+        let end = start + old.len();
+        from = end;
+        let before = line[..start].trim_end();
+        let whole = !line[end..].starts_with(|c: char| c.is_alphanumeric() || c == '_');
+        let as_key = (before.is_empty() || before.ends_with(['{', ',', '-'])) && line[end..].trim_start().starts_with(':');
+        let as_key_column = before.ends_with("key_column:");
+        if whole && (as_key || as_key_column) {
+            out.push_str(&line[last..start]);
+            out.push_str(new);
+            last = end;
+        }
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+/// Rewrite former field names in each `Table.dbc:` section with the names
+/// that table's schema gives them now.
+fn rename_in_text(content: &str, schema_dir: &Path) -> String {
+    let mut out = String::new();
+    let mut renames: Vec<(String, String)> = Vec::new();
+    for line in content.lines() {
+        // A key at the start of the line opens a table's section
+        if !line.starts_with([' ', '\t', '#', '-']) {
+            if let Some((table, _)) = line.split_once(':') {
+                renames = load_schema(schema_dir, table.trim())
+                    .map(|schema| {
+                        schema
+                            .former_names()
+                            .map(|(old, new)| (old.to_string(), new.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+            }
+        }
+        let mut line = line.to_string();
+        for (old, new) in &renames {
+            line = rename_in_line(&line, old, new);
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Whether two parses of a patch file describe the same changes.
+fn same_patches(a: &[PatchFile], b: &[PatchFile]) -> bool {
+    a.len() == b.len()
+        && a
+            .iter()
+            .zip(b)
+            .all(|(a, b)| {
+                a.dbc == b.dbc && a.experimental == b.experimental && a.truncate == b.truncate && a.changes == b.changes
+            })
+}
+
+/// Rewrite patch files that use deprecated forms (see `deprecations`) in
+/// the current ones, keeping comments and layout.  Each rewrite is parsed
+/// again and must give the same changes as the original with renamed
+/// fields replaced, or the file is left alone and reported.  With `check`
+/// nothing is written and the command fails when any file needs
+/// migrating.
+pub fn migrate_command(patch_paths: &[PathBuf], schema_dir: &Path, ids: &IdMap, check: bool) -> Result<()> {
+    let mut paths = patch_paths.to_vec();
+    paths.sort();
+    let (mut migrated, mut failed) = (0usize, 0usize);
+    for path in &paths {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read patch file {:?}", path))?;
+        let pfs = parse_patch_str(&content, path, ids)?;
+        let found = deprecations(&pfs.iter().collect::<Vec<_>>(), schema_dir);
+        if found.is_empty() {
+            continue;
+        }
+        println!("{}", path.display());
+        for deprecation in &found {
+            println!("  {} -> {}", deprecation.found, deprecation.replacement);
+        }
+
+        let mut text = content.clone();
+        if pfs.iter().any(|pf| pf.legacy_form) {
+            text = object_form_to_mapping(&text);
+        }
+        let text = rename_in_text(&text, schema_dir);
+
+        let mut expected = pfs;
+        for pf in &mut expected {
+            if let Some(schema) = load_schema(schema_dir, &pf.dbc) {
+                rename_fields(&mut pf.changes, &schema);
+            }
+        }
+        let rewritten = parse_patch_str(&text, path, ids).ok().filter(|new| {
+            same_patches(new, &expected) && deprecations(&new.iter().collect::<Vec<_>>(), schema_dir).is_empty()
+        });
+        if rewritten.is_none() {
+            println!("  cannot be rewritten automatically; update it by hand");
+            failed += 1;
+            continue;
+        }
+        if !check {
+            fs::write(path, &text).with_context(|| format!("Failed to write {:?}", path))?;
+            println!("  rewritten");
+        }
+        migrated += 1;
+    }
+
+    if check {
+        if migrated + failed > 0 {
+            bail!(
+                "{} patch file(s) use deprecated forms; run `migrate` to rewrite them",
+                migrated + failed
+            );
+        }
+        println!("No deprecated forms in {} patch file(s)", paths.len());
+        return Ok(());
+    }
+    println!("Migrated {} patch file(s)", migrated);
+    if failed > 0 {
+        bail!("{} patch file(s) could not be migrated automatically", failed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renamed_fields_are_rewritten_only_as_keys() {
+        let rename = |line| rename_in_line(line, "OldName", "NewName");
+        assert_eq!(rename("    values: { oldname: 5, Other: 1 }"), "    values: { NewName: 5, Other: 1 }");
+        assert_eq!(rename("    key_column: OldName"), "    key_column: NewName");
+        // Values, and names that merely start the same, are left alone
+        assert_eq!(rename("    values: { Name: OldName, OldNameX: 1 }"), "    values: { Name: OldName, OldNameX: 1 }");
+    }
+
+    #[test]
+    fn migrate_rewrites_the_object_form_and_renamed_fields() {
+        let dir = std::env::temp_dir().join(format!("wow_dbc_patcher-migrate-{}", std::process::id()));
+        let schema_dir = dir.join("schema");
+        fs::create_dir_all(&schema_dir).unwrap();
+        fs::write(schema_dir.join("Test.dbc.yaml"), "fields: [ID, NewName, Other]\nrenamed: { OldName: NewName }\n").unwrap();
+        let patch = dir.join("patch.yaml");
+        let original = "# effects\ndbc: Test.dbc\nchanges:\n  - type: update\n    key: 1\n    values: { OldName: 5 }  # keep\n";
+        fs::write(&patch, original).unwrap();
+        let paths = [patch.clone()];
+
+        // --check reports the file and leaves it alone
+        assert!(migrate_command(&paths, &schema_dir, &IdMap::new(), true).is_err());
+        assert_eq!(fs::read_to_string(&patch).unwrap(), original);
+
+        migrate_command(&paths, &schema_dir, &IdMap::new(), false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&patch).unwrap(),
+            "# effects\nTest.dbc:\n  - type: update\n    key: 1\n    values: { NewName: 5 }  # keep\n"
+        );
+        migrate_command(&paths, &schema_dir, &IdMap::new(), true).unwrap();
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+use wow_mpq::{compress, encrypt_block, hash_string, hash_type, BlockEntry, FormatVersion};
+
+/// Sector size of the archives written here, as a power of two times 512
+/// bytes: 16 KiB sectors, as `wow_mpq::ArchiveBuilder` writes them.
+const BLOCK_SIZE: u16 = 5;
+const SECTOR_SIZE: usize = 512 << BLOCK_SIZE;
+
+/// Signature at the start of an archive, `MPQ\x1A`.
+const MPQ_SIGNATURE: u32 = 0x1A51_504D;
+
+/// A file compressed for an archive, ready to be written: its name, its
+/// size, and the bytes and block flags it is stored with.
+pub struct CompressedFile {
+    pub name: String,
+    size: u32,
+    flags: u32,
+    stored: Vec<u8>,
+}
+
+/// Compress `data`, the file `name`, with the MPQ compression mask
+/// `method`, or store it as it is for 0.  A file that fits one sector is
+/// stored as a single unit; a larger one sector by sector behind a table
+/// of sector offsets.  Parts that compression does not shrink are stored
+/// as they are.  Files are compressed independently, so any number can
+/// be compressed at once before `write_archive` writes them.
+pub fn compress_file(name: &str, data: &[u8], method: u8) -> Result<CompressedFile> {
+    let size = u32::try_from(data.len()).with_context(|| format!("{} is too large for an MPQ archive", name))?;
+    let mut flags = BlockEntry::FLAG_EXISTS;
+    let mut pack = |part: &[u8]| -> Result<Vec<u8>> {
+        if method == 0 || part.is_empty() {
+            return Ok(part.to_vec());
+        }
+        let packed = compress(part, method).with_context(|| format!("Failed to compress {}", name))?;
+        // `compress` hands back the part itself when it does not shrink
+        if packed.len() < part.len() {
+            flags |= BlockEntry::FLAG_COMPRESS;
+        }
+        Ok(packed)
+    };
+    let stored = if data.len() <= SECTOR_SIZE {
+        let stored = pack(data)?;
+        flags |= BlockEntry::FLAG_SINGLE_UNIT;
+        stored
+    } else {
+        let sectors = data.chunks(SECTOR_SIZE).map(&mut pack).collect::<Result<Vec<_>>>()?;
+        let mut offset = (sectors.len() as u32 + 1) * 4;
+        let mut stored = offset.to_le_bytes().to_vec();
+        for sector in &sectors {
+            offset += sector.len() as u32;
+            stored.extend_from_slice(&offset.to_le_bytes());
+        }
+        stored.extend(sectors.concat());
+        stored
+    };
+    Ok(CompressedFile {
+        name: name.to_string(),
+        size,
+        flags,
+        stored,
+    })
+}
+
+/// Encrypt `words` with the key of the table named `name`, as the hash
+/// and block tables are stored.
+fn encrypt_table(mut words: Vec<u32>, name: &str) -> Vec<u8> {
+    encrypt_block(&mut words, hash_string(name, hash_type::FILE_KEY));
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// Write `files`, in order, into a new archive at `path` of `version` 1
+/// or 2, with a hash table twice the number of files (at least 16) and a
+/// block table.  Nothing is compressed here; see `compress_file`.  Names
+/// must be unique, compared as the client compares them.
+pub fn write_archive(path: &Path, version: FormatVersion, files: &[CompressedFile]) -> Result<()> {
+    let header_size: u32 = match version {
+        FormatVersion::V1 => 0x20,
+        FormatVersion::V2 => 0x2C,
+        _ => bail!("Only MPQ versions 1 and 2 are written from pre-compressed files"),
+    };
+
+    let table_size = (files.len() * 2).max(16).next_power_of_two();
+    // Empty hash entries are all ones, as Blizzard's tools leave them
+    let mut hash_table = vec![u32::MAX; table_size * 4];
+    let mut block_table = Vec::with_capacity(files.len() * 4);
+    let mut data = Vec::new();
+    for (block, file) in files.iter().enumerate() {
+        let (name_a, name_b) = (hash_string(&file.name, hash_type::NAME_A), hash_string(&file.name, hash_type::NAME_B));
+        let mut slot = hash_string(&file.name, hash_type::TABLE_OFFSET) as usize & (table_size - 1);
+        while hash_table[slot * 4 + 3] != u32::MAX {
+            if hash_table[slot * 4] == name_a && hash_table[slot * 4 + 1] == name_b {
+                bail!("{} is in the archive twice", file.name);
+            }
+            slot = (slot + 1) & (table_size - 1);
+        }
+        // Neutral locale and platform
+        hash_table[slot * 4..slot * 4 + 4].copy_from_slice(&[name_a, name_b, 0, block as u32]);
+
+        let position = header_size as usize + data.len();
+        block_table.extend([position as u32, file.stored.len() as u32, file.size, file.flags]);
+        data.extend_from_slice(&file.stored);
+    }
+
+    let hash_table_position = header_size as usize + data.len();
+    let block_table_position = hash_table_position + hash_table.len() * 4;
+    let archive_size = block_table_position + block_table.len() * 4;
+    let archive_size = u32::try_from(archive_size)
+        .with_context(|| format!("{:?} would be {} bytes, more than MPQ version 1 and 2 can hold", path, archive_size))?;
+
+    let mut out = Vec::with_capacity(archive_size as usize);
+    for word in [MPQ_SIGNATURE, header_size, archive_size] {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    out.extend_from_slice(&(version as u16).to_le_bytes());
+    out.extend_from_slice(&BLOCK_SIZE.to_le_bytes());
+    for word in [hash_table_position as u32, block_table_position as u32, table_size as u32, files.len() as u32] {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    if version == FormatVersion::V2 {
+        // No hi-block table, and no table past 4 GiB
+        out.extend_from_slice(&[0; 12]);
+    }
+    out.extend(data);
+    out.extend(encrypt_table(hash_table, "(hash table)"));
+    out.extend(encrypt_table(block_table, "(block table)"));
+    fs::write(path, out).with_context(|| format!("Failed to write MPQ {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archives_read_back_through_wow_mpq() {
+        let path = std::env::temp_dir().join(format!("wow_dbc_patcher-{}-archive.mpq", std::process::id()));
+        // A large file of several sectors, one compression does not
+        // shrink, and an empty one
+        let large: Vec<u8> = (0..100_000u32).flat_map(|i| (i % 251).to_le_bytes()).collect();
+        let noise: Vec<u8> = (0..3000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        let files = [
+            ("DBFilesClient\\Spell.dbc", large.clone()),
+            ("Textures\\noise.blp", noise.clone()),
+            ("empty.txt", Vec::new()),
+        ];
+        for version in [FormatVersion::V1, FormatVersion::V2] {
+            let compressed: Vec<CompressedFile> = files
+                .iter()
+                .map(|(name, data)| compress_file(name, data, wow_mpq::compression::flags::ZLIB).unwrap())
+                .collect();
+            assert!(compressed[0].stored.len() < large.len() / 4);
+            write_archive(&path, version, &compressed).unwrap();
+
+            let mut archive = wow_mpq::Archive::open(&path).unwrap();
+            for (name, data) in &files {
+                assert_eq!(&archive.read_file(name).unwrap(), data, "{} in version {:?}", name, version);
+            }
+        }
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn names_are_unique() {
+        let file = || compress_file("a.txt", b"a", 0).unwrap();
+        let path = std::env::temp_dir().join(format!("wow_dbc_patcher-{}-twice.mpq", std::process::id()));
+        assert!(write_archive(&path, FormatVersion::V1, &[file(), file()]).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Fields of a table that belong to someone else (the server core, another
+/// mod) and must never be changed by our patches, e.g. Spell.dbc reagents.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OwnershipRule {
+    /// Who owns the fields, shown in lint findings.
+    pub owner: String,
+    /// Table file name, e.g. `Spell.dbc`.
+    pub table: String,
+    /// Field names; `*` matches any run of characters (`Reagent_*`).
+    pub fields: Vec<String>,
+    /// Why the fields are protected, shown in lint findings.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl OwnershipRule {
+    /// Whether this rule protects `field` of `table`.
+    pub fn covers(&self, table: &str, field: &str) -> bool {
+        self.table.eq_ignore_ascii_case(table)
+            && self.fields.iter().any(|pattern| wildcard_match(pattern, field))
+    }
+}
+
+/// Case‑insensitive match of `name` against `pattern` where `*` matches any
+/// run of characters.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || !name[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// A shareable rules file: either a bare list of rules or a mapping with a
+/// `rules` key.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RulesFile {
+    List(Vec<OwnershipRule>),
+    Wrapped { rules: Vec<OwnershipRule> },
+}
+
+/// The manifest's `ownership` section.  Rules can be written inline or
+/// kept in separate YAML files so other projects can share them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Ownership {
+    /// Rules files, relative to the manifest.
+    pub include: Vec<PathBuf>,
+    /// Inline rules.
+    pub rules: Vec<OwnershipRule>,
+}
+
+impl Ownership {
+    /// All rules: the inline ones followed by those of each included file.
+    pub fn load_rules(&self, base_dir: &Path) -> Result<Vec<OwnershipRule>> {
+        let mut rules = self.rules.clone();
+        for include in &self.include {
+            let path = base_dir.join(include);
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read ownership rules {:?}", path))?;
+            let file: RulesFile = serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to parse ownership rules {:?}", path))?;
+            rules.extend(match file {
+                RulesFile::List(r) | RulesFile::Wrapped { rules: r } => r,
+            });
+        }
+        Ok(rules)
+    }
+}
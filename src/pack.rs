@@ -0,0 +1,644 @@
+use anyhow::{bail, Context, Result};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::apply::apply_command;
+use crate::changelog::write_changelog;
+use crate::dedup::{find_duplicates, report_duplicates, ArchiveEntry};
+use crate::diagnostics::check_strict;
+use crate::includes::{
+    normalize_archive_paths, select_includes, warn_case_collisions, IncludeGlobs, INCLUDES_MANIFEST,
+};
+use crate::ids::IdMap;
+use crate::l10n::Translations;
+use crate::manifest::{run_hooks, HookContext, Manifest};
+use crate::mpq::{compress_file, write_archive};
+use crate::parallel::map_parallel;
+use crate::units::Transformers;
+
+/// Read the payload of every archive entry on a pool of worker threads.
+/// Payloads are returned in entry order, so the archive layout does not
+/// depend on which worker finished first.
+pub fn load_payloads(entries: &[ArchiveEntry]) -> Result<Vec<Vec<u8>>> {
+    map_parallel(entries.iter().collect(), |entry: &ArchiveEntry| {
+        fs::read(&entry.path).with_context(|| format!("Failed to read {:?}", entry.path))
+    })
+    .into_iter()
+    .collect()
+}
+
+/// Archive path in the form used to compare entries: backslashes, lower
+/// case.
+fn archive_key(name: &str) -> String {
+    name.replace('/', "\\").to_lowercase()
+}
+
+/// Read the files of an existing archive that `entries` do not replace,
+/// so a build can be written over e.g. a server's `patch-O.mpq` without
+/// losing its other contents.  Names are compared case‑insensitively with
+/// either slash.  The archive's own `(listfile)`, `(attributes)` and
+/// `(signature)` are left out; the builder writes fresh ones.
+pub fn base_archive_files(path: &Path, entries: &[ArchiveEntry]) -> Result<Vec<(String, Vec<u8>)>> {
+    let replaced: HashSet<String> = entries.iter().map(|e| archive_key(&e.archive_name)).collect();
+    let mut archive = wow_mpq::Archive::open(path).with_context(|| format!("Failed to open base archive {:?}", path))?;
+    let listed = archive
+        .list()
+        .with_context(|| format!("Failed to list the files of {:?} (does it have a listfile?)", path))?;
+    let mut kept = Vec::new();
+    for entry in listed {
+        if entry.name.starts_with('(') || replaced.contains(&archive_key(&entry.name)) {
+            continue;
+        }
+        let data = archive
+            .read_file(&entry.name)
+            .with_context(|| format!("Failed to read {} from {:?}", entry.name, path))?;
+        kept.push((entry.name, data));
+    }
+    Ok(kept)
+}
+
+/// Seconds from 1601-01-01, where Windows FILETIMEs count from, to the
+/// Unix epoch.
+const FILETIME_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+/// `(attributes)` version understood by every client and MPQ tool.
+const ATTRIBUTES_VERSION: u32 = 100;
+
+/// `(attributes)` flags: CRC32, FILETIME and MD5 arrays present.
+const ATTRIBUTES_CRC32_FILETIME_MD5: u32 = 0x1 | 0x2 | 0x4;
+
+/// `time` as a FILETIME: 100 ns ticks since 1601-01-01.
+fn filetime(time: SystemTime) -> u64 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since.as_secs() + FILETIME_EPOCH_OFFSET) * 10_000_000 + u64::from(since.subsec_nanos() / 100)
+}
+
+/// How files are compressed in the archive.  zlib is what the client's
+/// own archives mostly use; bzip2 packs tighter but is slower to unpack,
+/// and `none` stores files as they are, which suits audio that is
+/// compressed already.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    Zlib,
+    Bzip2,
+    None,
+}
+
+impl Compression {
+    /// The MPQ compression mask of this method.
+    fn flags(self) -> u8 {
+        match self {
+            Compression::Zlib => wow_mpq::compression::flags::ZLIB,
+            Compression::Bzip2 => wow_mpq::compression::flags::BZIP2,
+            Compression::None => 0,
+        }
+    }
+}
+
+/// The MPQ format of version number `mpq_version`, or version 2 with a
+/// warning for an unknown number.
+fn format_version(mpq_version: u8) -> wow_mpq::FormatVersion {
+    match mpq_version {
+        1 => wow_mpq::FormatVersion::V1,
+        2 => wow_mpq::FormatVersion::V2,
+        3 => wow_mpq::FormatVersion::V3,
+        4 => wow_mpq::FormatVersion::V4,
+        _ => {
+            warning!("unknown MPQ version {}, defaulting to 2", mpq_version);
+            wow_mpq::FormatVersion::V2
+        }
+    }
+}
+
+/// A file going into the archive, with the time `(attributes)` records
+/// for it.
+struct ArchivedFile {
+    name: String,
+    data: Vec<u8>,
+    modified: SystemTime,
+}
+
+/// Write `files`, in order, into a new archive at `path` of `version`,
+/// each compressed with the method `compression_for` gives its name.
+/// For versions 1 and 2 the files are compressed on a pool of worker
+/// threads and written by [`write_archive`]; `wow_mpq::ArchiveBuilder`,
+/// which alone writes the HET and BET tables of versions 3 and 4,
+/// compresses them one at a time while writing.
+fn write_files(
+    path: &Path,
+    version: wow_mpq::FormatVersion,
+    files: Vec<ArchivedFile>,
+    compression_for: impl Fn(&str) -> Compression + Sync,
+) -> Result<()> {
+    if version <= wow_mpq::FormatVersion::V2 {
+        let compressed = map_parallel(files, |file: ArchivedFile| {
+            compress_file(&file.name, &file.data, compression_for(&file.name).flags())
+        });
+        let compressed = compressed.into_iter().collect::<Result<Vec<_>>>()?;
+        return write_archive(path, version, &compressed).with_context(|| format!("Failed to create MPQ at {:?}", path));
+    }
+    // The listfile, when wanted, is one of the files
+    let mut builder = wow_mpq::ArchiveBuilder::new()
+        .version(version)
+        .listfile_option(wow_mpq::ListfileOption::None);
+    for file in files {
+        let flags = compression_for(&file.name).flags();
+        builder = builder.add_file_data_with_options(file.data, &file.name, flags, false, 0);
+    }
+    builder
+        .build(path)
+        .with_context(|| format!("Failed to create MPQ at {:?}", path))
+}
+
+/// The `(listfile)` naming `files`: one path per line, with backslashes
+/// as the client writes them.
+fn listfile_data(files: &[ArchivedFile]) -> Vec<u8> {
+    let mut out = String::new();
+    for file in files {
+        out.push_str(&file.name.replace('/', "\\"));
+        out.push_str("\r\n");
+    }
+    out.into_bytes()
+}
+
+/// The `(attributes)` of an archive holding `files`, in the order they are
+/// added (which is the order of the block table), followed by
+/// `(attributes)` itself: version 100 and the CRC32, FILETIME and MD5
+/// arrays, one item per file.  The last item is zero, as a file cannot
+/// describe itself.
+fn attributes(files: &[ArchivedFile], now: SystemTime) -> Vec<u8> {
+    let count = files.len() + 1;
+    let mut out = Vec::with_capacity(8 + count * (4 + 8 + 16));
+    out.extend_from_slice(&ATTRIBUTES_VERSION.to_le_bytes());
+    out.extend_from_slice(&ATTRIBUTES_CRC32_FILETIME_MD5.to_le_bytes());
+    for file in files {
+        out.extend_from_slice(&crc32fast::hash(&file.data).to_le_bytes());
+    }
+    out.extend_from_slice(&0u32.to_le_bytes());
+    for file in files {
+        out.extend_from_slice(&filetime(file.modified).to_le_bytes());
+    }
+    out.extend_from_slice(&filetime(now).to_le_bytes());
+    for file in files {
+        out.extend_from_slice(&Md5::digest(&file.data));
+    }
+    out.extend_from_slice(&[0; 16]);
+    out
+}
+
+/// File `build --release-manifest` writes next to the archive.
+pub const RELEASE_MANIFEST: &str = "manifest.json";
+
+/// A file listed in the release manifest, with its SHA‑256 in lower‑case
+/// hex.
+#[derive(Serialize)]
+struct ManifestFile {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+/// The contents of the release manifest.
+#[derive(Serialize)]
+struct ReleaseManifest {
+    archive: ManifestFile,
+    tool: String,
+    tool_version: &'static str,
+    /// `git describe --always --dirty` of the project, when it is a git
+    /// checkout
+    patches_revision: Option<String>,
+    patches: Vec<ManifestFile>,
+    files: Vec<ManifestFile>,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The git revision of the checkout `dir` lies in, if any.
+fn git_revision(dir: &Path) -> Option<String> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let output = Command::new("git")
+        .args(["describe", "--always", "--dirty"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    let revision = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !revision.is_empty()).then_some(revision)
+}
+
+/// Write [`RELEASE_MANIFEST`] next to the archive at `mpq_path`: the size
+/// and SHA‑256 of the archive, of every file in it (`files`, archive path
+/// with its size and hash) and of the patch files it was built from, with
+/// the version of this tool and the git revision of the project in
+/// `base_dir`, so a release can be checked and reproduced.
+fn write_release_manifest(mpq_path: &Path, files: Vec<ManifestFile>, patch_paths: &[PathBuf], base_dir: &Path) -> Result<()> {
+    let hashed = |path: &Path| -> Result<ManifestFile> {
+        let data = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        Ok(ManifestFile {
+            path: path.display().to_string(),
+            size: data.len() as u64,
+            sha256: sha256_hex(&data),
+        })
+    };
+    let mut archive = hashed(mpq_path)?;
+    archive.path = mpq_path
+        .file_name()
+        .map_or_else(|| archive.path.clone(), |n| n.to_string_lossy().to_string());
+    let manifest = ReleaseManifest {
+        archive,
+        tool: env!("CARGO_PKG_NAME").to_string(),
+        tool_version: env!("CARGO_PKG_VERSION"),
+        patches_revision: git_revision(base_dir),
+        patches: patch_paths.iter().map(|p| hashed(p)).collect::<Result<_>>()?,
+        files,
+    };
+    let path = mpq_path.with_file_name(RELEASE_MANIFEST);
+    let json = serde_json::to_string_pretty(&manifest)?;
+    fs::write(&path, json + "\n").with_context(|| format!("Failed to write {:?}", path))?;
+    say!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Build an MPQ archive after applying patches.  First calls
+/// `apply_command` to produce the modified DBCs and then uses the
+/// `wow_mpq` crate to create an archive.  If MPQ creation fails the
+/// modified DBCs remain in the output directory.  Unless `listfile` is
+/// false a `(listfile)` and an `(attributes)` block are added, so other
+/// MPQ tools can list the archive and check its files.  Files are
+/// compressed with `compression` unless the manifest's `compression`
+/// section names their extension.  Include files are picked by
+/// [`select_includes`], with `include_globs` from the command line.  With
+/// `release_manifest` the hashes of the archive and its files are written
+/// next to it (see [`RELEASE_MANIFEST`]).  With `changelog` the notes of
+/// the applied patch entries are written there (see [`write_changelog`]).
+/// With `normalize_paths` archive paths get backslashes and one casing
+/// per directory (see [`normalize_archive_paths`]); paths differing only
+/// by case are warned about either way.
+#[allow(clippy::too_many_arguments)]
+pub fn build_command(
+    dbc_files: &[PathBuf],
+    patch_sets: &[Vec<PathBuf>],
+    out_dir: &Path,
+    mpq_path: &Path,
+    mpq_version: u8,
+    base_mpq: Option<&Path>,
+    listfile: bool,
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    include_globs: &IncludeGlobs,
+    provenance: bool,
+    translations: Option<&Translations>,
+    units: &Transformers,
+    ids: &IdMap,
+    compact_strings: bool,
+    self_check: bool,
+    compression: Compression,
+    release_manifest: bool,
+    changelog: Option<&Path>,
+    normalize_paths: bool,
+    manifest: &Manifest,
+    hook_ctx: &HookContext,
+) -> Result<()> {
+    // Apply patches first.  The modified DBCs will be written into out_dir.
+    run_hooks(&manifest.hooks.pre_apply, "pre_apply", hook_ctx)?;
+    let applied = apply_command(
+        dbc_files,
+        patch_sets,
+        out_dir,
+        dbc_dir,
+        schema_dir,
+        includes_dir,
+        provenance,
+        translations,
+        units,
+        ids,
+        compact_strings,
+        self_check,
+    )?;
+    manifest.budgets.check_tables(&applied)?;
+    check_strict()?;
+    if let Some(path) = changelog {
+        write_changelog(&applied, path)?;
+    }
+    run_hooks(&manifest.hooks.post_apply, "post_apply", hook_ctx)?;
+
+    // Collect the modified DBC files under DBFilesClient/ and the
+    // additional files from includes_dir, by their relative paths unless
+    // `includes.yaml` renames them
+    let mut entries: Vec<ArchiveEntry> = Vec::new();
+    for table in applied {
+        let path = table.out_path;
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid file name for {:?}", path))?;
+        let archive_name = format!("DBFilesClient/{}", file_name);
+        entries.push(ArchiveEntry { path, archive_name });
+    }
+    let (includes, excluded) = select_includes(includes_dir, manifest, include_globs)?;
+    entries.extend(includes);
+    if excluded > 0 {
+        say!(
+            "Left out {} include file(s) by exclude_includes, {} or the include globs",
+            excluded, INCLUDES_MANIFEST
+        );
+    }
+    if normalize_paths {
+        let changed = normalize_archive_paths(&mut entries);
+        if changed > 0 {
+            say!("Normalized {} archive path(s)", changed);
+        }
+    }
+    warn_case_collisions(&entries);
+
+    // Identical files stored under several names bloat the download
+    let payloads = load_payloads(&entries)?;
+    let duplicates = find_duplicates(&entries, &payloads);
+    report_duplicates(&entries, &payloads, &duplicates);
+
+    // Files of the base archive the build does not replace go in first
+    let now = SystemTime::now();
+    let mut files = Vec::new();
+    if let Some(base) = base_mpq {
+        let kept = base_archive_files(base, &entries)?;
+        say!(
+            "Keeping {} file(s) of {}; the build adds or replaces {}",
+            kept.len(),
+            base.display(),
+            entries.len()
+        );
+        for (name, data) in kept {
+            files.push(ArchivedFile { name, data, modified: now });
+        }
+    }
+    for (entry, data) in entries.iter().zip(payloads) {
+        let modified = fs::metadata(&entry.path).and_then(|m| m.modified()).unwrap_or(now);
+        files.push(ArchivedFile {
+            name: entry.archive_name.clone(),
+            data,
+            modified,
+        });
+    }
+    if listfile {
+        let data = listfile_data(&files);
+        files.push(ArchivedFile {
+            name: "(listfile)".to_string(),
+            data,
+            modified: now,
+        });
+        let data = attributes(&files, now);
+        files.push(ArchivedFile {
+            name: "(attributes)".to_string(),
+            data,
+            modified: now,
+        });
+    }
+    let mut listed = Vec::new();
+    if release_manifest {
+        for file in &files {
+            listed.push(ManifestFile {
+                path: file.name.clone(),
+                size: file.data.len() as u64,
+                sha256: sha256_hex(&file.data),
+            });
+        }
+    }
+
+    // Build the archive, unless a strict run warned since the tables were
+    // written
+    check_strict()?;
+    write_files(mpq_path, format_version(mpq_version), files, |name| {
+        manifest.compression_for(name, compression)
+    })?;
+    say!("Created MPQ {}", mpq_path.display());
+    let size = fs::metadata(mpq_path)
+        .with_context(|| format!("Failed to read size of {:?}", mpq_path))?
+        .len();
+    manifest.budgets.check_archive(size)?;
+    if release_manifest {
+        write_release_manifest(mpq_path, listed, &patch_sets.concat(), &manifest.base_dir)?;
+    }
+    Ok(())
+}
+
+/// What `merge-mpq` does with a file that several archives hold with
+/// different contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnConflict {
+    /// Keep the copy of the archive given last, as the client would
+    LastWins,
+    /// Stop without writing the merged archive
+    Error,
+    /// Ask which archive's copy to keep, file by file
+    Ask,
+}
+
+/// The copies of a file several archives hold: the index of each archive
+/// with its contents.
+type Copies = Vec<(usize, Vec<u8>)>;
+
+/// Ask on the terminal which of `copies` (archive index and contents) of
+/// `name` to keep; an empty answer keeps the last.
+fn ask_copy(name: &str, mpqs: &[PathBuf], copies: &[(usize, Vec<u8>)]) -> Result<usize> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    println!("{} differs between archives:", name);
+    for (choice, (index, data)) in copies.iter().enumerate() {
+        println!("  [{}] {} ({} bytes)", choice + 1, mpqs[*index].display(), data.len());
+    }
+    loop {
+        print!("Keep which copy? [1-{}, default {}]: ", copies.len(), copies.len());
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Ok(copies.len() - 1);
+        }
+        match line.trim().parse::<usize>() {
+            Ok(choice) if (1..=copies.len()).contains(&choice) => return Ok(choice - 1),
+            _ => println!("Enter a number from 1 to {}", copies.len()),
+        }
+    }
+}
+
+/// Combine the files of `mpqs` into one archive at `out`, e.g. patches
+/// from several community sources that the client would otherwise load
+/// as separate `patch-X.mpq` files.  Files several archives hold with the
+/// same contents are stored once; those whose contents differ are listed
+/// with the archives holding them, and resolved as `on_conflict` says.
+/// Names are compared case‑insensitively with either slash.  The
+/// archives need listfiles; the merged archive gets a fresh `(listfile)`
+/// and `(attributes)`.
+pub fn merge_mpq_command(
+    mpqs: &[PathBuf],
+    out: &Path,
+    on_conflict: OnConflict,
+    mpq_version: u8,
+    compression: Compression,
+) -> Result<()> {
+    // Archive key -> name as first listed, and every archive's copy
+    let mut merged: BTreeMap<String, (String, Copies)> = BTreeMap::new();
+    for (index, mpq) in mpqs.iter().enumerate() {
+        let mut archive = wow_mpq::Archive::open(mpq).with_context(|| format!("Failed to open archive {:?}", mpq))?;
+        let listed = archive
+            .list()
+            .with_context(|| format!("Failed to list the files of {:?} (does it have a listfile?)", mpq))?;
+        let mut count = 0;
+        for entry in listed {
+            if entry.name.starts_with('(') {
+                continue;
+            }
+            let data = archive
+                .read_file(&entry.name)
+                .with_context(|| format!("Failed to read {} from {:?}", entry.name, mpq))?;
+            merged
+                .entry(archive_key(&entry.name))
+                .or_insert_with(|| (entry.name.clone(), Vec::new()))
+                .1
+                .push((index, data));
+            count += 1;
+        }
+        say!("{}: {} file(s)", mpq.display(), count);
+    }
+
+    let shared = merged.values().filter(|(_, copies)| copies.len() > 1);
+    let (same, differing): (Vec<_>, Vec<_>) =
+        shared.partition(|(_, copies)| copies.iter().all(|(_, data)| *data == copies[0].1));
+    let holders = |copies: &[(usize, Vec<u8>)]| {
+        copies
+            .iter()
+            .map(|(index, data)| format!("{} ({} bytes)", mpqs[*index].display(), data.len()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if !same.is_empty() {
+        println!("{} file(s) are in several archives with the same contents:", same.len());
+        for (name, copies) in &same {
+            println!("  {}: {}", name, holders(copies));
+        }
+    }
+    if !differing.is_empty() {
+        println!("{} file(s) differ between archives:", differing.len());
+        for (name, copies) in &differing {
+            println!("  {}: {}", name, holders(copies));
+        }
+        if on_conflict == OnConflict::Error {
+            bail!(
+                "{} file(s) differ between the archives; pass --on-conflict last-wins or ask to merge anyway",
+                differing.len()
+            );
+        }
+    }
+
+    let now = SystemTime::now();
+    let mut files = Vec::with_capacity(merged.len() + 2);
+    for (name, mut copies) in merged.into_values() {
+        let differs = copies.iter().any(|(_, data)| *data != copies[0].1);
+        let keep = if differs && on_conflict == OnConflict::Ask {
+            ask_copy(&name, mpqs, &copies)?
+        } else {
+            copies.len() - 1
+        };
+        let (_, data) = copies.swap_remove(keep);
+        files.push(ArchivedFile { name, data, modified: now });
+    }
+    let data = listfile_data(&files);
+    files.push(ArchivedFile {
+        name: "(listfile)".to_string(),
+        data,
+        modified: now,
+    });
+    let data = attributes(&files, now);
+    files.push(ArchivedFile {
+        name: "(attributes)".to_string(),
+        data,
+        modified: now,
+    });
+
+    let count = files.len() - 2;
+    write_files(out, format_version(mpq_version), files, |_| compression)?;
+    say!("Merged {} file(s) from {} archive(s) into {}", count, mpqs.len(), out.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn attributes_describe_each_file_then_themselves() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let file = |name: &str, data: &[u8], secs| ArchivedFile {
+            name: name.to_string(),
+            data: data.to_vec(),
+            modified: at(secs),
+        };
+        let files = [file("a.blp", b"abc", 1), file("b.blp", b"", 2)];
+        let data = attributes(&files, at(3));
+        assert_eq!(data.len(), 8 + 3 * (4 + 8 + 16));
+        assert_eq!(data[0..8], [100, 0, 0, 0, 7, 0, 0, 0]);
+        let crcs: Vec<u32> = data[8..20].chunks(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect();
+        assert_eq!(crcs, [0x3524_41C2, 0, 0]);
+        let times: Vec<u64> = data[20..44].chunks(8).map(|c| u64::from_le_bytes(c.try_into().unwrap())).collect();
+        let ticks = |secs| (secs + FILETIME_EPOCH_OFFSET) * 10_000_000;
+        assert_eq!(times, [ticks(1), ticks(2), ticks(3)]);
+        assert_eq!(data[44..60], Md5::digest(b"abc")[..]);
+        assert_eq!(data[60..76], Md5::digest(b"")[..]);
+        assert_eq!(data[76..92], [0; 16]);
+    }
+
+    #[test]
+    fn merging_keeps_shared_files_once_and_resolves_conflicts() {
+        let dir = std::env::temp_dir().join(format!("wow_dbc_patcher-merge-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let archive = |name: &str, contents: &[(&str, &str)]| {
+            let path = dir.join(name);
+            let mut files: Vec<ArchivedFile> = contents
+                .iter()
+                .map(|(name, data)| ArchivedFile {
+                    name: name.to_string(),
+                    data: data.as_bytes().to_vec(),
+                    modified: UNIX_EPOCH,
+                })
+                .collect();
+            let data = listfile_data(&files);
+            files.push(ArchivedFile {
+                name: "(listfile)".to_string(),
+                data,
+                modified: UNIX_EPOCH,
+            });
+            write_files(&path, format_version(1), files, |_| Compression::None).unwrap();
+            path
+        };
+        let mpqs = [
+            archive("a.mpq", &[("Textures\\shared.blp", "same"), ("Sound\\a.wav", "a"), ("Textures\\glow.blp", "a")]),
+            archive("b.mpq", &[("TEXTURES\\SHARED.BLP", "same"), ("Textures\\glow.blp", "b")]),
+        ];
+        let out = dir.join("merged.mpq");
+
+        let err = merge_mpq_command(&mpqs, &out, OnConflict::Error, 1, Compression::Zlib).unwrap_err();
+        assert!(err.to_string().starts_with("1 file(s) differ"), "{}", err);
+        assert!(!out.exists());
+
+        merge_mpq_command(&mpqs, &out, OnConflict::LastWins, 1, Compression::Zlib).unwrap();
+        let mut merged = wow_mpq::Archive::open(&out).unwrap();
+        let mut names: Vec<String> = merged.list().unwrap().into_iter().map(|e| e.name).collect();
+        names.sort();
+        assert_eq!(names, ["Sound\\a.wav", "Textures\\glow.blp", "Textures\\shared.blp"]);
+        assert!(merged.read_file("(attributes)").is_ok());
+        assert_eq!(merged.read_file("Textures\\glow.blp").unwrap(), b"b");
+        assert_eq!(merged.read_file("Textures\\shared.blp").unwrap(), b"same");
+        fs::remove_dir_all(&dir).ok();
+    }
+}
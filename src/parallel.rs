@@ -0,0 +1,39 @@
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+/// Run `f` on every item on a pool of worker threads, one per core, and
+/// return the results in item order, so what is done with them does not
+/// depend on which worker finished first.
+pub fn map_parallel<T: Send, R: Send>(items: Vec<T>, f: impl Fn(T) -> R + Sync) -> Vec<R> {
+    let count = items.len();
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(count)
+        .max(1);
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let mut results: Vec<Option<R>> = (0..count).map(|_| None).collect();
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let (queue, f) = (&queue, &f);
+            scope.spawn(move || loop {
+                let Some((i, item)) = queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).next() else {
+                    break;
+                };
+                if tx.send((i, f(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+        for (i, result) in rx {
+            results[i] = Some(result);
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.expect("every item is handed to a worker"))
+        .collect()
+}
@@ -1,5 +1,7 @@
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::schema::FieldKind;
 
 /// Top level structure for a patch file.  A patch targets a single DBC
 /// table and contains a list of individual changes.  The DBC path is used
@@ -12,17 +14,60 @@ pub struct PatchFile {
     /// A list of changes to apply.  Each change may update an existing
     /// record or insert a new one.
     pub changes: Vec<PatchEntry>,
+    /// Unfinished work that `apply` and `build` leave out unless
+    /// `--include-experimental` is given.  In the mapping form the flag is
+    /// a top‑level `experimental: true` key and covers the whole file.
+    #[serde(default)]
+    pub experimental: bool,
+    /// Remove every record of the table before `changes` are applied, to
+    /// repopulate it from scratch.  Written as `truncate: true` next to
+    /// the table's `changes`.
+    #[serde(default)]
+    pub truncate: bool,
 
     /// Optional path to the patch file this patch was loaded from.  This is
     /// not populated by the YAML parser (hence `serde(skip)`) but filled
     /// in by the loader so warnings can reference the source file.
     #[serde(skip)]
     pub origin: Option<std::path::PathBuf>,
+    /// Whether the file was written in the deprecated `dbc:`/`changes:`
+    /// object form rather than as a `Table.dbc:` mapping.  Set by the
+    /// loader.
+    #[serde(skip)]
+    pub legacy_form: bool,
+    /// 1‑based line each entry of `changes` starts on in the patch file,
+    /// for diagnostics.  Set by the loader for YAML entries written in the
+    /// file one per line; empty otherwise.
+    #[serde(skip)]
+    pub entry_lines: Vec<usize>,
+    /// The `description` and `issue` written next to each entry of
+    /// `changes`, for the changelog of a build.  Set by the loader; empty
+    /// when no entry of the file has either.
+    #[serde(skip)]
+    pub notes: Vec<EntryNote>,
+}
+
+/// What a patch entry is for, as written in its optional `description`
+/// and `issue` keys: `description: Four Horsemen marks`, `issue: 42`.
+/// Neither changes what the entry does.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EntryNote {
+    pub description: Option<String>,
+    pub issue: Option<String>,
+}
+
+impl EntryNote {
+    /// Whether the entry has neither a description nor an issue.
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none() && self.issue.is_none()
+    }
 }
 
 /// A single patch entry.  Serialized using an internal tagging strategy so
-/// that entries can be either `update` or `insert` variants.
-#[derive(Debug, Deserialize)]
+/// that entries can be `update`, `update_where`, `insert`, `copy`,
+/// `delete`, `delete_where`, `disable`, `replace_string`, `script`, `zone`, or one of the table‑specific helpers `item_display`,
+/// `learn_spell`, `talent` and `spell_ranks`.
+#[derive(Debug, Deserialize, PartialEq)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum PatchEntry {
     /// Modify fields of an existing record identified by a key.  The key is
@@ -42,6 +87,18 @@ pub enum PatchEntry {
         /// with a warning.
         values: HashMap<String, ValueType>,
     },
+    /// Apply the same `values` to every record that meets all the
+    /// conditions in `where`, such as every fire spell or every spell whose
+    /// name contains "Inferno", instead of listing their keys.  Records
+    /// are matched before any of them is changed.
+    #[serde(rename = "update_where")]
+    UpdateWhere {
+        /// Field names (or indices) with the condition each must meet.
+        #[serde(rename = "where")]
+        conditions: BTreeMap<String, Condition>,
+        /// Mapping of field names (or indices) to new values.
+        values: HashMap<String, ValueType>,
+    },
     /// Insert a completely new record.  Only the fields listed in
     /// `values` will be set; unspecified fields default to zero.  When
     /// inserting a string value the writer will append the string to
@@ -55,35 +112,299 @@ pub enum PatchEntry {
         /// Column containing the key.  May be a field name or numeric index.
         #[serde(default)]
         key_column: Option<String>,
+        /// Named record template from the table's schema to start from
+        /// instead of zeros.  `values` only needs the fields that differ.
+        #[serde(default)]
+        template: Option<String>,
         /// Mapping of field names (or indices) to values for the new record.
+        #[serde(default)]
         values: HashMap<String, ValueType>,
     },
     /// Copy an existing record identified by a key into a new record,
     /// then apply field updates.  The key lookup works like Update: the
     /// key is matched in the specified key_column (defaults to column 0).
     /// The new record starts as an exact copy of the matched record and
-    /// only the provided fields are modified.
+    /// only the provided fields are modified.  `new_key`, when given, is
+    /// written to the key column of the copy before `values`, and a record
+    /// that already has it is an error rather than a skipped copy.
     Copy {
         /// Key value used to find the record to copy.
         key: u32,
         /// Column containing the key.  May be a field name or numeric string.
         #[serde(default)]
         key_column: Option<String>,
+        /// Key of the new record.
+        #[serde(default)]
+        new_key: Option<u32>,
         /// Mapping of field names (or indices) to new values for the copied record.
+        #[serde(default)]
+        values: HashMap<String, ValueType>,
+    },
+    /// Remove the record whose `key_column` (defaults to column 0) matches
+    /// `key`.  Strings it used stay in the string block.  When the table's
+    /// schema declares a `tombstone` the record is disabled instead, as by
+    /// `disable`, unless `hard` is set.
+    Delete {
+        /// Key value of the record to remove.
+        key: u32,
+        /// Column containing the key.  May be a field name or numeric string.
+        #[serde(default)]
+        key_column: Option<String>,
+        /// Remove the row even when the schema declares a tombstone.
+        #[serde(default)]
+        hard: bool,
+    },
+    /// Remove every record that meets all the conditions in `where`, with
+    /// the conditions of `update_where`.  Records are disabled instead
+    /// when the schema declares a tombstone, unless `hard` is set.
+    #[serde(rename = "delete_where")]
+    DeleteWhere {
+        /// Field names (or indices) with the condition each must meet.
+        #[serde(rename = "where")]
+        conditions: BTreeMap<String, Condition>,
+        /// Remove the rows even when the schema declares a tombstone.
+        #[serde(default)]
+        hard: bool,
+    },
+    /// Keep the record whose `key_column` (defaults to column 0) matches
+    /// `key` but set the fields the schema's `tombstone` declares, hiding
+    /// it from the client without breaking references to it.
+    Disable {
+        /// Key value of the record to disable.
+        key: u32,
+        /// Column containing the key.  May be a field name or numeric string.
+        #[serde(default)]
+        key_column: Option<String>,
+    },
+    /// Replace a string value in every string‑typed field of the table.
+    /// Only fields whose whole value equals `from` are changed, which makes
+    /// this suitable for repointing references after renaming an asset.
+    /// Requires a schema that declares the table's string fields.
+    #[serde(rename = "replace_string")]
+    ReplaceString {
+        /// The exact string to look for.
+        from: String,
+        /// The replacement string.
+        to: String,
+        /// Compare without regard to ASCII case (client paths are
+        /// case‑insensitive).  Defaults to false.
+        #[serde(default)]
+        ignore_case: bool,
+    },
+    /// Generate entries with a Rhai script, for patterns too repetitive or
+    /// computed to spell out (loops over spell ranks, math on radii).  The
+    /// script runs when the entry is reached during apply, so its lookups
+    /// see the table as patched so far.  Give the code inline or as a
+    /// path relative to the patch file.
+    Script {
+        /// Inline script source.
+        #[serde(default)]
+        code: Option<String>,
+        /// Script file, relative to the patch file.
+        #[serde(default)]
+        file: Option<std::path::PathBuf>,
+    },
+    /// Apply the nested `update`, `copy` and `script` entries only to keys
+    /// that belong to a zone, e.g. to restyle a vanilla spell only where
+    /// it is cast in Naxxramas.  Client tables do not record where a spell
+    /// is used, so membership comes from a `members` file (relative to the
+    /// patch file) mapping zones to keys; zone names are resolved through
+    /// AreaTable.dbc and include sub‑areas.  Script output is filtered the
+    /// same way.
+    Zone {
+        /// Zone name or AreaTable ID.
+        zone: String,
+        /// Membership file, relative to the patch file.
+        members: std::path::PathBuf,
+        /// Entries to filter.
+        changes: Vec<PatchEntry>,
+    },
+    /// Register a new item display (ItemDisplayInfo.dbc only), typically to
+    /// give a custom recipe or trinket its own inventory icon.  The row is
+    /// copied from `base` when given, otherwise it starts zeroed.  The icon
+    /// must be a stock icon or exist as `Interface/Icons/<icon>.blp` in the
+    /// includes directory; otherwise a warning is printed.
+    #[serde(rename = "item_display")]
+    ItemDisplay {
+        /// Display ID of the new row.
+        id: u32,
+        /// Icon name under `Interface\Icons`, without extension.  A full
+        /// client path is accepted and reduced to the name.
+        icon: String,
+        /// Existing display to start from, e.g. a stock recipe scroll.
+        #[serde(default)]
+        base: Option<u32>,
+        /// Further fields to set on the new row.
+        #[serde(default)]
+        values: HashMap<String, ValueType>,
+    },
+    /// Make a spell learnable (SkillLineAbility.dbc only) by linking it to
+    /// a skill line, e.g. so a custom test spell can be taught on a local
+    /// server.  The row ID defaults to the next free one.
+    #[serde(rename = "learn_spell")]
+    LearnSpell {
+        /// Row ID; defaults to one above the highest existing ID.
+        #[serde(default)]
+        id: Option<u32>,
+        /// Spell to make learnable.
+        spell: u32,
+        /// SkillLine.dbc ID the spell is listed under.
+        skill_line: u32,
+        /// Races allowed to learn it; 0 means all.
+        #[serde(default)]
+        race_mask: u32,
+        /// Classes allowed to learn it; 0 means all.
+        #[serde(default)]
+        class_mask: u32,
+        /// Skill rank needed before the spell is available.  Defaults to 1.
+        #[serde(default = "default_min_skill_rank")]
+        min_skill_rank: u32,
+        /// How the spell is acquired: 0 = taught explicitly (trainer or
+        /// command), 1 = with a profession skill, 2 = with a race or class
+        /// skill.  Defaults to 0.
+        #[serde(default)]
+        acquire_method: u32,
+        /// Further fields to set on the new row.
+        #[serde(default)]
+        values: HashMap<String, ValueType>,
+    },
+    /// Add a talent (Talent.dbc only) whose ranks are the given spells.
+    /// The row ID defaults to the next free one.
+    Talent {
+        /// Row ID; defaults to one above the highest existing ID.
+        #[serde(default)]
+        id: Option<u32>,
+        /// TalentTab.dbc ID of the tree.
+        tab: u32,
+        /// Row in the tree, starting at 0.
+        tier: u32,
+        /// Column in the tree, starting at 0.
+        column: u32,
+        /// Spell for each rank, lowest first (at most nine).
+        ranks: Vec<u32>,
+        /// Prerequisite talent and the points it needs.
+        #[serde(default)]
+        requires: Option<TalentRequirement>,
+        /// Further fields to set on the new row.
+        #[serde(default)]
+        values: HashMap<String, ValueType>,
+    },
+    /// Update every rank of the spell chain containing `spell` (Spell.dbc
+    /// only), so a restyled ability looks the same at every rank.  Ranks
+    /// are linked through SkillLineAbility.dbc in the DBC directory.
+    #[serde(rename = "spell_ranks")]
+    SpellRanks {
+        /// Any spell of the chain.
+        spell: u32,
+        /// Also update every spell with the same name as a rank, such as
+        /// the per‑NPC copies of a boss ability, which have no
+        /// SkillLineAbility rows.  Defaults to false.
+        #[serde(default)]
+        same_name: bool,
+        /// Fields to set on every rank.
         values: HashMap<String, ValueType>,
     },
 }
 
+impl PatchFile {
+    /// Where entry `i` of `changes` comes from, for diagnostics: the patch
+    /// file, followed by the line the entry starts on when it is known,
+    /// as in `patches/naxx.yaml:42`.
+    pub fn entry_origin(&self, i: usize) -> String {
+        let file = self
+            .origin
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "<unknown>".to_string());
+        match self.entry_lines.get(i) {
+            Some(line) => format!("{}:{}", file, line),
+            None => file,
+        }
+    }
+
+    /// The note of entry `i` of `changes`, if it has one.
+    pub fn entry_note(&self, i: usize) -> Option<&EntryNote> {
+        self.notes.get(i).filter(|note| !note.is_empty())
+    }
+}
+
+impl PatchEntry {
+    /// The `values` map of entries that carry one.
+    pub fn values(&self) -> Option<&HashMap<String, ValueType>> {
+        match self {
+            PatchEntry::Update { values, .. }
+            | PatchEntry::UpdateWhere { values, .. }
+            | PatchEntry::Insert { values, .. }
+            | PatchEntry::Copy { values, .. }
+            | PatchEntry::ItemDisplay { values, .. }
+            | PatchEntry::LearnSpell { values, .. }
+            | PatchEntry::Talent { values, .. }
+            | PatchEntry::SpellRanks { values, .. } => Some(values),
+            PatchEntry::Delete { .. }
+            | PatchEntry::DeleteWhere { .. }
+            | PatchEntry::Disable { .. }
+            | PatchEntry::ReplaceString { .. }
+            | PatchEntry::Script { .. }
+            | PatchEntry::Zone { .. } => None,
+        }
+    }
+
+    /// The `values` map of entries that carry one, for changing in place.
+    pub fn values_mut(&mut self) -> Option<&mut HashMap<String, ValueType>> {
+        match self {
+            PatchEntry::Update { values, .. }
+            | PatchEntry::UpdateWhere { values, .. }
+            | PatchEntry::Insert { values, .. }
+            | PatchEntry::Copy { values, .. }
+            | PatchEntry::ItemDisplay { values, .. }
+            | PatchEntry::LearnSpell { values, .. }
+            | PatchEntry::Talent { values, .. }
+            | PatchEntry::SpellRanks { values, .. } => Some(values),
+            PatchEntry::Delete { .. }
+            | PatchEntry::DeleteWhere { .. }
+            | PatchEntry::Disable { .. }
+            | PatchEntry::ReplaceString { .. }
+            | PatchEntry::Script { .. }
+            | PatchEntry::Zone { .. } => None,
+        }
+    }
+}
+
+fn default_min_skill_rank() -> u32 {
+    1
+}
+
+/// Prerequisite of a `talent` entry.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct TalentRequirement {
+    /// Talent.dbc ID of the prerequisite.
+    pub talent: u32,
+    /// Points that must be spent in it (1 = first rank).
+    pub points: u32,
+}
+
+/// A condition of an `update_where` entry on one field.  Written as a bare
+/// value for equality, `{contains: text}` for a string field that contains
+/// the text, or `{range: [low, high]}` for a number between the bounds
+/// (inclusive; compared as floats for float fields).
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Condition {
+    Contains { contains: String },
+    Range { range: [f64; 2] },
+    Equals(ValueType),
+}
 
 /// Values in patches are represented by an untagged enum.  Supported
 /// primitives include signed and unsigned integers, floating point numbers,
 /// booleans and strings.  When a string is specified the writer will
 /// allocate a new entry in the DBC string block and replace the field with
-/// the offset to the string.  Floats and booleans are currently parsed but
-/// will be truncated to integers because the underlying simple DBC writer
-/// assumes 32‑bit integers.  Extending the type system and record writer
-/// to honour floats and booleans is left as a future exercise.
-#[derive(Debug, Deserialize, Clone)]
+/// the offset to the string.  Fields the schema gives a `type` are checked
+/// against it (see [`ValueType::typed_cell`]); for untyped fields the YAML
+/// type decides, so floats are stored as their 32‑bit bits and booleans as
+/// 0 or 1.  A one‑key mapping such as `{seconds: 8}` is a number with a
+/// unit, converted when patches are loaded (see `units::Transformers`).
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum ValueType {
     Int(i64),
@@ -91,16 +412,108 @@ pub enum ValueType {
     Float(f64),
     Bool(bool),
     String(String),
+    Unit(BTreeMap<String, ValueType>),
+    /// Flag names (or numbers) combined into one value, for fields whose
+    /// schema declares `flags`.
+    List(Vec<ValueType>),
+}
+
+/// An operation a value such as `{or: 0x80}` applies to the bits a field
+/// already holds, so one flag can be set or cleared without restating the
+/// others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOp {
+    /// `andnot`: clear the given bits.
+    AndNot,
+    /// `or`: set the given bits.
+    Or,
+    /// `xor`: flip the given bits.
+    Xor,
+}
+
+impl BitOp {
+    /// The operations, in the order a value combining several applies them.
+    const ALL: [(&'static str, BitOp); 3] = [("andnot", BitOp::AndNot), ("or", BitOp::Or), ("xor", BitOp::Xor)];
+
+    /// The bits of `cell` after the operation with `bits`.
+    pub fn apply(self, cell: u32, bits: u32) -> u32 {
+        match self {
+            BitOp::AndNot => cell & !bits,
+            BitOp::Or => cell | bits,
+            BitOp::Xor => cell ^ bits,
+        }
+    }
+}
+
+/// An operation a value such as `{add: 5}` or `{mul: 1.25}` applies to
+/// the number a field already holds, so a tuning pass can scale many
+/// records without restating each result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberOp {
+    /// `mul`: multiply by the given number.
+    Mul,
+    /// `add`: add the given number, which may be negative.
+    Add,
+}
+
+impl NumberOp {
+    /// The operations, in the order a value combining both applies them.
+    const ALL: [(&'static str, NumberOp); 2] = [("mul", NumberOp::Mul), ("add", NumberOp::Add)];
+
+    /// `current` after the operation with `operand`.
+    pub fn apply(self, current: f64, operand: f64) -> f64 {
+        match self {
+            NumberOp::Mul => current * operand,
+            NumberOp::Add => current + operand,
+        }
+    }
+}
+
+/// The operations of `value` when it is a mapping whose keys are all
+/// names of `all` (case‑insensitively), in the order `all` lists them.
+fn operations<'a, T: Copy>(value: &'a ValueType, all: &[(&str, T)]) -> Option<Vec<(T, &'a ValueType)>> {
+    let ValueType::Unit(map) = value else {
+        return None;
+    };
+    let is_op = |name: &String| all.iter().any(|(op, _)| name.eq_ignore_ascii_case(op));
+    if map.is_empty() || !map.keys().all(is_op) {
+        return None;
+    }
+    Some(
+        all.iter()
+            .filter_map(|&(name, op)| {
+                map.iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, operand)| (op, operand))
+            })
+            .collect(),
+    )
 }
 
 impl ValueType {
+    /// The operations of a value written as a mapping of `or`, `andnot`
+    /// and `xor` to their bits, e.g. `{or: 0x80}` or
+    /// `{andnot: 0x4, or: 0x80}`, in the order they apply: `andnot`, then
+    /// `or`, then `xor`.  `None` for any other value.
+    pub fn bit_operations(&self) -> Option<Vec<(BitOp, &ValueType)>> {
+        operations(self, &BitOp::ALL)
+    }
+
+    /// The operations of a value written as a mapping of `mul` and `add`
+    /// to numbers, e.g. `{mul: 1.1}` or `{mul: 2, add: -1}`, in the order
+    /// they apply: `mul`, then `add`.  `None` for any other value.
+    pub fn number_operations(&self) -> Option<Vec<(NumberOp, &ValueType)>> {
+        operations(self, &NumberOp::ALL)
+    }
+
     /// Convert this `ValueType` into a u32 suitable for storage in the DBC
     /// record.  Floats are truncated, booleans become 0 or 1 and strings
     /// cannot be directly converted (the caller must handle string
     /// allocation and supply the resulting offset instead).
     pub fn as_u32(&self) -> Option<u32> {
         match *self {
-            ValueType::Int(v) if v >= 0 && v <= u32::MAX as i64 => Some(v as u32),
+            // Negative integers are stored as their two's complement
+            ValueType::Int(v) if v >= i32::MIN as i64 && v <= u32::MAX as i64 => Some(v as u32),
             ValueType::UInt(v) if v <= u32::MAX as u64 => Some(v as u32),
             ValueType::Float(v) => {
                 // Interpret floats as 32‑bit IEEE‐754 values.  The caller
@@ -115,4 +528,48 @@ impl ValueType {
             _ => None,
         }
     }
+
+    /// Convert this value for a field declared as `kind`, or describe why
+    /// it does not fit.  Integers are accepted for floats, and 0 and 1 for
+    /// booleans; negative integers are stored as their two's complement.
+    /// Strings give `Ok(None)`; the caller interns them.
+    pub fn typed_cell(&self, kind: FieldKind) -> Result<Option<u32>, String> {
+        match (kind, self) {
+            (FieldKind::String, ValueType::String(_)) => Ok(None),
+            (FieldKind::Int, ValueType::Int(v)) if (i32::MIN as i64..=u32::MAX as i64).contains(v) => {
+                Ok(Some(*v as u32))
+            }
+            (FieldKind::Int, ValueType::UInt(v)) if *v <= u32::MAX as u64 => Ok(Some(*v as u32)),
+            (FieldKind::Int, ValueType::Int(_) | ValueType::UInt(_)) => {
+                Err(format!("{} does not fit in 32 bits", self.describe()))
+            }
+            (FieldKind::Int32, ValueType::Int(v)) if i32::try_from(*v).is_ok() => Ok(Some(*v as u32)),
+            (FieldKind::Int32, ValueType::UInt(v)) if i32::try_from(*v).is_ok() => Ok(Some(*v as u32)),
+            (FieldKind::Int32, ValueType::Int(_) | ValueType::UInt(_)) => {
+                Err(format!("{} does not fit in a signed 32‑bit integer", self.describe()))
+            }
+            (FieldKind::Float, ValueType::Float(v)) if v.is_finite() && v.abs() <= f32::MAX as f64 => {
+                Ok(Some((*v as f32).to_bits()))
+            }
+            (FieldKind::Float, ValueType::Int(v)) => Ok(Some((*v as f32).to_bits())),
+            (FieldKind::Float, ValueType::UInt(v)) => Ok(Some((*v as f32).to_bits())),
+            (FieldKind::Float, ValueType::Float(_)) => Err(format!("{} is not a 32‑bit float", self.describe())),
+            (FieldKind::Bool, ValueType::Bool(b)) => Ok(Some(u32::from(*b))),
+            (FieldKind::Bool, ValueType::Int(v @ (0 | 1))) => Ok(Some(*v as u32)),
+            _ => Err(format!("expected {}, got {}", kind.name(), self.describe())),
+        }
+    }
+
+    /// The value with its YAML type, for error messages.
+    fn describe(&self) -> String {
+        match self {
+            ValueType::Int(v) => format!("integer {}", v),
+            ValueType::UInt(v) => format!("integer {}", v),
+            ValueType::Float(v) => format!("float {:?}", v),
+            ValueType::Bool(v) => format!("boolean {}", v),
+            ValueType::String(v) => format!("string {:?}", v),
+            ValueType::Unit(v) => format!("unconverted unit {:?}", v.keys().collect::<Vec<_>>()),
+            ValueType::List(_) => "a list, which only fields with `flags` take".to_string(),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::manifest::Manifest;
+use crate::includes::{select_includes, IncludeGlobs, INCLUDES_MANIFEST};
+use crate::source::DbcTable;
+
+/// Client path of an include archived as `archive_name`, e.g.
+/// `spells\dangerzone.m2`, lower‑cased.
+fn client_path(archive_name: &str) -> String {
+    archive_name.replace('/', "\\").to_lowercase()
+}
+
+/// The names a table or model may use for an include: its path and file
+/// name, with `.mdx` for models shipped as `.m2`.
+fn reference_names(client_path: &str) -> Vec<String> {
+    let mut names = vec![client_path.to_string()];
+    if let Some(stem) = client_path.strip_suffix(".m2") {
+        names.push(format!("{}.mdx", stem));
+    }
+    let file_names: Vec<String> = names
+        .iter()
+        .filter_map(|n| n.rsplit('\\').next().map(str::to_string))
+        .collect();
+    names.extend(file_names);
+    names
+}
+
+/// Whether `data` (lower‑cased) contains `needle`.
+fn embeds(data: &[u8], needle: &str) -> bool {
+    !needle.is_empty() && data.windows(needle.len()).any(|w| w == needle.as_bytes())
+}
+
+/// List the files under `includes_dir` that nothing refers to: neither a
+/// string of `tables` (the patched tables and the stock ones) nor a
+/// referenced include embedding its path, the way a model names its
+/// textures.  A file counts as referenced when its path or bare file name
+/// appears, since SoundEntries stores directory and file name apart, so the
+/// check errs towards keeping files.  It cannot see assets that only the
+/// client's own models use; the manifest's `keep_includes` protects those.
+/// Files the manifest or `includes.yaml` already exclude are left out.  The unused ones can
+/// be excluded from the archive through the manifest, or deleted with
+/// `delete` after confirmation (`assume_yes` skips it).
+pub fn prune_command(
+    tables: &[DbcTable],
+    includes_dir: &Path,
+    manifest: &Manifest,
+    delete: bool,
+    assume_yes: bool,
+) -> Result<()> {
+    let mut strings: HashSet<String> = HashSet::new();
+    for table in tables {
+        for bytes in table.string_block.split(|&b| b == 0) {
+            if bytes.is_empty() {
+                continue;
+            }
+            let s = String::from_utf8_lossy(bytes).replace('/', "\\").to_lowercase();
+            if let Some((_, file_name)) = s.rsplit_once('\\') {
+                strings.insert(file_name.to_string());
+            }
+            strings.insert(s);
+        }
+    }
+
+    let (selected, excluded) = select_includes(includes_dir, manifest, &IncludeGlobs::default())?;
+    let includes: Vec<(PathBuf, String)> = selected
+        .into_iter()
+        .map(|entry| (entry.path, client_path(&entry.archive_name)))
+        .collect();
+
+    // Start from what the tables name, then follow embedded paths out of
+    // referenced files until nothing new turns up
+    let mut referenced: Vec<bool> = includes
+        .iter()
+        .map(|(_, name)| {
+            manifest.keeps_include(name) || reference_names(name).iter().any(|n| strings.contains(n))
+        })
+        .collect();
+    let mut pending: Vec<usize> = (0..includes.len()).filter(|&i| referenced[i]).collect();
+    while let Some(i) = pending.pop() {
+        let data = fs::read(&includes[i].0)
+            .with_context(|| format!("Failed to read {:?}", includes[i].0))?
+            .to_ascii_lowercase();
+        for (j, (_, name)) in includes.iter().enumerate() {
+            if !referenced[j] && reference_names(name).iter().any(|n| embeds(&data, n)) {
+                referenced[j] = true;
+                pending.push(j);
+            }
+        }
+    }
+
+    let unused: Vec<&(PathBuf, String)> = includes
+        .iter()
+        .zip(&referenced)
+        .filter(|(_, &r)| !r)
+        .map(|(include, _)| include)
+        .collect();
+    let bytes: u64 = unused
+        .iter()
+        .filter_map(|(path, _)| fs::metadata(path).ok())
+        .map(|m| m.len())
+        .sum();
+    println!(
+        "{} of {} include file(s) are not referenced ({} bytes){}",
+        unused.len(),
+        includes.len(),
+        bytes,
+        if excluded > 0 {
+            format!("; {} already excluded by the manifest or {}", excluded, INCLUDES_MANIFEST)
+        } else {
+            String::new()
+        }
+    );
+    if unused.is_empty() {
+        return Ok(());
+    }
+    for (path, _) in &unused {
+        println!("  {}", path.display());
+    }
+
+    if !delete {
+        println!("To leave them out of the archive, add to the manifest:");
+        println!("exclude_includes:");
+        for (path, _) in &unused {
+            let rel = path.strip_prefix(includes_dir).unwrap_or(path);
+            println!("  - {:?}", rel.to_string_lossy().replace('\\', "/"));
+        }
+        println!("or re-run with --delete to remove them.");
+        return Ok(());
+    }
+    if !assume_yes {
+        print!("Delete {} file(s)? [y/N] ", unused.len());
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("Nothing deleted");
+            return Ok(());
+        }
+    }
+    for (path, _) in &unused {
+        fs::remove_file(path).with_context(|| format!("Failed to delete {:?}", path))?;
+    }
+    println!("Deleted {} file(s)", unused.len());
+    Ok(())
+}
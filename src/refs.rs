@@ -0,0 +1,340 @@
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::apply::{patch_tables, ProvenanceStep};
+use crate::ids::IdMap;
+use crate::dbc::{dbc_file_name, find_dbc_file, read_dbc_with_schema, string_at};
+use crate::schema::load_schema;
+use crate::source::DbcSource;
+use crate::units::Transformers;
+
+/// Every file under `dir`, recursively, sorted by path.
+pub fn walk_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+    let mut stack: Vec<PathBuf> = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// List every row that references `target`.  A numeric target is treated
+/// as a record ID and matched against columns whose schema declares a
+/// `ref:` (restricted to references into `table` when given).  Any other
+/// target is treated as an asset path or name and matched
+/// case‑insensitively against string fields; files under `includes_dir`
+/// that embed the path (e.g. an M2 naming a BLP texture) are listed too.
+pub fn where_used_command(
+    target: &str,
+    table: Option<&str>,
+    dbc_files: &[PathBuf],
+    schema_dir: &Path,
+    includes_dir: &Path,
+) -> Result<()> {
+    let id = target.parse::<u32>().ok();
+    let needle = target.to_lowercase();
+    let mut hits = 0usize;
+    for dbc_path in dbc_files {
+        let file_name = dbc_file_name(dbc_path)?;
+        let Some(schema) = load_schema(schema_dir, &file_name) else {
+            continue;
+        };
+        let fields: Vec<_> = match id {
+            Some(_) => schema
+                .reference_fields()
+                .filter(|f| {
+                    table.is_none_or(|t| {
+                        f.reference.as_deref().is_some_and(|r| r.eq_ignore_ascii_case(t))
+                    })
+                })
+                .cloned()
+                .collect(),
+            None => schema.string_fields().cloned().collect(),
+        };
+        if fields.is_empty() {
+            continue;
+        }
+        let (_header, records, string_block) = match read_dbc_with_schema(dbc_path, schema_dir) {
+            Ok(t) => t,
+            Err(err) => {
+                warning!("skipping {}: {:#}", file_name, err);
+                continue;
+            }
+        };
+        for record in &records {
+            let key = record.first().copied().unwrap_or(0);
+            for field in &fields {
+                let Some(&value) = record.get(field.index) else {
+                    continue;
+                };
+                match id {
+                    Some(id) => {
+                        if value == id {
+                            println!(
+                                "{}  key {}  {} -> {}",
+                                file_name,
+                                key,
+                                field.name,
+                                field.reference.as_deref().unwrap_or("?")
+                            );
+                            hits += 1;
+                        }
+                    }
+                    None => {
+                        if let Some(s) = string_at(&string_block, value) {
+                            if s.to_lowercase().contains(&needle) {
+                                println!("{}  key {}  {}: {:?}", file_name, key, field.name, s);
+                                hits += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if id.is_none() {
+        for path in walk_files(includes_dir)? {
+            let data = fs::read(&path)?;
+            let lowered = data.to_ascii_lowercase();
+            if lowered
+                .windows(needle.len().max(1))
+                .any(|w| w == needle.as_bytes())
+            {
+                println!("{}  (embedded reference)", path.display());
+                hits += 1;
+            }
+        }
+    }
+    println!("{} reference(s) to {}", hits, target);
+    Ok(())
+}
+
+/// Keys (column 0) of patched tables, read on first use.
+struct TableKeys<'a, S: DbcSource> {
+    source: &'a S,
+    dbc_dir: &'a Path,
+    /// By lower‑cased table name; `None` for tables that cannot be read
+    keys: HashMap<String, Option<HashSet<u32>>>,
+}
+
+impl<S: DbcSource> TableKeys<'_, S> {
+    fn get(&mut self, name: &str) -> Option<&HashSet<u32>> {
+        self.keys
+            .entry(name.to_lowercase())
+            .or_insert_with(|| match self.source.read_table(&find_dbc_file(self.dbc_dir, name)) {
+                Ok(table) => Some(table.records.iter().filter_map(|r| r.first().copied()).collect()),
+                Err(err) => {
+                    warning!("cannot check references into {}: {:#}", name, err);
+                    None
+                }
+            })
+            .as_ref()
+    }
+}
+
+/// The entry that last set column `col` of a record to its current value,
+/// given the entries that changed the record.
+fn entry_setting(steps: &[ProvenanceStep], stock: Option<&[u32]>, col: usize) -> Option<String> {
+    let mut before = stock.and_then(|r| r.get(col));
+    let mut setter = None;
+    for step in steps {
+        if step.removed {
+            before = None;
+            continue;
+        }
+        let value = step.record.get(col);
+        if value != before {
+            setter = Some(step.entry.clone());
+        }
+        before = value;
+    }
+    setter
+}
+
+/// Apply `patch_sets` in memory and check the foreign keys the schemas
+/// declare with `ref:` against the patched tables.  A value other than 0
+/// or -1 with no record of that key (column 0) in the referenced table is
+/// dangling.  Only references patches are responsible for are reported, those in
+/// records they changed or inserted and those into records they deleted,
+/// unless `all` is set.  Fails when any are found.
+#[allow(clippy::too_many_arguments)]
+pub fn check_refs_command(
+    dbc_files: &[PathBuf],
+    patch_sets: &[Vec<PathBuf>],
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    units: &Transformers,
+    ids: &IdMap,
+    all: bool,
+) -> Result<()> {
+    let (overlay, progress) = patch_tables(dbc_files, patch_sets, dbc_dir, schema_dir, includes_dir, true, None, units, ids)?;
+
+    let mut keys = TableKeys {
+        source: &overlay,
+        dbc_dir,
+        keys: HashMap::new(),
+    };
+    // Stock keys patches deleted, by lower‑cased table name
+    let mut deleted: HashMap<String, HashSet<u32>> = HashMap::new();
+    for (lower, table) in &progress {
+        let (Some(provenance), Some(present)) = (&table.provenance, keys.get(lower)) else {
+            continue;
+        };
+        let gone: HashSet<u32> = provenance.keys().filter(|id| !present.contains(id)).copied().collect();
+        if !gone.is_empty() {
+            deleted.insert(lower.clone(), gone);
+        }
+    }
+
+    let mut dangling = 0usize;
+    for dbc_path in dbc_files {
+        let file_name = dbc_file_name(dbc_path)?;
+        let Some(schema) = load_schema(schema_dir, &file_name) else {
+            continue;
+        };
+        let fields: Vec<_> = schema.reference_fields().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let provenance = progress.get(&file_name.to_lowercase()).and_then(|p| p.provenance.as_ref());
+        // Only tables pointing at deleted records need a full scan
+        let into_deleted = fields
+            .iter()
+            .any(|f| deleted.contains_key(&f.reference.as_deref().unwrap_or_default().to_lowercase()));
+        if !all && provenance.is_none() && !into_deleted {
+            continue;
+        }
+        let table = match overlay.read_table(dbc_path) {
+            Ok(table) => table,
+            Err(err) => {
+                warning!("skipping {}: {:#}", file_name, err);
+                continue;
+            }
+        };
+        let stock: HashMap<u32, Vec<u32>> = match provenance {
+            Some(_) => read_dbc_with_schema(dbc_path, schema_dir)?
+                .1
+                .into_iter()
+                .filter_map(|r| Some((*r.first()?, r)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        for record in &table.records {
+            let key = record.first().copied().unwrap_or(0);
+            let steps = provenance.and_then(|p| p.get(&key));
+            for field in &fields {
+                let target = field.reference.as_deref().unwrap_or_default();
+                let Some(&value) = record.get(field.index) else {
+                    continue;
+                };
+                // 0 and -1 both mean "none"
+                if value == 0 || value == u32::MAX {
+                    continue;
+                }
+                let was_deleted = deleted.get(&target.to_lowercase()).is_some_and(|d| d.contains(&value));
+                if !all && steps.is_none() && !was_deleted {
+                    continue;
+                }
+                if keys.get(target).is_none_or(|k| k.contains(&value)) {
+                    continue;
+                }
+                let cause = if was_deleted {
+                    let entry = progress[&target.to_lowercase()].provenance.as_ref().and_then(|p| {
+                        p.get(&value)?.iter().rev().find(|s| s.removed).map(|s| s.entry.clone())
+                    });
+                    entry.map(|e| format!(" (deleted by {})", e)).unwrap_or_default()
+                } else {
+                    steps
+                        .and_then(|steps| entry_setting(steps, stock.get(&key).map(Vec::as_slice), field.index))
+                        .map(|e| format!(" (set by {})", e))
+                        .unwrap_or_default()
+                };
+                println!(
+                    "{}  key {}  {} -> {} {}: no such record{}",
+                    file_name, key, field.name, target, value, cause
+                );
+                dangling += 1;
+            }
+        }
+    }
+
+    if dangling > 0 {
+        bail!("{} dangling reference(s)", dangling);
+    }
+    println!("No dangling references");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbc::{write_dbc, DbcFormat, DbcHeader, Endianness};
+
+    fn write_table(path: &Path, field_count: u32, records: &[Vec<u32>]) {
+        let header = DbcHeader {
+            magic: *b"WDBC",
+            record_count: records.len() as u32,
+            field_count,
+            record_size: field_count * 4,
+            string_block_size: 1,
+            format: DbcFormat::Wdbc,
+            endianness: Endianness::Little,
+            layout: None,
+        };
+        write_dbc(path, &header, records, &[0]).unwrap();
+    }
+
+    #[test]
+    fn only_references_patches_break_are_reported() {
+        let dir = std::env::temp_dir().join(format!("wow_dbc_patcher-refs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_table(&dir.join("Kit.dbc"), 1, &[vec![1], vec![2], vec![3]]);
+        // Spell 12 already points at a kit that does not exist
+        write_table(&dir.join("Spell.dbc"), 2, &[vec![10, 1], vec![11, 2], vec![12, 99]]);
+        fs::write(dir.join("Kit.dbc.yaml"), "fields: [ID]\n").unwrap();
+        fs::write(dir.join("Spell.dbc.yaml"), "fields: [ID, { name: Kit, ref: Kit.dbc }]\n").unwrap();
+        let dbc_files = [dir.join("Kit.dbc"), dir.join("Spell.dbc")];
+        let check = |patch: &str, all: bool| {
+            let path = dir.join("patch.yaml");
+            fs::write(&path, patch).unwrap();
+            check_refs_command(
+                &dbc_files,
+                &[vec![path]],
+                &dir,
+                &dir,
+                &dir.join("includes"),
+                &Transformers::builtin(),
+                &IdMap::new(),
+                all,
+            )
+            .map_err(|err| err.to_string())
+        };
+
+        let moved = "Spell.dbc:\n  - type: update\n    key: 10\n    values: { Kit: 3 }\n";
+        assert_eq!(check(moved, false), Ok(()));
+        assert_eq!(check(moved, true), Err("1 dangling reference(s)".to_string()));
+
+        let unknown = "Spell.dbc:\n  - type: update\n    key: 10\n    values: { Kit: 50 }\n";
+        assert_eq!(check(unknown, false), Err("1 dangling reference(s)".to_string()));
+
+        // Spell 11 was not patched, but its kit was deleted
+        let deleted = "Kit.dbc:\n  - type: delete\n    key: 2\n";
+        assert_eq!(check(deleted, false), Err("1 dangling reference(s)".to_string()));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
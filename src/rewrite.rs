@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dbc::{
+    build_string_map, dbc_file_name, intern_string, pending_string_at, read_dbc_with_schema, write_dbc,
+};
+use crate::schema::{load_schema, Schema};
+
+/// A single string field changed by `rewrite_strings`.
+#[derive(Debug)]
+pub struct StringRewrite {
+    /// Value of column 0 of the affected record.
+    pub key: u32,
+    /// Schema name of the rewritten field.
+    pub field: String,
+    /// The string that was replaced.
+    pub old: String,
+}
+
+/// Replace every string‑typed field whose value equals `from` with `to`.
+/// Only whole values are replaced, never substrings, so renaming
+/// `Spells\Foo.m2` does not touch `Spells\FooBar.m2`.  The replacement is
+/// allocated through `intern_string` so it shares an offset with any
+/// identical string already in the block.  Returns the changed fields.
+#[allow(clippy::too_many_arguments)]
+pub fn rewrite_strings(
+    records: &mut [Vec<u32>],
+    schema: &Schema,
+    from: &str,
+    to: &str,
+    ignore_case: bool,
+    string_map: &mut HashMap<String, u32>,
+    new_strings: &mut Vec<String>,
+    string_block: &[u8],
+) -> Vec<StringRewrite> {
+    let mut changed = Vec::new();
+    let string_fields: Vec<_> = schema.string_fields().cloned().collect();
+    for record in records.iter_mut() {
+        let key = record.first().copied().unwrap_or(0);
+        for field in &string_fields {
+            let Some(&offset) = record.get(field.index) else {
+                continue;
+            };
+            let Some(current) = pending_string_at(string_block, new_strings, offset) else {
+                continue;
+            };
+            let hit = if ignore_case {
+                current.eq_ignore_ascii_case(from)
+            } else {
+                current == from
+            };
+            if !hit {
+                continue;
+            }
+            record[field.index] = intern_string(to, string_map, new_strings, string_block.len());
+            changed.push(StringRewrite {
+                key,
+                field: field.name.clone(),
+                old: current,
+            });
+        }
+    }
+    changed
+}
+
+/// Rewrite a string value across the given tables and write every table
+/// that changed into `out_dir`.  With `dry_run` the affected rows are
+/// listed but nothing is written.  Tables whose schema declares no string
+/// fields are skipped.
+pub fn rewrite_strings_command(
+    from: &str,
+    to: &str,
+    dbc_files: &[PathBuf],
+    schema_dir: &Path,
+    out_dir: &Path,
+    ignore_case: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let mut total = 0usize;
+    for dbc_path in dbc_files {
+        let file_name = dbc_file_name(dbc_path)?;
+        let Some(schema) = load_schema(schema_dir, &file_name) else {
+            continue;
+        };
+        if schema.string_fields().next().is_none() {
+            continue;
+        }
+        let (header, mut records, mut string_block) = read_dbc_with_schema(dbc_path, schema_dir)
+            .with_context(|| format!("Failed to read DBC file {:?}", dbc_path))?;
+        let mut string_map = build_string_map(&string_block);
+        let mut new_strings: Vec<String> = Vec::new();
+        let changed = rewrite_strings(
+            &mut records,
+            &schema,
+            from,
+            to,
+            ignore_case,
+            &mut string_map,
+            &mut new_strings,
+            &string_block,
+        );
+        if changed.is_empty() {
+            continue;
+        }
+        for c in &changed {
+            println!("{}  key {}  {}: {:?} -> {:?}", file_name, c.key, c.field, c.old, to);
+        }
+        total += changed.len();
+        if dry_run {
+            continue;
+        }
+        for s in &new_strings {
+            string_block.extend_from_slice(s.as_bytes());
+            string_block.push(0);
+        }
+        fs::create_dir_all(out_dir)
+            .with_context(|| format!("Failed to create output directory {:?}", out_dir))?;
+        let out_path = out_dir.join(&file_name);
+        write_dbc(&out_path, &header, &records, &string_block)
+            .with_context(|| format!("Failed to write output DBC for {}", file_name))?;
+        say!("Wrote {}", out_path.display());
+    }
+    if dry_run {
+        println!("{} field(s) would be rewritten (dry run, nothing written)", total);
+    } else {
+        println!("{} field(s) rewritten", total);
+    }
+    Ok(())
+}
@@ -0,0 +1,671 @@
+use include_dir::{include_dir, Dir};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::patch::ValueType;
+
+/// The canonical 1.12 schemas under `schema/`, built into the binary so
+/// field names work wherever the tool is run from.
+static BUILTIN_SCHEMAS: Dir = include_dir!("$CARGO_MANIFEST_DIR/schema");
+
+/// The kind of value a column holds.  Every column is read into a 32‑bit
+/// cell; the kind only tells the tool how to interpret it.  Fields without
+/// an explicit `type` in the schema default to `Int` but are not checked
+/// (see [`FieldDef::typed`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Int,
+    /// A signed integer, such as a spell effect's base points, which is
+    /// often negative and is shown as such.
+    Int32,
+    Float,
+    String,
+    Bool,
+}
+
+impl FieldKind {
+    fn parse(name: &str) -> Option<FieldKind> {
+        match name.to_lowercase().as_str() {
+            "int" | "uint" | "u32" => Some(FieldKind::Int),
+            "int32" | "i32" => Some(FieldKind::Int32),
+            "float" | "f32" => Some(FieldKind::Float),
+            "string" | "str" => Some(FieldKind::String),
+            "bool" | "boolean" => Some(FieldKind::Bool),
+            _ => None,
+        }
+    }
+
+    /// The name of the kind as written in schemas.
+    pub fn name(self) -> &'static str {
+        match self {
+            FieldKind::Int => "int",
+            FieldKind::Int32 => "int32",
+            FieldKind::Float => "float",
+            FieldKind::String => "string",
+            FieldKind::Bool => "bool",
+        }
+    }
+}
+
+/// A single named column in a schema.
+#[derive(Debug, Clone)]
+pub struct FieldDef {
+    /// Field name as written in the schema (original casing).
+    pub name: String,
+    /// Zero‑based column index in the record.
+    pub index: usize,
+    /// How the column should be interpreted.
+    pub kind: FieldKind,
+    /// Whether the schema declared the kind with `type`.  Values written to
+    /// typed fields must match the kind; untyped fields store whatever the
+    /// YAML value is.
+    pub typed: bool,
+    /// Table this column refers to by ID (e.g. `SpellVisualKit.dbc`), as
+    /// declared with `ref:` in the schema.  The referenced table is keyed
+    /// by its column 0.
+    pub reference: Option<String>,
+    /// Bytes the column takes in the record: 4, or 1 or 2 for the byte and
+    /// short columns of some custom tables.
+    pub width: usize,
+    /// Byte offset of the column in the record, when the schema gives it
+    /// with `offset`; otherwise it follows the previous column.
+    pub offset: Option<usize>,
+    /// Names of the column's values, declared with `enum` or `flags`.
+    pub constants: Option<Constants>,
+}
+
+/// Names a schema gives the values of an integer field: with `enum` each
+/// name is one value, as in `School: Fire`; with `flags` each is a bit,
+/// and a patch combines them as in `Attributes: [PASSIVE, HIDDEN]`.
+/// Names are matched case‑insensitively.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constants {
+    pub flags: bool,
+    pub names: Vec<(String, u32)>,
+}
+
+impl Constants {
+    /// The value called `name`.
+    pub fn value(&self, name: &str) -> Option<u32> {
+        self.names
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, value)| value)
+    }
+
+    /// The number a patch value written by name stands for: a name, or for
+    /// flags a list of names and numbers, which are combined.  `None` when
+    /// `value` is not written that way; `Err` names what is unknown.
+    pub fn resolve(&self, value: &ValueType) -> Option<Result<ValueType, String>> {
+        let unknown = |name: &str| {
+            let known: Vec<&str> = self.names.iter().map(|(n, _)| n.as_str()).collect();
+            format!("unknown {} {:?}; known are {}", self.kind(), name, known.join(", "))
+        };
+        match value {
+            ValueType::String(name) => {
+                Some(self.value(name).map(|v| ValueType::Int(v as i64)).ok_or_else(|| unknown(name)))
+            }
+            ValueType::List(items) if self.flags => {
+                let mut bits = 0u32;
+                for item in items {
+                    let item_bits = match item {
+                        ValueType::String(name) => self.value(name).ok_or_else(|| unknown(name)),
+                        ValueType::Int(_) | ValueType::UInt(_) => item
+                            .as_u32()
+                            .ok_or_else(|| format!("{:?} does not fit in 32 bits", item)),
+                        other => Err(format!("{:?} is neither a flag name nor a whole number", other)),
+                    };
+                    match item_bits {
+                        Ok(item_bits) => bits |= item_bits,
+                        Err(err) => return Some(Err(err)),
+                    }
+                }
+                Some(Ok(ValueType::Int(bits as i64)))
+            }
+            _ => None,
+        }
+    }
+
+    /// `value` by name: the name of an enum value, or the names of the
+    /// flags it sets with any bits no flag names left as a number, e.g.
+    /// `PASSIVE | HIDDEN | 0x100`.  `None` when nothing about it has a
+    /// name.
+    pub fn describe(&self, value: u32) -> Option<String> {
+        if !self.flags {
+            return self.names.iter().find(|&&(_, v)| v == value).map(|(n, _)| n.clone());
+        }
+        let (parts, rest) = self.flag_names(value);
+        if parts.is_empty() {
+            return None;
+        }
+        let mut parts: Vec<String> = parts.into_iter().map(str::to_string).collect();
+        if rest != 0 {
+            parts.push(format!("{:#x}", rest));
+        }
+        Some(parts.join(" | "))
+    }
+
+    /// The names of the flags `value` sets, and the bits no flag names.
+    pub fn flag_names(&self, value: u32) -> (Vec<&str>, u32) {
+        let mut rest = value;
+        let mut names = Vec::new();
+        for (name, bits) in &self.names {
+            if *bits != 0 && value & bits == *bits {
+                names.push(name.as_str());
+                rest &= !bits;
+            }
+        }
+        (names, rest)
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.flags {
+            "flag"
+        } else {
+            "value"
+        }
+    }
+}
+
+/// Field layout for one DBC table.  Names are matched case‑insensitively.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    pub fields: Vec<FieldDef>,
+    by_name: HashMap<String, usize>,
+    /// Named starting points for `insert` entries, keyed by lower‑cased
+    /// name, each mapping fields to values.
+    templates: HashMap<String, HashMap<String, ValueType>>,
+    /// Values that mark a record disabled rather than deleted, for tables
+    /// whose client honours such flags.
+    tombstone: Option<HashMap<String, ValueType>>,
+    /// Values `insert` entries start from for the fields that declare a
+    /// `default`, keyed by field name (the bare name for `loc` fields).
+    defaults: HashMap<String, ValueType>,
+    /// Fields kept in step with the rest of their record, each with the
+    /// Rhai expression giving its value, in the order the schema lists
+    /// them (see [`crate::computed`]).
+    computed: Vec<(String, String)>,
+    /// Former names of fields, lower‑cased, with the name that replaced
+    /// each.  Patches using them still work but are warned about.
+    renamed: HashMap<String, String>,
+}
+
+impl Schema {
+    fn push(&mut self, field: FieldDef) {
+        self.by_name.insert(field.name.to_lowercase(), self.fields.len());
+        self.fields.push(field);
+    }
+
+    /// Look up the column index of a field by name (case‑insensitive).
+    /// Former names declared under `renamed` find the field too, and an
+    /// element of an array field may be written `Effect[2]` or `Effect2`
+    /// as well as `Effect_2`.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        let lookup = |name: String| {
+            let name = self.renamed.get(&name).map_or(name, |current| current.to_lowercase());
+            self.by_name.get(&name).map(|&i| self.fields[i].index)
+        };
+        let name = name.to_lowercase();
+        lookup(name.clone()).or_else(|| lookup(array_element(&name)?))
+    }
+
+    /// The current name of a field the schema declares renamed, if `name`
+    /// is one of its former names.
+    pub fn renamed(&self, name: &str) -> Option<&str> {
+        self.renamed.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    /// Every former field name (lower‑cased) with the current name.
+    pub fn former_names(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.renamed.iter().map(|(old, new)| (old.as_str(), new.as_str()))
+    }
+
+    /// The columns of the localized string `name` (e.g. `Name` for
+    /// `Name_lang_0`…`Name_lang_Mask`): its first locale slot and its mask.
+    /// Declared with `type: loc` or spelled out column by column.
+    pub fn loc_columns(&self, name: &str) -> Option<(usize, usize)> {
+        let first = self.column(&format!("{}_lang_0", name))?;
+        let mask = self.column(&format!("{}_lang_Mask", name))?;
+        (mask == first + LOCALE_SLOTS).then_some((first, mask))
+    }
+
+    /// The field at column `index`, if the schema names it.
+    pub fn field_at(&self, index: usize) -> Option<&FieldDef> {
+        self.fields.iter().find(|f| f.index == index)
+    }
+
+    /// The record template called `name` (case‑insensitive).
+    pub fn template(&self, name: &str) -> Option<&HashMap<String, ValueType>> {
+        self.templates.get(&name.to_lowercase())
+    }
+
+    /// The field values that soft‑delete a record, if the schema declares
+    /// tombstone semantics.
+    pub fn tombstone(&self) -> Option<&HashMap<String, ValueType>> {
+        self.tombstone.as_ref()
+    }
+
+    /// The declared `default` of every field that has one, which new
+    /// records start from before templates and the entry's own values.
+    pub fn defaults(&self) -> &HashMap<String, ValueType> {
+        &self.defaults
+    }
+
+    /// The fields the schema computes, each with its expression.
+    pub fn computed(&self) -> &[(String, String)] {
+        &self.computed
+    }
+
+    /// All fields declared as strings.
+    pub fn string_fields(&self) -> impl Iterator<Item = &FieldDef> {
+        self.fields.iter().filter(|f| f.kind == FieldKind::String)
+    }
+
+    /// Whether any field declares a `width` or `offset`, so the schema
+    /// describes the byte layout of the table's records (see
+    /// [`crate::dbc::RecordLayout`]).
+    pub fn declares_layout(&self) -> bool {
+        self.fields.iter().any(|f| f.width != 4 || f.offset.is_some())
+    }
+
+    /// All fields declaring a reference to another table.
+    pub fn reference_fields(&self) -> impl Iterator<Item = &FieldDef> {
+        self.fields.iter().filter(|f| f.reference.is_some())
+    }
+}
+
+/// `Effect[2]` or `Effect2` spelled the way array elements are named,
+/// `Effect_2`.
+fn array_element(name: &str) -> Option<String> {
+    if let Some((base, rest)) = name.split_once('[') {
+        let element: usize = rest.strip_suffix(']')?.trim().parse().ok()?;
+        return Some(format!("{}_{}", base.trim_end(), element));
+    }
+    let base = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if base.len() == name.len() || base.is_empty() || base.ends_with('_') {
+        return None;
+    }
+    Some(format!("{}_{}", base, &name[base.len()..]))
+}
+
+/// Number of locale slots of a `loc` field; a mask column follows them.
+pub const LOCALE_SLOTS: usize = 8;
+
+/// Parse one element of a `fields` sequence, starting at column `index`.
+/// An element may be a bare field name or a mapping with `name` and
+/// optional `type`, `ref`, `count`, `width`, `offset` and `default` (see
+/// `parse_fields`).  A `loc` field
+/// spans the columns of a localized string: `<name>_lang_0`…`_lang_7`
+/// strings and an int `<name>_lang_Mask`.  A field with `count: N` is an
+/// array of N columns named `<name>_1`…`<name>_N`.  `width` (1, 2 or 4
+/// bytes) and `offset` (in bytes) only matter for tables whose records are
+/// not packed 4‑byte cells; an array's elements each take `width` bytes
+/// and follow each other from `offset`.
+fn parse_field_entry(item: &Value, index: usize, path: &Path) -> Vec<FieldDef> {
+    match item {
+        Value::String(name) => vec![FieldDef {
+            name: name.clone(),
+            index,
+            kind: FieldKind::Int,
+            typed: false,
+            reference: None,
+            width: 4,
+            offset: None,
+            constants: None,
+        }],
+        Value::Mapping(map) => {
+            let Some(name) = map.get("name").and_then(|v| v.as_str()) else {
+                return Vec::new();
+            };
+            let width = match map.get("width") {
+                None => 4,
+                Some(width) => match width.as_u64() {
+                    Some(width @ (1 | 2 | 4)) => width as usize,
+                    _ => {
+                        warning!(
+                            "`width` of {} in schema {} must be 1, 2 or 4 – treating it as 4",
+                            name,
+                            path.display()
+                        );
+                        4
+                    }
+                },
+            };
+            let offset = map.get("offset").and_then(|v| v.as_u64()).map(|o| o as usize);
+            let declared = map.get("type").and_then(|v| v.as_str());
+            if declared.is_some_and(|t| t.eq_ignore_ascii_case("loc")) {
+                let mut fields: Vec<FieldDef> = (0..LOCALE_SLOTS)
+                    .map(|slot| FieldDef {
+                        name: format!("{}_lang_{}", name, slot),
+                        index: index + slot,
+                        kind: FieldKind::String,
+                        typed: true,
+                        reference: None,
+                        width: 4,
+                        offset: offset.map(|o| o + slot * 4),
+                        constants: None,
+                    })
+                    .collect();
+                fields.push(FieldDef {
+                    name: format!("{}_lang_Mask", name),
+                    index: index + LOCALE_SLOTS,
+                    kind: FieldKind::Int,
+                    typed: true,
+                    reference: None,
+                    width: 4,
+                    offset: offset.map(|o| o + LOCALE_SLOTS * 4),
+                    constants: None,
+                });
+                return fields;
+            }
+            let kind = match declared {
+                Some(t) => FieldKind::parse(t).unwrap_or_else(|| {
+                    warning!(
+                        "unknown field type '{}' for {} in schema {} – treating as int",
+                        t,
+                        name,
+                        path.display()
+                    );
+                    FieldKind::Int
+                }),
+                None => FieldKind::Int,
+            };
+            let reference = map
+                .get("ref")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            let constants = parse_constants(map, name, path);
+            let Some(count) = map.get("count") else {
+                return vec![FieldDef {
+                    name: name.to_string(),
+                    index,
+                    kind,
+                    typed: declared.is_some(),
+                    reference,
+                    width,
+                    offset,
+                    constants,
+                }];
+            };
+            let Some(count) = count.as_u64().filter(|&n| n > 0) else {
+                warning!(
+                    "`count` of {} in schema {} is not a positive number – skipping the field",
+                    name,
+                    path.display()
+                );
+                return Vec::new();
+            };
+            (1..=count as usize)
+                .map(|element| FieldDef {
+                    name: format!("{}_{}", name, element),
+                    index: index + element - 1,
+                    kind,
+                    typed: declared.is_some(),
+                    reference: reference.clone(),
+                    width,
+                    offset: offset.map(|o| o + (element - 1) * width),
+                    constants: constants.clone(),
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The `enum` or `flags` of the field `name`, a mapping of names to
+/// values.  Entries that are not a name and a 32‑bit number are warned
+/// about and left out.
+fn parse_constants(map: &serde_yaml::Mapping, name: &str, path: &Path) -> Option<Constants> {
+    let (flags, defs) = match (map.get("enum"), map.get("flags")) {
+        (Some(defs), None) => (false, defs),
+        (None, Some(defs)) => (true, defs),
+        (None, None) => return None,
+        (Some(_), Some(_)) => {
+            warning!("{} in schema {} has both `enum` and `flags` – ignoring both", name, path.display());
+            return None;
+        }
+    };
+    let key = if flags { "flags" } else { "enum" };
+    let Value::Mapping(defs) = defs else {
+        warning!("`{}` of {} in schema {} must map names to values – ignoring it", key, name, path.display());
+        return None;
+    };
+    let mut names = Vec::with_capacity(defs.len());
+    for (constant, value) in defs {
+        match (constant.as_str(), value.as_u64().and_then(|v| u32::try_from(v).ok())) {
+            (Some(constant), Some(value)) => names.push((constant.to_string(), value)),
+            _ => warning!(
+                "`{}` of {} in schema {} gives {:?} as {:?}, not a name and a number – skipping it",
+                key,
+                name,
+                path.display(),
+                constant,
+                value
+            ),
+        }
+    }
+    Some(Constants { flags, names })
+}
+
+/// Parse a `fields` sequence into `schema`.  Entries that cannot be
+/// parsed still take up their column so later fields stay aligned.  A
+/// `default` of an entry applies to each column of an array, and to a
+/// `loc` field as a whole.
+fn parse_fields(seq: &[Value], schema: &mut Schema, path: &Path) {
+    let mut index = 0;
+    for item in seq {
+        let fields = parse_field_entry(item, index, path);
+        index += fields.len().max(1);
+        if let Some(default) = item.get("default") {
+            let name = item.get("name").and_then(Value::as_str).unwrap_or_default();
+            let is_loc = item
+                .get("type")
+                .and_then(Value::as_str)
+                .is_some_and(|t| t.eq_ignore_ascii_case("loc"));
+            match serde_yaml::from_value::<ValueType>(default.clone()) {
+                Ok(value) if is_loc => {
+                    schema.defaults.insert(name.to_string(), value);
+                }
+                Ok(value) => {
+                    for field in &fields {
+                        schema.defaults.insert(field.name.clone(), value.clone());
+                    }
+                }
+                Err(err) => warning!("ignoring `default` of {} in schema {}: {}", name, path.display(), err),
+            }
+        }
+        for field in fields {
+            schema.push(field);
+        }
+    }
+}
+
+/// Parse the `renamed` mapping of a schema: former field names to the
+/// fields that replaced them.  Entries naming no field of the schema are
+/// skipped with a warning.
+fn parse_renamed(value: &Value, schema: &Schema, path: &Path) -> HashMap<String, String> {
+    let mut renamed = HashMap::new();
+    let Some(map) = value.as_mapping() else {
+        warning!("`renamed` in schema {} is not a mapping", path.display());
+        return renamed;
+    };
+    for (old, new) in map {
+        match (old.as_str(), new.as_str()) {
+            (Some(old), Some(new)) if schema.by_name.contains_key(&new.to_lowercase()) => {
+                renamed.insert(old.to_lowercase(), new.to_string());
+            }
+            _ => warning!(
+                "ignoring `renamed` entry {:?}: {:?} in schema {}, which must name a field of the schema",
+                old,
+                new,
+                path.display()
+            ),
+        }
+    }
+    renamed
+}
+
+/// Parse the `computed` mapping of a schema: field names to the Rhai
+/// expressions giving their values.  Entries naming no field of the
+/// schema, or whose expression is not a string, are skipped with a
+/// warning.
+fn parse_computed(value: &Value, schema: &Schema, path: &Path) -> Vec<(String, String)> {
+    let mut computed = Vec::new();
+    let Some(map) = value.as_mapping() else {
+        warning!("`computed` in schema {} is not a mapping", path.display());
+        return computed;
+    };
+    for (name, expression) in map {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        if schema.column(name).is_none() {
+            warning!("`computed` in schema {} names unknown field {}", path.display(), name);
+            continue;
+        }
+        match expression.as_str() {
+            Some(expression) => computed.push((name.to_string(), expression.to_string())),
+            None => warning!(
+                "ignoring `computed` {} in schema {}: the expression is not a string",
+                name,
+                path.display()
+            ),
+        }
+    }
+    computed
+}
+
+/// Parse the `templates` mapping of a schema: template names to mappings
+/// of field names (or column numbers) to values.  Malformed templates are
+/// skipped with a warning.
+fn parse_templates(value: &Value, path: &Path) -> HashMap<String, HashMap<String, ValueType>> {
+    let mut templates = HashMap::new();
+    let Some(map) = value.as_mapping() else {
+        warning!("`templates` in schema {} is not a mapping", path.display());
+        return templates;
+    };
+    for (name, fields) in map {
+        let name = match name {
+            Value::String(s) => s.clone(),
+            other => serde_yaml::to_string(other).unwrap_or_default().trim().to_string(),
+        };
+        match serde_yaml::from_value::<HashMap<String, ValueType>>(fields.clone()) {
+            Ok(values) => {
+                templates.insert(name.to_lowercase(), values);
+            }
+            Err(err) => warning!(
+                "skipping template {} in schema {}: {}",
+                name,
+                path.display(),
+                err
+            ),
+        }
+    }
+    templates
+}
+
+/// Load the schema for a given DBC file.  The schema directory must
+/// contain a YAML file whose name is derived from the DBC file name with
+/// `.yaml` appended (for example `Spell.dbc.yaml`).  The YAML can be either
+/// a sequence of field entries in column order, or a mapping with a
+/// `fields` entry that is such a sequence.  A field entry is either a plain
+/// name or a mapping such as `{ name: FileName, type: string }` or
+/// `{ name: CastKit, ref: SpellVisualKit.dbc }`; types are `int`,
+/// `int32`, `float`, `string`, `bool` and `loc`, `count` makes an array and
+/// `width` and `offset` place byte or short columns (see
+/// `parse_field_entry`).  The
+/// mapping form may also hold `templates`, named records `insert` entries
+/// can start from (see `parse_templates`), and `tombstone`, a mapping of
+/// fields to the values that mark a record disabled, which `delete` and
+/// `disable` entries then set instead of removing the row, and `renamed`,
+/// a mapping of former field names to current ones that patches may still
+/// use (see `migrate`), and `computed`, a mapping of fields to the
+/// expressions that derive them from the rest of the record (see
+/// [`crate::computed`]).  As a last resort a mapping of
+/// names to column indices is accepted.  Returns `None` if the file doesn't
+/// exist or cannot be parsed.
+pub fn load_schema(schema_dir: &Path, dbc_file_name: &str) -> Option<Schema> {
+    // A YAML file for this DBC in the provided schema directory takes
+    // precedence; otherwise the built‑in 1.12 definition is used, so
+    // --schema-dir only needs to hold the tables it overrides.
+    let yaml_name = format!("{}.yaml", dbc_file_name);
+    let path = schema_dir.join(&yaml_name);
+    if path.exists() {
+        if let Some(schema) = load_schema_file(&path) {
+            debug!("schema for {} read from {}", dbc_file_name, path.display());
+            return Some(schema);
+        }
+    }
+    let text = BUILTIN_SCHEMAS.get_file(&yaml_name)?.contents_utf8()?;
+    debug!("schema for {} is the built-in one", dbc_file_name);
+    parse_schema(text, &Path::new("<built-in>").join(&yaml_name))
+}
+
+/// Load one schema file (see [`load_schema`] for the format).  Returns
+/// `None` if it cannot be read or parsed.
+pub fn load_schema_file(path: &Path) -> Option<Schema> {
+    let text = fs::read_to_string(path).ok()?;
+    parse_schema(&text, path)
+}
+
+/// Parse the YAML `text` of a schema; `path` names it in warnings.
+fn parse_schema(text: &str, path: &Path) -> Option<Schema> {
+    let value: Value = match serde_yaml::from_str(text) {
+        Ok(v) => v,
+        Err(err) => {
+            warning!("failed to parse schema {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    let mut schema = Schema::default();
+    match value {
+        Value::Sequence(seq) => {
+            parse_fields(&seq, &mut schema, path);
+            Some(schema)
+        }
+        Value::Mapping(map) => {
+            // Try to find "fields" entry
+            if let Some(Value::Sequence(seq)) = map.get("fields") {
+                parse_fields(seq, &mut schema, path);
+                if let Some(templates) = map.get("templates") {
+                    schema.templates = parse_templates(templates, path);
+                }
+                if let Some(tombstone) = map.get("tombstone") {
+                    match serde_yaml::from_value::<HashMap<String, ValueType>>(tombstone.clone()) {
+                        Ok(values) if !values.is_empty() => schema.tombstone = Some(values),
+                        Ok(_) => warning!("`tombstone` in schema {} sets no fields", path.display()),
+                        Err(err) => warning!("ignoring `tombstone` in schema {}: {}", path.display(), err),
+                    }
+                }
+                if let Some(renamed) = map.get("renamed") {
+                    schema.renamed = parse_renamed(renamed, &schema, path);
+                }
+                if let Some(computed) = map.get("computed") {
+                    schema.computed = parse_computed(computed, &schema, path);
+                }
+                return Some(schema);
+            }
+            // Fallback: treat mapping keys as names and values as indices
+            for (k, v) in map.iter() {
+                if let (Value::String(name), Value::Number(num)) = (k, v) {
+                    if let Some(i) = num.as_u64() {
+                        schema.push(FieldDef {
+                            name: name.clone(),
+                            index: i as usize,
+                            kind: FieldKind::Int,
+                            typed: false,
+                            reference: None,
+                            width: 4,
+                            offset: None,
+                            constants: None,
+                        });
+                    }
+                }
+            }
+            (!schema.fields.is_empty()).then_some(schema)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{anyhow, Result};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, FLOAT, INT};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::dbc::pending_string_at;
+use crate::diagnostics::say;
+use crate::patch::{PatchEntry, ValueType};
+use crate::schema::Schema;
+
+/// Upper bound on the work a single script may do before it is aborted.
+/// Generous enough for loops over every row of Spell.dbc.
+const MAX_OPERATIONS: u64 = 50_000_000;
+
+/// Read‑only view of the table a script runs against.
+struct TableView {
+    records: Vec<Vec<u32>>,
+    rows: HashMap<u32, usize>,
+    string_block: Vec<u8>,
+    new_strings: Vec<String>,
+    schema: Option<Schema>,
+}
+
+impl TableView {
+    fn column(&self, field: &str) -> Option<usize> {
+        field.parse::<usize>().ok().or_else(|| self.schema.as_ref()?.column(field))
+    }
+
+    fn cell(&self, key: INT, field: &str) -> Result<u32, Box<EvalAltResult>> {
+        let col = self
+            .column(field)
+            .ok_or_else(|| format!("unknown field '{}'", field))?;
+        let row = u32::try_from(key)
+            .ok()
+            .and_then(|k| self.rows.get(&k))
+            .ok_or_else(|| format!("no record with key {}", key))?;
+        self.records[*row]
+            .get(col)
+            .copied()
+            .ok_or_else(|| format!("field '{}' out of range", field).into())
+    }
+}
+
+fn to_values(map: Map) -> Result<HashMap<String, ValueType>, Box<EvalAltResult>> {
+    let mut values = HashMap::new();
+    for (name, v) in map {
+        let value = if let Some(i) = v.clone().try_cast::<INT>() {
+            ValueType::Int(i)
+        } else if let Some(f) = v.clone().try_cast::<FLOAT>() {
+            ValueType::Float(f)
+        } else if let Some(b) = v.clone().try_cast::<bool>() {
+            ValueType::Bool(b)
+        } else if v.is_string() {
+            ValueType::String(v.into_string()?)
+        } else {
+            return Err(format!("unsupported value for field '{}': {}", name, v.type_name()).into());
+        };
+        values.insert(name.to_string(), value);
+    }
+    Ok(values)
+}
+
+fn to_key(key: INT) -> Result<u32, Box<EvalAltResult>> {
+    u32::try_from(key).map_err(|_| format!("key {} is not a valid record ID", key).into())
+}
+
+/// Run a patch script against the current state of a table and return the
+/// entries it generated, in the order it generated them.
+///
+/// Scripts are Rhai code.  They cannot touch the file system or the
+/// network, and are aborted after a fixed operation budget.  Besides the
+/// `TABLE` constant (the table name) they can call:
+///
+/// * `update(key, #{ Field: value, ... })`, `insert(#{ ... })` and
+///   `copy(key, #{ ... })` to emit entries;
+/// * `exists(key)`, `keys()`, `get(key, field)`, `get_float(key, field)` and
+///   `get_string(key, field)` to look up rows of the table as patched so far;
+/// * `column(field)` (-1 if unknown), `field_type(field)` and `fields()` to
+///   inspect the schema.
+pub fn run_script(
+    code: &str,
+    table: &str,
+    records: &[Vec<u32>],
+    string_block: &[u8],
+    new_strings: &[String],
+    schema: &Option<Schema>,
+) -> Result<Vec<PatchEntry>> {
+    let rows = records
+        .iter()
+        .enumerate()
+        .filter_map(|(i, r)| r.first().map(|&k| (k, i)))
+        .collect();
+    let view = Rc::new(TableView {
+        records: records.to_vec(),
+        rows,
+        string_block: string_block.to_vec(),
+        new_strings: new_strings.to_vec(),
+        schema: schema.clone(),
+    });
+    let emitted: Rc<RefCell<Vec<PatchEntry>>> = Rc::new(RefCell::new(Vec::new()));
+
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_string_size(64 * 1024);
+    engine.disable_symbol("eval");
+    // Scripts run on apply's workers, whose output is held back
+    engine.on_print(|text| say(text.to_string()));
+
+    let out = emitted.clone();
+    engine.register_fn("update", move |key: INT, values: Map| -> Result<(), Box<EvalAltResult>> {
+        out.borrow_mut().push(PatchEntry::Update {
+            key: to_key(key)?,
+            key_column: None,
+            values: to_values(values)?,
+        });
+        Ok(())
+    });
+    let out = emitted.clone();
+    engine.register_fn("insert", move |values: Map| -> Result<(), Box<EvalAltResult>> {
+        out.borrow_mut().push(PatchEntry::Insert {
+            key: None,
+            key_column: None,
+            template: None,
+            values: to_values(values)?,
+        });
+        Ok(())
+    });
+    let out = emitted.clone();
+    engine.register_fn("copy", move |key: INT, values: Map| -> Result<(), Box<EvalAltResult>> {
+        out.borrow_mut().push(PatchEntry::Copy {
+            key: to_key(key)?,
+            key_column: None,
+            new_key: None,
+            values: to_values(values)?,
+        });
+        Ok(())
+    });
+
+    let v = view.clone();
+    engine.register_fn("exists", move |key: INT| {
+        u32::try_from(key).is_ok_and(|k| v.rows.contains_key(&k))
+    });
+    let v = view.clone();
+    engine.register_fn("keys", move || -> Array {
+        v.records
+            .iter()
+            .filter_map(|r| r.first().map(|&k| Dynamic::from(k as INT)))
+            .collect()
+    });
+    let v = view.clone();
+    engine.register_fn("get", move |key: INT, field: &str| -> Result<INT, Box<EvalAltResult>> {
+        Ok(v.cell(key, field)? as INT)
+    });
+    let v = view.clone();
+    engine.register_fn(
+        "get_float",
+        move |key: INT, field: &str| -> Result<FLOAT, Box<EvalAltResult>> {
+            Ok(f32::from_bits(v.cell(key, field)?) as FLOAT)
+        },
+    );
+    let v = view.clone();
+    engine.register_fn(
+        "get_string",
+        move |key: INT, field: &str| -> Result<String, Box<EvalAltResult>> {
+            let offset = v.cell(key, field)?;
+            pending_string_at(&v.string_block, &v.new_strings, offset)
+                .ok_or_else(|| format!("string offset {} out of range", offset).into())
+        },
+    );
+    let v = view.clone();
+    engine.register_fn("column", move |field: &str| -> INT {
+        v.column(field).map_or(-1, |c| c as INT)
+    });
+    let v = view.clone();
+    engine.register_fn("field_type", move |field: &str| -> String {
+        let kind = v
+            .schema
+            .as_ref()
+            .and_then(|s| s.fields.iter().find(|f| f.name.eq_ignore_ascii_case(field)))
+            .map(|f| f.kind);
+        kind.map(|k| k.name().to_string()).unwrap_or_default()
+    });
+    let v = view.clone();
+    engine.register_fn("fields", move || -> Array {
+        v.schema
+            .as_ref()
+            .map(|s| s.fields.iter().map(|f| Dynamic::from(f.name.clone())).collect())
+            .unwrap_or_default()
+    });
+
+    let mut scope = Scope::new();
+    scope.push_constant("TABLE", table.to_string());
+    engine
+        .run_with_scope(&mut scope, code)
+        .map_err(|err| anyhow!("{}", err))?;
+    drop(engine);
+    let entries = Rc::try_unwrap(emitted)
+        .map_err(|_| anyhow!("script state still in use"))?
+        .into_inner();
+    Ok(entries)
+}
@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::dbc::{dbc_file_name, read_dbc_with_schema, DbcHeader};
+
+/// A table held in memory: its header, records and string block.
+#[derive(Debug, Clone)]
+pub struct DbcTable {
+    pub header: DbcHeader,
+    pub records: Vec<Vec<u32>>,
+    pub string_block: Vec<u8>,
+}
+
+/// Where tables are read from before patching.  A single patch set reads
+/// the DBC files themselves; later sets of a layered build read the tables
+/// the earlier sets produced from an [`Overlay`] instead, so nothing
+/// intermediate is written to disk.
+pub trait DbcSource {
+    /// Read the table stored at `path`.
+    fn read_table(&self, path: &Path) -> Result<DbcTable>;
+}
+
+/// Reads tables from their DBC files, with the schemas of a schema
+/// directory (see [`read_dbc_with_schema`]).
+pub struct FileSource {
+    schema_dir: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(schema_dir: &Path) -> Self {
+        FileSource {
+            schema_dir: schema_dir.to_path_buf(),
+        }
+    }
+}
+
+impl DbcSource for FileSource {
+    fn read_table(&self, path: &Path) -> Result<DbcTable> {
+        let (header, records, string_block) =
+            read_dbc_with_schema(path, &self.schema_dir).with_context(|| format!("Failed to read DBC file {:?}", path))?;
+        Ok(DbcTable {
+            header,
+            records,
+            string_block,
+        })
+    }
+}
+
+/// Tables produced in memory by earlier patch sets, on top of a base
+/// source that supplies every table they did not touch.  Tables are
+/// matched by file name, ignoring case, so a layer finds its table
+/// whichever directory the path points into.
+pub struct Overlay<S: DbcSource> {
+    base: S,
+    tables: HashMap<String, DbcTable>,
+}
+
+impl<S: DbcSource> Overlay<S> {
+    pub fn new(base: S) -> Self {
+        Overlay {
+            base,
+            tables: HashMap::new(),
+        }
+    }
+
+    /// Put `table` in front of the base as the current version of `name`.
+    pub fn insert(&mut self, name: &str, table: DbcTable) {
+        self.tables.insert(name.to_lowercase(), table);
+    }
+}
+
+impl<S: DbcSource> DbcSource for Overlay<S> {
+    fn read_table(&self, path: &Path) -> Result<DbcTable> {
+        match self.tables.get(&dbc_file_name(path)?.to_lowercase()) {
+            Some(table) => Ok(table.clone()),
+            None => self.base.read_table(path),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+/// Letters of variables that read one of the spell's three effects, as in
+/// `$s1` or `$o2`; without an index they read the first.
+const EFFECT_VARIABLES: &str = "smotaxebqf";
+
+/// Letters of variables of the spell as a whole, as in `$d` or `$h`.
+const SPELL_VARIABLES: &str = "dhnurivz";
+
+/// Whether `field` of `table` is tooltip text the client runs through its
+/// token parser: the spell and aura descriptions in every locale.
+pub fn is_spell_text_field(table: &str, field: &str) -> bool {
+    table.eq_ignore_ascii_case("Spell.dbc")
+        && (field.starts_with("Description_lang_") || field.starts_with("AuraDescription_lang_"))
+        && !field.ends_with("_Mask")
+}
+
+/// Describe every `$` token of `text` the 1.12 client's tooltip parser
+/// cannot read, and would show as raw text ("Deals $ s1 damage"), with the
+/// token and its 1‑based character position.  Accepted tokens are
+///
+/// - variables, `$d`, `$h`, and effect variables with an optional index
+///   from 1 to 3, `$s1`, `$o2`, `$t3`;
+/// - the same read from another spell by id, `$12345s1`;
+/// - scaled by a `/N;` or `*N;` prefix, `$/10;s1`, `$*5;12345s1`;
+/// - singular/plural and male/female choices, `$lpoint:points;`,
+///   `$ghis:her;`.
+pub fn check_spell_text(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut problems = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '$' {
+            i += 1;
+            continue;
+        }
+        match parse_token(&chars, i + 1) {
+            Ok(end) => i = end,
+            Err((end, why)) => {
+                let end = end.clamp(i + 1, chars.len());
+                let token: String = chars[i..end].iter().collect();
+                problems.push(format!("{:?} at character {}: {}", token, i + 1, why));
+                i = end;
+            }
+        }
+    }
+    problems
+}
+
+/// Read the token whose `$` is just before `i` and return where it ends,
+/// or where reading stopped and why.
+fn parse_token(chars: &[char], mut i: usize) -> Result<usize, (usize, String)> {
+    let digits = |from: usize| chars[from.min(chars.len())..].iter().take_while(|c| c.is_ascii_digit()).count();
+
+    // A `/N;` or `*N;` scale and a spell id, in either order
+    let (mut scaled, mut spell_id) = (false, false);
+    loop {
+        match chars.get(i) {
+            Some(&op @ ('/' | '*')) if !scaled => {
+                let n = digits(i + 1);
+                if n == 0 {
+                    return Err((i + 1, format!("`{}` must be followed by a number and `;`", op)));
+                }
+                if chars.get(i + 1 + n) != Some(&';') {
+                    return Err((i + 1 + n, format!("the number after `{}` must end with `;`", op)));
+                }
+                if op == '/' && chars[i + 1..i + 1 + n].iter().all(|&c| c == '0') {
+                    return Err((i + 2 + n, "divides by zero".to_string()));
+                }
+                scaled = true;
+                i += n + 2;
+            }
+            Some(c) if c.is_ascii_digit() && !spell_id => {
+                spell_id = true;
+                i += digits(i);
+            }
+            _ => break,
+        }
+    }
+
+    let Some(&c) = chars.get(i) else {
+        return Err((i, "the text ends before the variable".to_string()));
+    };
+    let letter = c.to_ascii_lowercase();
+    if letter == 'l' || letter == 'g' {
+        let choice = |from: usize, end: char| {
+            chars[from.min(chars.len())..]
+                .iter()
+                .position(|&c| c == end || c == '$' || c == ';' || c == ':')
+                .filter(|&p| chars[from + p] == end)
+                .map(|p| from + p + 1)
+        };
+        let form = if letter == 'l' { "singular:plural" } else { "male:female" };
+        return choice(i + 1, ':')
+            .and_then(|after| choice(after, ';'))
+            .ok_or((i + 1, format!("`${}` must be written `${}{};`", c, c, form)));
+    }
+    if EFFECT_VARIABLES.contains(letter) {
+        return match chars.get(i + 1) {
+            Some('1'..='3') => Ok(i + 2),
+            Some(d) if d.is_ascii_digit() => Err((i + 2, "spells have three effects, numbered 1 to 3".to_string())),
+            _ => Ok(i + 1),
+        };
+    }
+    if SPELL_VARIABLES.contains(letter) {
+        return Ok(i + 1);
+    }
+    if c.is_ascii_alphabetic() {
+        Err((i + 1, format!("`{}` is not a variable the client knows", c)))
+    } else {
+        Err((i + 1, "`$` must be followed by a variable such as `$s1` or `$d`".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stock_token_forms_are_accepted() {
+        let text = "Deals $s1 to $S2 Fire damage over $d, $/10;s1 per $t1 sec \
+                    and $12345o2 more ($*5;12345s3) within $a1 $lyard:yards; of $ghim:her;.";
+        assert!(check_spell_text(text).is_empty(), "{:?}", check_spell_text(text));
+    }
+
+    #[test]
+    fn malformed_tokens_are_reported() {
+        let problems = |text: &str| check_spell_text(text).len();
+        assert_eq!(problems("reduced by $ s1%"), 1);
+        assert_eq!(problems("increases health by $1%."), 1);
+        assert_eq!(problems("$/10s1 per second"), 1);
+        assert_eq!(problems("$/0;s1"), 1);
+        assert_eq!(problems("$s4 damage"), 1);
+        assert_eq!(problems("$k"), 1);
+        assert_eq!(problems("lasts $lsec:secs"), 1);
+        assert_eq!(problems("ends with $"), 1);
+        assert_eq!(problems("${$m1*3} Fire"), 1);
+    }
+}
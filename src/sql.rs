@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::dbc::{dbc_file_name, read_dbc_with_schema, string_at};
+use crate::schema::{load_schema, FieldKind};
+
+/// Records per `INSERT` statement, to keep statements within server
+/// packet limits.
+const ROWS_PER_INSERT: usize = 500;
+
+/// Database the SQL is written for.  They differ in how strings are
+/// escaped and in column type names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SqlDialect {
+    /// MySQL or MariaDB, as vanilla server cores use for the world database
+    Mysql,
+    Sqlite,
+}
+
+impl SqlDialect {
+    /// `s` as a string literal.  MySQL reads backslashes as escapes, and
+    /// the client's paths are full of them.
+    fn quote(self, s: &str) -> String {
+        let s = s.replace('\'', "''");
+        match self {
+            SqlDialect::Mysql => format!("'{}'", s.replace('\\', "\\\\")),
+            SqlDialect::Sqlite => format!("'{}'", s),
+        }
+    }
+
+    /// The column type for values of `kind`.
+    fn column_type(self, kind: FieldKind) -> &'static str {
+        match (self, kind) {
+            (_, FieldKind::String) => "TEXT",
+            (SqlDialect::Mysql, FieldKind::Int) => "INT UNSIGNED",
+            (SqlDialect::Mysql, FieldKind::Int32) => "INT",
+            (SqlDialect::Mysql, FieldKind::Bool) => "TINYINT",
+            (SqlDialect::Mysql, FieldKind::Float) => "FLOAT",
+            (SqlDialect::Sqlite, FieldKind::Float) => "REAL",
+            (SqlDialect::Sqlite, FieldKind::Int | FieldKind::Int32 | FieldKind::Bool) => "INTEGER",
+        }
+    }
+}
+
+/// Default SQL table for a DBC file name: `dbc_` and the lower‑cased
+/// table name, e.g. `dbc_spellvisualkit` for `SpellVisualKit.dbc`.
+pub fn default_sql_table(file_name: &str) -> String {
+    let stem = file_name.strip_suffix(".dbc").unwrap_or(file_name);
+    format!("dbc_{}", stem.to_lowercase())
+}
+
+/// A cell as an SQL literal for a column of `kind`.
+fn literal(dialect: SqlDialect, kind: FieldKind, value: u32, block: &[u8]) -> String {
+    match kind {
+        FieldKind::String => dialect.quote(&string_at(block, value).unwrap_or_default()),
+        FieldKind::Float => {
+            let f = f32::from_bits(value);
+            if f.is_finite() {
+                format!("{:?}", f)
+            } else {
+                "NULL".to_string()
+            }
+        }
+        FieldKind::Int32 => (value as i32).to_string(),
+        FieldKind::Int | FieldKind::Bool => value.to_string(),
+    }
+}
+
+/// Write every record of a DBC table, typically one `apply` produced, as
+/// SQL into `out`: a `CREATE TABLE IF NOT EXISTS` for `sql_table` with the
+/// schema's field names as columns, a `DELETE` of what the table held and
+/// batched `INSERT`s, so loading the file leaves the table equal to the
+/// DBC.  Strings are written as text and floats as numbers, or `NULL` for
+/// infinities and NaNs; columns the schema does not name are called
+/// `field_<N>`.
+pub fn export_sql_command(
+    dbc_path: &Path,
+    schema_dir: &Path,
+    sql_table: &str,
+    dialect: SqlDialect,
+    out: &Path,
+) -> Result<()> {
+    let table = dbc_file_name(dbc_path)?;
+    let (header, records, string_block) =
+        read_dbc_with_schema(dbc_path, schema_dir).with_context(|| format!("Failed to read DBC file {:?}", dbc_path))?;
+    let schema = load_schema(schema_dir, &table);
+    if schema.is_none() {
+        warning!("no schema for {}, columns are written as field_<N> with raw values", table);
+    }
+    let field = |col: usize| schema.as_ref().and_then(|s| s.field_at(col));
+    let columns: Vec<(String, FieldKind)> = (0..header.field_count as usize)
+        .map(|col| match field(col) {
+            Some(f) => (f.name.clone(), f.kind),
+            None => (format!("field_{}", col), FieldKind::Int),
+        })
+        .collect();
+
+    let mut sql = format!(
+        "-- {} record(s) of {}, written by wow_dbc_patcher export-sql\n",
+        records.len(),
+        table
+    );
+    let definitions: Vec<String> = columns
+        .iter()
+        .map(|(name, kind)| format!("  `{}` {}", name, dialect.column_type(*kind)))
+        .collect();
+    sql.push_str(&format!(
+        "CREATE TABLE IF NOT EXISTS `{}` (\n{}\n);\n",
+        sql_table,
+        definitions.join(",\n")
+    ));
+    sql.push_str(&format!("DELETE FROM `{}`;\n", sql_table));
+    let names: Vec<String> = columns.iter().map(|(name, _)| format!("`{}`", name)).collect();
+    for batch in records.chunks(ROWS_PER_INSERT) {
+        sql.push_str(&format!("INSERT INTO `{}` ({}) VALUES\n", sql_table, names.join(", ")));
+        let rows: Vec<String> = batch
+            .iter()
+            .map(|record| {
+                let cells: Vec<String> = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(col, (_, kind))| {
+                        literal(dialect, *kind, record.get(col).copied().unwrap_or(0), &string_block)
+                    })
+                    .collect();
+                format!("({})", cells.join(", "))
+            })
+            .collect();
+        sql.push_str(&rows.join(",\n"));
+        sql.push_str(";\n");
+    }
+    fs::write(out, sql).with_context(|| format!("Failed to write {:?}", out))?;
+    println!(
+        "Wrote {} record(s) of {} to {} as table {}",
+        records.len(),
+        table,
+        out.display(),
+        sql_table
+    );
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use crate::changelog::NotedChange;
+
+/// Counts of what applying patches did to one table.  Updates, inserts,
+/// copies and deletes count records, a record disabled by its tombstone
+/// as deleted; `skipped` counts entries that changed nothing
+/// (missing keys, duplicate keys, entries for another table), of which
+/// `missing` and `duplicates` break down the first two.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TableStats {
+    pub read: usize,
+    pub updated: usize,
+    pub inserted: usize,
+    pub copied: usize,
+    pub deleted: usize,
+    pub skipped: usize,
+    pub missing: usize,
+    pub duplicates: usize,
+    pub strings_added: usize,
+    pub bytes_written: u64,
+}
+
+/// The outcome of applying patches to one table.
+#[derive(Debug)]
+pub struct AppliedTable {
+    pub file_name: String,
+    pub out_path: PathBuf,
+    pub rows_added: usize,
+    pub string_growth: u64,
+    pub stats: TableStats,
+    /// Entries with a description or an issue, for the changelog
+    pub changelog: Vec<NotedChange>,
+}
+
+/// Print one line per table with the counts from `TableStats`, followed by
+/// a total, so a table nothing was applied to stands out.
+pub fn print_summary(tables: &[AppliedTable]) {
+    if tables.is_empty() {
+        return;
+    }
+    let width = tables
+        .iter()
+        .map(|t| t.file_name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Table".len());
+    println!();
+    println!(
+        "{:<width$} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10}",
+        "Table", "read", "updated", "inserted", "copied", "deleted", "skipped", "strings", "bytes",
+    );
+    let mut total = TableStats::default();
+    for table in tables {
+        let s = &table.stats;
+        println!(
+            "{:<width$} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10}",
+            table.file_name,
+            s.read,
+            s.updated,
+            s.inserted,
+            s.copied,
+            s.deleted,
+            s.skipped,
+            s.strings_added,
+            s.bytes_written,
+        );
+        total.read += s.read;
+        total.updated += s.updated;
+        total.inserted += s.inserted;
+        total.copied += s.copied;
+        total.deleted += s.deleted;
+        total.skipped += s.skipped;
+        total.strings_added += s.strings_added;
+        total.bytes_written += s.bytes_written;
+    }
+    if tables.len() > 1 {
+        println!(
+            "{:<width$} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10}",
+            "total",
+            total.read,
+            total.updated,
+            total.inserted,
+            total.copied,
+            total.deleted,
+            total.skipped,
+            total.strings_added,
+            total.bytes_written,
+        );
+    }
+}
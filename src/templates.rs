@@ -0,0 +1,166 @@
+use anyhow::{anyhow, bail, Result};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Take the `templates` of a patch file section into `templates`: each is
+/// a named set of `Table.dbc:` changes with `${name}` placeholders.
+pub fn collect_templates(section: &mut Value, templates: &mut HashMap<String, Value>, path: &Path) -> Result<()> {
+    let Some(defs) = section.as_mapping_mut().and_then(|m| m.remove("templates")) else {
+        return Ok(());
+    };
+    let Value::Mapping(defs) = defs else {
+        bail!("`templates` in {:?} must map template names to tables and their changes", path);
+    };
+    for (name, body) in defs {
+        let name = name
+            .as_str()
+            .ok_or_else(|| anyhow!("Template names in {:?} must be strings, got {:?}", path, name))?;
+        if !body.is_mapping() {
+            bail!("Template {} in {:?} must map tables to their changes", name, path);
+        }
+        if templates.insert(name.to_string(), body).is_some() {
+            bail!("Template {} is defined twice in {:?}", name, path);
+        }
+    }
+    Ok(())
+}
+
+/// The text a placeholder is replaced with inside a longer string.
+fn param_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Why placeholder `name` cannot be filled when it has no parameter.
+fn unset(name: &str) -> String {
+    format!("uses ${{{}}}, which is not set", name)
+}
+
+/// Replace the placeholders in `value`.  A string that is a single
+/// placeholder becomes the parameter itself, so `"${id}"` stays a number;
+/// placeholders within longer strings are replaced by the parameter's
+/// text.  With `keep_unknown` a placeholder with no parameter is left as
+/// it is, such as a `${$m1*3}` formula of spell text; otherwise `Err`
+/// says which placeholder cannot be filled.
+fn substitute(value: &Value, params: &Mapping, keep_unknown: bool) -> Result<Value, String> {
+    match value {
+        Value::String(s) => {
+            let whole = s.strip_prefix("${").and_then(|rest| rest.strip_suffix('}'));
+            if let Some(name) = whole.filter(|name| !name.contains(['$', '{', '}'])) {
+                return match params.get(name) {
+                    Some(param) => Ok(param.clone()),
+                    None if keep_unknown => Ok(value.clone()),
+                    None => Err(unset(name)),
+                };
+            }
+            let (mut out, mut rest) = (String::new(), s.as_str());
+            while let Some(start) = rest.find("${") {
+                let Some(len) = rest[start..].find('}') else {
+                    break;
+                };
+                let name = &rest[start + 2..start + len];
+                let Some(param) = params.get(name) else {
+                    if !keep_unknown {
+                        return Err(unset(name));
+                    }
+                    out.push_str(&rest[..start + len + 1]);
+                    rest = &rest[start + len + 1..];
+                    continue;
+                };
+                let text = param_text(param)
+                    .ok_or_else(|| format!("puts ${{{}}} inside a string, but it is not a single value", name))?;
+                out.push_str(&rest[..start]);
+                out.push_str(&text);
+                rest = &rest[start + len + 1..];
+            }
+            out.push_str(rest);
+            Ok(Value::String(out))
+        }
+        Value::Sequence(seq) => Ok(Value::Sequence(
+            seq.iter().map(|v| substitute(v, params, keep_unknown)).collect::<Result<_, _>>()?,
+        )),
+        Value::Mapping(map) => {
+            let mut out = Mapping::new();
+            for (k, v) in map {
+                out.insert(substitute(k, params, keep_unknown)?, substitute(v, params, keep_unknown)?);
+            }
+            Ok(Value::Mapping(out))
+        }
+        other => Ok(other.clone()),
+    }
+}
+
+/// Expand the `instantiate` list of a patch file section: each item names
+/// a `template` and the `params` to fill its placeholders with, and gives
+/// a mapping of tables to changes.  Placeholders without a param are
+/// filled from the file's `vars`.
+pub fn expand_instances(
+    section: &mut Value,
+    templates: &HashMap<String, Value>,
+    vars: &Mapping,
+    path: &Path,
+) -> Result<Vec<Value>> {
+    let Some(instances) = section.as_mapping_mut().and_then(|m| m.remove("instantiate")) else {
+        return Ok(Vec::new());
+    };
+    let Value::Sequence(instances) = instances else {
+        bail!("`instantiate` in {:?} must be a list of templates and their params", path);
+    };
+    let mut expanded = Vec::with_capacity(instances.len());
+    for (i, instance) in instances.iter().enumerate() {
+        let name = instance
+            .get("template")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("Instance {} in {:?} names no `template`", i + 1, path))?;
+        let body = templates
+            .get(name)
+            .ok_or_else(|| anyhow!("Instance {} in {:?} uses unknown template {}", i + 1, path, name))?;
+        let mut params = vars.clone();
+        match instance.get("params") {
+            Some(Value::Mapping(own)) => params.extend(own.clone()),
+            None => {}
+            Some(_) => bail!("`params` of instance {} in {:?} must be a mapping", i + 1, path),
+        }
+        let value = substitute(body, &params, false)
+            .map_err(|err| anyhow!("Template {} {} (instance {} in {:?})", name, err, i + 1, path))?;
+        expanded.push(value);
+    }
+    Ok(expanded)
+}
+
+/// Take the `vars` of a patch file section into `vars`: named numbers,
+/// texts or booleans that `${name}` uses anywhere in the file stand for.
+pub fn collect_vars(section: &mut Value, vars: &mut Mapping, path: &Path) -> Result<()> {
+    let Some(defs) = section.as_mapping_mut().and_then(|m| m.remove("vars")) else {
+        return Ok(());
+    };
+    let Value::Mapping(defs) = defs else {
+        bail!("`vars` in {:?} must map names to values", path);
+    };
+    for (name, value) in defs {
+        let name = name
+            .as_str()
+            .ok_or_else(|| anyhow!("Variable names in {:?} must be strings, got {:?}", path, name))?;
+        if param_text(&value).is_none() {
+            bail!("Variable {} in {:?} must be a number, text or boolean", name, path);
+        }
+        if vars.insert(Value::String(name.to_string()), value).is_some() {
+            bail!("Variable {} is declared twice in {:?}", name, path);
+        }
+    }
+    Ok(())
+}
+
+/// Replace the `${name}` uses of `vars` in `section`, leaving other
+/// placeholders as they are.
+pub fn substitute_vars(section: &mut Value, vars: &Mapping, path: &Path) -> Result<()> {
+    if !vars.is_empty() {
+        *section = substitute(section, vars, true).map_err(|err| anyhow!("Patch file {:?} {}", path, err))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,303 @@
+use anyhow::{anyhow, Result};
+use rhai::{Dynamic, Engine, Scope, FLOAT, INT};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::l10n::is_per_locale;
+use crate::patch::{Condition, PatchEntry, PatchFile, ValueType};
+
+/// Upper bound on the work one custom unit expression may do.
+const MAX_OPERATIONS: u64 = 10_000;
+
+/// Spell schools by the number Spell.dbc's `School` column stores.
+pub const SCHOOLS: [&str; 7] = ["physical", "holy", "fire", "nature", "frost", "shadow", "arcane"];
+
+/// Converts the number written with a unit into the value the DBC stores.
+pub type Transform = Box<dyn Fn(&ValueType) -> Result<ValueType, String>>;
+
+/// Unit conversions for patch values written as a one‑key mapping, e.g.
+/// `Duration: {seconds: 8}`.  Names are matched case‑insensitively.
+pub struct Transformers {
+    by_name: HashMap<String, Transform>,
+}
+
+/// The number a unit was given, or why it is not one.
+fn number(value: &ValueType) -> Result<f64, String> {
+    match *value {
+        ValueType::Int(v) => Ok(v as f64),
+        ValueType::UInt(v) => Ok(v as f64),
+        ValueType::Float(v) => Ok(v),
+        _ => Err("expected a number".to_string()),
+    }
+}
+
+/// A conversion to whole milliseconds, which durations, cast times and
+/// cooldowns are stored in.
+fn to_milliseconds(factor: f64) -> Transform {
+    Box::new(move |value| Ok(ValueType::Int((number(value)? * factor).round() as i64)))
+}
+
+impl Transformers {
+    /// The built‑in units:
+    ///
+    /// - `milliseconds`, `seconds`, `minutes` and `hours`, stored as
+    ///   milliseconds;
+    /// - `degrees`, stored as radians (facings and cone angles);
+    /// - `points`, a spell effect amount as the tooltip shows it, stored as
+    ///   `EffectBasePoints`, which holds one less because the client adds
+    ///   `EffectDieSides` (1 for a fixed amount).
+    pub fn builtin() -> Transformers {
+        let mut units = Transformers { by_name: HashMap::new() };
+        units.register("milliseconds", to_milliseconds(1.0));
+        units.register("seconds", to_milliseconds(1_000.0));
+        units.register("minutes", to_milliseconds(60_000.0));
+        units.register("hours", to_milliseconds(3_600_000.0));
+        units.register("degrees", Box::new(|value| Ok(ValueType::Float(number(value)?.to_radians()))));
+        units.register(
+            "points",
+            Box::new(|value| match *value {
+                ValueType::Int(v) => Ok(ValueType::Int(v - 1)),
+                ValueType::UInt(v) => Ok(ValueType::Int(v as i64 - 1)),
+                _ => Err("expected a whole number".to_string()),
+            }),
+        );
+        units
+    }
+
+    /// The built‑in units plus the project's own, each a Rhai expression
+    /// of `value`, e.g. `ticks: "value * 3000"`.  A project unit replaces a
+    /// built‑in one of the same name.
+    pub fn with_custom(custom: &BTreeMap<String, String>) -> Result<Transformers> {
+        let mut units = Transformers::builtin();
+        for (name, expression) in custom {
+            let mut engine = Engine::new();
+            engine.set_max_operations(MAX_OPERATIONS);
+            let ast = engine
+                .compile_expression(expression)
+                .map_err(|e| anyhow!("Invalid expression for unit {:?} in the manifest: {}", name, e))?;
+            units.register(
+                name,
+                Box::new(move |value| {
+                    let mut scope = Scope::new();
+                    scope.push(
+                        "value",
+                        match value {
+                            ValueType::Int(v) => Dynamic::from(*v as INT),
+                            ValueType::UInt(v) => Dynamic::from(*v as INT),
+                            ValueType::Float(v) => Dynamic::from(*v as FLOAT),
+                            ValueType::Bool(v) => Dynamic::from(*v),
+                            ValueType::String(v) => Dynamic::from(v.clone()),
+                            ValueType::Unit(_) => return Err("units cannot be nested".to_string()),
+                            ValueType::List(_) => return Err("expected a single value".to_string()),
+                        },
+                    );
+                    let result: Dynamic = engine
+                        .eval_ast_with_scope(&mut scope, &ast)
+                        .map_err(|e| e.to_string())?;
+                    if let Some(v) = result.clone().try_cast::<INT>() {
+                        Ok(ValueType::Int(v))
+                    } else if let Some(v) = result.clone().try_cast::<FLOAT>() {
+                        Ok(ValueType::Float(v))
+                    } else if let Some(v) = result.clone().try_cast::<bool>() {
+                        Ok(ValueType::Bool(v))
+                    } else if result.is_string() {
+                        result.into_string().map(ValueType::String).map_err(|t| format!("unexpected {}", t))
+                    } else {
+                        Err(format!("the expression gave a {}", result.type_name()))
+                    }
+                }),
+            );
+        }
+        Ok(units)
+    }
+
+    /// Add the `school_color` unit for the project's colour theme: the
+    /// value is a school, by name or by the number Spell.dbc's `School`
+    /// column stores (`{school_color: fire}` or `{school_color: 2}`), and
+    /// gives the colour `colors` assigns it, so every encounter warns
+    /// about fire the same way.
+    pub fn with_school_colors(mut self, colors: &BTreeMap<String, ValueType>) -> Result<Transformers> {
+        let mut by_school: HashMap<usize, ValueType> = HashMap::new();
+        for (name, color) in colors {
+            let school = SCHOOLS
+                .iter()
+                .position(|s| s.eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow!("Unknown spell school {:?} in school_colors; schools are {}", name, SCHOOLS.join(", ")))?;
+            by_school.insert(school, color.clone());
+        }
+        if by_school.is_empty() {
+            return Ok(self);
+        }
+        self.register(
+            "school_color",
+            Box::new(move |value| {
+                let school = match value {
+                    ValueType::String(name) => SCHOOLS.iter().position(|s| s.eq_ignore_ascii_case(name)),
+                    ValueType::Int(v) => usize::try_from(*v).ok().filter(|&v| v < SCHOOLS.len()),
+                    ValueType::UInt(v) => usize::try_from(*v).ok().filter(|&v| v < SCHOOLS.len()),
+                    _ => None,
+                }
+                .ok_or_else(|| format!("expected a spell school ({}) or its number 0 to 6", SCHOOLS.join(", ")))?;
+                by_school
+                    .get(&school)
+                    .cloned()
+                    .ok_or_else(|| format!("the manifest's school_colors has no colour for {}", SCHOOLS[school]))
+            }),
+        );
+        Ok(self)
+    }
+
+    /// Make `name` usable as a unit in patch values.
+    pub fn register(&mut self, name: &str, transform: Transform) {
+        self.by_name.insert(name.to_lowercase(), transform);
+    }
+
+    /// Convert a value written with a unit; other values are returned as
+    /// they are.
+    pub fn resolve(&self, value: &ValueType) -> Result<ValueType, String> {
+        let ValueType::Unit(map) = value else {
+            return Ok(value.clone());
+        };
+        let mut pairs = map.iter();
+        let (Some((name, amount)), None) = (pairs.next(), pairs.next()) else {
+            return Err(format!("a unit value has exactly one key, e.g. {{seconds: 8}}; got {} keys", map.len()));
+        };
+        let transform = self.by_name.get(&name.to_lowercase()).ok_or_else(|| {
+            let mut known: Vec<&String> = self.by_name.keys().collect();
+            known.sort();
+            format!(
+                "unknown unit '{}'; known units are {}",
+                name,
+                known.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")
+            )
+        })?;
+        transform(amount).map_err(|err| format!("{{{}: {:?}}}: {}", name, amount, err))
+    }
+
+    /// Convert the values written with a unit in entry `i` of `table`,
+    /// and in the entries nested in it.
+    fn resolve_entry(&self, change: &mut PatchEntry, i: usize, origin: &str, table: &str) -> Result<()> {
+        if let PatchEntry::Zone { changes, .. } = change {
+            for change in changes {
+                self.resolve_entry(change, i, origin, table)?;
+            }
+            return Ok(());
+        }
+        // Conditions compare against stored values, so `{seconds: 8}`
+        // in a `where` means what it does in `values`
+        let (conditions, values) = match change {
+            PatchEntry::UpdateWhere { conditions, values } => (Some(conditions), Some(values)),
+            PatchEntry::DeleteWhere { conditions, .. } => (Some(conditions), None),
+            other => (None, other.values_mut()),
+        };
+        let conditions = conditions.into_iter().flatten().filter_map(|(field, condition)| match condition {
+            Condition::Equals(value) => Some((field, value)),
+            _ => None,
+        });
+        for (field, value) in conditions.chain(values.into_iter().flatten()) {
+            // Texts per locale and operations on the current value are
+            // applied by `apply`
+            let applied_later =
+                is_per_locale(value) || value.bit_operations().is_some() || value.number_operations().is_some();
+            if matches!(value, ValueType::Unit(_)) && !applied_later {
+                *value = self.resolve(value).map_err(|err| {
+                    anyhow!(
+                        "Invalid value for '{}' in {} entry {}: {} (patch file: {})",
+                        field,
+                        table,
+                        i + 1,
+                        err,
+                        origin
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert every value written with a unit in loaded patch files.
+    pub fn resolve_patches(&self, patches: &mut [PatchFile]) -> Result<()> {
+        for pf in patches {
+            for i in 0..pf.changes.len() {
+                let origin = pf.entry_origin(i);
+                self.resolve_entry(&mut pf.changes[i], i, &origin, &pf.dbc)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apply::parse_patch_str;
+    use crate::ids::IdMap;
+    use std::path::Path;
+
+    fn unit(name: &str, value: ValueType) -> ValueType {
+        ValueType::Unit(BTreeMap::from([(name.to_string(), value)]))
+    }
+
+    #[test]
+    fn builtin_units_convert_to_stored_values() {
+        let units = Transformers::builtin();
+        assert_eq!(units.resolve(&unit("Seconds", ValueType::Float(1.5))), Ok(ValueType::Int(1500)));
+        assert_eq!(units.resolve(&unit("minutes", ValueType::UInt(2))), Ok(ValueType::Int(120_000)));
+        assert_eq!(units.resolve(&unit("points", ValueType::Int(10))), Ok(ValueType::Int(9)));
+        let Ok(ValueType::Float(radians)) = units.resolve(&unit("degrees", ValueType::Int(180))) else {
+            panic!("degrees are stored as a float");
+        };
+        assert!((radians - std::f64::consts::PI).abs() < 1e-12);
+        // Values without a unit pass through
+        assert_eq!(units.resolve(&ValueType::Int(7)), Ok(ValueType::Int(7)));
+        assert!(units.resolve(&unit("fortnights", ValueType::Int(1))).unwrap_err().contains("unknown unit"));
+        assert!(units.resolve(&unit("points", ValueType::Float(1.5))).is_err());
+    }
+
+    #[test]
+    fn custom_units_evaluate_their_expression() {
+        let custom = BTreeMap::from([
+            ("ticks".to_string(), "value * 3000".to_string()),
+            // Replaces the built-in unit
+            ("seconds".to_string(), "value * 2".to_string()),
+        ]);
+        let units = Transformers::with_custom(&custom).unwrap();
+        assert_eq!(units.resolve(&unit("ticks", ValueType::Int(4))), Ok(ValueType::Int(12_000)));
+        assert_eq!(units.resolve(&unit("seconds", ValueType::Int(4))), Ok(ValueType::Int(8)));
+        assert_eq!(units.resolve(&unit("hours", ValueType::Int(1))), Ok(ValueType::Int(3_600_000)));
+
+        let broken = BTreeMap::from([("ticks".to_string(), "value *".to_string())]);
+        assert!(Transformers::with_custom(&broken).is_err());
+    }
+
+    #[test]
+    fn school_colors_take_names_and_numbers() {
+        let colors = BTreeMap::from([("Fire".to_string(), ValueType::UInt(0xFF4000))]);
+        let units = Transformers::builtin().with_school_colors(&colors).unwrap();
+        let fire = Ok(ValueType::UInt(0xFF4000));
+        assert_eq!(units.resolve(&unit("school_color", ValueType::String("fire".to_string()))), fire);
+        assert_eq!(units.resolve(&unit("school_color", ValueType::Int(2))), fire);
+        let frost = units.resolve(&unit("school_color", ValueType::String("frost".to_string())));
+        assert!(frost.unwrap_err().contains("no colour for frost"));
+        assert!(units.resolve(&unit("school_color", ValueType::Int(7))).is_err());
+
+        let unknown = BTreeMap::from([("Chaos".to_string(), ValueType::UInt(0))]);
+        assert!(Transformers::builtin().with_school_colors(&unknown).is_err());
+    }
+
+    #[test]
+    fn patches_convert_values_and_conditions() {
+        let yaml = "Spell.dbc:\n  - type: update_where\n    where: { CastingTimeIndex: { seconds: 2 } }\n    values: { DurationIndex: { minutes: 1 } }\n";
+        let mut pfs = parse_patch_str(yaml, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        Transformers::builtin().resolve_patches(&mut pfs).unwrap();
+        let PatchEntry::UpdateWhere { conditions, values } = &pfs[0].changes[0] else {
+            panic!("expected an update_where");
+        };
+        assert!(matches!(&conditions["CastingTimeIndex"], Condition::Equals(ValueType::Int(2000))));
+        assert_eq!(values["DurationIndex"], ValueType::Int(60_000));
+
+        let yaml = "Spell.dbc:\n  - type: update\n    key: 1\n    values: { DurationIndex: { weeks: 1 } }\n";
+        let mut pfs = parse_patch_str(yaml, Path::new("test.yaml"), &IdMap::new()).unwrap();
+        let err = Transformers::builtin().resolve_patches(&mut pfs).unwrap_err();
+        assert!(err.to_string().contains("Invalid value for 'DurationIndex' in Spell.dbc entry 1"), "{}", err);
+    }
+}
@@ -0,0 +1,342 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::apply::{describe_entry, load_patches_with_ids, patch_tables, ProvenanceStep};
+use crate::ids::IdMap;
+use crate::dbc::{find_dbc_file, read_dbc_from};
+use crate::diff::{column_name, render_value};
+use crate::schema::{load_schema, Schema};
+use crate::source::{DbcSource, DbcTable, FileSource};
+use crate::units::Transformers;
+
+/// What `verify` found for one patch entry.
+struct EntryCheck {
+    entry: String,
+    table: String,
+    change: String,
+    /// `ok`, `missing` or `no-op`
+    status: &'static str,
+    problems: Vec<String>,
+}
+
+/// The steps one entry made, as (record key, record before the step, step).
+type EntrySteps<'a> = Vec<(u32, Option<&'a [u32]>, &'a ProvenanceStep)>;
+
+/// Read `DBFilesClient\<name>` out of an open archive, with the schemas of
+/// `schema_dir`, or `None` when the archive does not hold it.
+fn archive_table(archive: &mut wow_mpq::Archive, mpq: &Path, name: &str, schema_dir: &Path) -> Result<Option<DbcTable>> {
+    let archive_name = format!("DBFilesClient\\{}", name);
+    let Ok(data) = archive.read_file(&archive_name) else {
+        return Ok(None);
+    };
+    let (header, records, string_block) = read_dbc_from(
+        data.as_slice(),
+        data.len() as u64,
+        format!("{}:{}", mpq.display(), archive_name),
+        schema_dir,
+    )?;
+    Ok(Some(DbcTable {
+        header,
+        records,
+        string_block,
+    }))
+}
+
+/// Records by their key in column 0.
+fn by_key(records: &[Vec<u32>]) -> HashMap<u32, &Vec<u32>> {
+    records.iter().filter_map(|r| Some((*r.first()?, r))).collect()
+}
+
+/// Check the records one entry changed against the archive: each field
+/// it set must hold the value the finished build gives it, and a record
+/// it deleted must be gone unless a later entry brought it back.
+fn check_steps(
+    table: &str,
+    steps: &EntrySteps,
+    expected: &HashMap<u32, &Vec<u32>>,
+    expected_block: &[u8],
+    built: &HashMap<u32, &Vec<u32>>,
+    built_block: &[u8],
+    schema: Option<&Schema>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    for &(id, before, step) in steps {
+        let Some(want) = expected.get(&id) else {
+            if built.contains_key(&id) {
+                problems.push(format!("{} {} is still in the archive", table, id));
+            }
+            continue;
+        };
+        let Some(have) = built.get(&id) else {
+            problems.push(format!("{} {} is not in the archive", table, id));
+            continue;
+        };
+        if step.removed {
+            continue;
+        }
+        for (col, &value) in step.record.iter().enumerate() {
+            if before.and_then(|b| b.get(col)) == Some(&value) {
+                continue;
+            }
+            let (name, field) = column_name(schema, col);
+            let want = want.get(col).map(|&v| render_value(field, v, expected_block));
+            let have = have.get(col).map(|&v| render_value(field, v, built_block));
+            if want != have {
+                problems.push(format!(
+                    "{} {} {}: expected {}, archive has {}",
+                    table,
+                    id,
+                    name,
+                    want.unwrap_or_else(|| "nothing".to_string()),
+                    have.unwrap_or_else(|| "nothing".to_string())
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Write the results as YAML, for scripts and CI.
+fn write_report(path: &Path, mpq: &Path, checks: &[EntryCheck]) -> Result<()> {
+    let count = |status: &str| checks.iter().filter(|c| c.status == status).count();
+    let mut yaml = format!("# Patch entries checked against {}\n", mpq.display());
+    yaml.push_str(&format!(
+        "summary:\n  ok: {}\n  missing: {}\n  no_op: {}\nentries:\n",
+        count("ok"),
+        count("missing"),
+        count("no-op")
+    ));
+    for check in checks {
+        yaml.push_str(&format!("  - entry: {:?}\n", check.entry));
+        yaml.push_str(&format!("    table: {:?}\n", check.table));
+        yaml.push_str(&format!("    change: {:?}\n", check.change));
+        yaml.push_str(&format!("    status: {}\n", check.status));
+        if !check.problems.is_empty() {
+            yaml.push_str("    problems:\n");
+            for problem in &check.problems {
+                yaml.push_str(&format!("      - {:?}\n", problem));
+            }
+        }
+    }
+    fs::write(path, yaml).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Apply `patch_sets` to the stock tables in memory and confirm that every
+/// change they make is present in the tables packed into `mpq`.  Each
+/// entry is reported as `ok`, `missing` (with the fields or records that
+/// differ) or `no-op` when it changes nothing in the stock tables and
+/// there is nothing to look for.  With `report` the results are also
+/// written there as YAML.  Fails when any entry is missing.
+#[allow(clippy::too_many_arguments)]
+pub fn verify_command(
+    mpq: &Path,
+    patch_sets: &[Vec<PathBuf>],
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    includes_dir: &Path,
+    units: &Transformers,
+    ids: &IdMap,
+    report: Option<&Path>,
+) -> Result<()> {
+    // Entries in the order they are applied, with the table they target
+    let mut entries: Vec<(String, String, String)> = Vec::new();
+    let mut tables: Vec<PathBuf> = Vec::new();
+    for patch_files in patch_sets {
+        for pfs in load_patches_with_ids(patch_files, ids)?.values() {
+            // Numbered as `patch_tables` labels them
+            let mut numbered: HashMap<String, usize> = HashMap::new();
+            for pf in pfs {
+                let origin = pf
+                    .origin
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| "<unknown>".to_string());
+                let next = numbered.entry(origin.clone()).or_default();
+                for change in &pf.changes {
+                    *next += 1;
+                    entries.push((format!("{} entry {}", origin, next), pf.dbc.clone(), describe_entry(change)));
+                }
+            }
+            if let Some(pf) = pfs.first() {
+                let path = find_dbc_file(dbc_dir, &pf.dbc);
+                if !tables.contains(&path) {
+                    tables.push(path);
+                }
+            }
+        }
+    }
+    if entries.is_empty() {
+        bail!("No patch entries to verify");
+    }
+
+    let (overlay, progress) = patch_tables(&tables, patch_sets, dbc_dir, schema_dir, includes_dir, true, None, units, ids)?;
+    let mut archive = wow_mpq::Archive::open(mpq).with_context(|| format!("Failed to open archive {:?}", mpq))?;
+
+    // Problems found for each entry, by table (lower‑cased) and entry
+    // label, which repeats across the tables of one patch file
+    let mut problems: HashMap<(String, String), Vec<String>> = HashMap::new();
+    let mut absent: Vec<String> = Vec::new();
+    for path in &tables {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let Some(steps) = progress
+            .get(&name.to_lowercase())
+            .and_then(|p| p.provenance.as_ref())
+            .filter(|p| !p.is_empty())
+        else {
+            continue;
+        };
+        let Some(built) = archive_table(&mut archive, mpq, &name, schema_dir)? else {
+            absent.push(name.to_lowercase());
+            continue;
+        };
+        let stock = FileSource::new(schema_dir).read_table(path)?;
+        let expected = overlay.read_table(path)?;
+        let schema = load_schema(schema_dir, &name);
+        let (stock_records, expected_records, built_records) =
+            (by_key(&stock.records), by_key(&expected.records), by_key(&built.records));
+
+        // Group each record's steps by the entry that made them, with the
+        // record as it was before the step
+        let mut by_entry: HashMap<&str, EntrySteps> = HashMap::new();
+        for (&id, record_steps) in steps {
+            let mut before = stock_records.get(&id).map(|r| r.as_slice());
+            for step in record_steps {
+                by_entry.entry(step.entry.as_str()).or_default().push((id, before, step));
+                before = (!step.removed).then_some(step.record.as_slice());
+            }
+        }
+        for (entry, entry_steps) in by_entry {
+            let found = check_steps(
+                &name,
+                &entry_steps,
+                &expected_records,
+                &expected.string_block,
+                &built_records,
+                &built.string_block,
+                schema.as_ref(),
+            );
+            problems.entry((name.to_lowercase(), entry.to_string())).or_default().extend(found);
+        }
+    }
+
+    let checks: Vec<EntryCheck> = entries
+        .into_iter()
+        .map(|(entry, table, change)| {
+            let (status, problems) = match problems.get(&(table.to_lowercase(), entry.clone())).cloned() {
+                Some(found) if found.is_empty() => ("ok", found),
+                Some(found) => ("missing", found),
+                None if absent.contains(&table.to_lowercase()) => {
+                    ("missing", vec![format!("{} is not in the archive", table)])
+                }
+                None => ("no-op", Vec::new()),
+            };
+            EntryCheck {
+                entry,
+                table,
+                change,
+                status,
+                problems,
+            }
+        })
+        .collect();
+
+    println!();
+    for check in &checks {
+        println!("{:<8} {} ({})", check.status, check.entry, check.change);
+        for problem in &check.problems {
+            println!("         {}", problem);
+        }
+    }
+    let count = |status: &str| checks.iter().filter(|c| c.status == status).count();
+    let missing = count("missing");
+    println!(
+        "{} entr{} present in {}, {} missing, {} changed nothing",
+        count("ok"),
+        if count("ok") == 1 { "y" } else { "ies" },
+        mpq.display(),
+        missing,
+        count("no-op")
+    );
+    if let Some(report) = report {
+        write_report(report, mpq, &checks)?;
+        say!("Wrote {}", report.display());
+    }
+    if missing > 0 {
+        bail!("{} patch entr{} not present in {}", missing, if missing == 1 { "y is" } else { "ies are" }, mpq.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbc::{write_dbc, DbcFormat, DbcHeader, Endianness};
+    use crate::mpq::{compress_file, write_archive};
+    use wow_mpq::FormatVersion;
+
+    /// Write `records` as a two‑column Test.dbc at `path`.
+    fn write_table(path: &Path, records: &[Vec<u32>]) {
+        let header = DbcHeader {
+            magic: *b"WDBC",
+            record_count: records.len() as u32,
+            field_count: 2,
+            record_size: 8,
+            string_block_size: 1,
+            format: DbcFormat::Wdbc,
+            endianness: Endianness::Little,
+            layout: None,
+        };
+        write_dbc(path, &header, records, &[0]).unwrap();
+    }
+
+    /// Pack `records` of Test.dbc into the archive `mpq`.
+    fn archive(dir: &Path, mpq: &Path, records: &[Vec<u32>]) {
+        let path = dir.join("packed.dbc");
+        write_table(&path, records);
+        let file = compress_file("DBFilesClient\\Test.dbc", &fs::read(&path).unwrap(), 0).unwrap();
+        write_archive(mpq, FormatVersion::V1, &[file]).unwrap();
+    }
+
+    #[test]
+    fn entries_are_checked_against_the_built_archive() {
+        let dir = std::env::temp_dir().join(format!("wow_dbc_patcher-verify-{}", std::process::id()));
+        fs::create_dir_all(dir.join("dbc")).unwrap();
+        write_table(&dir.join("dbc/Test.dbc"), &[vec![1, 0], vec![2, 0], vec![3, 5]]);
+        fs::write(dir.join("Test.dbc.yaml"), "fields: [ID, Value]\n").unwrap();
+        let patch = dir.join("patch.yaml");
+        fs::write(
+            &patch,
+            "Test.dbc:\n  - type: update\n    key: 1\n    values: { Value: 7 }\n  - type: delete\n    key: 3\n",
+        )
+        .unwrap();
+        let (mpq, report) = (dir.join("patch.mpq"), dir.join("report.yaml"));
+        let verify = || {
+            verify_command(
+                &mpq,
+                &[vec![patch.clone()]],
+                &dir.join("dbc"),
+                &dir,
+                &dir.join("includes"),
+                &Transformers::builtin(),
+                &IdMap::new(),
+                Some(&report),
+            )
+        };
+
+        archive(&dir, &mpq, &[vec![1, 7], vec![2, 0]]);
+        verify().unwrap();
+        let written = fs::read_to_string(&report).unwrap();
+        assert!(written.contains("summary:\n  ok: 2\n  missing: 0\n  no_op: 0\n"), "{}", written);
+
+        // The stock table, as if the build had not packed the patched one
+        archive(&dir, &mpq, &[vec![1, 0], vec![2, 0], vec![3, 5]]);
+        let err = verify().unwrap_err();
+        assert!(err.to_string().starts_with("2 patch entries are not present"), "{}", err);
+        let written = fs::read_to_string(&report).unwrap();
+        assert!(written.contains("Test.dbc 1 Value: expected 7, archive has 0"), "{}", written);
+        assert!(written.contains("Test.dbc 3 is still in the archive"), "{}", written);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
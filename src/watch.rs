@@ -0,0 +1,108 @@
+use anyhow::{bail, Context, Result};
+use notify::{RecursiveMode, Watcher};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How long to wait for more changes after the first one before running,
+/// so an editor saving several files, or writing one in steps, triggers
+/// one run.
+const SETTLE: Duration = Duration::from_millis(300);
+
+/// Run this executable with `args`, e.g. `apply` and its options, and
+/// report how long it took.  A failing run is reported, not returned, so
+/// watching carries on until the next change fixes it.
+fn run_once(args: &[String]) -> Result<()> {
+    let exe = env::current_exe().context("Failed to find the running executable")?;
+    let started = Instant::now();
+    let status = Command::new(&exe)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {:?}", exe))?;
+    let elapsed = started.elapsed().as_secs_f64();
+    if status.success() {
+        println!("Finished in {:.2}s; watching for changes", elapsed);
+    } else {
+        println!("Failed with {} after {:.2}s; watching for changes", status, elapsed);
+    }
+    Ok(())
+}
+
+/// The watched directory `path` lies in, for reports.
+fn changed_dir<'a>(dirs: &'a [PathBuf], path: &Path) -> &'a Path {
+    dirs.iter()
+        .map(PathBuf::as_path)
+        .find(|dir| path.starts_with(dir))
+        .unwrap_or(Path::new("."))
+}
+
+/// Run `args` (a subcommand with its options, such as `build --mpq …`)
+/// once, then again whenever a file under `dirs` changes, until
+/// interrupted.  Directories that do not exist are skipped with a warning.
+/// Each run is a fresh process, so it reads the patches, schemas and
+/// manifest anew and takes the project lock like a manual run.
+pub fn watch_command(dirs: &[PathBuf], args: &[String]) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to start watching for changes")?;
+    let mut watched = Vec::new();
+    for dir in dirs {
+        if !dir.is_dir() {
+            warning!("not watching {}: it is not a directory", dir.display());
+            continue;
+        }
+        watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {:?}", dir))?;
+        // Events carry absolute paths
+        watched.push(dir.canonicalize().unwrap_or_else(|_| dir.clone()));
+    }
+    if watched.is_empty() {
+        bail!("None of the directories to watch exist");
+    }
+    println!(
+        "Watching {} for changes",
+        dirs.iter()
+            .filter(|dir| dir.is_dir())
+            .map(|dir| dir.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    run_once(args)?;
+    loop {
+        let event = match rx.recv() {
+            Ok(Ok(event)) => event,
+            Ok(Err(err)) => {
+                warning!("watching failed: {}", err);
+                continue;
+            }
+            Err(_) => bail!("Stopped receiving file changes"),
+        };
+        // Reading a file is not a change
+        if event.kind.is_access() {
+            continue;
+        }
+        let mut paths = event.paths;
+        let deadline = Instant::now() + SETTLE;
+        while let Ok(more) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            if let Ok(more) = more {
+                paths.extend(more.paths);
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        let Some(first) = paths.first() else {
+            continue;
+        };
+        println!();
+        println!(
+            "{} changed in {} ({} path(s)); running again",
+            first.file_name().map_or_else(|| first.display().to_string(), |n| n.to_string_lossy().to_string()),
+            changed_dir(&watched, first).display(),
+            paths.len()
+        );
+        run_once(args)?;
+    }
+}
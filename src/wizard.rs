@@ -0,0 +1,193 @@
+use anyhow::{bail, Context, Result};
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::dbc::{find_dbc_file, read_dbc_with_schema, string_at};
+use crate::schema::{load_schema, FieldDef, FieldKind};
+
+/// Maximum number of candidates listed by the foreign‑key picker.
+const PICKER_LIMIT: usize = 20;
+
+fn prompt(input: &mut impl BufRead, text: &str) -> Result<Option<String>> {
+    print!("{}", text);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// Quote a string for YAML output.  Single quotes keep backslashes in
+/// client paths literal; embedded quotes are doubled.
+fn yaml_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// List records of the referenced table whose string fields contain
+/// `needle`, so the user can pick an ID without leaving the wizard.
+fn pick_reference(dbc_dir: &Path, schema_dir: &Path, table: &str, needle: &str) -> Result<()> {
+    let path = find_dbc_file(dbc_dir, table);
+    let (_header, records, string_block) = read_dbc_with_schema(&path, schema_dir)
+        .with_context(|| format!("Failed to read DBC file {:?}", path))?;
+    let schema = load_schema(schema_dir, table);
+    let string_fields: Vec<FieldDef> = schema
+        .as_ref()
+        .map(|s| s.string_fields().cloned().collect())
+        .unwrap_or_default();
+    if string_fields.is_empty() {
+        println!("  {} has no string fields to search; enter an ID directly", table);
+        return Ok(());
+    }
+    let needle = needle.to_lowercase();
+    let mut shown = 0usize;
+    for record in &records {
+        let hit = string_fields.iter().find_map(|f| {
+            let s = string_at(&string_block, *record.get(f.index)?)?;
+            s.to_lowercase().contains(&needle).then_some(s)
+        });
+        if let Some(s) = hit {
+            println!("  {}  {:?}", record.first().copied().unwrap_or(0), s);
+            shown += 1;
+            if shown == PICKER_LIMIT {
+                println!("  … more matches, refine the search");
+                break;
+            }
+        }
+    }
+    if shown == 0 {
+        println!("  no matches in {}", table);
+    }
+    Ok(())
+}
+
+/// Convert wizard input for `field` into the YAML scalar to emit, or explain
+/// why it is not acceptable.
+fn format_value(field: &FieldDef, input: &str) -> Result<String, String> {
+    match field.kind {
+        FieldKind::String => Ok(yaml_string(input)),
+        FieldKind::Float => match input.parse::<f32>() {
+            // Keep a decimal point so the YAML reads as a float
+            Ok(f) if f.fract() == 0.0 => Ok(format!("{:.1}", f)),
+            Ok(_) => Ok(input.to_string()),
+            Err(_) => Err(format!("'{}' is not a number", input)),
+        },
+        FieldKind::Bool => match input.to_lowercase().as_str() {
+            "true" | "yes" | "1" => Ok("true".to_string()),
+            "false" | "no" | "0" => Ok("false".to_string()),
+            _ => Err(format!("'{}' is not true or false", input)),
+        },
+        FieldKind::Int32 => match input.parse::<i32>() {
+            Ok(_) => Ok(input.to_string()),
+            Err(_) => Err(format!("'{}' is not a signed 32‑bit integer", input)),
+        },
+        FieldKind::Int if field.typed => match input.parse::<i64>() {
+            Ok(_) => Ok(input.to_string()),
+            Err(_) => Err(format!("'{}' is not an integer", input)),
+        },
+        FieldKind::Int => {
+            if input.parse::<i64>().is_ok() {
+                Ok(input.to_string())
+            } else if let Ok(f) = input.parse::<f64>() {
+                // Keep a decimal point so the patch loader reads a float
+                Ok(if f.fract() == 0.0 {
+                    format!("{:.1}", f)
+                } else {
+                    input.to_string()
+                })
+            } else {
+                Err(format!("'{}' is not a number", input))
+            }
+        }
+    }
+}
+
+/// Guided creation of an `insert` entry.  Prompts for a table (unless
+/// given), then walks its schema fields showing each field's type and
+/// foreign key.  An empty answer leaves a field at zero, `?text` searches
+/// the referenced table of a foreign‑key field and `.` finishes early.  The
+/// resulting YAML is printed and, if `out` is given, appended to that patch
+/// file.
+pub fn wizard_command(
+    table: Option<&str>,
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    out: Option<&Path>,
+) -> Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    let table = match table {
+        Some(t) => t.to_string(),
+        None => match prompt(&mut input, "Table (e.g. SpellVisualKit.dbc): ")? {
+            Some(t) if !t.is_empty() => t,
+            _ => bail!("No table given"),
+        },
+    };
+    let Some(schema) = load_schema(schema_dir, &table) else {
+        bail!("No schema found for {}; the wizard needs field names", table);
+    };
+
+    // Suggest the next free ID based on the current table contents
+    let path = find_dbc_file(dbc_dir, &table);
+    if let Ok((_header, records, _)) = read_dbc_with_schema(&path, schema_dir) {
+        let max = records.iter().filter_map(|r| r.first()).max().copied().unwrap_or(0);
+        println!("{} has {} records; highest ID is {}", table, records.len(), max);
+    }
+    println!("Enter a value for each field.  Empty = 0, '.' = finish, '?text' = search a referenced table.");
+
+    let mut values: Vec<(String, String)> = Vec::new();
+    'fields: for field in &schema.fields {
+        loop {
+            let kind = field.kind.name();
+            let label = match &field.reference {
+                Some(r) => format!("{} ({}, ref {}): ", field.name, kind, r),
+                None => format!("{} ({}): ", field.name, kind),
+            };
+            let Some(answer) = prompt(&mut input, &label)? else {
+                break 'fields;
+            };
+            if answer == "." {
+                break 'fields;
+            }
+            if answer.is_empty() {
+                break;
+            }
+            if let Some(needle) = answer.strip_prefix('?') {
+                match &field.reference {
+                    Some(r) => pick_reference(dbc_dir, schema_dir, r, needle)?,
+                    None => println!("  {} is not a foreign key", field.name),
+                }
+                continue;
+            }
+            match format_value(field, &answer) {
+                Ok(v) => {
+                    values.push((field.name.clone(), v));
+                    break;
+                }
+                Err(msg) => println!("  {}", msg),
+            }
+        }
+    }
+    if values.is_empty() {
+        bail!("No values entered; nothing to emit");
+    }
+
+    let mut yaml = format!("\n{}:\n  - type: insert\n    values:\n", table);
+    for (name, value) in &values {
+        yaml.push_str(&format!("      {}: {}\n", name, value));
+    }
+    print!("{}", yaml);
+    if let Some(out) = out {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(out)
+            .with_context(|| format!("Failed to open {:?}", out))?;
+        file.write_all(yaml.as_bytes())
+            .with_context(|| format!("Failed to write {:?}", out))?;
+        println!("Appended to {}", out.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,562 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dbc::find_dbc_file;
+use crate::diff::{column_name, render_value};
+use crate::groups::{select_groups, Date};
+use crate::ids::{assign_ids, load_id_map, IdMap};
+use crate::l10n::Translations;
+use crate::manifest::{run_hooks, HookContext, Manifest, DEFAULT_MANIFEST};
+use crate::includes::{select_includes, IncludeGlobs};
+use crate::schema::load_schema;
+use crate::source::{DbcSource, DbcTable, FileSource};
+use crate::units::Transformers;
+use crate::apply::{exclude_experimental, load_patches_with_ids, patch_paths_or_dir, patch_tables};
+use crate::pack::{build_command, Compression};
+
+/// The `workspace` section of a manifest: patch projects built together,
+/// each a directory with its own `patches`, `includes` and, optionally,
+/// manifest.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Workspace {
+    /// In build order; in a combined archive later members are applied
+    /// on top of earlier ones.
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Member {
+    pub name: String,
+    /// Project directory, relative to the workspace manifest.
+    pub path: PathBuf,
+    /// Archive to build the member into on its own.  Defaults to
+    /// `<out-dir>/<name>.mpq`.
+    #[serde(default)]
+    pub mpq: Option<PathBuf>,
+}
+
+/// A member with its directory resolved and its manifest loaded.
+struct Project<'a> {
+    member: &'a Member,
+    dir: PathBuf,
+    manifest: Manifest,
+    patch_paths: Vec<PathBuf>,
+    units: Transformers,
+}
+
+impl Project<'_> {
+    fn patch_dir(&self) -> PathBuf {
+        self.dir.join("patches")
+    }
+
+    fn includes_dir(&self) -> PathBuf {
+        self.dir.join("includes")
+    }
+
+    /// Include files that go into the member's archive (see
+    /// [`select_includes`]), with the paths they are stored under.
+    fn includes(&self) -> Result<Vec<(PathBuf, String)>> {
+        let (entries, _) = select_includes(&self.includes_dir(), &self.manifest, &IncludeGlobs::default())?;
+        Ok(entries.into_iter().map(|e| (e.path, e.archive_name)).collect())
+    }
+}
+
+/// What one member's patches do to a record: the rendered value of every
+/// column they change, or `None` when they delete it.
+type RecordChanges = Option<BTreeMap<usize, String>>;
+
+/// The records `patched` differs from `stock` in, by key (column 0).
+fn table_changes(stock: &DbcTable, patched: &DbcTable, table: &str, schema_dir: &Path) -> BTreeMap<u32, RecordChanges> {
+    let schema = load_schema(schema_dir, table);
+    let field = |col: usize| column_name(schema.as_ref(), col).1;
+    let index = |t: &DbcTable| -> BTreeMap<u32, usize> {
+        let mut index = BTreeMap::new();
+        for (i, record) in t.records.iter().enumerate() {
+            index.entry(record[0]).or_insert(i);
+        }
+        index
+    };
+    let (before, after) = (index(stock), index(patched));
+    let mut changes = BTreeMap::new();
+    for (&key, &i) in &after {
+        let new = &patched.records[i];
+        let mut columns = BTreeMap::new();
+        for (col, &value) in new.iter().enumerate() {
+            let rendered = render_value(field(col), value, &patched.string_block);
+            let unchanged = before.get(&key).is_some_and(|&j| {
+                render_value(field(col), stock.records[j][col], &stock.string_block) == rendered
+            });
+            if !unchanged {
+                columns.insert(col, rendered);
+            }
+        }
+        if !columns.is_empty() {
+            changes.insert(key, Some(columns));
+        }
+    }
+    for key in before.keys().filter(|key| !after.contains_key(key)) {
+        changes.insert(*key, None);
+    }
+    changes
+}
+
+/// The records one member changes, by lower‑cased table name, with the
+/// name as a patch file spells it.
+type MemberChanges = BTreeMap<String, (String, BTreeMap<u32, RecordChanges>)>;
+
+/// Patch each member's tables in memory from the stock ones and list
+/// every record field two members set to different values, every record
+/// one deletes and another changes, and every include path two members
+/// ship different files under.
+fn find_conflicts(projects: &[Project], dbc_dir: &Path, schema_dir: &Path, ids: &IdMap) -> Result<Vec<String>> {
+    let mut changes: Vec<MemberChanges> = Vec::new();
+    for project in projects {
+        println!("Checking member {}", project.member.name);
+        let mut names: Vec<String> = load_patches_with_ids(&project.patch_paths, ids)?
+            .values()
+            .map(|pfs| pfs[0].dbc.clone())
+            .collect();
+        names.sort_by_key(|n| n.to_lowercase());
+        let dbc_paths: Vec<PathBuf> = names.iter().map(|name| find_dbc_file(dbc_dir, name)).collect();
+        let (overlay, _) = patch_tables(
+            &dbc_paths,
+            std::slice::from_ref(&project.patch_paths),
+            dbc_dir,
+            schema_dir,
+            &project.includes_dir(),
+            false,
+            None,
+            &project.units,
+            ids,
+        )?;
+        let mut tables = BTreeMap::new();
+        for (name, path) in names.iter().zip(&dbc_paths) {
+            let stock = FileSource::new(schema_dir).read_table(path)?;
+            let patched = overlay.read_table(path)?;
+            tables.insert(
+                name.to_lowercase(),
+                (name.clone(), table_changes(&stock, &patched, name, schema_dir)),
+            );
+        }
+        changes.push(tables);
+    }
+
+    let mut conflicts = Vec::new();
+    for (a, first) in projects.iter().enumerate() {
+        for (b, second) in projects.iter().enumerate().skip(a + 1) {
+            let (x, y) = (&first.member.name, &second.member.name);
+            for (lower, (table, records)) in &changes[a] {
+                let Some((_, other)) = changes[b].get(lower) else {
+                    continue;
+                };
+                let schema = load_schema(schema_dir, table);
+                for (key, change) in records {
+                    match (change, other.get(key)) {
+                        (_, None) | (None, Some(None)) => {}
+                        (None, Some(Some(_))) => {
+                            conflicts.push(format!("{} record {}: {} deletes it, {} changes it", table, key, x, y))
+                        }
+                        (Some(_), Some(None)) => {
+                            conflicts.push(format!("{} record {}: {} changes it, {} deletes it", table, key, x, y))
+                        }
+                        (Some(mine), Some(Some(theirs))) => {
+                            for (col, value) in mine {
+                                match theirs.get(col) {
+                                    Some(other_value) if other_value != value => conflicts.push(format!(
+                                        "{} record {} {}: {} sets {}, {} sets {}",
+                                        table,
+                                        key,
+                                        column_name(schema.as_ref(), *col).0,
+                                        x,
+                                        value,
+                                        y,
+                                        other_value
+                                    )),
+                                    _ => {}
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Include files, compared by content
+    let mut shipped: HashMap<String, (&str, PathBuf)> = HashMap::new();
+    for project in projects {
+        for (path, name) in project.includes()? {
+            let name = name.replace('/', "\\").to_lowercase();
+            match shipped.get(&name) {
+                Some((owner, earlier)) => {
+                    let same = fs::read(earlier)
+                        .with_context(|| format!("Failed to read {:?}", earlier))?
+                        == fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+                    if !same {
+                        conflicts.push(format!(
+                            "include {}: {} and {} ship different files",
+                            name, owner, project.member.name
+                        ));
+                    }
+                }
+                None => {
+                    shipped.insert(name, (&project.member.name, path));
+                }
+            }
+        }
+    }
+    Ok(conflicts)
+}
+
+/// Copy the members' includes into `staging` under the paths they are
+/// archived as, later members replacing earlier ones' files of the same
+/// client path, so a combined build packs them as one includes directory.
+fn stage_includes(projects: &[Project], staging: &Path) -> Result<()> {
+    if staging.exists() {
+        fs::remove_dir_all(staging).with_context(|| format!("Failed to clear {:?}", staging))?;
+    }
+    fs::create_dir_all(staging).with_context(|| format!("Failed to create {:?}", staging))?;
+    let mut staged: HashMap<String, PathBuf> = HashMap::new();
+    for project in projects {
+        for (path, name) in project.includes()? {
+            let dest = staging.join(&name);
+            if let Some(earlier) = staged.insert(name.to_lowercase(), dest.clone()) {
+                fs::remove_file(&earlier).with_context(|| format!("Failed to replace {:?}", earlier))?;
+            }
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+            }
+            fs::copy(&path, &dest).with_context(|| format!("Failed to copy {:?} to {:?}", path, dest))?;
+        }
+    }
+    Ok(())
+}
+
+/// The tables `patch_sets` patch, looked up in `dbc_dir`.
+fn patched_dbc_paths(patch_sets: &[Vec<PathBuf>], dbc_dir: &Path, ids: &IdMap) -> Result<Vec<PathBuf>> {
+    let mut names: Vec<String> = load_patches_with_ids(&patch_sets.concat(), ids)?.into_keys().collect();
+    names.sort();
+    Ok(names.iter().map(|name| find_dbc_file(dbc_dir, name)).collect())
+}
+
+/// Build the workspace members listed in `manifest`, or those named in
+/// `only`, each into its own archive or, with `combined`, all into one.
+/// Conflicts between the members are reported first; they stop a
+/// combined build unless `allow_conflicts` is set, in which case later
+/// members win.
+#[allow(clippy::too_many_arguments)]
+pub fn workspace_command(
+    manifest: &Manifest,
+    only: &[String],
+    combined: Option<&Path>,
+    allow_conflicts: bool,
+    check_only: bool,
+    dbc_dir: &Path,
+    schema_dir: &Path,
+    out_dir: &Path,
+    mpq_version: u8,
+    compression: Compression,
+    include_experimental: bool,
+    as_of: Date,
+) -> Result<()> {
+    let members = &manifest.workspace.members;
+    if members.is_empty() {
+        bail!("The manifest lists no workspace members");
+    }
+    for name in only {
+        if !members.iter().any(|m| &m.name == name) {
+            bail!(
+                "Unknown workspace member '{}'; members are {}",
+                name,
+                members.iter().map(|m| m.name.as_str()).collect::<Vec<_>>().join(", ")
+            );
+        }
+    }
+
+    let mut projects = Vec::new();
+    for member in members.iter().filter(|m| only.is_empty() || only.contains(&m.name)) {
+        let dir = manifest.base_dir.join(&member.path);
+        if !dir.is_dir() {
+            bail!("Workspace member '{}' directory {:?} does not exist", member.name, dir);
+        }
+        let manifest_path = dir.join(DEFAULT_MANIFEST);
+        let member_manifest = if manifest_path.exists() {
+            Manifest::load(Some(&manifest_path))?
+        } else {
+            Manifest {
+                base_dir: dir.clone(),
+                ..Default::default()
+            }
+        };
+        if !member_manifest.workspace.members.is_empty() {
+            bail!("Workspace member '{}' is itself a workspace", member.name);
+        }
+        let patch_paths = select_groups(
+            &member_manifest.groups,
+            &member_manifest.seasons,
+            &member_manifest.base_dir,
+            patch_paths_or_dir(&[], &dir.join("patches"))?,
+            true,
+            as_of,
+        )?;
+        let patch_paths = exclude_experimental(patch_paths, include_experimental)?;
+        let units =
+            Transformers::with_custom(&member_manifest.units)?.with_school_colors(&member_manifest.school_colors)?;
+        projects.push(Project {
+            member,
+            dir,
+            manifest: member_manifest,
+            patch_paths,
+            units,
+        });
+    }
+
+    // Members share the workspace's ID map, so no two of them are given
+    // the same ID
+    let all_paths: Vec<PathBuf> = projects.iter().flat_map(|p| p.patch_paths.iter().cloned()).collect();
+    let mut ids = load_id_map(&manifest.ids, &manifest.base_dir)?;
+    assign_ids(&mut ids, &all_paths, dbc_dir, schema_dir, &manifest.ids, &manifest.base_dir, true)?;
+
+    let conflicts = if projects.len() > 1 {
+        find_conflicts(&projects, dbc_dir, schema_dir, &ids)?
+    } else {
+        Vec::new()
+    };
+    if conflicts.is_empty() {
+        println!("No conflicts between {} member(s)", projects.len());
+    } else if combined.is_some() && !allow_conflicts {
+        for conflict in &conflicts {
+            println!("Conflict: {}", conflict);
+        }
+        bail!(
+            "{} conflict(s) between workspace members; pass --allow-conflicts to let later members win",
+            conflicts.len()
+        );
+    } else {
+        // Separate archives load in name order, so one still shadows the other
+        for conflict in &conflicts {
+            warning!("workspace conflict: {}", conflict);
+        }
+    }
+    if check_only {
+        return Ok(());
+    }
+
+    let translations = Translations::for_locales(&[])?;
+    match combined {
+        Some(mpq_path) => {
+            // Units of every member, for patches of any member to use
+            let mut custom = manifest.units.clone();
+            for project in &projects {
+                for (name, expression) in &project.manifest.units {
+                    if custom.get(name).is_some_and(|e| e != expression) {
+                        bail!(
+                            "Workspace member '{}' defines unit '{}' differently from an earlier member",
+                            project.member.name,
+                            name
+                        );
+                    }
+                    custom.insert(name.clone(), expression.clone());
+                }
+            }
+            // Likewise one colour theme for all of them
+            let mut colors = manifest.school_colors.clone();
+            for project in &projects {
+                for (school, color) in &project.manifest.school_colors {
+                    if colors.get(school).is_some_and(|c| c != color) {
+                        bail!(
+                            "Workspace member '{}' gives {} a different school colour from an earlier member",
+                            project.member.name,
+                            school
+                        );
+                    }
+                    colors.insert(school.clone(), color.clone());
+                }
+            }
+            let units = Transformers::with_custom(&custom)?.with_school_colors(&colors)?;
+            let patch_sets: Vec<Vec<PathBuf>> = projects.iter().map(|p| p.patch_paths.clone()).collect();
+            let includes_dir = out_dir.join("workspace-includes");
+            stage_includes(&projects, &includes_dir)?;
+            let hook_ctx = HookContext {
+                dbc_dir: dbc_dir.to_path_buf(),
+                patch_dir: manifest.base_dir.clone(),
+                schema_dir: schema_dir.to_path_buf(),
+                out_dir: out_dir.to_path_buf(),
+                includes_dir: Some(includes_dir.clone()),
+                mpq_path: Some(mpq_path.to_path_buf()),
+            };
+            run_hooks(&manifest.hooks.pre_build, "pre_build", &hook_ctx)?;
+            build_command(
+                &patched_dbc_paths(&patch_sets, dbc_dir, &ids)?,
+                &patch_sets,
+                out_dir,
+                mpq_path,
+                mpq_version,
+                None,
+                true,
+                dbc_dir,
+                schema_dir,
+                &includes_dir,
+                &IncludeGlobs::default(),
+                false,
+                Some(&translations),
+                &units,
+                &ids,
+                false,
+                false,
+                compression,
+                false,
+                None,
+                manifest.defaults.normalize_paths,
+                manifest,
+                &hook_ctx,
+            )?;
+            run_hooks(&manifest.hooks.post_build, "post_build", &hook_ctx)?;
+        }
+        None => {
+            for project in &projects {
+                println!("Building member {}", project.member.name);
+                let member_out = out_dir.join(&project.member.name);
+                let mpq_path = match &project.member.mpq {
+                    Some(path) => manifest.base_dir.join(path),
+                    None => out_dir.join(format!("{}.mpq", project.member.name)),
+                };
+                let patch_sets = vec![project.patch_paths.clone()];
+                let hook_ctx = HookContext {
+                    dbc_dir: dbc_dir.to_path_buf(),
+                    patch_dir: project.patch_dir(),
+                    schema_dir: schema_dir.to_path_buf(),
+                    out_dir: member_out.clone(),
+                    includes_dir: Some(project.includes_dir()),
+                    mpq_path: Some(mpq_path.clone()),
+                };
+                run_hooks(&project.manifest.hooks.pre_build, "pre_build", &hook_ctx)?;
+                build_command(
+                    &patched_dbc_paths(&patch_sets, dbc_dir, &ids)?,
+                    &patch_sets,
+                    &member_out,
+                    &mpq_path,
+                    mpq_version,
+                    None,
+                    true,
+                    dbc_dir,
+                    schema_dir,
+                    &project.includes_dir(),
+                    &IncludeGlobs::default(),
+                    false,
+                    Some(&translations),
+                    &project.units,
+                    &ids,
+                    false,
+                    false,
+                    compression,
+                    false,
+                    None,
+                    project.manifest.defaults.normalize_paths,
+                    &project.manifest,
+                    &hook_ctx,
+                )?;
+                run_hooks(&project.manifest.hooks.post_build, "post_build", &hook_ctx)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbc::{read_dbc, write_dbc, DbcFormat, DbcHeader, Endianness};
+
+    /// A workspace whose members `a` and `b` both set `Value` of record 1
+    /// of a two‑column Test.dbc, differently, and ship different files as
+    /// the same include; `b` also sets record 2.
+    fn workspace(name: &str) -> Manifest {
+        let dir = std::env::temp_dir().join(format!("wow_dbc_patcher-workspace-{}-{}", std::process::id(), name));
+        for sub in ["dbc", "schema", "a/patches", "a/includes/Textures", "b/patches", "b/includes/Textures"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        let header = DbcHeader {
+            magic: *b"WDBC",
+            record_count: 2,
+            field_count: 2,
+            record_size: 8,
+            string_block_size: 1,
+            format: DbcFormat::Wdbc,
+            endianness: Endianness::Little,
+            layout: None,
+        };
+        write_dbc(dir.join("dbc/Test.dbc"), &header, &[vec![1, 0], vec![2, 0]], &[0]).unwrap();
+        fs::write(dir.join("schema/Test.dbc.yaml"), "fields: [ID, Value]\n").unwrap();
+        fs::write(
+            dir.join("a/patches/a.yaml"),
+            "Test.dbc:\n  - type: update\n    key: 1\n    values: { Value: 1 }\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("b/patches/b.yaml"),
+            "Test.dbc:\n  - type: update\n    key: 1\n    values: { Value: 2 }\n\
+             \x20 - type: update\n    key: 2\n    values: { Value: 3 }\n",
+        )
+        .unwrap();
+        fs::write(dir.join("a/includes/Textures/glow.blp"), "a").unwrap();
+        fs::write(dir.join("b/includes/Textures/glow.blp"), "b").unwrap();
+        let member = |name: &str| Member {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            mpq: None,
+        };
+        Manifest {
+            workspace: Workspace {
+                members: vec![member("a"), member("b")],
+            },
+            base_dir: dir,
+            ..Default::default()
+        }
+    }
+
+    fn run(manifest: &Manifest, combined: Option<&Path>, allow_conflicts: bool, check_only: bool) -> Result<()> {
+        let dir = &manifest.base_dir;
+        workspace_command(
+            manifest,
+            &[],
+            combined,
+            allow_conflicts,
+            check_only,
+            &dir.join("dbc"),
+            &dir.join("schema"),
+            &dir.join("out"),
+            1,
+            Compression::Zlib,
+            false,
+            "2026-10-16".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn conflicts_stop_a_combined_build() {
+        let manifest = workspace("conflicts");
+        let mpq = manifest.base_dir.join("out/combined.mpq");
+        let err = run(&manifest, Some(&mpq), false, true).unwrap_err();
+        assert!(err.to_string().starts_with("2 conflict(s)"), "{}", err);
+        // Separate archives only warn
+        run(&manifest, None, false, true).unwrap();
+        fs::remove_dir_all(&manifest.base_dir).ok();
+    }
+
+    #[test]
+    fn later_members_win_with_allow_conflicts() {
+        let manifest = workspace("allow");
+        let mpq = manifest.base_dir.join("out/combined.mpq");
+        run(&manifest, Some(&mpq), true, false).unwrap();
+        let (_, records, _) = read_dbc(manifest.base_dir.join("out/Test.dbc")).unwrap();
+        assert_eq!(records, [vec![1, 2], vec![2, 3]]);
+        let mut archive = wow_mpq::Archive::open(&mpq).unwrap();
+        assert_eq!(archive.read_file("Textures\\glow.blp").unwrap(), b"b");
+        fs::remove_dir_all(&manifest.base_dir).ok();
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::dbc::{find_dbc_file, string_at};
+use crate::source::{DbcSource, DbcTable};
+
+/// Column of the map in AreaTable.dbc.
+const AREA_MAP_COLUMN: usize = 1;
+/// Column of the parent area in AreaTable.dbc.
+const AREA_PARENT_COLUMN: usize = 2;
+/// Column of the English area name in AreaTable.dbc.
+const AREA_NAME_COLUMN: usize = 11;
+/// Column of the instance type in Map.dbc (0 for open world maps).
+const MAP_INSTANCE_TYPE_COLUMN: usize = 2;
+
+/// One row of AreaTable.dbc.
+struct Area {
+    id: u32,
+    map: u32,
+    parent: u32,
+    name: String,
+}
+
+/// The zone hierarchy from AreaTable.dbc, used to turn zone names into
+/// area IDs.
+pub struct AreaTable {
+    areas: Vec<Area>,
+    /// Maps that are dungeons, raids or battlegrounds, from Map.dbc
+    instance_maps: HashSet<u32>,
+}
+
+impl AreaTable {
+    /// Load AreaTable.dbc, and Map.dbc if present, of `dbc_dir` from
+    /// `source`, so areas earlier patch sets added are found too.
+    pub fn load(source: &dyn DbcSource, dbc_dir: &Path) -> Result<AreaTable> {
+        let path = find_dbc_file(dbc_dir, "AreaTable.dbc");
+        let DbcTable { records, string_block, .. } = source.read_table(&path)?;
+        let areas = records
+            .iter()
+            .filter_map(|r| {
+                Some(Area {
+                    id: r[0],
+                    map: *r.get(AREA_MAP_COLUMN)?,
+                    parent: *r.get(AREA_PARENT_COLUMN)?,
+                    name: string_at(&string_block, *r.get(AREA_NAME_COLUMN)?).unwrap_or_default(),
+                })
+            })
+            .collect();
+        // Without Map.dbc instance sub‑zones are only found through parents
+        let instance_maps = source
+            .read_table(&find_dbc_file(dbc_dir, "Map.dbc"))
+            .map(|table| {
+                table
+                    .records
+                    .iter()
+                    .filter(|r| r.get(MAP_INSTANCE_TYPE_COLUMN).is_some_and(|&t| t != 0))
+                    .map(|r| r[0])
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(AreaTable {
+            areas,
+            instance_maps,
+        })
+    }
+
+    /// IDs of the areas a zone name (case‑insensitive) or numeric ID
+    /// denotes, without sub‑areas.  Several areas can share a name.
+    fn lookup(&self, zone: &str) -> Result<HashSet<u32>> {
+        let ids: HashSet<u32> = match zone.trim().parse::<u32>() {
+            Ok(id) => [id].into_iter().collect(),
+            Err(_) => self
+                .areas
+                .iter()
+                .filter(|a| a.name.eq_ignore_ascii_case(zone.trim()))
+                .map(|a| a.id)
+                .collect(),
+        };
+        if ids.is_empty() {
+            bail!("No area named {:?} in AreaTable.dbc", zone);
+        }
+        Ok(ids)
+    }
+
+    /// Resolve a zone given by name or ID to the set of area IDs it covers:
+    /// the zone itself and all of its sub‑areas.  Sub‑zones of instances
+    /// are often not parented, so a top‑level zone on an instance map also
+    /// covers every other area of that map.
+    pub fn resolve(&self, zone: &str) -> Result<HashSet<u32>> {
+        let mut ids = self.lookup(zone)?;
+        let maps: HashSet<u32> = self
+            .areas
+            .iter()
+            .filter(|a| ids.contains(&a.id) && a.parent == 0 && self.instance_maps.contains(&a.map))
+            .map(|a| a.map)
+            .collect();
+        ids.extend(self.areas.iter().filter(|a| maps.contains(&a.map)).map(|a| a.id));
+        // Pull in sub‑areas until the set stops growing
+        loop {
+            let before = ids.len();
+            for a in &self.areas {
+                if a.parent != 0 && ids.contains(&a.parent) {
+                    ids.insert(a.id);
+                }
+            }
+            if ids.len() == before {
+                break;
+            }
+        }
+        Ok(ids)
+    }
+}
+
+/// Record keys that belong to `zone` according to a membership file.  The
+/// file maps zones (names or area IDs) to lists of keys, for example the
+/// spells cast by creatures spawned there as exported from a server
+/// database:
+///
+/// ```yaml
+/// Naxxramas: [28240, 28241]
+/// 3456: [28157]
+/// ```
+///
+/// A listed zone matches when it is `zone` or one of its sub‑areas, so
+/// asking for a zone also picks up keys listed under its sub‑areas.
+pub fn zone_keys(areas: &AreaTable, members: &Path, zone: &str) -> Result<HashSet<u32>> {
+    let content = fs::read_to_string(members)
+        .with_context(|| format!("Failed to read zone membership file {:?}", members))?;
+    let listing: BTreeMap<String, Vec<u32>> = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse zone membership file {:?}", members))?;
+    let wanted = areas.resolve(zone)?;
+    let mut keys = HashSet::new();
+    for (listed_zone, listed_keys) in listing {
+        let listed = areas
+            .lookup(&listed_zone)
+            .with_context(|| format!("In zone membership file {:?}", members))?;
+        if listed.iter().any(|id| wanted.contains(id)) {
+            keys.extend(listed_keys);
+        }
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbc::{DbcFormat, DbcHeader, Endianness};
+    use crate::source::{FileSource, Overlay};
+
+    fn table(field_count: u32, records: Vec<Vec<u32>>, string_block: &[u8]) -> DbcTable {
+        DbcTable {
+            header: DbcHeader {
+                magic: *b"WDBC",
+                record_count: records.len() as u32,
+                field_count,
+                record_size: field_count * 4,
+                string_block_size: string_block.len() as u32,
+                format: DbcFormat::Wdbc,
+                endianness: Endianness::Little,
+                layout: None,
+            },
+            records,
+            string_block: string_block.to_vec(),
+        }
+    }
+
+    #[test]
+    fn areas_of_earlier_patch_sets_are_found() {
+        // Neither table is on disk; both come from an earlier layer
+        let mut overlay = Overlay::new(FileSource::new(Path::new("")));
+        let mut naxxramas = vec![0u32; 12];
+        (naxxramas[0], naxxramas[AREA_MAP_COLUMN], naxxramas[AREA_NAME_COLUMN]) = (3456, 533, 1);
+        let mut wing = vec![0u32; 12];
+        (wing[0], wing[AREA_MAP_COLUMN]) = (3457, 533);
+        overlay.insert("AreaTable.dbc", table(12, vec![naxxramas, wing], b"\0Naxxramas\0"));
+        overlay.insert("Map.dbc", table(3, vec![vec![533, 0, 1]], b"\0"));
+
+        let areas = AreaTable::load(&overlay, Path::new("no-such-dbc-dir")).unwrap();
+        assert_eq!(areas.resolve("naxxramas").unwrap(), HashSet::from([3456, 3457]));
+    }
+}